@@ -0,0 +1,343 @@
+//! Relay throughput with many handles on many threads.
+//!
+//! Each handle echoes every incoming RTP packet back with `relay_media_packet` like an echo test
+//! plugin does so both the dispatch path and the callbacks path are being measured.
+//! Janus core is being faked with plain structs and counting callbacks.
+//!
+//! ```text
+//! cargo bench --bench relay
+//! ```
+
+use std::cell::Cell;
+use std::ffi::{c_void, CString};
+use std::os::raw::{c_char, c_int};
+use std::sync::{OnceLock, RwLock};
+use std::thread;
+use std::time::Instant;
+
+use jansson_sys::json_t;
+use janus_app::plugin::{self, App, Callbacks, Core, JanusCallbacks, JanusPlugin, PluginApp};
+use janus_app::{
+    lazy_static, Error, Handle, IncomingMessage, MediaEvent, MessageResponse, Plugin, Responder,
+};
+use janus_plugin_sys::janus_refcount as JanusRefcount;
+use janus_plugin_sys::plugin::janus_plugin_session as JanusPluginSession;
+use serde_derive::Serialize;
+
+const HANDLES_COUNT: usize = 1000;
+const THREADS_COUNTS: &[usize] = &[1, 2, 4, 8];
+const PACKETS_PER_THREAD: usize = 200_000;
+const PACKET_SIZE: usize = 1200;
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Serialize)]
+struct BenchHandle {
+    id: u64,
+}
+
+impl Handle for BenchHandle {
+    type IncomingMessagePayload = ();
+    type OutgoingMessagePayload = ();
+
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn handle_media_event(&self, media_event: &MediaEvent) {
+        if let MediaEvent::Media {
+            protocol,
+            kind,
+            buffer,
+            ..
+        } = media_event
+        {
+            Callbacks::<BenchPlugin>::relay_media_packet(self, *protocol, *kind, buffer)
+                .expect("Failed to relay packet");
+        }
+    }
+
+    fn handle_message(
+        &mut self,
+        _message: IncomingMessage<()>,
+        _responder: Responder<()>,
+    ) -> Result<MessageResponse<()>, Error> {
+        Ok(MessageResponse::Ack)
+    }
+}
+
+struct BenchPlugin;
+
+impl Plugin for BenchPlugin {
+    type Handle = BenchHandle;
+    type Config = ();
+    type IncomingAdminMessagePayload = ();
+    type OutgoingAdminMessagePayload = ();
+
+    const VERSION: i32 = 1;
+    const VERSION_STRING: &'static str = "0.0.1";
+    const NAME: &'static str = "Bench";
+    const DESCRIPTION: &'static str = "Relay benchmark plugin";
+    const AUTHOR: &'static str = "Bench";
+    const PACKAGE: &'static str = "janus.plugin.bench";
+
+    fn init(_config: ()) -> Result<Box<Self>, Error> {
+        Ok(Box::new(Self))
+    }
+
+    fn build_handle(&self, id: u64) -> Self::Handle {
+        BenchHandle { id }
+    }
+}
+
+// Implemented manually instead of `janus_plugin!` to avoid linking against Janus.
+lazy_static! {
+    static ref APP: RwLock<Option<App<BenchPlugin>>> = RwLock::new(None);
+}
+
+static CORE: OnceLock<Core<BenchPlugin>> = OnceLock::new();
+
+impl PluginApp for BenchPlugin {
+    fn janus_plugin() -> *mut JanusPlugin {
+        std::ptr::null_mut()
+    }
+
+    fn app() -> &'static RwLock<Option<App<Self>>> {
+        &APP
+    }
+
+    fn core() -> &'static OnceLock<Core<Self>> {
+        &CORE
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+thread_local! {
+    // Per-thread counter so counting doesn't make threads contend.
+    static RELAYED: Cell<u64> = const { Cell::new(0) };
+}
+
+extern "C" fn push_event(
+    _handle: *mut JanusPluginSession,
+    _plugin: *mut JanusPlugin,
+    _transaction: *const c_char,
+    _message: *mut json_t,
+    _jsep: *mut json_t,
+) -> c_int {
+    0
+}
+
+#[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
+mod relay {
+    use super::*;
+
+    pub extern "C" fn relay_rtp(
+        _handle: *mut JanusPluginSession,
+        _video: c_int,
+        _buf: *mut c_char,
+        _len: c_int,
+    ) {
+        RELAYED.with(|relayed| relayed.set(relayed.get() + 1));
+    }
+
+    pub extern "C" fn relay_rtcp(
+        _handle: *mut JanusPluginSession,
+        _video: c_int,
+        _buf: *mut c_char,
+        _len: c_int,
+    ) {
+    }
+
+    pub extern "C" fn relay_data(_handle: *mut JanusPluginSession, _buf: *mut c_char, _len: c_int) {
+    }
+
+    pub fn incoming_rtp(raw_handle: *mut JanusPluginSession, packet: &mut [c_char]) {
+        let len = packet.len() as c_int;
+        plugin::incoming_rtp::<BenchPlugin>(raw_handle, 1, packet.as_mut_ptr(), len);
+    }
+}
+
+/// Janus 0.10+ passes packets in structs.
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+mod relay {
+    use janus_app::plugin::{JanusPluginData, JanusPluginRtcp, JanusPluginRtp};
+
+    use super::*;
+
+    pub extern "C" fn relay_rtp(_handle: *mut JanusPluginSession, _packet: *mut JanusPluginRtp) {
+        RELAYED.with(|relayed| relayed.set(relayed.get() + 1));
+    }
+
+    pub extern "C" fn relay_rtcp(_handle: *mut JanusPluginSession, _packet: *mut JanusPluginRtcp) {}
+
+    pub extern "C" fn relay_data(_handle: *mut JanusPluginSession, _packet: *mut JanusPluginData) {}
+
+    pub extern "C" fn send_pli(_handle: *mut JanusPluginSession) {}
+
+    #[cfg(feature = "janus-1")]
+    pub extern "C" fn send_pli_stream(_handle: *mut JanusPluginSession, _mindex: c_int) {}
+
+    pub extern "C" fn send_remb(_handle: *mut JanusPluginSession, _bitrate: u32) {}
+
+    pub extern "C" fn auth_is_signature_valid(
+        _plugin: *mut JanusPlugin,
+        _token: *const c_char,
+    ) -> c_int {
+        0
+    }
+
+    pub extern "C" fn auth_signature_contains(
+        _plugin: *mut JanusPlugin,
+        _token: *const c_char,
+        _descriptor: *const c_char,
+    ) -> c_int {
+        0
+    }
+
+    pub fn incoming_rtp(raw_handle: *mut JanusPluginSession, packet: &mut [c_char]) {
+        let mut packet = JanusPluginRtp {
+            #[cfg(feature = "janus-1")]
+            mindex: 0,
+            video: 1,
+            buffer: packet.as_mut_ptr(),
+            length: packet.len() as u16,
+            extensions: Default::default(),
+        };
+
+        plugin::incoming_rtp::<BenchPlugin>(raw_handle, &mut packet);
+    }
+}
+
+extern "C" fn noop(_handle: *mut JanusPluginSession) {}
+
+extern "C" fn events_is_enabled() -> c_int {
+    0
+}
+
+extern "C" fn notify_event(
+    _plugin: *mut JanusPlugin,
+    _handle: *mut JanusPluginSession,
+    _event: *mut json_t,
+) {
+}
+
+extern "C" fn free_refcount(_refcount: *const JanusRefcount) {}
+
+/// Leading fields of Janus's `janus_ice_handle` which are being read by the crate.
+#[repr(C)]
+struct FakeIceHandle {
+    session: *const c_void,
+    handle_id: u64,
+}
+
+fn create_raw_handle(id: u64) -> *mut JanusPluginSession {
+    let ice_handle = Box::into_raw(Box::new(FakeIceHandle {
+        session: std::ptr::null(),
+        handle_id: id,
+    }));
+
+    Box::into_raw(Box::new(JanusPluginSession {
+        gateway_handle: ice_handle as *mut c_void,
+        plugin_handle: std::ptr::null_mut(),
+        stopped: 0,
+        ref_: JanusRefcount {
+            count: 1,
+            free: free_refcount,
+        },
+    }))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+fn main() {
+    let callbacks = Box::leak(Box::new(JanusCallbacks {
+        push_event,
+        relay_rtp: relay::relay_rtp,
+        relay_rtcp: relay::relay_rtcp,
+        relay_data: relay::relay_data,
+        #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+        send_pli: relay::send_pli,
+        #[cfg(feature = "janus-1")]
+        send_pli_stream: relay::send_pli_stream,
+        #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+        send_remb: relay::send_remb,
+        close_pc: noop,
+        end_session: noop,
+        events_is_enabled,
+        notify_event,
+        #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+        auth_is_signature_valid: relay::auth_is_signature_valid,
+        #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+        auth_signature_contains: relay::auth_signature_contains,
+    }));
+
+    let config_path = CString::new("/tmp").unwrap();
+
+    if plugin::init::<BenchPlugin>(callbacks, config_path.as_ptr()) != 0 {
+        panic!("Failed to init plugin");
+    }
+
+    // Raw pointers are not `Send` so pass them to threads as addresses.
+    let raw_handles = (0..HANDLES_COUNT)
+        .map(|idx| {
+            let raw_handle = create_raw_handle(idx as u64 + 1);
+            let mut error = 0;
+            plugin::create_session::<BenchPlugin>(raw_handle, &mut error);
+            assert_eq!(error, 0, "Failed to create session");
+            raw_handle as usize
+        })
+        .collect::<Vec<_>>();
+
+    println!(
+        "{} handles, {} packets of {} bytes per thread",
+        HANDLES_COUNT, PACKETS_PER_THREAD, PACKET_SIZE
+    );
+
+    for &threads_count in THREADS_COUNTS {
+        let start = Instant::now();
+
+        let threads = (0..threads_count)
+            .map(|thread_idx| {
+                let raw_handles = raw_handles.clone();
+
+                thread::spawn(move || {
+                    let mut packet = vec![0 as c_char; PACKET_SIZE];
+                    packet[0] = 0x80u8 as c_char;
+
+                    for idx in 0..PACKETS_PER_THREAD {
+                        // Spread threads over different handles like Janus's per-handle threads.
+                        let raw_handle =
+                            raw_handles[(thread_idx + idx * threads_count) % HANDLES_COUNT];
+
+                        relay::incoming_rtp(raw_handle as *mut JanusPluginSession, &mut packet);
+                    }
+
+                    RELAYED.with(Cell::take)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let relayed = threads
+            .into_iter()
+            .map(|thread| thread.join().expect("Benchmark thread panicked"))
+            .sum::<u64>();
+
+        let elapsed = start.elapsed();
+        assert_eq!(relayed as usize, threads_count * PACKETS_PER_THREAD);
+
+        println!(
+            "{:>2} threads: {:>10.0} packets/s ({:?})",
+            threads_count,
+            relayed as f64 / elapsed.as_secs_f64(),
+            elapsed
+        );
+    }
+
+    for raw_handle in raw_handles {
+        let mut error = 0;
+        plugin::destroy_session::<BenchPlugin>(raw_handle as *mut JanusPluginSession, &mut error);
+    }
+
+    plugin::destroy::<BenchPlugin>();
+}
@@ -0,0 +1,14 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// Requests sent with Janus's Admin API `message_plugin` call.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "request")]
+pub enum AdminRequest {
+    GetConfig,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminResponse {
+    Config { ping_response: String },
+}
@@ -1,13 +1,9 @@
 use std::sync::Arc;
 
-use futures::executor::ThreadPool;
-use janus_app::{
-    plugin::Callbacks, Error, IncomingMessage, MediaEvent, MessageResponse, OutgoingMessage,
-};
+use janus_app::{Error, IncomingMessage, MediaEvent, MessageResponse, Responder};
 use serde_derive::{Deserialize, Serialize};
 
 use crate::config::Config;
-use crate::ExamplePlugin;
 
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "lowercase", tag = "method")]
@@ -25,17 +21,11 @@ pub struct Handle {
     id: u64,
     #[serde(skip)]
     config: Arc<Config>,
-    #[serde(skip)]
-    thread_pool: Arc<ThreadPool>,
 }
 
 impl Handle {
-    pub(crate) fn new(id: u64, config: Arc<Config>, thread_pool: Arc<ThreadPool>) -> Self {
-        Self {
-            id,
-            config,
-            thread_pool,
-        }
+    pub(crate) fn new(id: u64, config: Arc<Config>) -> Self {
+        Self { id, config }
     }
 }
 
@@ -50,69 +40,48 @@ impl janus_app::Handle for Handle {
     fn handle_media_event(&self, media_event: &MediaEvent) {
         match media_event {
             MediaEvent::Setup => {
-                println!("Media setup");
+                log::info!("Media setup");
             }
             MediaEvent::Media {
                 protocol,
                 kind,
                 buffer,
+                ..
             } => {
-                println!("Got {} bytes of {} by {}", buffer.len(), kind, protocol);
+                log::trace!("Got {} bytes of {} by {}", buffer.len(), kind, protocol);
             }
-            MediaEvent::Data { buffer } => {
-                println!("Got {} bytes of data", buffer.len());
+            MediaEvent::Data { buffer, .. } => {
+                log::trace!("Got {} bytes of data", buffer.len());
             }
             MediaEvent::SlowLink { kind, uplink } => {
-                println!("Slow link on {} media: {}", kind, uplink);
+                log::warn!("Slow link on {} media: {}", kind, uplink);
             }
             MediaEvent::Hangup => {
-                println!("Media hangup");
+                log::info!("Media hangup");
             }
         }
     }
 
     fn handle_message(
-        &self,
+        &mut self,
         message: IncomingMessage<Self::IncomingMessagePayload>,
+        _responder: Responder<Self::OutgoingMessagePayload>,
     ) -> Result<MessageResponse<Self::OutgoingMessagePayload>, Error> {
-        let id = self.id();
-
-        let future = async move {
-            use janus_app::plugin::PluginApp;
+        let config = self.config.clone();
 
-            // TODO: Add a more beautiful way to get plugin handle by ID.
-            match ExamplePlugin::app().read() {
-                Err(err) => println!("Failed to acquire app read lock: {}", err),
-                Ok(app_ref) => match &*app_ref {
-                    None => println!("Plugin not initialized"),
-                    Some(app) => match app.handle(id) {
-                        None => println!("Handle {} not found", id),
-                        Some(handle) => match message.payload() {
-                            IncomingMessagePayload::Ping { ref data } => {
-                                handle.ping(message.transaction(), data);
-                            }
-                        },
-                    },
-                },
+        // The response is being pushed with the message's transaction when the future completes.
+        Ok(MessageResponse::deferred(async move {
+            match message.payload() {
+                IncomingMessagePayload::Ping { ref data } => Ok(ping(&config, data)),
             }
-        };
-
-        self.thread_pool.spawn_ok(future);
-        Ok(MessageResponse::Ack)
+        }))
     }
 }
 
-impl Handle {
-    fn ping(&self, transaction: &str, data: &str) {
-        let message = OutgoingMessage::new(
-            transaction.to_owned(),
-            OutgoingMessagePayload::Pong {
-                data: format!("{} {}", data, self.config.ping_response),
-            },
-        );
+fn ping(config: &Config, data: &str) -> OutgoingMessagePayload {
+    log::debug!("Ping: {}", data);
 
-        if let Err(err) = Callbacks::<ExamplePlugin>::push_event(self, &message) {
-            println!("{}", err);
-        }
+    OutgoingMessagePayload::Pong {
+        data: format!("{} {}", data, config.general.ping_response),
     }
 }
@@ -1,28 +1,27 @@
-use std::path::Path;
 use std::sync::Arc;
 
-use futures::executor::ThreadPool;
 use janus_app::{janus_plugin, Error, Plugin};
 
+use crate::admin::{AdminRequest, AdminResponse};
 use crate::{config::Config, handle::Handle};
 
 pub struct ExamplePlugin {
-    #[allow(dead_code)]
     config: Arc<Config>,
-    thread_pool: Arc<ThreadPool>,
 }
 
 impl ExamplePlugin {
-    fn new(config: Config, thread_pool: ThreadPool) -> Self {
+    fn new(config: Config) -> Self {
         Self {
             config: Arc::new(config),
-            thread_pool: Arc::new(thread_pool),
         }
     }
 }
 
 impl Plugin for ExamplePlugin {
     type Handle = Handle;
+    type Config = Config;
+    type IncomingAdminMessagePayload = AdminRequest;
+    type OutgoingAdminMessagePayload = AdminResponse;
 
     const VERSION: i32 = 1;
     const VERSION_STRING: &'static str = "0.0.1";
@@ -31,30 +30,95 @@ impl Plugin for ExamplePlugin {
     const AUTHOR: &'static str = "Fey Martynov";
     const PACKAGE: &'static str = "janus.plugin.app_example";
 
-    fn init(config_path: &Path) -> Result<Box<Self>, Error> {
-        let config = Config::from_path(config_path)
-            .map_err(|err| Error::new(&format!("Failed to load config: {}", err)))?;
-
-        let thread_pool = ThreadPool::new()
-            .map_err(|err| Error::new(&format!("Failed to start thread pool: {}", err)))?;
-
-        let plugin = Self::new(config, thread_pool);
-        println!("Example plugin initialized");
+    fn init(config: Config) -> Result<Box<Self>, Error> {
+        let plugin = Self::new(config);
+        log::info!("Example plugin initialized");
         Ok(Box::new(plugin))
     }
 
     fn build_handle(&self, id: u64) -> Self::Handle {
-        Handle::new(id, self.config.clone(), self.thread_pool.clone())
+        Handle::new(id, self.config.clone())
+    }
+
+    fn handle_admin_message(&self, payload: AdminRequest) -> Result<AdminResponse, Error> {
+        match payload {
+            AdminRequest::GetConfig => Ok(AdminResponse::Config {
+                ping_response: self.config.general.ping_response.clone(),
+            }),
+        }
     }
 }
 
 impl Drop for ExamplePlugin {
     fn drop(&mut self) {
-        println!("Example plugin destroyed");
+        log::info!("Example plugin destroyed");
     }
 }
 
 janus_plugin!(ExamplePlugin);
 
+mod admin;
 mod config;
 mod handle;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use janus_app::testing::{Call, MessageResult, MockJanus};
+    use serde_json::json;
+
+    use super::*;
+
+    fn mock_janus() -> MockJanus<ExamplePlugin> {
+        let config_dir = std::env::temp_dir().join("janus-app-example-test");
+        std::fs::create_dir_all(&config_dir).unwrap();
+
+        std::fs::write(
+            config_dir.join("janus.plugin.app_example.jcfg"),
+            "general: {\n    ping_response = \"pong\"\n}\n",
+        )
+        .unwrap();
+
+        MockJanus::<ExamplePlugin>::new(&config_dir).unwrap()
+    }
+
+    #[test]
+    fn ping() {
+        let janus = mock_janus();
+        let handle_id = janus.create_handle().unwrap();
+
+        let payload = json!({"method": "ping", "data": "hello"});
+        let result = janus
+            .send_message(handle_id, "txn", &payload, None)
+            .unwrap();
+        assert_eq!(result, MessageResult::OkWait);
+
+        let call = janus
+            .wait_for(Duration::from_secs(5), Call::is_push_event)
+            .unwrap();
+
+        match call {
+            Call::PushEvent {
+                transaction,
+                message,
+                ..
+            } => {
+                assert_eq!(transaction.as_deref(), Some("txn"));
+                assert_eq!(message, json!({"Pong": {"data": "hello pong"}}));
+            }
+            other => panic!("Unexpected call: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn get_config() {
+        let janus = mock_janus();
+
+        let response = janus
+            .send_admin_message(&json!({"request": "get_config"}))
+            .unwrap();
+
+        assert_eq!(response, json!({"config": {"ping_response": "pong"}}));
+    }
+}
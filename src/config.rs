@@ -0,0 +1,330 @@
+//! Loading plugin configs from Janus's config directory.
+//!
+//! Janus passes the directory with its configs to plugins on init. The crate looks there for
+//! a file named after the plugin's [package](../trait.Plugin.html#associatedconstant.PACKAGE),
+//! i.e. `janus.plugin.my_plugin.jcfg` like Janus's own configs, see [jcfg](../jcfg/index.html),
+//! or `.toml`, `.json`, `.yaml` or `.yml`, checked in this order, and deserializes it into
+//! [Plugin::Config](../trait.Plugin.html#associatedtype.Config).
+//!
+//! Environment variables prefixed with the package name in upper case with non-alphanumeric
+//! characters replaced by `_` override the file's values. Nested keys are separated with `__`,
+//! e.g. `JANUS_PLUGIN_MY_PLUGIN_RECORDINGS__PATH=/var/recordings` sets `path` in the
+//! `recordings` section. Values missing in both are taken from `#[serde(default)]` attributes
+//! so the file itself is optional:
+//!
+//! ```
+//! use serde_derive::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! pub struct Config {
+//!     #[serde(default = "default_max_rooms")]
+//!     pub max_rooms: usize,
+//!     #[serde(default)]
+//!     pub recordings: RecordingsConfig,
+//! }
+//!
+//! #[derive(Default, Deserialize)]
+//! pub struct RecordingsConfig {
+//!     pub path: Option<String>,
+//! }
+//!
+//! fn default_max_rooms() -> usize {
+//!     100
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use ::config::{Config as Parser, ConfigError, File, FileFormat, Source, Value};
+use serde::de::DeserializeOwned;
+
+use crate::jcfg::JcfgFile;
+use crate::Error;
+
+/// Config file format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Jcfg,
+    Toml,
+    Json,
+    Yaml,
+}
+
+/// Config file extensions with their formats in the order of lookup.
+const FORMATS: [(&str, Format); 5] = [
+    ("jcfg", Format::Jcfg),
+    ("toml", Format::Toml),
+    ("json", Format::Json),
+    ("yaml", Format::Yaml),
+    ("yml", Format::Yaml),
+];
+
+/// Separator of nested keys in environment variable names.
+const ENV_SEPARATOR: &str = "__";
+
+/// Loads the config of `package` from `config_dir` with environment variable overrides.
+pub fn load<C: DeserializeOwned>(config_dir: &Path, package: &str) -> Result<C, Error> {
+    load_from(config_dir, package, std::env::vars())
+}
+
+/// Same as [load](fn.load.html) but with the environment passed in.
+fn load_from<C, E>(config_dir: &Path, package: &str, env: E) -> Result<C, Error>
+where
+    C: DeserializeOwned,
+    E: Iterator<Item = (String, String)>,
+{
+    let mut parser = Parser::default();
+    let file = find_file(config_dir, package);
+
+    if let Some((path, format)) = &file {
+        let path_str = path.to_string_lossy();
+
+        let merge_result = match format {
+            Format::Jcfg => parser.merge(JcfgFile::new(path)),
+            Format::Toml => parser.merge(File::new(&path_str, FileFormat::Toml)),
+            Format::Json => parser.merge(File::new(&path_str, FileFormat::Json)),
+            Format::Yaml => parser.merge(File::new(&path_str, FileFormat::Yaml)),
+        };
+
+        merge_result.map_err(|err| Error::new(&format!("Failed to read config: {}", err)))?;
+    }
+
+    parser
+        .merge(EnvVars::new(package, env))
+        .map_err(|err| Error::new(&format!("Failed to read config from environment: {}", err)))?;
+
+    let empty = parser
+        .cache
+        .clone()
+        .into_table()
+        .map(|table| table.is_empty());
+
+    let result = parser.try_into().or_else(|err| {
+        // An empty table doesn't deserialize into `()` which plugins without configs have.
+        if empty.unwrap_or(false) {
+            Parser::default().try_into().map_err(|_| err)
+        } else {
+            Err(err)
+        }
+    });
+
+    result.map_err(|err| {
+        let source = match &file {
+            Some((path, _)) => path.display().to_string(),
+            None => String::from("environment"),
+        };
+
+        Error::new(&format!("Failed to parse config ({}): {}", source, err))
+    })
+}
+
+/// Path of the first existing config file of `package` with its format.
+pub fn find_file(config_dir: &Path, package: &str) -> Option<(PathBuf, Format)> {
+    FORMATS.iter().find_map(|(extension, format)| {
+        // Package names contain dots so `Path::set_extension` would cut them.
+        let path = config_dir.join(format!("{}.{}", package, extension));
+
+        if path.is_file() {
+            Some((path, *format))
+        } else {
+            None
+        }
+    })
+}
+
+/// Environment variable prefix for `package`, e.g. `JANUS_PLUGIN_MY_PLUGIN`.
+pub fn env_prefix(package: &str) -> String {
+    package
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Environment variables of a package as a source for the [config](https://docs.rs/config/0.10)
+/// crate. Unlike its `Environment` they are being taken from the given variables rather than
+/// the process environment.
+#[derive(Clone, Debug)]
+struct EnvVars {
+    vars: HashMap<String, String>,
+}
+
+impl EnvVars {
+    fn new<E: Iterator<Item = (String, String)>>(package: &str, env: E) -> Self {
+        let prefix = format!("{}_", env_prefix(package));
+
+        let vars = env
+            .filter_map(|(name, value)| {
+                let key = match name.get(..prefix.len()) {
+                    Some(head) if head.eq_ignore_ascii_case(&prefix) => &name[prefix.len()..],
+                    _ => return None,
+                };
+
+                Some((key.replace(ENV_SEPARATOR, ".").to_lowercase(), value))
+            })
+            .collect();
+
+        Self { vars }
+    }
+}
+
+impl Source for EnvVars {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<HashMap<String, Value>, ConfigError> {
+        let uri = String::from("the environment");
+
+        Ok(self
+            .vars
+            .iter()
+            .map(|(key, value)| (key.clone(), Value::new(Some(&uri), value.as_str())))
+            .collect())
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_derive::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Config {
+        name: String,
+        #[serde(default = "default_port")]
+        port: u16,
+        #[serde(default)]
+        nested: Nested,
+    }
+
+    #[derive(Debug, Default, PartialEq, Deserialize)]
+    struct Nested {
+        enabled: bool,
+    }
+
+    fn default_port() -> u16 {
+        8000
+    }
+
+    fn config_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("janus-app-config-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn formats() {
+        let dir = config_dir("formats");
+        let package = "janus.plugin.formats";
+
+        fs::write(dir.join("janus.plugin.formats.yml"), "name: yml\n").unwrap();
+        let config: Config = load(&dir, package).unwrap();
+        assert_eq!(config.name, "yml");
+        assert_eq!(config.port, 8000);
+
+        let json = r#"{"name": "json", "port": 8001, "nested": {"enabled": true}}"#;
+        fs::write(dir.join("janus.plugin.formats.json"), json).unwrap();
+
+        assert_eq!(
+            load::<Config>(&dir, package).unwrap(),
+            Config {
+                name: String::from("json"),
+                port: 8001,
+                nested: Nested { enabled: true },
+            }
+        );
+
+        fs::write(dir.join("janus.plugin.formats.toml"), "name = \"toml\"\n").unwrap();
+        assert_eq!(load::<Config>(&dir, package).unwrap().name, "toml");
+
+        let jcfg = "name = \"jcfg\"\nport = \"8002\"\nnested: { enabled = \"yes\" }\n";
+        fs::write(dir.join("janus.plugin.formats.jcfg"), jcfg).unwrap();
+
+        assert_eq!(
+            load::<Config>(&dir, package).unwrap(),
+            Config {
+                name: String::from("jcfg"),
+                port: 8002,
+                nested: Nested { enabled: true },
+            }
+        );
+    }
+
+    #[test]
+    fn unit() {
+        let dir = config_dir("unit");
+        load::<()>(&dir, "janus.plugin.unit").unwrap();
+    }
+
+    #[test]
+    fn env_overrides() {
+        let dir = config_dir("env");
+        let toml = "name = \"file\"\nport = 8001\n";
+        fs::write(dir.join("janus.plugin.env-test.toml"), toml).unwrap();
+        assert_eq!(env_prefix("janus.plugin.env-test"), "JANUS_PLUGIN_ENV_TEST");
+
+        let env = vec![
+            ("JANUS_PLUGIN_ENV_TEST_PORT", "9000"),
+            ("janus_plugin_env_test_nested__enabled", "true"),
+            ("JANUS_PLUGIN_OTHER_PORT", "9001"),
+        ];
+
+        let env_vars = env
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value.to_owned()));
+
+        let config: Config = load_from(&dir, "janus.plugin.env-test", env_vars).unwrap();
+        assert_eq!((config.name.as_str(), config.port), ("file", 9000));
+        assert!(config.nested.enabled);
+
+        let config: Config = load_from(&dir, "janus.plugin.env-test", std::iter::empty()).unwrap();
+        assert_eq!((config.port, config.nested.enabled), (8001, false));
+    }
+
+    #[test]
+    fn errors() {
+        let dir = config_dir("errors");
+        let err = load::<Config>(&dir, "janus.plugin.missing").unwrap_err();
+        assert!(err.as_str().contains("(environment)"), "{}", err);
+        assert!(err.as_str().contains("missing field `name`"), "{}", err);
+
+        fs::write(dir.join("janus.plugin.invalid.toml"), "name = \n").unwrap();
+        let err = load::<Config>(&dir, "janus.plugin.invalid").unwrap_err();
+        assert!(err.as_str().starts_with("Failed to read config"), "{}", err);
+
+        fs::write(dir.join("janus.plugin.broken.jcfg"), "name = \"x").unwrap();
+        let err = load::<Config>(&dir, "janus.plugin.broken").unwrap_err();
+        let expected = "Unterminated string at line 1, column 8 in ";
+        assert!(err.as_str().contains(expected), "{}", err);
+        assert!(
+            err.as_str().ends_with("janus.plugin.broken.jcfg"),
+            "{}",
+            err
+        );
+
+        fs::write(
+            dir.join("janus.plugin.mistyped.toml"),
+            "name = \"mistyped\"\nport = \"http\"",
+        )
+        .unwrap();
+        let err = load::<Config>(&dir, "janus.plugin.mistyped").unwrap_err();
+        assert!(
+            err.as_str().contains("janus.plugin.mistyped.toml"),
+            "{}",
+            err
+        );
+    }
+}
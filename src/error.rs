@@ -1,23 +1,239 @@
 use std::error::Error as StdError;
 use std::fmt::{self, Display};
 
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::Value as JsonValue;
+
+/// Error category which defines the default numeric code sent to the client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// Malformed or invalid request.
+    BadRequest,
+    /// Missing or invalid credentials.
+    Unauthorized,
+    /// Not allowed for the requester.
+    Forbidden,
+    /// Requested entity doesn't exist.
+    NotFound,
+    /// Operation didn't finish in time.
+    Timeout,
+    /// Operation conflicts with the current state, e.g. an entity already exists.
+    Conflict,
+    /// Failure on the plugin's side.
+    Internal,
+}
+
+impl ErrorKind {
+    /// Numeric code to send as `error_code` unless overridden with
+    /// [Error::set_code](struct.Error.html#method.set_code).
+    pub fn code(self) -> u32 {
+        match self {
+            Self::BadRequest => 400,
+            Self::Unauthorized => 401,
+            Self::Forbidden => 403,
+            Self::NotFound => 404,
+            Self::Timeout => 408,
+            Self::Conflict => 409,
+            Self::Internal => 500,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::BadRequest => "bad_request",
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden => "forbidden",
+            Self::NotFound => "not_found",
+            Self::Timeout => "timeout",
+            Self::Conflict => "conflict",
+            Self::Internal => "internal",
+        }
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.as_str())
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Crate-wide error.
+///
+/// Errors returned from [Handle::handle_message](trait.Handle.html#tymethod.handle_message)
+/// are being sent back to the client as a JSON object like the stock Janus plugins do:
+///
+/// ```json
+/// {"error_code": 404, "error": "Room 123 not found", "data": {"room": 123}}
+/// ```
+///
+/// `data` is present only when set.
+///
+/// ```
+/// use janus_app::{Error, ErrorKind};
+///
+/// let err = Error::with_kind(ErrorKind::NotFound, "Room 123 not found")
+///     .set_data(serde_json::json!({ "room": 123 }));
+///
+/// assert_eq!(err.code(), 404);
+/// ```
 #[derive(Debug)]
-pub struct Error(String);
+pub struct Error {
+    kind: ErrorKind,
+    code: Option<u32>,
+    detail: String,
+    data: Option<JsonValue>,
+    source: Option<Box<dyn StdError + Send + Sync + 'static>>,
+}
 
 impl Error {
+    /// Creates an internal error.
     pub fn new(detail: &str) -> Self {
-        Self(detail.to_owned())
+        Self::with_kind(ErrorKind::Internal, detail)
+    }
+
+    pub fn with_kind(kind: ErrorKind, detail: &str) -> Self {
+        Self {
+            kind,
+            code: None,
+            detail: detail.to_owned(),
+            data: None,
+            source: None,
+        }
+    }
+
+    pub fn bad_request(detail: &str) -> Self {
+        Self::with_kind(ErrorKind::BadRequest, detail)
+    }
+
+    pub fn unauthorized(detail: &str) -> Self {
+        Self::with_kind(ErrorKind::Unauthorized, detail)
+    }
+
+    pub fn forbidden(detail: &str) -> Self {
+        Self::with_kind(ErrorKind::Forbidden, detail)
+    }
+
+    pub fn not_found(detail: &str) -> Self {
+        Self::with_kind(ErrorKind::NotFound, detail)
+    }
+
+    pub fn timeout(detail: &str) -> Self {
+        Self::with_kind(ErrorKind::Timeout, detail)
+    }
+
+    pub fn conflict(detail: &str) -> Self {
+        Self::with_kind(ErrorKind::Conflict, detail)
+    }
+
+    /// Overrides the default code of the kind, e.g. to keep compatibility with codes
+    /// of an existing plugin.
+    pub fn set_code(self, code: u32) -> Self {
+        Self {
+            code: Some(code),
+            ..self
+        }
+    }
+
+    /// Sets an arbitrary payload to send to the client along with the error.
+    pub fn set_data(self, data: JsonValue) -> Self {
+        Self {
+            data: Some(data),
+            ..self
+        }
+    }
+
+    /// Sets the underlying cause available through `std::error::Error::source`.
+    pub fn set_source<E>(self, source: E) -> Self
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        Self {
+            source: Some(Box::new(source)),
+            ..self
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn code(&self) -> u32 {
+        self.code.unwrap_or_else(|| self.kind.code())
     }
 
     pub fn as_str(&self) -> &str {
-        &self.0
+        &self.detail
+    }
+
+    pub fn data(&self) -> Option<&JsonValue> {
+        self.data.as_ref()
     }
 }
 
-impl StdError for Error {}
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source
+            .as_ref()
+            .map(|source| &**source as &(dyn StdError + 'static))
+    }
+}
 
 impl Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(&self.0, fmt)
+        fmt::Display::fmt(&self.detail, fmt)
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = if self.data.is_some() { 3 } else { 2 };
+        let mut map = serializer.serialize_map(Some(len))?;
+        map.serialize_entry("error_code", &self.code())?;
+        map.serialize_entry("error", &self.detail)?;
+
+        if let Some(ref data) = self.data {
+            map.serialize_entry("data", data)?;
+        }
+
+        map.end()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as StdError;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn serialize() {
+        let err = Error::bad_request("Missing room");
+        let value = serde_json::to_value(&err).unwrap();
+        assert_eq!(value, json!({"error_code": 400, "error": "Missing room"}));
+
+        let err = Error::not_found("Room not found")
+            .set_code(426)
+            .set_data(json!({"room": 1}));
+
+        let value = serde_json::to_value(&err).unwrap();
+
+        assert_eq!(
+            value,
+            json!({"error_code": 426, "error": "Room not found", "data": {"room": 1}})
+        );
+    }
+
+    #[test]
+    fn source() {
+        let io_err = std::io::Error::other("disk is full");
+        let err = Error::new("Failed to save recording").set_source(io_err);
+        assert_eq!(err.kind(), ErrorKind::Internal);
+        assert_eq!(err.source().unwrap().to_string(), "disk is full");
     }
 }
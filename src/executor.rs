@@ -0,0 +1,38 @@
+//! Executors driving [deferred](../enum.MessageResponse.html#variant.Deferred) message responses.
+//!
+//! By default deferred responses are being driven on a thread pool shared by all plugins in the
+//! process. Override [Plugin::executor](../trait.Plugin.html#method.executor) to use the plugin's
+//! own runtime instead.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use futures::executor::ThreadPool;
+use lazy_static::lazy_static;
+
+/// Boxed `Send` future as being passed to an executor.
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+/// Something able to run a future to completion in background.
+pub trait Executor: Send + Sync {
+    fn spawn(&self, future: BoxFuture<()>);
+}
+
+impl Executor for ThreadPool {
+    fn spawn(&self, future: BoxFuture<()>) {
+        self.spawn_ok(future);
+    }
+}
+
+lazy_static! {
+    static ref DEFAULT_EXECUTOR: ThreadPool = ThreadPool::builder()
+        .name_prefix("janus-app-")
+        .create()
+        .expect("Failed to start default executor thread pool");
+}
+
+/// The thread pool used unless the plugin overrides its executor.
+/// It's being started on first use.
+pub fn default_executor() -> &'static ThreadPool {
+    &DEFAULT_EXECUTOR
+}
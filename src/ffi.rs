@@ -1,4 +1,29 @@
-/// Necessary low-level stuff missing in janus-plugin-sys crate.
+//! Necessary low-level stuff missing in janus-plugin-sys crate.
+
+use std::os::raw::{c_char, c_int};
+
+use jansson_sys::json_t;
+use janus_plugin_sys::plugin::{
+    janus_plugin_result as JanusPluginResult, janus_plugin_result_type as JanusPluginResultType,
+};
+
+#[cfg(all(feature = "janus-0-10", feature = "janus-1"))]
+compile_error!("Features `janus-0-10` and `janus-1` are mutually exclusive");
+
+/// Plugin API version Janus checks the plugin against on loading.
+#[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
+pub(crate) const JANUS_PLUGIN_API_VERSION: c_int = 13;
+#[cfg(feature = "janus-0-10")]
+pub(crate) const JANUS_PLUGIN_API_VERSION: c_int = 15;
+#[cfg(feature = "janus-1")]
+pub(crate) const JANUS_PLUGIN_API_VERSION: c_int = 100;
+
+// Janus 0.8 tables match janus-plugin-sys.
+#[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
+pub use janus_plugin_sys::plugin::{janus_callbacks, janus_plugin};
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+pub use self::packets::*;
 
 #[repr(C)]
 #[derive(Debug)]
@@ -7,3 +32,306 @@ pub(crate) struct janus_ice_handle {
     pub handle_id: u64,
     // There are a lot more fields but we need only `handle_id`.
 }
+
+type JanusPluginResultNew = unsafe extern "C" fn(
+    type_: JanusPluginResultType,
+    text: *const c_char,
+    content: *mut json_t,
+) -> *mut JanusPluginResult;
+
+/// Resolves a symbol of Janus core in runtime so the crate also works outside of Janus,
+/// e.g. in tests.
+pub(crate) fn janus_symbol<T>(name: &[u8]) -> Option<*mut T> {
+    let ptr = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr() as *const c_char) };
+
+    if ptr.is_null() {
+        None
+    } else {
+        Some(ptr as *mut T)
+    }
+}
+
+/// Janus frees the result with `janus_plugin_result_destroy` so it must be allocated by the core.
+/// Outside of Janus it's being allocated in Rust and must be freed with
+/// [free_plugin_result](fn.free_plugin_result.html).
+pub(crate) fn new_plugin_result(result: JanusPluginResult) -> *mut JanusPluginResult {
+    let janus_fn = janus_symbol::<()>(b"janus_plugin_result_new\0")
+        .map(|ptr| unsafe { std::mem::transmute::<*mut (), JanusPluginResultNew>(ptr) });
+
+    match janus_fn {
+        Some(janus_fn) => unsafe { janus_fn(result.type_, result.text, result.content) },
+        None => Box::into_raw(Box::new(result)),
+    }
+}
+
+/// Frees a result allocated with [new_plugin_result](fn.new_plugin_result.html) outside of Janus.
+/// The content is left to the caller.
+#[cfg(any(test, feature = "testing"))]
+pub(crate) unsafe fn free_plugin_result(result: *mut JanusPluginResult) -> JanusPluginResult {
+    let result = *Box::from_raw(result);
+
+    if !result.text.is_null() {
+        drop(std::ffi::CString::from_raw(result.text as *mut c_char));
+    }
+
+    JanusPluginResult {
+        text: std::ptr::null(),
+        ..result
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Plugin API since Janus 0.9 where media is being passed in packet structs.
+/// The layouts follow `plugins/plugin.h` of the targeted Janus version.
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+#[allow(non_camel_case_types)]
+mod packets {
+    use std::convert::TryFrom;
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_int};
+
+    use jansson_sys::json_t;
+    use janus_plugin_sys::plugin::{
+        janus_plugin_result as JanusPluginResult, janus_plugin_session as JanusPluginSession,
+    };
+
+    use crate::{DataInfo, MediaInfo, RtpExtensions};
+
+    pub type gboolean = c_int;
+
+    #[repr(C)]
+    pub struct janus_plugin {
+        pub init: unsafe extern "C" fn(*mut janus_callbacks, *const c_char) -> c_int,
+        pub destroy: unsafe extern "C" fn(),
+        pub get_api_compatibility: unsafe extern "C" fn() -> c_int,
+        pub get_version: unsafe extern "C" fn() -> c_int,
+        pub get_version_string: unsafe extern "C" fn() -> *const c_char,
+        pub get_description: unsafe extern "C" fn() -> *const c_char,
+        pub get_name: unsafe extern "C" fn() -> *const c_char,
+        pub get_author: unsafe extern "C" fn() -> *const c_char,
+        pub get_package: unsafe extern "C" fn() -> *const c_char,
+        pub create_session: unsafe extern "C" fn(*mut JanusPluginSession, *mut c_int),
+        pub handle_message: unsafe extern "C" fn(
+            *mut JanusPluginSession,
+            *mut c_char,
+            *mut json_t,
+            *mut json_t,
+        ) -> *mut JanusPluginResult,
+        pub handle_admin_message: unsafe extern "C" fn(*mut json_t) -> *mut json_t,
+        pub setup_media: unsafe extern "C" fn(*mut JanusPluginSession),
+        pub incoming_rtp: unsafe extern "C" fn(*mut JanusPluginSession, *mut janus_plugin_rtp),
+        pub incoming_rtcp: unsafe extern "C" fn(*mut JanusPluginSession, *mut janus_plugin_rtcp),
+        pub incoming_data: unsafe extern "C" fn(*mut JanusPluginSession, *mut janus_plugin_data),
+        pub data_ready: unsafe extern "C" fn(*mut JanusPluginSession),
+        /// `(handle, uplink, video)`.
+        #[cfg(feature = "janus-0-10")]
+        pub slow_link: unsafe extern "C" fn(*mut JanusPluginSession, c_int, c_int),
+        /// `(handle, mindex, video, uplink)`.
+        #[cfg(feature = "janus-1")]
+        pub slow_link: unsafe extern "C" fn(*mut JanusPluginSession, c_int, gboolean, gboolean),
+        pub hangup_media: unsafe extern "C" fn(*mut JanusPluginSession),
+        pub destroy_session: unsafe extern "C" fn(*mut JanusPluginSession, *mut c_int),
+        pub query_session: unsafe extern "C" fn(*mut JanusPluginSession) -> *mut json_t,
+    }
+
+    #[repr(C)]
+    pub struct janus_callbacks {
+        pub push_event: extern "C" fn(
+            *mut JanusPluginSession,
+            *mut janus_plugin,
+            *const c_char,
+            *mut json_t,
+            *mut json_t,
+        ) -> c_int,
+        pub relay_rtp: extern "C" fn(*mut JanusPluginSession, *mut janus_plugin_rtp),
+        pub relay_rtcp: extern "C" fn(*mut JanusPluginSession, *mut janus_plugin_rtcp),
+        pub relay_data: extern "C" fn(*mut JanusPluginSession, *mut janus_plugin_data),
+        pub send_pli: extern "C" fn(*mut JanusPluginSession),
+        #[cfg(feature = "janus-1")]
+        pub send_pli_stream: extern "C" fn(*mut JanusPluginSession, c_int),
+        pub send_remb: extern "C" fn(*mut JanusPluginSession, u32),
+        pub close_pc: extern "C" fn(*mut JanusPluginSession),
+        pub end_session: extern "C" fn(*mut JanusPluginSession),
+        pub events_is_enabled: extern "C" fn() -> c_int,
+        pub notify_event: extern "C" fn(*mut janus_plugin, *mut JanusPluginSession, *mut json_t),
+        pub auth_is_signature_valid: extern "C" fn(*mut janus_plugin, *const c_char) -> gboolean,
+        pub auth_signature_contains:
+            extern "C" fn(*mut janus_plugin, *const c_char, *const c_char) -> gboolean,
+    }
+
+    /// RTP header extensions parsed by Janus. Missing values are -1 or `FALSE`.
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct janus_plugin_rtp_extensions {
+        pub audio_level: i8,
+        pub audio_level_vad: gboolean,
+        pub video_rotation: i16,
+        pub video_back_camera: gboolean,
+        pub video_flipped: gboolean,
+        #[cfg(feature = "janus-1")]
+        pub min_delay: i16,
+        #[cfg(feature = "janus-1")]
+        pub max_delay: i16,
+        #[cfg(feature = "janus-1")]
+        pub dd_len: u8,
+        #[cfg(feature = "janus-1")]
+        pub dd_content: [u8; 256],
+        #[cfg(feature = "janus-1")]
+        pub abs_capture_ts: u64,
+    }
+
+    impl Default for janus_plugin_rtp_extensions {
+        /// Same as `janus_plugin_rtp_extensions_reset`.
+        fn default() -> Self {
+            Self {
+                audio_level: -1,
+                audio_level_vad: 0,
+                video_rotation: -1,
+                video_back_camera: 0,
+                video_flipped: 0,
+                #[cfg(feature = "janus-1")]
+                min_delay: -1,
+                #[cfg(feature = "janus-1")]
+                max_delay: -1,
+                #[cfg(feature = "janus-1")]
+                dd_len: 0,
+                #[cfg(feature = "janus-1")]
+                dd_content: [0; 256],
+                #[cfg(feature = "janus-1")]
+                abs_capture_ts: 0,
+            }
+        }
+    }
+
+    impl From<janus_plugin_rtp_extensions> for RtpExtensions {
+        fn from(extensions: janus_plugin_rtp_extensions) -> Self {
+            Self {
+                audio_level: u8::try_from(extensions.audio_level).ok(),
+                voice_activity: extensions.audio_level_vad != 0,
+                video_rotation: u16::try_from(extensions.video_rotation).ok(),
+                video_back_camera: extensions.video_back_camera != 0,
+                video_flipped: extensions.video_flipped != 0,
+            }
+        }
+    }
+
+    #[repr(C)]
+    pub struct janus_plugin_rtp {
+        #[cfg(feature = "janus-1")]
+        pub mindex: c_int,
+        pub video: gboolean,
+        pub buffer: *mut c_char,
+        pub length: u16,
+        pub extensions: janus_plugin_rtp_extensions,
+    }
+
+    impl janus_plugin_rtp {
+        pub(crate) fn info(&self) -> MediaInfo {
+            MediaInfo {
+                #[cfg(feature = "janus-1")]
+                mindex: usize::try_from(self.mindex).ok(),
+                #[cfg(not(feature = "janus-1"))]
+                mindex: None,
+                extensions: Some(self.extensions.into()),
+            }
+        }
+    }
+
+    #[repr(C)]
+    pub struct janus_plugin_rtcp {
+        #[cfg(feature = "janus-1")]
+        pub mindex: c_int,
+        pub video: gboolean,
+        pub buffer: *mut c_char,
+        pub length: u16,
+    }
+
+    impl janus_plugin_rtcp {
+        pub(crate) fn info(&self) -> MediaInfo {
+            MediaInfo {
+                #[cfg(feature = "janus-1")]
+                mindex: usize::try_from(self.mindex).ok(),
+                #[cfg(not(feature = "janus-1"))]
+                mindex: None,
+                extensions: None,
+            }
+        }
+    }
+
+    #[repr(C)]
+    pub struct janus_plugin_data {
+        pub label: *mut c_char,
+        pub protocol: *mut c_char,
+        pub binary: gboolean,
+        pub buffer: *mut c_char,
+        pub length: u16,
+    }
+
+    impl janus_plugin_data {
+        pub(crate) fn info(&self) -> DataInfo<'_> {
+            DataInfo {
+                label: unsafe { c_str(self.label) },
+                protocol: unsafe { c_str(self.protocol) },
+                binary: self.binary != 0,
+            }
+        }
+    }
+
+    unsafe fn c_str<'a>(ptr: *const c_char) -> Option<&'a str> {
+        if ptr.is_null() {
+            None
+        } else {
+            CStr::from_ptr(ptr).to_str().ok()
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(all(test, any(feature = "janus-0-10", feature = "janus-1")))]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+    use crate::{DataInfo, RtpExtensions};
+
+    #[test]
+    fn rtp_extensions() {
+        let missing = RtpExtensions::from(janus_plugin_rtp_extensions::default());
+        assert_eq!(missing, RtpExtensions::default());
+
+        let extensions = RtpExtensions::from(janus_plugin_rtp_extensions {
+            audio_level: 30,
+            audio_level_vad: 1,
+            video_rotation: 90,
+            ..Default::default()
+        });
+
+        assert_eq!(extensions.audio_level, Some(30));
+        assert!(extensions.voice_activity);
+        assert_eq!(extensions.video_rotation, Some(90));
+    }
+
+    #[test]
+    fn data_info() {
+        let label = CString::new("chat").unwrap();
+        let mut buffer = [104i8, 105];
+
+        let packet = janus_plugin_data {
+            label: label.as_ptr() as *mut c_char,
+            protocol: std::ptr::null_mut(),
+            binary: 0,
+            buffer: buffer.as_mut_ptr() as *mut c_char,
+            length: buffer.len() as u16,
+        };
+
+        let expected = DataInfo {
+            label: Some("chat"),
+            protocol: None,
+            binary: false,
+        };
+
+        assert_eq!(packet.info(), expected);
+    }
+}
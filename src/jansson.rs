@@ -0,0 +1,1501 @@
+//! Native serde bridge for [Jansson](https://github.com/akheron/jansson)'s `json_t` trees.
+//!
+//! Janus core passes messages to plugins and receives events from them as `json_t` pointers.
+//! Instead of dumping a value to a JSON string with one library and parsing it back with
+//! the other, this module builds `json_t` trees directly from `Serialize` types and walks them
+//! to produce `Deserialize` types.
+//! Payloads which are already serde_json values are walked by the same deserializer so their
+//! errors carry the same paths.
+
+use std::ffi::{CStr, CString};
+use std::fmt::{self, Display};
+use std::iter::{Enumerate, Map};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::os::raw::c_char;
+use std::slice;
+
+use jansson_sys::{
+    json_array, json_array_append_new, json_array_get, json_array_size, json_decref, json_false,
+    json_integer, json_integer_value, json_null, json_object, json_object_iter,
+    json_object_iter_key, json_object_iter_next, json_object_iter_value, json_object_set_new,
+    json_object_size, json_real, json_real_value, json_string_length, json_string_value,
+    json_stringn_nocheck, json_t, json_true, json_type,
+};
+use serde::{de, ser};
+use serde_json::Value as JsonValue;
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Serializes `value` into a new `json_t` tree.
+/// The caller owns the returned reference.
+pub(crate) fn to_json<S: ser::Serialize + ?Sized>(value: &S) -> Result<*mut json_t, Error> {
+    value.serialize(Serializer).map(Json::into_raw)
+}
+
+/// Deserializes a value from a `json_t` tree without taking ownership of it.
+pub(crate) fn from_json<D: de::DeserializeOwned>(json: *const json_t) -> Result<D, Error> {
+    match unsafe { json.as_ref() } {
+        Some(json_ref) => D::deserialize(Deserializer::new(json_ref)),
+        None => Err(Error::new("Null JSON pointer")),
+    }
+}
+
+/// Deserializes a value from a serde_json tree with the same path-aware errors as
+/// [from_json](fn.from_json.html).
+pub(crate) fn from_value<D: de::DeserializeOwned>(value: &JsonValue) -> Result<D, Error> {
+    D::deserialize(Deserializer::new(value))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Step into a JSON tree on the way to the value which failed to deserialize.
+#[derive(Debug)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug)]
+pub(crate) struct Error {
+    message: String,
+    // Innermost first since segments are being added while the error bubbles up.
+    path: Vec<PathSegment>,
+    expected: Option<String>,
+    variants: Option<&'static [&'static str]>,
+}
+
+impl Error {
+    fn new(detail: &str) -> Self {
+        Self::with_message(detail.to_owned())
+    }
+
+    fn with_message(message: String) -> Self {
+        Self {
+            message,
+            path: vec![],
+            expected: None,
+            variants: None,
+        }
+    }
+
+    fn set_expected<E: Display + ?Sized>(mut self, expected: &E) -> Self {
+        self.expected = Some(expected.to_string());
+        self
+    }
+
+    fn at(mut self, segment: PathSegment) -> Self {
+        self.path.push(segment);
+        self
+    }
+
+    /// Description of the failure without the path.
+    pub(crate) fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// JSONPath-like location of the failed value, e.g. `$.streams[1].mid`.
+    pub(crate) fn path(&self) -> String {
+        let mut path = String::from("$");
+
+        for segment in self.path.iter().rev() {
+            match segment {
+                PathSegment::Key(key) if is_identifier(key) => {
+                    path.push('.');
+                    path.push_str(key);
+                }
+                PathSegment::Key(key) => {
+                    let quoted = serde_json::to_string(key).unwrap_or_default();
+                    path.push_str(&format!("[{}]", quoted));
+                }
+                PathSegment::Index(index) => path.push_str(&format!("[{}]", index)),
+            }
+        }
+
+        path
+    }
+
+    /// What the deserializer expected at the path, e.g. `u64` or `a string`.
+    pub(crate) fn expected(&self) -> Option<&str> {
+        self.expected.as_deref()
+    }
+
+    /// Accepted enum variants, i.e. `method` tags, when the failure is an unknown one.
+    pub(crate) fn variants(&self) -> Option<&'static [&'static str]> {
+        self.variants
+    }
+}
+
+fn is_identifier(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Formats names the same way serde does in its default error messages.
+fn one_of(names: &[&str]) -> String {
+    match names {
+        [] => String::from("nothing"),
+        [name] => format!("`{}`", name),
+        [first, second] => format!("`{}` or `{}`", first, second),
+        names => {
+            let quoted = names.iter().map(|name| format!("`{}`", name));
+            format!("one of {}", quoted.collect::<Vec<_>>().join(", "))
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.message, fmt)
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::with_message(msg.to_string())
+    }
+}
+
+// Messages are the same as serde's default ones but the details are being kept apart as well
+// to report them in a structured way.
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::with_message(msg.to_string())
+    }
+
+    fn invalid_type(unexpected: de::Unexpected, expected: &dyn de::Expected) -> Self {
+        let message = format!("invalid type: {}, expected {}", unexpected, expected);
+        Self::with_message(message).set_expected(expected)
+    }
+
+    fn invalid_value(unexpected: de::Unexpected, expected: &dyn de::Expected) -> Self {
+        let message = format!("invalid value: {}, expected {}", unexpected, expected);
+        Self::with_message(message).set_expected(expected)
+    }
+
+    fn invalid_length(len: usize, expected: &dyn de::Expected) -> Self {
+        let message = format!("invalid length {}, expected {}", len, expected);
+        Self::with_message(message).set_expected(expected)
+    }
+
+    fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+        let message = match expected {
+            [] => format!("unknown variant `{}`, there are no variants", variant),
+            _ => format!(
+                "unknown variant `{}`, expected {}",
+                variant,
+                one_of(expected)
+            ),
+        };
+
+        let mut err = Self::with_message(message).set_expected(&one_of(expected));
+        err.variants = Some(expected);
+        err
+    }
+
+    fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+        let message = match expected {
+            [] => format!("unknown field `{}`, there are no fields", field),
+            _ => format!("unknown field `{}`, expected {}", field, one_of(expected)),
+        };
+
+        Self::with_message(message)
+            .set_expected(&one_of(expected))
+            .at(PathSegment::Key(field.to_owned()))
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Self::with_message(format!("missing field `{}`", field))
+            .at(PathSegment::Key(field.to_owned()))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Owned `json_t` reference which is being released on drop unless taken with `into_raw`.
+struct Json(*mut json_t);
+
+impl Json {
+    fn new(ptr: *mut json_t) -> Result<Self, Error> {
+        if ptr.is_null() {
+            Err(Error::new("Failed to allocate JSON value"))
+        } else {
+            Ok(Self(ptr))
+        }
+    }
+
+    fn into_raw(self) -> *mut json_t {
+        let ptr = self.0;
+        std::mem::forget(self);
+        ptr
+    }
+
+    fn set(&mut self, key: &str, value: Json) -> Result<(), Error> {
+        let key = CString::new(key)
+            .map_err(|err| Error::with_message(format!("Failed to cast object key: {}", err)))?;
+
+        match unsafe { json_object_set_new(self.0, key.as_ptr(), value.into_raw()) } {
+            0 => Ok(()),
+            _ => Err(Error::new("Failed to set object item")),
+        }
+    }
+
+    fn append(&mut self, value: Json) -> Result<(), Error> {
+        match unsafe { json_array_append_new(self.0, value.into_raw()) } {
+            0 => Ok(()),
+            _ => Err(Error::new("Failed to append array item")),
+        }
+    }
+}
+
+impl Drop for Json {
+    fn drop(&mut self) {
+        unsafe { json_decref(self.0) };
+    }
+}
+
+fn string(value: &str) -> Result<Json, Error> {
+    Json::new(unsafe { json_stringn_nocheck(value.as_ptr() as *const c_char, value.len()) })
+}
+
+fn real(value: f64) -> Result<Json, Error> {
+    // serde_json writes non-finite floats as `null` while Jansson refuses them at all.
+    if value.is_finite() {
+        Json::new(unsafe { json_real(value) })
+    } else {
+        Json::new(unsafe { json_null() })
+    }
+}
+
+fn wrap(variant: &str, value: Json) -> Result<Json, Error> {
+    let mut object = Json::new(unsafe { json_object() })?;
+    object.set(variant, value)?;
+    Ok(object)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Json;
+    type Error = Error;
+
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeArray;
+    type SerializeMap = SerializeObject;
+    type SerializeStruct = SerializeObject;
+    type SerializeStructVariant = SerializeObject;
+
+    fn serialize_bool(self, value: bool) -> Result<Json, Error> {
+        Json::new(unsafe {
+            if value {
+                json_true()
+            } else {
+                json_false()
+            }
+        })
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<Json, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<Json, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<Json, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<Json, Error> {
+        Json::new(unsafe { json_integer(value) })
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<Json, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<Json, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<Json, Error> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<Json, Error> {
+        // Jansson integers are signed 64-bit.
+        if value > i64::MAX as u64 {
+            return Err(Error::with_message(format!("Integer {} is too big", value)));
+        }
+
+        self.serialize_i64(value as i64)
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<Json, Error> {
+        // Widen through the shortest decimal representation like serde_json output does
+        // so that `0.1f32` becomes `0.1` rather than `0.10000000149011612`.
+        match value.to_string().parse::<f64>() {
+            Ok(widened) => real(widened),
+            Err(_) => real(f64::from(value)),
+        }
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<Json, Error> {
+        real(value)
+    }
+
+    fn serialize_char(self, value: char) -> Result<Json, Error> {
+        string(value.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<Json, Error> {
+        string(value)
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<Json, Error> {
+        let mut array = Json::new(unsafe { json_array() })?;
+
+        for byte in value {
+            array.append(self::Serializer.serialize_u8(*byte)?)?;
+        }
+
+        Ok(array)
+    }
+
+    fn serialize_none(self) -> Result<Json, Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: ser::Serialize + ?Sized>(self, value: &T) -> Result<Json, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Json, Error> {
+        Json::new(unsafe { json_null() })
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Json, Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Json, Error> {
+        string(variant)
+    }
+
+    fn serialize_newtype_struct<T: ser::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Json, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ser::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Json, Error> {
+        wrap(variant, value.serialize(self)?)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<SerializeArray, Error> {
+        SerializeArray::new(None)
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeArray, Error> {
+        SerializeArray::new(Some(variant))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeObject, Error> {
+        SerializeObject::new(None)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<SerializeObject, Error> {
+        SerializeObject::new(None)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeObject, Error> {
+        SerializeObject::new(Some(variant))
+    }
+}
+
+struct SerializeArray {
+    array: Json,
+    variant: Option<&'static str>,
+}
+
+impl SerializeArray {
+    fn new(variant: Option<&'static str>) -> Result<Self, Error> {
+        Ok(Self {
+            array: Json::new(unsafe { json_array() })?,
+            variant,
+        })
+    }
+
+    fn push<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.array.append(value.serialize(Serializer)?)
+    }
+
+    fn finish(self) -> Result<Json, Error> {
+        match self.variant {
+            None => Ok(self.array),
+            Some(variant) => wrap(variant, self.array),
+        }
+    }
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = Json;
+    type Error = Error;
+
+    fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Json, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = Json;
+    type Error = Error;
+
+    fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Json, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = Json;
+    type Error = Error;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Json, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeArray {
+    type Ok = Json;
+    type Error = Error;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Json, Error> {
+        self.finish()
+    }
+}
+
+struct SerializeObject {
+    object: Json,
+    variant: Option<&'static str>,
+    next_key: Option<String>,
+}
+
+impl SerializeObject {
+    fn new(variant: Option<&'static str>) -> Result<Self, Error> {
+        Ok(Self {
+            object: Json::new(unsafe { json_object() })?,
+            variant,
+            next_key: None,
+        })
+    }
+
+    fn finish(self) -> Result<Json, Error> {
+        match self.variant {
+            None => Ok(self.object),
+            Some(variant) => wrap(variant, self.object),
+        }
+    }
+}
+
+impl ser::SerializeMap for SerializeObject {
+    type Ok = Json;
+    type Error = Error;
+
+    fn serialize_key<T: ser::Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.next_key = Some(key.serialize(MapKeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .next_key
+            .take()
+            .ok_or_else(|| Error::new("Map value serialized before its key"))?;
+
+        self.object.set(&key, value.serialize(Serializer)?)
+    }
+
+    fn end(self) -> Result<Json, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for SerializeObject {
+    type Ok = Json;
+    type Error = Error;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.object.set(key, value.serialize(Serializer)?)
+    }
+
+    fn end(self) -> Result<Json, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeObject {
+    type Ok = Json;
+    type Error = Error;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.object.set(key, value.serialize(Serializer)?)
+    }
+
+    fn end(self) -> Result<Json, Error> {
+        self.finish()
+    }
+}
+
+/// JSON object keys are strings so map keys get stringified the same way serde_json does it.
+struct MapKeySerializer;
+
+fn key_must_be_a_string() -> Error {
+    Error::new("Key must be a string")
+}
+
+impl ser::Serializer for MapKeySerializer {
+    type Ok = String;
+    type Error = Error;
+
+    type SerializeSeq = ser::Impossible<String, Error>;
+    type SerializeTuple = ser::Impossible<String, Error>;
+    type SerializeTupleStruct = ser::Impossible<String, Error>;
+    type SerializeTupleVariant = ser::Impossible<String, Error>;
+    type SerializeMap = ser::Impossible<String, Error>;
+    type SerializeStruct = ser::Impossible<String, Error>;
+    type SerializeStructVariant = ser::Impossible<String, Error>;
+
+    fn serialize_bool(self, value: bool) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_f32(self, _value: f32) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_f64(self, _value: f64) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_char(self, value: char) -> Result<String, Error> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_str(self, value: &str) -> Result<String, Error> {
+        Ok(value.to_owned())
+    }
+
+    fn serialize_bytes(self, _value: &[u8]) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_none(self) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_some<T: ser::Serialize + ?Sized>(self, _value: &T) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit(self) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String, Error> {
+        Ok(variant.to_owned())
+    }
+
+    fn serialize_newtype_struct<T: ser::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ser::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(key_must_be_a_string())
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// JSON tree node the deserializer walks: a `json_t` or a serde_json value.
+trait Node<'a>: Copy {
+    type Items: ExactSizeIterator<Item = Result<Self, Error>>;
+    type Entries: Iterator<Item = Result<(&'a str, Self), Error>>;
+
+    fn kind(self) -> Result<Kind<'a, Self>, Error>;
+}
+
+enum Kind<'a, N: Node<'a>> {
+    Object { entries: N::Entries, len: usize },
+    Array(N::Items),
+    String(&'a str),
+    Signed(i64),
+    Unsigned(u64),
+    Float(f64),
+    Bool(bool),
+    Null,
+}
+
+impl<'a, N: Node<'a>> Kind<'a, N> {
+    fn unexpected(&self) -> de::Unexpected<'a> {
+        match *self {
+            Self::Object { .. } => de::Unexpected::Map,
+            Self::Array(_) => de::Unexpected::Seq,
+            Self::String(value) => de::Unexpected::Str(value),
+            Self::Signed(value) => de::Unexpected::Signed(value),
+            Self::Unsigned(value) => de::Unexpected::Unsigned(value),
+            Self::Float(value) => de::Unexpected::Float(value),
+            Self::Bool(value) => de::Unexpected::Bool(value),
+            Self::Null => de::Unexpected::Unit,
+        }
+    }
+}
+
+impl<'a> Node<'a> for &'a json_t {
+    type Items = JsonItems<'a>;
+    type Entries = JsonEntries<'a>;
+
+    fn kind(self) -> Result<Kind<'a, Self>, Error> {
+        let ptr = self as *const json_t;
+
+        let kind = match self.type_ {
+            json_type::JSON_OBJECT => Kind::Object {
+                entries: JsonEntries::new(self),
+                len: unsafe { json_object_size(ptr) },
+            },
+            json_type::JSON_ARRAY => Kind::Array(JsonItems {
+                array: self,
+                indexes: 0..unsafe { json_array_size(ptr) },
+            }),
+            json_type::JSON_STRING => {
+                let bytes = unsafe {
+                    let value = json_string_value(ptr) as *const u8;
+                    std::slice::from_raw_parts(value, json_string_length(ptr))
+                };
+
+                let value = std::str::from_utf8(bytes).map_err(|err| {
+                    Error::with_message(format!("Failed to cast string value: {}", err))
+                })?;
+
+                Kind::String(value)
+            }
+            json_type::JSON_INTEGER => match unsafe { json_integer_value(ptr) } {
+                // serde_json visits non-negative integers as unsigned.
+                value if value < 0 => Kind::Signed(value),
+                value => Kind::Unsigned(value as u64),
+            },
+            json_type::JSON_REAL => Kind::Float(unsafe { json_real_value(ptr) }),
+            json_type::JSON_TRUE => Kind::Bool(true),
+            json_type::JSON_FALSE => Kind::Bool(false),
+            json_type::JSON_NULL => Kind::Null,
+        };
+
+        Ok(kind)
+    }
+}
+
+fn node<'a>(ptr: *const json_t) -> Result<&'a json_t, Error> {
+    unsafe { ptr.as_ref() }.ok_or_else(|| Error::new("Null JSON pointer"))
+}
+
+struct JsonItems<'a> {
+    array: &'a json_t,
+    indexes: Range<usize>,
+}
+
+impl<'a> Iterator for JsonItems<'a> {
+    type Item = Result<&'a json_t, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.indexes.next()?;
+        Some(node(unsafe { json_array_get(self.array, index) }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.indexes.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for JsonItems<'a> {}
+
+struct JsonEntries<'a> {
+    object: *mut json_t,
+    iter: *mut std::ffi::c_void,
+    phantom: PhantomData<&'a json_t>,
+}
+
+impl<'a> JsonEntries<'a> {
+    fn new(object: &'a json_t) -> Self {
+        // Jansson iteration functions take mutable pointers but don't modify the object.
+        let object = object as *const json_t as *mut json_t;
+
+        Self {
+            object,
+            iter: unsafe { json_object_iter(object) },
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a> Iterator for JsonEntries<'a> {
+    type Item = Result<(&'a str, &'a json_t), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.iter.is_null() {
+            return None;
+        }
+
+        let entry = unsafe {
+            let key = object_key(self.iter);
+            let value = node(json_object_iter_value(self.iter));
+            self.iter = json_object_iter_next(self.object, self.iter);
+            key.and_then(|key| value.map(|value| (key, value)))
+        };
+
+        Some(entry)
+    }
+}
+
+unsafe fn object_key<'a>(iter: *mut std::ffi::c_void) -> Result<&'a str, Error> {
+    CStr::from_ptr(json_object_iter_key(iter))
+        .to_str()
+        .map_err(|err| Error::with_message(format!("Failed to cast object key: {}", err)))
+}
+
+type ValueItems<'a> = Map<slice::Iter<'a, JsonValue>, fn(&'a JsonValue) -> ValueItem<'a>>;
+type ValueItem<'a> = Result<&'a JsonValue, Error>;
+type ValueEntries<'a> = Map<serde_json::map::Iter<'a>, fn(ValueEntryRef<'a>) -> ValueEntry<'a>>;
+type ValueEntryRef<'a> = (&'a String, &'a JsonValue);
+type ValueEntry<'a> = Result<(&'a str, &'a JsonValue), Error>;
+
+fn value_entry((key, value): ValueEntryRef) -> ValueEntry {
+    Ok((key, value))
+}
+
+impl<'a> Node<'a> for &'a JsonValue {
+    type Items = ValueItems<'a>;
+    type Entries = ValueEntries<'a>;
+
+    fn kind(self) -> Result<Kind<'a, Self>, Error> {
+        let kind = match self {
+            JsonValue::Object(object) => Kind::Object {
+                entries: object.iter().map(value_entry as fn(_) -> _),
+                len: object.len(),
+            },
+            JsonValue::Array(array) => Kind::Array(array.iter().map(Ok as fn(_) -> _)),
+            JsonValue::String(value) => Kind::String(value),
+            JsonValue::Number(number) => match (number.as_u64(), number.as_i64()) {
+                (Some(value), _) => Kind::Unsigned(value),
+                (None, Some(value)) => Kind::Signed(value),
+                (None, None) => Kind::Float(number.as_f64().unwrap_or_default()),
+            },
+            JsonValue::Bool(value) => Kind::Bool(*value),
+            JsonValue::Null => Kind::Null,
+        };
+
+        Ok(kind)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Borrowing deserializer over a JSON tree node.
+struct Deserializer<N> {
+    node: N,
+}
+
+impl<N> Deserializer<N> {
+    fn new(node: N) -> Self {
+        Self { node }
+    }
+}
+
+impl<'de, 'a, N: Node<'a>> de::Deserializer<'de> for Deserializer<N> {
+    type Error = Error;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.node.kind()? {
+            Kind::Object { entries, len } => visitor.visit_map(ObjectAccess {
+                entries,
+                len,
+                entry: None,
+            }),
+            Kind::Array(items) => visitor.visit_seq(ArrayAccess {
+                items: items.enumerate(),
+            }),
+            Kind::String(value) => visitor.visit_str(value),
+            Kind::Signed(value) => visitor.visit_i64(value),
+            Kind::Unsigned(value) => visitor.visit_u64(value),
+            Kind::Float(value) => visitor.visit_f64(value),
+            Kind::Bool(value) => visitor.visit_bool(value),
+            Kind::Null => visitor.visit_unit(),
+        }
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.node.kind()? {
+            Kind::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.node.kind()? {
+            Kind::String(variant) => visitor.visit_enum(EnumAccess::<N> {
+                variant,
+                value: None,
+            }),
+            Kind::Object { mut entries, len } => match entries.next() {
+                Some(entry) if len == 1 => {
+                    let (variant, value) = entry?;
+
+                    visitor.visit_enum(EnumAccess {
+                        variant,
+                        value: Some(value),
+                    })
+                }
+                _ => Err(de::Error::invalid_value(
+                    de::Unexpected::Map,
+                    &"map with a single key",
+                )),
+            },
+            kind => Err(de::Error::invalid_type(kind.unexpected(), &"string or map")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct ArrayAccess<I> {
+    items: Enumerate<I>,
+}
+
+impl<'de, 'a, N, I> de::SeqAccess<'de> for ArrayAccess<I>
+where
+    N: Node<'a>,
+    I: ExactSizeIterator<Item = Result<N, Error>>,
+{
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.items.next() {
+            Some((index, item)) => seed
+                .deserialize(Deserializer::new(item?))
+                .map(Some)
+                .map_err(|err| err.at(PathSegment::Index(index))),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct ObjectAccess<'a, N, E> {
+    entries: E,
+    len: usize,
+    // The key is being kept for the error path.
+    entry: Option<(&'a str, N)>,
+}
+
+impl<'de, 'a, N, E> de::MapAccess<'de> for ObjectAccess<'a, N, E>
+where
+    N: Node<'a>,
+    E: Iterator<Item = Result<(&'a str, N), Error>>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some(entry) => {
+                let (key, value) = entry?;
+                self.entry = Some((key, value));
+                seed.deserialize(KeyDeserializer(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.entry.take() {
+            Some((key, value)) => seed
+                .deserialize(Deserializer::new(value))
+                .map_err(|err| err.at(PathSegment::Key(key.to_owned()))),
+            None => Err(Error::new("Map value requested before its key")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+/// Object keys are always strings but serde_json allows numeric and boolean map keys
+/// so parse them on demand the same way.
+struct KeyDeserializer<'a>(&'a str);
+
+macro_rules! deserialize_parsed_key {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.0.parse() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(_) => Err(de::Error::invalid_value(de::Unexpected::Str(self.0), &visitor)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for KeyDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_str(self.0)
+    }
+
+    deserialize_parsed_key! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(EnumAccess::<&json_t> {
+            variant: self.0,
+            value: None,
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        f32 f64 char str string bytes byte_buf unit unit_struct seq tuple tuple_struct map
+        struct identifier ignored_any
+    }
+}
+
+struct EnumAccess<'a, N> {
+    variant: &'a str,
+    value: Option<N>,
+}
+
+impl<'de, 'a, N: Node<'a>> de::EnumAccess<'de> for EnumAccess<'a, N> {
+    type Error = Error;
+    type Variant = VariantAccess<'a, N>;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantAccess<'a, N>), Error> {
+        let variant = seed.deserialize(de::value::StrDeserializer::<Error>::new(self.variant))?;
+        let access = VariantAccess {
+            variant: self.variant,
+            value: self.value,
+        };
+
+        Ok((variant, access))
+    }
+}
+
+struct VariantAccess<'a, N> {
+    variant: &'a str,
+    value: Option<N>,
+}
+
+impl<'a, N: Node<'a>> VariantAccess<'a, N> {
+    /// Deserializes the variant's value adding the variant to the path of errors
+    /// since it's the key of the value in the object.
+    fn deserialize<T, F>(self, expected: &str, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(Deserializer<N>) -> Result<T, Error>,
+    {
+        match self.value {
+            Some(value) => f(Deserializer::new(value))
+                .map_err(|err| err.at(PathSegment::Key(self.variant.to_owned()))),
+            None => Err(de::Error::invalid_type(
+                de::Unexpected::UnitVariant,
+                &expected,
+            )),
+        }
+    }
+}
+
+impl<'de, 'a, N: Node<'a>> de::VariantAccess<'de> for VariantAccess<'a, N> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.value {
+            None => Ok(()),
+            Some(_) => self.deserialize("unit variant", de::Deserialize::deserialize),
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        self.deserialize("newtype variant", |value| seed.deserialize(value))
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize("tuple variant", |value| {
+            de::Deserializer::deserialize_seq(value, visitor)
+        })
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize("struct variant", |value| {
+            de::Deserializer::deserialize_map(value, visitor)
+        })
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::ffi::CString;
+
+    use jansson_sys::{json_decref, json_dumps, json_equal, json_loads, json_t};
+    use serde::{de::DeserializeOwned, ser::Serialize};
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::{from_json, from_value, to_json};
+
+    const JSON_DECODE_ANY: usize = 0x4;
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    #[serde(rename_all = "lowercase", tag = "method")]
+    enum Tagged {
+        Ping { data: String },
+        Join { room: u64, muted: Option<bool> },
+        Leave,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    enum External {
+        Unit,
+        Newtype(i32),
+        Tuple(u8, String),
+        Struct { x: f64, y: f64 },
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Newtype(Vec<i64>);
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Tuple(bool, char, ());
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Everything {
+        flag: bool,
+        small: i8,
+        negative: i64,
+        unsigned: u32,
+        float: f32,
+        double: f64,
+        text: String,
+        unicode: String,
+        missing: Option<String>,
+        present: Option<u16>,
+        list: Vec<External>,
+        map: BTreeMap<String, Tagged>,
+        int_keys: BTreeMap<u32, bool>,
+        newtype: Newtype,
+        tuple: Tuple,
+        #[serde(default)]
+        defaulted: Vec<u8>,
+    }
+
+    fn everything() -> Everything {
+        let mut map = BTreeMap::new();
+        map.insert("a".to_owned(), Tagged::Leave);
+
+        map.insert(
+            "b".to_owned(),
+            Tagged::Join {
+                room: 42,
+                muted: Some(true),
+            },
+        );
+
+        let mut int_keys = BTreeMap::new();
+        int_keys.insert(1, true);
+        int_keys.insert(20, false);
+
+        Everything {
+            flag: true,
+            small: -8,
+            negative: i64::MIN,
+            unsigned: u32::MAX,
+            float: 0.1,
+            double: 1e-7,
+            text: "quo\"ted \\ \n text".to_owned(),
+            unicode: "Привет, 世界 🦀".to_owned(),
+            missing: None,
+            present: Some(7),
+            list: vec![
+                External::Unit,
+                External::Newtype(-1),
+                External::Tuple(255, "t".to_owned()),
+                External::Struct { x: 1.5, y: -0.0 },
+            ],
+            map,
+            int_keys,
+            newtype: Newtype(vec![1, 2, 3]),
+            tuple: Tuple(false, 'ё', ()),
+            defaulted: vec![],
+        }
+    }
+
+    // The string round-tripping path that has been used before the native bridge.
+    fn serialize_via_string<S: Serialize>(object: &S) -> *mut json_t {
+        let dump = CString::new(serde_json::to_string(object).unwrap()).unwrap();
+        let flags = JSON_DECODE_ANY;
+        let ptr = unsafe { json_loads(dump.as_ptr(), flags, std::ptr::null_mut()) };
+        assert!(!ptr.is_null());
+        ptr
+    }
+
+    fn deserialize_via_string<D: DeserializeOwned>(json: *mut json_t) -> D {
+        let dump = unsafe { CString::from_raw(json_dumps(json, 0)) };
+        serde_json::from_str(dump.to_str().unwrap()).unwrap()
+    }
+
+    fn assert_same_json<S: Serialize>(object: &S) {
+        let native = to_json(object).unwrap();
+        let reference = serialize_via_string(object);
+        assert_eq!(unsafe { json_equal(native, reference) }, 1);
+
+        unsafe {
+            json_decref(native);
+            json_decref(reference);
+        }
+    }
+
+    #[test]
+    fn serialize_matches_string_path() {
+        assert_same_json(&everything());
+        assert_same_json(&Tagged::Ping { data: "x".into() });
+        assert_same_json(&vec![Some(1.0), None, Some(f64::NAN)]);
+        assert_same_json(&serde_json::json!({"a": [1, "2", {"b": null}], "c": -3.25}));
+        assert_same_json(&"plain string");
+        assert_same_json(&(u64::from(u32::MAX) * 2));
+    }
+
+    #[test]
+    fn serialize_too_big_integer() {
+        assert!(to_json(&u64::MAX).is_err());
+    }
+
+    #[test]
+    fn deserialize_matches_string_path() {
+        let json = serialize_via_string(&everything());
+        let native = from_json::<Everything>(json).unwrap();
+        let reference = deserialize_via_string::<Everything>(json);
+        assert_eq!(native, reference);
+        assert_eq!(native, everything());
+
+        let native_value = from_json::<serde_json::Value>(json).unwrap();
+        let reference_value = deserialize_via_string::<serde_json::Value>(json);
+        assert_eq!(native_value, reference_value);
+        assert_eq!(
+            from_value::<Everything>(&reference_value).unwrap(),
+            everything()
+        );
+        assert_eq!(
+            from_value::<serde_json::Value>(&reference_value).unwrap(),
+            reference_value
+        );
+
+        unsafe { json_decref(json) };
+    }
+
+    #[test]
+    fn deserialize_tagged_enum() {
+        let json = serialize_via_string(&serde_json::json!({"method": "join", "room": 1}));
+
+        assert_eq!(
+            from_json::<Tagged>(json).unwrap(),
+            Tagged::Join {
+                room: 1,
+                muted: None
+            }
+        );
+
+        unsafe { json_decref(json) };
+    }
+
+    #[test]
+    fn deserialize_errors() {
+        let json = serialize_via_string(&serde_json::json!({"method": "unknown"}));
+        let err = from_json::<Tagged>(json).unwrap_err().to_string();
+        assert!(err.contains("unknown variant `unknown`"), "{}", err);
+        unsafe { json_decref(json) };
+
+        let json = serialize_via_string(&serde_json::json!({"method": "join", "room": "one"}));
+        let err = from_json::<Tagged>(json).unwrap_err().to_string();
+        assert!(err.contains("invalid type: string \"one\""), "{}", err);
+        unsafe { json_decref(json) };
+
+        assert!(from_json::<Tagged>(std::ptr::null()).is_err());
+    }
+
+    fn deserialize_error<D: DeserializeOwned + std::fmt::Debug>(
+        value: serde_json::Value,
+    ) -> super::Error {
+        let json = serialize_via_string(&value);
+        let err = from_json::<D>(json).unwrap_err();
+        unsafe { json_decref(json) };
+
+        // Deserializing the serde_json value must fail the same way.
+        let value_err = from_value::<D>(&value).unwrap_err();
+        assert_eq!(value_err.path(), err.path());
+        assert_eq!(value_err.message(), err.message());
+        assert_eq!(value_err.expected(), err.expected());
+        assert_eq!(value_err.variants(), err.variants());
+        err
+    }
+
+    #[test]
+    fn deserialize_error_paths() {
+        let mut value = serde_json::to_value(everything()).unwrap();
+        value["list"][3]["Struct"]["y"] = serde_json::json!("zero");
+        let err = deserialize_error::<Everything>(value);
+        assert_eq!(err.path(), "$.list[3].Struct.y");
+        assert_eq!(err.expected(), Some("f64"));
+
+        let mut value = serde_json::to_value(everything()).unwrap();
+        value["map"]["with space"] = serde_json::json!({"method": "join"});
+        let err = deserialize_error::<Everything>(value);
+        assert_eq!(err.path(), "$.map[\"with space\"].room");
+        assert_eq!(err.message(), "missing field `room`");
+
+        let err = deserialize_error::<Vec<Tagged>>(serde_json::json!([{"method": "kick"}]));
+        assert_eq!(err.path(), "$[0].method");
+        assert_eq!(err.expected(), Some("one of `ping`, `join`, `leave`"));
+        assert_eq!(err.variants(), Some(&["ping", "join", "leave"][..]));
+
+        let err = deserialize_error::<Vec<External>>(serde_json::json!(["Unit", 5]));
+        assert_eq!(err.path(), "$[1]");
+        assert_eq!(err.expected(), Some("string or map"));
+    }
+}
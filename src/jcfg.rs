@@ -0,0 +1,613 @@
+//! Janus's native `.jcfg` config format.
+//!
+//! Janus and its stock plugins use [libconfig](https://hyperrealm.github.io/libconfig/)-style
+//! files:
+//!
+//! ```text
+//! general: {
+//!     # Comments start with `#` or `//`.
+//!     events = true
+//!     string_ids = false
+//! }
+//!
+//! room-1234: {
+//!     description = "Demo Room"
+//!     publishers = 6
+//!     bitrate = 128000
+//!     audiocodecs = ["opus", "pcmu"]
+//! }
+//! ```
+//!
+//! Settings are `name = value` or `name: value` optionally followed by `;` or `,`. Values are
+//! booleans, integers (decimal or `0x` hex with optional `L` suffix), floats, strings with
+//! C escapes, `[...]` arrays of scalars, `(...)` lists of any values and `{...}` groups of
+//! settings. `@include` directives aren't supported.
+//!
+//! Janus itself reads all the values as strings so operators often quote numbers and booleans.
+//! Scalars are being converted on deserialization the same way: `"6"` deserializes into an integer
+//! and `yes` or `1` into `true`.
+//!
+//! ```
+//! use std::collections::HashMap;
+//!
+//! use serde_derive::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Room {
+//!     description: String,
+//!     publishers: u32,
+//! }
+//!
+//! let input = r#"
+//!     room-1234: { description = "Demo Room"; publishers = "6" }
+//! "#;
+//!
+//! let rooms: HashMap<String, Room> = janus_app::jcfg::from_str(input)?;
+//! assert_eq!(rooms["room-1234"].publishers, 6);
+//! # Ok::<(), janus_app::Error>(())
+//! ```
+//!
+//! Plugin configs named `janus.plugin.<name>.jcfg` are being found by the default
+//! [config loader](../config/index.html).
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use ::config::{ConfigError, Source, Value as ConfigValue};
+use serde::de::DeserializeOwned;
+
+use crate::Error;
+
+/// Deserializes a `.jcfg` document into `T`.
+pub fn from_str<T: DeserializeOwned>(input: &str) -> Result<T, Error> {
+    let settings =
+        parse(input).map_err(|err| Error::new(&format!("Failed to parse jcfg: {}", err)))?;
+
+    Value::Group(settings)
+        .into_config_value(None)
+        .try_into()
+        .map_err(|err| Error::new(&format!("Failed to deserialize jcfg: {}", err)))
+}
+
+/// `.jcfg` file source for the [config](https://docs.rs/config/0.10) crate to merge it
+/// with other sources, e.g. environment variables.
+#[derive(Clone, Debug)]
+pub struct JcfgFile {
+    path: PathBuf,
+}
+
+impl JcfgFile {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl Source for JcfgFile {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<HashMap<String, ConfigValue>, ConfigError> {
+        let uri = self.path.to_string_lossy().into_owned();
+
+        let file_parse_error = |cause| ConfigError::FileParse {
+            uri: Some(uri.clone()),
+            cause,
+        };
+
+        let input =
+            std::fs::read_to_string(&self.path).map_err(|err| file_parse_error(Box::new(err)))?;
+
+        let settings = parse(&input).map_err(|err| file_parse_error(Box::new(err)))?;
+
+        Ok(settings
+            .into_iter()
+            .map(|(name, value)| (name, value.into_config_value(Some(&uri))))
+            .collect())
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    // Both `[...]` arrays and `(...)` lists.
+    Array(Vec<Value>),
+    Group(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn into_config_value(self, origin: Option<&String>) -> ConfigValue {
+        match self {
+            Self::Bool(value) => ConfigValue::new(origin, value),
+            Self::Integer(value) => ConfigValue::new(origin, value),
+            Self::Float(value) => ConfigValue::new(origin, value),
+            Self::String(value) => ConfigValue::new(origin, value),
+            Self::Array(values) => {
+                let values = values
+                    .into_iter()
+                    .map(|value| value.into_config_value(origin));
+                ConfigValue::new(origin, values.collect::<Vec<_>>())
+            }
+            Self::Group(settings) => {
+                let settings = settings
+                    .into_iter()
+                    .map(|(name, value)| (name, value.into_config_value(origin)));
+
+                ConfigValue::new(origin, settings.collect::<HashMap<_, _>>())
+            }
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Self::Array(_) | Self::Group(_))
+    }
+}
+
+#[derive(Debug)]
+struct ParseError {
+    line: usize,
+    column: usize,
+    detail: String,
+}
+
+impl std::error::Error for ParseError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{} at line {}, column {}",
+            self.detail, self.line, self.column
+        )
+    }
+}
+
+fn parse(input: &str) -> Result<Vec<(String, Value)>, ParseError> {
+    let mut parser = Parser {
+        input: input.as_bytes(),
+        pos: 0,
+    };
+
+    parser.parse_settings(None)
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn error_at(&self, pos: usize, detail: &str) -> ParseError {
+        let consumed = &self.input[..pos.min(self.input.len())];
+        let line_start = consumed
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+
+        ParseError {
+            line: consumed.iter().filter(|&&b| b == b'\n').count() + 1,
+            column: pos - line_start + 1,
+            detail: detail.to_owned(),
+        }
+    }
+
+    fn error(&self, detail: &str) -> ParseError {
+        self.error_at(self.pos, detail)
+    }
+
+    /// Settings until `closing` byte or the end of input for the top level.
+    fn parse_settings(&mut self, closing: Option<u8>) -> Result<Vec<(String, Value)>, ParseError> {
+        let mut settings: Vec<(String, Value)> = vec![];
+
+        loop {
+            self.skip_whitespace()?;
+
+            match (self.peek(), closing) {
+                (None, None) => return Ok(settings),
+                (None, Some(closing)) => {
+                    return Err(self.error(&format!("Expected `{}`", closing as char)))
+                }
+                (Some(byte), Some(closing)) if byte == closing => {
+                    self.pos += 1;
+                    return Ok(settings);
+                }
+                (Some(b'@'), _) => return Err(self.error("`@include` directives aren't supported")),
+                _ => (),
+            }
+
+            let start = self.pos;
+            let name = self.parse_name()?;
+
+            if settings.iter().any(|(other, _)| *other == name) {
+                return Err(self.error_at(start, &format!("Duplicate setting `{}`", name)));
+            }
+
+            self.skip_whitespace()?;
+
+            match self.next() {
+                Some(b'=') | Some(b':') => (),
+                _ => return Err(self.error_at(self.pos - 1, "Expected `=` or `:`")),
+            }
+
+            let value = self.parse_value()?;
+            self.skip_whitespace()?;
+
+            if let Some(b';') | Some(b',') = self.peek() {
+                self.pos += 1;
+            }
+
+            settings.push((name, value));
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+
+        match self.peek() {
+            Some(byte) if byte.is_ascii_alphabetic() || byte == b'*' => self.pos += 1,
+            _ => return Err(self.error("Expected a setting name")),
+        }
+
+        while let Some(byte) = self.peek() {
+            if byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-' || byte == b'*' {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+
+        Ok(String::from_utf8_lossy(&self.input[start..self.pos]).into_owned())
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        self.skip_whitespace()?;
+
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                self.parse_settings(Some(b'}')).map(Value::Group)
+            }
+            Some(b'[') => {
+                let start = self.pos;
+                self.pos += 1;
+                let values = self.parse_values(b']')?;
+
+                if values.iter().all(Value::is_scalar) {
+                    Ok(Value::Array(values))
+                } else {
+                    Err(self.error_at(start, "Arrays may contain only scalars"))
+                }
+            }
+            Some(b'(') => {
+                self.pos += 1;
+                self.parse_values(b')').map(Value::Array)
+            }
+            Some(b'"') => self.parse_string().map(Value::String),
+            Some(_) => self.parse_scalar(),
+            None => Err(self.error("Expected a value")),
+        }
+    }
+
+    /// Comma separated values until `closing` byte.
+    fn parse_values(&mut self, closing: u8) -> Result<Vec<Value>, ParseError> {
+        let mut values = vec![];
+
+        loop {
+            self.skip_whitespace()?;
+
+            if self.peek() == Some(closing) {
+                self.pos += 1;
+                return Ok(values);
+            }
+
+            values.push(self.parse_value()?);
+            self.skip_whitespace()?;
+
+            match self.next() {
+                Some(b',') => (),
+                Some(byte) if byte == closing => return Ok(values),
+                _ => {
+                    let detail = format!("Expected `,` or `{}`", closing as char);
+                    return Err(self.error_at(self.pos.saturating_sub(1), &detail));
+                }
+            }
+        }
+    }
+
+    /// A string literal with adjacent ones concatenated like in C.
+    fn parse_string(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        let mut bytes = vec![];
+
+        while self.peek() == Some(b'"') {
+            self.pos += 1;
+
+            loop {
+                match self.next() {
+                    None => return Err(self.error_at(start, "Unterminated string")),
+                    Some(b'"') => break,
+                    Some(b'\\') => bytes.push(self.parse_escape()?),
+                    Some(byte) => bytes.push(byte),
+                }
+            }
+
+            self.skip_whitespace()?;
+        }
+
+        String::from_utf8(bytes).map_err(|_| self.error_at(start, "Invalid UTF-8 in string"))
+    }
+
+    fn parse_escape(&mut self) -> Result<u8, ParseError> {
+        match self.next() {
+            Some(b'\\') => Ok(b'\\'),
+            Some(b'"') => Ok(b'"'),
+            Some(b'f') => Ok(0x0c),
+            Some(b'n') => Ok(b'\n'),
+            Some(b'r') => Ok(b'\r'),
+            Some(b't') => Ok(b'\t'),
+            Some(b'x') => {
+                let digits = self.input.get(self.pos..self.pos + 2).unwrap_or_default();
+                let digits = std::str::from_utf8(digits).unwrap_or_default();
+
+                match u8::from_str_radix(digits, 16) {
+                    Ok(byte) if digits.len() == 2 => {
+                        self.pos += 2;
+                        Ok(byte)
+                    }
+                    _ => Err(self.error("Expected two hex digits")),
+                }
+            }
+            _ => Err(self.error_at(self.pos - 1, "Unknown escape sequence")),
+        }
+    }
+
+    fn parse_scalar(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+
+        while let Some(byte) = self.peek() {
+            if byte.is_ascii_alphanumeric() || byte == b'+' || byte == b'-' || byte == b'.' {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+
+        let token = String::from_utf8_lossy(&self.input[start..self.pos]);
+
+        parse_scalar(&token).ok_or_else(|| {
+            if token.is_empty() {
+                self.error("Expected a value")
+            } else {
+                self.error_at(start, &format!("Invalid value `{}`", token))
+            }
+        })
+    }
+
+    /// Skips whitespace and `#`, `//` and `/* */` comments.
+    fn skip_whitespace(&mut self) -> Result<(), ParseError> {
+        loop {
+            match (self.peek(), self.input.get(self.pos + 1)) {
+                (Some(byte), _) if byte.is_ascii_whitespace() => self.pos += 1,
+                (Some(b'#'), _) | (Some(b'/'), Some(b'/')) => {
+                    while !matches!(self.next(), Some(b'\n') | None) {}
+                }
+                (Some(b'/'), Some(b'*')) => {
+                    let start = self.pos;
+                    self.pos += 2;
+
+                    loop {
+                        match (self.next(), self.peek()) {
+                            (Some(b'*'), Some(b'/')) => {
+                                self.pos += 1;
+                                break;
+                            }
+                            (None, _) => return Err(self.error_at(start, "Unterminated comment")),
+                            _ => (),
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+fn parse_scalar(token: &str) -> Option<Value> {
+    if token.eq_ignore_ascii_case("true") {
+        return Some(Value::Bool(true));
+    }
+
+    if token.eq_ignore_ascii_case("false") {
+        return Some(Value::Bool(false));
+    }
+
+    // 64-bit integers may be marked with `L` or `LL` suffix.
+    let integer = token.trim_end_matches('L');
+
+    let parsed_integer = match integer
+        .strip_prefix("0x")
+        .or_else(|| integer.strip_prefix("0X"))
+    {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => integer.parse(),
+    };
+
+    if let Ok(value) = parsed_integer {
+        return Some(Value::Integer(value));
+    }
+
+    // Rust also parses `inf` and `NaN` which libconfig doesn't have.
+    match token.bytes().next() {
+        Some(byte) if byte.is_ascii_digit() || byte == b'.' || byte == b'-' || byte == b'+' => {
+            token.parse().ok().map(Value::Float)
+        }
+        _ => None,
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_derive::Deserialize;
+
+    use super::*;
+
+    #[test]
+    fn syntax() {
+        let input = r#"
+            # Comment
+            general: {
+                events = true; // Another comment
+                debug_level = 0x10L,
+                /* Multiline
+                   comment */
+                ratio = -1.5e2
+            }
+
+            room-1: {
+                description = "Demo " "\"Room\"\t\x41"
+                codecs = ["opus", "pcmu",]
+                mixed = (1, "two", { three = 3 })
+                empty = {}
+            }
+        "#;
+
+        let settings = parse(input).unwrap();
+        let (name, general) = &settings[0];
+        assert_eq!(name, "general");
+
+        assert_eq!(
+            general,
+            &Value::Group(vec![
+                (String::from("events"), Value::Bool(true)),
+                (String::from("debug_level"), Value::Integer(16)),
+                (String::from("ratio"), Value::Float(-150.0)),
+            ])
+        );
+
+        let room = match &settings[1] {
+            (name, Value::Group(room)) if name == "room-1" => room,
+            other => panic!("Unexpected setting: {:?}", other),
+        };
+
+        assert_eq!(room[0].1, Value::String(String::from("Demo \"Room\"\tA")));
+
+        assert_eq!(
+            room[1].1,
+            Value::Array(vec![
+                Value::String(String::from("opus")),
+                Value::String(String::from("pcmu")),
+            ])
+        );
+
+        assert_eq!(
+            room[2].1,
+            Value::Array(vec![
+                Value::Integer(1),
+                Value::String(String::from("two")),
+                Value::Group(vec![(String::from("three"), Value::Integer(3))]),
+            ])
+        );
+
+        assert_eq!(room[3].1, Value::Group(vec![]));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let cases = [
+            ("a = ", "Expected a value at line 1, column 5"),
+            ("a = 1\nb = \"x", "Unterminated string at line 2, column 5"),
+            ("a = { b = 1", "Expected `}` at line 1, column 12"),
+            ("a = 1\na = 2", "Duplicate setting `a` at line 2, column 1"),
+            (
+                "a = [{}]",
+                "Arrays may contain only scalars at line 1, column 5",
+            ),
+            ("a = yes", "Invalid value `yes` at line 1, column 5"),
+            ("a 1", "Expected `=` or `:` at line 1, column 3"),
+            (
+                "@include \"x.jcfg\"",
+                "`@include` directives aren't supported at line 1, column 1",
+            ),
+        ];
+
+        for (input, expected) in cases.iter() {
+            assert_eq!(parse(input).unwrap_err().to_string(), *expected);
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        general: General,
+        #[serde(rename = "room-1234")]
+        demo_room: Room,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct General {
+        admin_key: Option<String>,
+        events: bool,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Room {
+        description: String,
+        publishers: u32,
+        #[serde(default)]
+        audiocodecs: Vec<String>,
+    }
+
+    #[test]
+    fn deserialize() {
+        let input = r#"
+            general: {
+                events = "yes"
+            }
+
+            room-1234: {
+                description = "Demo Room"
+                publishers = "6"
+                audiocodecs = ["opus", "pcmu"]
+            }
+
+        "#;
+
+        let config: Config = from_str(input).unwrap();
+        assert!(config.general.events);
+        assert_eq!(config.general.admin_key, None);
+        assert_eq!(config.demo_room.publishers, 6);
+        assert_eq!(config.demo_room.audiocodecs, vec!["opus", "pcmu"]);
+
+        let input = "room-5678: { description = 5678, publishers = 1 }";
+        let rooms: BTreeMap<String, Room> = from_str(input).unwrap();
+        assert_eq!(rooms["room-5678"].description, "5678");
+
+        let err = from_str::<Room>("description = \"x\"\npublishers = \"six\"").unwrap_err();
+        assert!(
+            err.as_str().starts_with("Failed to deserialize jcfg"),
+            "{}",
+            err
+        );
+    }
+}
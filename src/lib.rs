@@ -26,7 +26,8 @@
 //!
 //! The simpliest way is to copy-paste and change the
 //! [example](https://github.com/feymartynov/janus-app/tree/master/example) plugin but here
-//! is the explanation of how to write it from scratch.
+//! is the explanation of how to write it from scratch. The snippets below are fragments of such
+//! a plugin so the example is the place to see them compiled together.
 //!
 //! ## Creating a project
 //!
@@ -60,6 +61,18 @@
 //! serde_json = "1.0"
 //! ```
 //!
+//! By default the plugin is built for Janus 0.8 (plugin API 13). Newer Janus versions pass media
+//! in packet structs and refuse plugins of other API versions so select the target with a feature:
+//! `janus-0-10` for Janus 0.10.x or `janus-1` for Janus 1.x:
+//!
+//! ```toml
+//! janus-app = { version = "*", features = ["janus-1"] }
+//! ```
+//!
+//! Plugin code stays the same. The extra details Janus provides, e.g. m-line indexes,
+//! RTP extensions and data channel labels, are available in
+//! [MediaInfo](struct.MediaInfo.html) and [DataInfo](struct.DataInfo.html) of media events.
+//!
 //!
 //! ## Definining a plugin
 //!
@@ -68,22 +81,26 @@
 //!
 //! In your `src/lib.rs` add:
 //!
-//! ```rust
-//! use janus_app::{janus_plugin, Plugin};
+//! ```rust,ignore
+//! use janus_app::{janus_plugin, Error, Plugin};
 //!
 //! pub struct MyPlugin {
 //! }
 //!
 //! impl Plugin for MyPlugin {
 //!   type Handle = MyHandle;
+//!   type Config = ();
+//!   type IncomingAdminMessagePayload = ();
+//!   type OutgoingAdminMessagePayload = ();
 //!
 //!   const VERSION: i32 = 1;
 //!   const VERSION_STRING: &'static str = "0.0.1";
-//!   const NAME: &'static str = "Author name";
+//!   const NAME: &'static str = "My plugin";
 //!   const DESCRIPTION: &'static str = "My plugin description";
+//!   const AUTHOR: &'static str = "Author name";
 //!   const PACKAGE: &'static str = "janus.plugin.my_plugin";
 //!
-//!   fn init(_config_path: &Path) -> Result<Box<Self>, Error> {
+//!   fn init(_config: Self::Config) -> Result<Box<Self>, Error> {
 //!     Ok(Box::new(Self {}))
 //!   }
 //!
@@ -97,15 +114,15 @@
 //!
 //! The [Plugin](trait.Plugin.html) trait requires to define an
 //! [associated type for plugin handle](trait.Plugin.html#associatedtype.Handle) which we'll define
-//! later, [plugin info constants](trait.Plugin.html#associated-const),
+//! later, [config type](#configuration) and [admin message types](#admin-api) which are `()`
+//! until the plugin needs them,
+//! [plugin info constants](trait.Plugin.html#associated-const),
 //! [init](trait.Plugin.html#tymethod.init) function and
 //! [build_handle](trait.Plugin.html#tymethod.build_handle) method.
 //!
 //! [init](trait.Plugin.html#tymethod.init) function is being called to create an instance of the
-//! plugin. Here we can initialize the plugin state. `config_path` argument is being supplied and
-//! contains config *directory* path. You may use it to read and parse the plugin's config then
-//! store it in the plugin object but this is out of scope now since we're building a minimal setup.
-//! See example plugin for details.
+//! plugin. Here we can initialize the plugin state. It receives the plugin's config which is
+//! `()` for now since we're building a minimal setup.
 //!
 //! [build_handle](trait.Plugin.html#tymethod.build_handle) method is for creating a plugin handle
 //! instance. Here we may want to pass in some data from the plugin state.
@@ -121,7 +138,8 @@
 //!
 //! ```rust
 //! use janus_app::{
-//!     plugin::Callbacks, Error, IncomingMessage, MessageResponse, MediaEvent, OutgoingMessage,
+//!     plugin::Callbacks, Error, Handle, IncomingMessage, MessageResponse, MediaEvent,
+//!     OutgoingMessage, Responder,
 //! };
 //!
 //! use serde_derive::{Deserialize, Serialize};
@@ -132,6 +150,7 @@
 //! Before defining the handle type let's define message types to associate it with:
 //!
 //! ```rust
+//! # use serde_derive::{Deserialize, Serialize};
 //! #[derive(Clone, Debug, Deserialize)]
 //! #[serde(rename_all = "lowercase", tag = "method")]
 //! pub enum IncomingMessagePayload {
@@ -148,13 +167,22 @@
 //! Then in [handle_message](trait.Handle.html#tymethod.handle_message) function we can `match`
 //! on this enum and apply coresponding logic.
 //!
+//! Payloads which fail to deserialize are being rejected with a `bad_request` error containing
+//! the path of the offending field and the accepted `method` tags. Constraints like ranges
+//! and string lengths may be declared on the fields as well, see [validation](validation/index.html).
+//!
 //! [OutgoingMessagePayload](trait.Handle.html#associatedtype.OutgoingMessagePayload) is for
 //! responses. We return it from [handle_message](trait.Handle.html#tymethod.handle_message) and
 //! then it gets serialized to JSON by serde.
 //!
+//! With many methods the `match` grows big so consider a [Router](router/index.html) instead.
+//! It maps method names to separate handler functions with their own request and response types
+//! and middleware.
+//!
 //! ### Defining the handle struct
 //!
 //! ```rust
+//! # use serde_derive::Serialize;
 //! #[derive(Clone, Serialize)]
 //! struct MyHandle {
 //!   id: u64,
@@ -183,7 +211,7 @@
 //! things like incoming RTP/RTCP packets etc. and
 //! [handle_message](trait.Handle.html#tymethod.handle_message) for handling incoming messages.
 //!
-//! ```rust
+//! ```rust,ignore
 //! impl Handle for MyHandle {
 //!   type IncomingMessagePayload = IncomingMessagePayload;
 //!   type OutgoingMessagePayload = OutgoingMessagePayload;
@@ -196,8 +224,9 @@
 //!   }
 //!
 //!   fn handle_message(
-//!     &self,
-//!     _message: IncomingMessage<Self::IncomingMessagePayload>
+//!     &mut self,
+//!     _message: IncomingMessage<Self::IncomingMessagePayload>,
+//!     _responder: Responder<Self::OutgoingMessagePayload>,
 //!   ) -> Result<MessageResponse<Self::OutgoingMessagePayload>, Error> {
 //!     Ok(MessageResponse::Ack)
 //!   }
@@ -212,8 +241,31 @@
 //! [MessageResponse](enum.MessageResponse.html) variant which is
 //! [Synchronous(P)](enum.MessageResponse.html#variant.Syncronous) for immediate response
 //! or [Ack](enum.MessageResponse.html#variant.Ack) for deferred response.
-//! In this case an ack response will be sent immediately and further event(s) on this transaction
-//! may be sent using [push_event](plugin/trait.Callbacks.html#method.push_event).
+//! In this case an ack response will be sent immediately and the reply should be sent later using
+//! the [Responder](struct.Responder.html) passed along with the message. It may be moved into
+//! another thread and replies only once. If no reply is sent within
+//! [response_timeout](trait.Plugin.html#method.response_timeout) the client gets a `timeout` error.
+//! Further unsolicited events may be sent using
+//! [push_event](plugin/trait.Callbacks.html#method.push_event).
+//!
+//! For handlers doing I/O there's also [Deferred](enum.MessageResponse.html#variant.Deferred)
+//! which takes a future. The crate acks the message immediately, drives the future on the
+//! [plugin's executor](trait.Plugin.html#method.executor) and pushes its result as an event
+//! with the original transaction:
+//!
+//! ```rust
+//! # use janus_app::{Error, IncomingMessage, MessageResponse};
+//! # fn handle_message(
+//! #     message: IncomingMessage<serde_json::Value>,
+//! # ) -> Result<MessageResponse<serde_json::Value>, Error> {
+//! let payload = message.payload().clone();
+//!
+//! Ok(MessageResponse::deferred(async move {
+//!     // Do some I/O here.
+//!     Ok(payload)
+//! }))
+//! # }
+//! ```
 //!
 //!
 //! ## Calling callbacks
@@ -229,10 +281,116 @@
 //! [Callbacks](plugin/trait.Callbacks.html) is a generic trait so it requires the plugin type
 //! as a generic type parameter so a method call looks like this:
 //!
-//! ```rust
-//! Callbacks::<MyPlugin>::push_event(self, &message);
+//! ```rust,ignore
+//! Callbacks::<MyPlugin>::send_remb(self, 256_000)?;
+//!
+//! // Replies to an acked message go through its responder.
+//! responder.reply(payload)?;
 //! ```
 //!
+//! Conferencing plugins don't need to relay media between handles by hand: put the handles into
+//! [rooms](plugin/room/index.html) as publishers and subscribers and pass media events
+//! to [room::relay](plugin/room/fn.relay.html). Likewise [forwarders](plugin/forwarder/index.html)
+//! copy a handle's media to external UDP destinations, [recorders](plugin/recorder/index.html)
+//! archive it into `.mjr` files and [players](plugin/player/index.html) play recordings back
+//! into a handle.
+//!
+//!
+//! ## Configuration
+//!
+//! The plugin's [Config](trait.Plugin.html#associatedtype.Config) type is being loaded by the
+//! crate from Janus's config directory and passed to [init](trait.Plugin.html#tymethod.init).
+//! The file is named after the package, e.g. `janus.plugin.my_plugin.jcfg` in Janus's own
+//! [format](jcfg/index.html), and may also be TOML, JSON or YAML. Environment variables like
+//! `JANUS_PLUGIN_MY_PLUGIN_MAX_ROOMS` override its values.
+//! If parsing fails the plugin fails to init and the error is being logged with the file name.
+//!
+//! ```rust,ignore
+//! use janus_app::{Error, Plugin};
+//! use serde_derive::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! pub struct Config {
+//!     #[serde(default = "default_max_rooms")]
+//!     max_rooms: usize,
+//! }
+//!
+//! fn default_max_rooms() -> usize {
+//!     100
+//! }
+//!
+//! pub struct MyPlugin {
+//!     config: Config,
+//! }
+//!
+//! impl Plugin for MyPlugin {
+//!     type Handle = MyHandle;
+//!     type Config = Config;
+//!
+//!     // ...
+//!
+//!     fn init(config: Config) -> Result<Box<Self>, Error> {
+//!         log::info!("Up to {} rooms", config.max_rooms);
+//!         Ok(Box::new(Self { config }))
+//!     }
+//! }
+//! ```
+//!
+//! Plugins with other needs may override [load_config](trait.Plugin.html#method.load_config)
+//! which receives the directory path.
+//!
+//!
+//! ## Admin API
+//!
+//! Operators may query and control the plugin through Janus's Admin API with a `message_plugin`
+//! request. Its `request` object is being deserialized into the plugin's
+//! [IncomingAdminMessagePayload](trait.Plugin.html#associatedtype.IncomingAdminMessagePayload)
+//! and passed to [handle_admin_message](trait.Plugin.html#method.handle_admin_message).
+//! It requires Janus 0.10+ so enable `janus-0-10` or `janus-1` feature.
+//!
+//! ```rust,ignore
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//!
+//! use janus_app::{Error, Plugin};
+//! use serde_derive::{Deserialize, Serialize};
+//!
+//! #[derive(Deserialize)]
+//! #[serde(rename_all = "snake_case", tag = "request")]
+//! pub enum AdminRequest {
+//!     GetBitrate,
+//!     SetBitrate { bitrate: u64 },
+//! }
+//!
+//! #[derive(Serialize)]
+//! pub struct AdminResponse {
+//!     bitrate: u64,
+//! }
+//!
+//! pub struct MyPlugin {
+//!     bitrate: AtomicU64,
+//! }
+//!
+//! impl Plugin for MyPlugin {
+//!     type Handle = MyHandle;
+//!     type IncomingAdminMessagePayload = AdminRequest;
+//!     type OutgoingAdminMessagePayload = AdminResponse;
+//!
+//!     // ...
+//!
+//!     fn handle_admin_message(&self, payload: AdminRequest) -> Result<AdminResponse, Error> {
+//!         if let AdminRequest::SetBitrate { bitrate } = payload {
+//!             self.bitrate.store(bitrate, Ordering::Relaxed);
+//!         }
+//!
+//!         Ok(AdminResponse {
+//!             bitrate: self.bitrate.load(Ordering::Relaxed),
+//!         })
+//!     }
+//! }
+//! ```
+//!
+//! An error is being returned as `error_code` and `error` fields like for handle messages.
+//!
 //!
 //! ## Compiling and installing
 //!
@@ -249,14 +407,22 @@
 ///////////////////////////////////////////////////////////////////////////////
 
 use std::fmt;
+use std::future::Future;
 use std::marker::Sized;
 use std::path::Path;
+use std::time::Duration;
 
 use serde::{de, ser};
 use serde_derive::{Deserialize, Serialize};
 
-pub use error::Error;
+pub use error::{Error, ErrorKind};
 pub use lazy_static::lazy_static;
+pub use responder::Responder;
+
+use executor::{BoxFuture, Executor};
+use rtcp::RtcpPacket;
+use rtp::RtpPacket;
+use sdp::SessionDescription;
 
 ///////////////////////////////////////////////////////////////////////////////
 
@@ -277,7 +443,7 @@ impl fmt::Display for MediaProtocol {
 }
 
 /// Buffer media type: video or audio.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum MediaKind {
     Video,
     Audio,
@@ -302,24 +468,109 @@ pub enum MediaEvent<'a> {
         protocol: MediaProtocol,
         kind: MediaKind,
         buffer: &'a [i8],
+        info: MediaInfo,
     },
     /// Incoming buffer from data channel.
-    Data { buffer: &'a [i8] },
+    Data {
+        buffer: &'a [i8],
+        info: DataInfo<'a>,
+    },
     /// Slow link detected by Janus core.
     SlowLink { kind: MediaKind, uplink: isize },
     /// PeerConnection hanged up.
     Hangup,
 }
 
+impl<'a> MediaEvent<'a> {
+    /// Parses the buffer of an incoming RTP media event.
+    /// Returns `None` for other events including RTCP.
+    pub fn rtp_packet(&self) -> Option<Result<RtpPacket<'a>, Error>> {
+        match self {
+            Self::Media {
+                protocol: MediaProtocol::Rtp,
+                buffer,
+                ..
+            } => Some(RtpPacket::new(buffer)),
+            _ => None,
+        }
+    }
+
+    /// Parses the compound packet of an incoming RTCP media event.
+    /// Returns `None` for other events including RTP.
+    pub fn rtcp_packets(&self) -> Option<Result<Vec<RtcpPacket>, Error>> {
+        match self {
+            Self::Media {
+                protocol: MediaProtocol::Rtcp,
+                buffer,
+                ..
+            } => Some(rtcp::parse(buffer)),
+            _ => None,
+        }
+    }
+}
+
+/// Details of a media packet which newer Janus versions pass along with the buffer.
+/// Fields not provided by the targeted Janus version are `None`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MediaInfo {
+    /// Index of the m-line in SDP the packet belongs to. Janus 1.x only.
+    pub mindex: Option<usize>,
+    /// RTP header extensions parsed by Janus. Janus 0.10+, RTP only.
+    pub extensions: Option<RtpExtensions>,
+}
+
+/// RTP header extensions values parsed by Janus.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RtpExtensions {
+    /// Audio level in -dBov (RFC 6464).
+    pub audio_level: Option<u8>,
+    /// Voice activity flag of the audio level extension.
+    pub voice_activity: bool,
+    /// Video orientation in degrees (CVO).
+    pub video_rotation: Option<u16>,
+    pub video_back_camera: bool,
+    pub video_flipped: bool,
+}
+
+/// Details of a data channel message. Janus 0.10+ only, empty for older versions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DataInfo<'a> {
+    /// Label of the data channel the message came from.
+    pub label: Option<&'a str>,
+    /// Subprotocol of the data channel.
+    pub protocol: Option<&'a str>,
+    /// Whether the message is binary rather than text.
+    pub binary: bool,
+}
+
 /// JSEP (Javascript Session Establishment Protocol) object containing
 /// SDP (Session Description Protocol offer wither answer.
 /// Being used for signalling.
-#[derive(Debug, Deserialize, Serialize)]
+///
+/// SDP is being parsed into [SessionDescription](sdp/struct.SessionDescription.html) and
+/// serialized back to the same text unless modified.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase", tag = "type")]
 pub enum Jsep {
-    // TODO: Parse SDP.
-    Offer { sdp: String },
-    Answer { sdp: String },
+    Offer { sdp: SessionDescription },
+    Answer { sdp: SessionDescription },
+}
+
+impl Jsep {
+    /// Session description of either an offer or an answer.
+    pub fn sdp(&self) -> &SessionDescription {
+        match self {
+            Self::Offer { sdp } => sdp,
+            Self::Answer { sdp } => sdp,
+        }
+    }
+
+    pub fn sdp_mut(&mut self) -> &mut SessionDescription {
+        match self {
+            Self::Offer { sdp } => sdp,
+            Self::Answer { sdp } => sdp,
+        }
+    }
 }
 
 /// Incoming message sent by Janus's `message` request.
@@ -357,6 +608,10 @@ impl<P: de::DeserializeOwned> IncomingMessage<P> {
     pub fn jsep(&self) -> Option<&Jsep> {
         self.jsep.as_ref()
     }
+
+    pub(crate) fn into_parts(self) -> (String, P, Option<Jsep>) {
+        (self.transaction, self.payload, self.jsep)
+    }
 }
 
 /// Outgoing message to send with `push_event` callback.
@@ -397,19 +652,62 @@ impl<P: ser::Serialize> OutgoingMessage<P> {
 }
 
 /// Response for `IncomingMessage`.
-#[derive(Debug)]
 pub enum MessageResponse<P: ser::Serialize> {
     /// Immediate (synchronous) response with the provided payload.
     Syncronous(P),
-    /// Deferred (asynchronous) response using
-    /// [push_event](plugin/trait.Callbacks.html#method.push_event) later on.
+    /// Deferred (asynchronous) response using the message's [Responder](struct.Responder.html)
+    /// or [push_event](plugin/trait.Callbacks.html#method.push_event) with its transaction later on.
     Ack,
+    /// Deferred (asynchronous) response which is being sent automatically.
+    ///
+    /// The future is being spawned on the [plugin's executor](trait.Plugin.html#method.executor)
+    /// and its result is being pushed as an event with the original transaction.
+    /// An error is being pushed as JSON like an error returned from
+    /// [handle_message](trait.Handle.html#tymethod.handle_message).
+    Deferred(BoxFuture<Result<P, Error>>),
+}
+
+impl<P: ser::Serialize> MessageResponse<P> {
+    /// Shortcut for [Deferred](#variant.Deferred) to pass an `async` block without boxing.
+    ///
+    /// ```
+    /// # use janus_app::{Error, MessageResponse};
+    /// # fn fetch_room_name() -> impl std::future::Future<Output = Result<String, Error>> {
+    /// #     async { Ok(String::from("lobby")) }
+    /// # }
+    /// let response = MessageResponse::deferred(async move {
+    ///     let name = fetch_room_name().await?;
+    ///     Ok(serde_json::json!({ "room": name }))
+    /// });
+    /// ```
+    pub fn deferred<F>(future: F) -> Self
+    where
+        F: Future<Output = Result<P, Error>> + Send + 'static,
+    {
+        MessageResponse::Deferred(Box::pin(future))
+    }
+}
+
+impl<P: ser::Serialize + fmt::Debug> fmt::Debug for MessageResponse<P> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Syncronous(payload) => fmt.debug_tuple("Syncronous").field(payload).finish(),
+            Self::Ack => fmt.write_str("Ack"),
+            Self::Deferred(_) => fmt.write_str("Deferred(..)"),
+        }
+    }
 }
 
 /// Plugin handle trait.
-pub trait Handle: Clone + Sized + ser::Serialize {
+///
+/// Handles are being dispatched from Janus threads concurrently so they must be `Send + Sync`.
+///
+/// Messages and hooks take the handle mutably so they may change its state. Meanwhile other
+/// events of this handle are waiting while events of other handles keep being dispatched.
+/// Media events take the handle shared so they're being dispatched in parallel.
+pub trait Handle: Clone + Sized + Send + Sync + ser::Serialize {
     type IncomingMessagePayload: de::DeserializeOwned;
-    type OutgoingMessagePayload: ser::Serialize;
+    type OutgoingMessagePayload: ser::Serialize + Send + 'static;
 
     /// Handle ID getter.
     fn id(&self) -> u64;
@@ -417,10 +715,46 @@ pub trait Handle: Clone + Sized + ser::Serialize {
     /// Media event handler.
     fn handle_media_event(&self, media_event: &MediaEvent);
 
+    /// Being called right after the handle is registered and before any event is dispatched
+    /// to it. Events of the handle are waiting until it returns.
+    fn on_created(&mut self) {}
+
+    /// Being called when the handle's PeerConnection is up.
+    /// Defaults to dispatching [MediaEvent::Setup](enum.MediaEvent.html#variant.Setup).
+    fn on_media_setup(&mut self) {
+        self.handle_media_event(&MediaEvent::Setup);
+    }
+
+    /// Being called when the handle's PeerConnection has gone.
+    /// Defaults to dispatching [MediaEvent::Hangup](enum.MediaEvent.html#variant.Hangup).
+    fn on_hangup(&mut self) {
+        self.handle_media_event(&MediaEvent::Hangup);
+    }
+
+    /// Being called when a [player](plugin/player/index.html) of the handle has played its media
+    /// to the end. The player is already stopped.
+    fn on_playback_complete(&mut self, _player_id: u32) {}
+
+    /// Being called when Janus destroys the handle, e.g. to notify other room members or
+    /// stop timers. The handle leaves its [rooms](plugin/room/index.html) and its
+    /// [forwarders](plugin/forwarder/index.html), [recorders](plugin/recorder/index.html) and
+    /// [players](plugin/player/index.html) are being stopped right after it.
+    ///
+    /// It's being called after the messages and media events being handled at the moment are
+    /// done and no more events are being dispatched to the handle afterwards. The handle is
+    /// already unregistered so [Callbacks](plugin/trait.Callbacks.html) methods fail here.
+    fn on_destroy(&mut self) {}
+
     /// Incoming message handler.
+    /// An error is being sent back to the client as a JSON object with `error_code` and `error`
+    /// fields, see [Error](struct.Error.html).
+    ///
+    /// `responder` is for replying to an [Ack](enum.MessageResponse.html#variant.Ack)'ed message
+    /// later on. It may be dropped when returning any other response.
     fn handle_message(
-        &self,
+        &mut self,
         message: IncomingMessage<Self::IncomingMessagePayload>,
+        responder: Responder<Self::OutgoingMessagePayload>,
     ) -> Result<MessageResponse<Self::OutgoingMessagePayload>, Error>;
 }
 
@@ -429,6 +763,17 @@ pub trait Plugin {
     /// The plugin handle type.
     type Handle: Handle;
 
+    /// Plugin settings being [loaded](#method.load_config) before
+    /// [init](#tymethod.init). Set it to `()` if the plugin has no config.
+    type Config: de::DeserializeOwned;
+
+    /// Admin API request type, i.e. the `request` object of Janus's `message_plugin` admin call.
+    /// Set it to `()` if the plugin doesn't handle admin messages.
+    type IncomingAdminMessagePayload: de::DeserializeOwned;
+
+    /// Admin API response type.
+    type OutgoingAdminMessagePayload: ser::Serialize;
+
     /// Numeric plugin version.
     /// Increment this with each release no matter whether it's major or minor.
     const VERSION: i32;
@@ -449,18 +794,70 @@ pub trait Plugin {
     /// This value must be used by clients to create a plugin handle with Janus's `attach` call.
     const PACKAGE: &'static str;
 
+    /// Loads the config from `config_path` which is a path to the *directory* with configs.
+    /// By default it's a file named after [PACKAGE](#associatedconstant.PACKAGE) with
+    /// environment variable overrides as described in [config](config/index.html) module.
+    /// Failing to load it fails the plugin's init.
+    fn load_config(config_path: &Path) -> Result<Self::Config, Error> {
+        config::load(config_path, Self::PACKAGE)
+    }
+
     /// This is being called when initializing the plugin to create its instance.
-    ///
-    /// `config_path` is a path to the *directory* with configs.
-    fn init(config_path: &Path) -> Result<Box<Self>, Error>;
+    fn init(config: Self::Config) -> Result<Box<Self>, Error>;
 
     /// A method to build a handle object.
     /// Being called when a client calls Janus's `attach` method.
     fn build_handle(&self, id: u64) -> Self::Handle;
+
+    /// Executor to drive [deferred](enum.MessageResponse.html#variant.Deferred) message responses
+    /// on. Defaults to a [shared thread pool](executor/fn.default_executor.html).
+    fn executor(&self) -> &dyn Executor {
+        executor::default_executor()
+    }
+
+    /// Time for a handler to reply to an [Ack](enum.MessageResponse.html#variant.Ack)'ed or
+    /// [deferred](enum.MessageResponse.html#variant.Deferred) message before the client gets
+    /// a `timeout` error.
+    fn response_timeout(&self) -> Duration {
+        responder::DEFAULT_RESPONSE_TIMEOUT
+    }
+
+    /// Handles a request sent with Janus's Admin API `message_plugin` call so operators can query
+    /// and control the plugin in runtime. The payload or the error is being returned as
+    /// the `response` of the admin call.
+    ///
+    /// Janus 0.8 doesn't pass admin messages to plugins so it's being called only with
+    /// `janus-0-10` or `janus-1` feature.
+    fn handle_admin_message(
+        &self,
+        _payload: Self::IncomingAdminMessagePayload,
+    ) -> Result<Self::OutgoingAdminMessagePayload, Error> {
+        Err(Error::bad_request("Admin messages are not supported"))
+    }
+
+    /// Being called when Janus destroys the plugin, after
+    /// [on_destroy](trait.Handle.html#method.on_destroy) of the remaining handles and before
+    /// the plugin is dropped.
+    fn shutdown(&self) {}
 }
 
 ///////////////////////////////////////////////////////////////////////////////
 
+pub mod config;
 mod error;
+pub mod executor;
 mod ffi;
+mod jansson;
+pub mod jcfg;
+pub mod logger;
+pub mod mjr;
+pub mod pcap;
 pub mod plugin;
+mod responder;
+pub mod router;
+pub mod rtcp;
+pub mod rtp;
+pub mod sdp;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod validation;
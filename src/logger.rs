@@ -0,0 +1,186 @@
+//! [log](https://docs.rs/log) backend writing through the Janus logger.
+//!
+//! It's being installed on plugin init so the ordinary `log` macros may be used in a plugin.
+//! Records are being filtered by the Janus log level (`debug_level` in `janus.jcfg`
+//! or `--debug-level` CLI option) and formatted like this:
+//!
+//! ```text
+//! [WARN] [janus.plugin.my_plugin] [handle 123] my_plugin::handle: Something went wrong
+//! ```
+//!
+//! The handle ID is being taken from the `handle_id` key-value of the record, e.g.
+//! `log::warn!(handle_id = 123; "Something went wrong")`, or from the current
+//! [handle scope](fn.handle_scope.html). The scope is being entered automatically
+//! when dispatching a message or a media event to a handle.
+
+use std::cell::Cell;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::sync::OnceLock;
+
+use log::{kv::Key, Level, LevelFilter, Log, Metadata, Record};
+
+use crate::ffi::janus_symbol;
+
+// Janus log levels from `debug.h`.
+const JANUS_LOG_ERR: c_int = 2;
+const JANUS_LOG_WARN: c_int = 3;
+const JANUS_LOG_INFO: c_int = 4;
+const JANUS_LOG_VERB: c_int = 5;
+const JANUS_LOG_DBG: c_int = 7;
+
+/// Janus level to use when running outside of Janus, e.g. in tests.
+const DEFAULT_LOG_LEVEL: c_int = JANUS_LOG_INFO;
+
+const HANDLE_ID_KEY: &str = "handle_id";
+
+type JanusVprintf = unsafe extern "C" fn(format: *const c_char, ...);
+
+thread_local! {
+    static HANDLE_ID: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+static SYMBOLS: OnceLock<Symbols> = OnceLock::new();
+
+///////////////////////////////////////////////////////////////////////////////
+
+struct JanusLogger {
+    package: &'static str,
+}
+
+impl Log for JanusLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        janus_level(metadata.level()) <= max_janus_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let handle_id = record
+            .key_values()
+            .get(Key::from_str(HANDLE_ID_KEY))
+            .and_then(|value| value.to_u64())
+            .or_else(|| HANDLE_ID.with(Cell::get));
+
+        let handle = match handle_id {
+            Some(id) => format!("[handle {}] ", id),
+            None => String::new(),
+        };
+
+        let line = format!(
+            "{}[{}] {}{}: {}\n",
+            prefix(record.level()),
+            self.package,
+            handle,
+            record.target(),
+            record.args()
+        );
+
+        write(&line);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger. It's being called by the plugin's `init` so normally there's no need
+/// to call it manually. Does nothing if a logger is already installed.
+pub fn init(package: &'static str) {
+    SYMBOLS.get_or_init(Symbols::resolve);
+
+    if log::set_boxed_logger(Box::new(JanusLogger { package })).is_ok() {
+        // Filtering is being done against the Janus log level which may change in runtime.
+        log::set_max_level(LevelFilter::Trace);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Guard returned by [handle_scope](fn.handle_scope.html).
+pub struct HandleScope {
+    previous: Option<u64>,
+}
+
+impl Drop for HandleScope {
+    fn drop(&mut self) {
+        HANDLE_ID.with(|cell| cell.set(self.previous));
+    }
+}
+
+/// Adds the handle ID to the records logged on the current thread until the guard is dropped.
+/// Useful for handle tasks running on other threads.
+pub fn handle_scope(handle_id: u64) -> HandleScope {
+    let previous = HANDLE_ID.with(|cell| cell.replace(Some(handle_id)));
+    HandleScope { previous }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Janus core symbols being used for each record. They're being resolved once since
+/// records may be logged on the media path where `dlsym` is too slow.
+struct Symbols {
+    log_level: Option<*const c_int>,
+    log_colors: Option<*const c_int>,
+    vprintf: Option<JanusVprintf>,
+}
+
+// Janus keeps the variables for the whole process lifetime and they're only being read.
+unsafe impl Send for Symbols {}
+unsafe impl Sync for Symbols {}
+
+impl Symbols {
+    fn resolve() -> Self {
+        Self {
+            log_level: janus_symbol::<c_int>(b"janus_log_level\0").map(|ptr| ptr as *const _),
+            log_colors: janus_symbol::<c_int>(b"janus_log_colors\0").map(|ptr| ptr as *const _),
+            vprintf: janus_symbol::<()>(b"janus_vprintf\0")
+                .map(|ptr| unsafe { std::mem::transmute::<*mut (), JanusVprintf>(ptr) }),
+        }
+    }
+}
+
+fn symbols() -> &'static Symbols {
+    SYMBOLS.get_or_init(Symbols::resolve)
+}
+
+fn janus_level(level: Level) -> c_int {
+    match level {
+        Level::Error => JANUS_LOG_ERR,
+        Level::Warn => JANUS_LOG_WARN,
+        Level::Info => JANUS_LOG_INFO,
+        Level::Debug => JANUS_LOG_VERB,
+        Level::Trace => JANUS_LOG_DBG,
+    }
+}
+
+fn max_janus_level() -> c_int {
+    match symbols().log_level {
+        Some(level) => unsafe { std::ptr::read_volatile(level) },
+        None => DEFAULT_LOG_LEVEL,
+    }
+}
+
+fn prefix(level: Level) -> &'static str {
+    let colors = match symbols().log_colors {
+        Some(colors) => unsafe { std::ptr::read_volatile(colors) != 0 },
+        None => false,
+    };
+
+    match (level, colors) {
+        (Level::Error, false) => "[ERR] ",
+        (Level::Error, true) => "\x1b[31m[ERR]\x1b[0m ",
+        (Level::Warn, false) => "[WARN] ",
+        (Level::Warn, true) => "\x1b[33m[WARN]\x1b[0m ",
+        _ => "",
+    }
+}
+
+fn write(line: &str) {
+    match (symbols().vprintf, CString::new(line.replace('\0', ""))) {
+        (Some(vprintf), Ok(c_line)) => unsafe {
+            vprintf(b"%s\0".as_ptr() as *const c_char, c_line.as_ptr())
+        },
+        _ => eprint!("{}", line),
+    }
+}
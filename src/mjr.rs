@@ -0,0 +1,387 @@
+//! Janus media recording (`.mjr`) format, the one `janus-pp-rec` post-processes.
+//!
+//! A recording holds RTP packets of a single media kind. It starts with the `MJR00002` magic and
+//! a JSON info header with the codec and times followed by frames: the `MEET` marker,
+//! milliseconds since the first packet, the packet length and the packet itself.
+//!
+//! ```
+//! use janus_app::mjr::Writer;
+//! use janus_app::MediaKind;
+//!
+//! # fn record(packets: &[&[u8]]) -> Result<Vec<u8>, janus_app::Error> {
+//! let mut writer = Writer::new(vec![], MediaKind::Video, "vp8");
+//!
+//! for packet in packets {
+//!     writer.write_packet(packet)?;
+//! }
+//!
+//! let file = writer.into_inner();
+//! # Ok(file)
+//! # }
+//! ```
+//!
+//! [Reader](struct.Reader.html) reads recordings back, e.g. to play them with
+//! [plugin::player](../plugin/player/index.html). Use [plugin::recorder](../plugin/recorder/index.html)
+//! to record handles' media into files.
+
+use std::convert::TryFrom;
+use std::io::{ErrorKind as IoErrorKind, Read, Write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{Error, MediaKind};
+
+const MAGIC: &[u8] = b"MJR00002";
+const FRAME_MARKER: &[u8] = b"MEET";
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Recording info from the header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Info {
+    pub kind: MediaKind,
+    /// Lowercase codec name as in Janus, e.g. `opus` or `vp8`.
+    pub codec: String,
+    pub fmtp: Option<String>,
+    /// Creation time of the recording in microseconds since Unix epoch.
+    pub created_at: u64,
+    /// Time of the first packet in microseconds since Unix epoch.
+    pub started_at: u64,
+}
+
+/// The header as Janus writes it.
+#[derive(Serialize, Deserialize)]
+struct RawInfo {
+    t: String,
+    c: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    f: Option<String>,
+    s: u64,
+    u: u64,
+}
+
+impl From<&Info> for RawInfo {
+    fn from(info: &Info) -> Self {
+        let kind = match info.kind {
+            MediaKind::Audio => "a",
+            MediaKind::Video => "v",
+        };
+
+        Self {
+            t: kind.to_owned(),
+            c: info.codec.clone(),
+            f: info.fmtp.clone(),
+            s: info.created_at,
+            u: info.started_at,
+        }
+    }
+}
+
+impl TryFrom<RawInfo> for Info {
+    type Error = Error;
+
+    fn try_from(raw: RawInfo) -> Result<Self, Error> {
+        let kind = match raw.t.as_str() {
+            "a" => MediaKind::Audio,
+            "v" => MediaKind::Video,
+            other => {
+                let err = format!("Unsupported recording type: {}", other);
+                return Err(Error::new(&err));
+            }
+        };
+
+        Ok(Self {
+            kind,
+            codec: raw.c,
+            fmtp: raw.f,
+            created_at: raw.s,
+            started_at: raw.u,
+        })
+    }
+}
+
+/// A recorded RTP packet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// Milliseconds since the first packet of the recording.
+    pub time: u32,
+    pub packet: Vec<u8>,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Writes RTP packets into a recording.
+///
+/// The header is being written along with the first packet since it holds the packet's time.
+/// Nothing is being written if there were no packets.
+pub struct Writer<W: Write> {
+    inner: W,
+    info: Info,
+    started: Option<Instant>,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W, kind: MediaKind, codec: &str) -> Self {
+        Self {
+            inner,
+            info: Info {
+                kind,
+                codec: codec.to_lowercase(),
+                fmtp: None,
+                created_at: now_micros(),
+                started_at: 0,
+            },
+            started: None,
+        }
+    }
+
+    /// Sets the `a=fmtp` parameters of the codec, e.g. H.264 profile.
+    pub fn set_fmtp(self, fmtp: &str) -> Self {
+        let info = Info {
+            fmtp: Some(fmtp.to_owned()),
+            ..self.info
+        };
+
+        Self { info, ..self }
+    }
+
+    pub fn info(&self) -> &Info {
+        &self.info
+    }
+
+    /// Writes an RTP packet as a frame stamped with the time since the first one.
+    pub fn write_packet(&mut self, packet: &[u8]) -> Result<(), Error> {
+        if packet.len() > u16::MAX as usize {
+            let err = format!("RTP packet is too long to record: {} bytes", packet.len());
+            return Err(Error::new(&err));
+        }
+
+        let elapsed = match self.started {
+            Some(started) => started.elapsed().as_millis() as u32,
+            None => {
+                self.info.started_at = now_micros();
+                self.write_header()?;
+                self.started = Some(Instant::now());
+                0
+            }
+        };
+
+        let mut frame = Vec::with_capacity(FRAME_MARKER.len() + 6 + packet.len());
+        frame.extend_from_slice(FRAME_MARKER);
+        frame.extend_from_slice(&elapsed.to_be_bytes());
+        frame.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+        frame.extend_from_slice(packet);
+        self.write(&frame)
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.inner
+            .flush()
+            .map_err(|err| Error::new(&format!("Failed to flush recording: {}", err)))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn write_header(&mut self) -> Result<(), Error> {
+        let info = serde_json::to_vec(&RawInfo::from(&self.info))
+            .map_err(|err| Error::new(&format!("Failed to dump recording info: {}", err)))?;
+
+        let mut header = Vec::with_capacity(MAGIC.len() + 2 + info.len());
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&(info.len() as u16).to_be_bytes());
+        header.extend_from_slice(&info);
+        self.write(&header)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.inner
+            .write_all(bytes)
+            .map_err(|err| Error::new(&format!("Failed to write recording: {}", err)))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Reads RTP packets from a recording.
+///
+/// Only the `MJR00002` format of Janus 0.7.3+ is supported. A truncated frame at the end,
+/// e.g. of a recording which hasn't been closed properly, is being treated as the end.
+pub struct Reader<R: Read> {
+    inner: R,
+    info: Info,
+}
+
+impl<R: Read> Reader<R> {
+    /// Reads the header. Fails if the recording is in another format or isn't audio or video.
+    pub fn new(mut inner: R) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+
+        if read_full(&mut inner, &mut magic)? < magic.len() || magic != MAGIC {
+            return Err(Error::new("Not a recording of MJR00002 format"));
+        }
+
+        let mut len = [0; 2];
+
+        if read_full(&mut inner, &mut len)? < len.len() {
+            return Err(Error::new("Recording info is missing"));
+        }
+
+        let mut info = vec![0; u16::from_be_bytes(len) as usize];
+
+        if read_full(&mut inner, &mut info)? < info.len() {
+            return Err(Error::new("Recording info is truncated"));
+        }
+
+        let raw_info: RawInfo = serde_json::from_slice(&info)
+            .map_err(|err| Error::new(&format!("Failed to parse recording info: {}", err)))?;
+
+        Ok(Self {
+            inner,
+            info: Info::try_from(raw_info)?,
+        })
+    }
+
+    pub fn info(&self) -> &Info {
+        &self.info
+    }
+
+    /// Reads the next frame or returns `None` at the end of the recording.
+    pub fn read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        let mut header = [0; 10];
+
+        if read_full(&mut self.inner, &mut header)? < header.len() {
+            return Ok(None);
+        }
+
+        if &header[..4] != FRAME_MARKER {
+            return Err(Error::new("Recording frame marker is missing"));
+        }
+
+        let time = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let mut packet = vec![0; u16::from_be_bytes([header[8], header[9]]) as usize];
+
+        if read_full(&mut self.inner, &mut packet)? < packet.len() {
+            Ok(None)
+        } else {
+            Ok(Some(Frame { time, packet }))
+        }
+    }
+}
+
+/// Reads until the buffer is full or the end and returns the number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, Error> {
+    let mut len = 0;
+
+    while len < buffer.len() {
+        match reader.read(&mut buffer[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(ref err) if err.kind() == IoErrorKind::Interrupted => (),
+            Err(err) => return Err(Error::new(&format!("Failed to read recording: {}", err))),
+        }
+    }
+
+    Ok(len)
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value as JsonValue;
+
+    use super::*;
+
+    const PACKET: [u8; 14] = [0x80, 0x60, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 1, 2];
+
+    #[test]
+    fn write() {
+        let mut writer =
+            Writer::new(vec![], MediaKind::Video, "H264").set_fmtp("packetization-mode=1");
+        assert_eq!(writer.info().started_at, 0);
+        writer.write_packet(&PACKET).unwrap();
+        writer.write_packet(&PACKET[..12]).unwrap();
+        let started_at = writer.info().started_at;
+        let created_at = writer.info().created_at;
+        assert!(started_at >= created_at);
+
+        let bytes = writer.into_inner();
+        assert_eq!(&bytes[..8], b"MJR00002");
+        let info_len = u16::from_be_bytes([bytes[8], bytes[9]]) as usize;
+        let info: JsonValue = serde_json::from_slice(&bytes[10..10 + info_len]).unwrap();
+
+        assert_eq!(
+            info,
+            serde_json::json!({
+                "t": "v",
+                "c": "h264",
+                "f": "packetization-mode=1",
+                "s": created_at,
+                "u": started_at,
+            })
+        );
+
+        let frames = &bytes[10 + info_len..];
+        assert_eq!(&frames[..4], b"MEET");
+        assert_eq!(&frames[4..8], &[0, 0, 0, 0]);
+        assert_eq!(&frames[8..10], &[0, 14]);
+        assert_eq!(&frames[10..24], &PACKET);
+        assert_eq!(&frames[24..28], b"MEET");
+        assert_eq!(&frames[32..34], &[0, 12]);
+        assert_eq!(&frames[34..], &PACKET[..12]);
+    }
+
+    #[test]
+    fn read() {
+        let mut writer = Writer::new(vec![], MediaKind::Audio, "opus");
+        writer.write_packet(&PACKET).unwrap();
+        writer.write_packet(&PACKET[..12]).unwrap();
+        let info = writer.info().clone();
+        let mut bytes = writer.into_inner();
+        // Truncated frame of a recording which hasn't been closed properly.
+        bytes.extend_from_slice(&[b'M', b'E', b'E', b'T', 0, 0, 0, 1, 0, 14, 0x80]);
+
+        let mut reader = Reader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.info(), &info);
+        let frame = reader.read_frame().unwrap().unwrap();
+        assert_eq!(frame.packet, PACKET.to_vec());
+        let frame = reader.read_frame().unwrap().unwrap();
+        assert_eq!(frame.packet, PACKET[..12].to_vec());
+        assert_eq!(reader.read_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn read_invalid() {
+        assert!(Reader::new(&b"MJR00001"[..]).is_err());
+        assert!(Reader::new(&b"MJR00002\x00\x10{}"[..]).is_err());
+
+        let mut bytes = b"MJR00002".to_vec();
+        let info = br#"{"t":"d","c":"text","s":1,"u":2}"#;
+        bytes.extend_from_slice(&(info.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(info);
+        assert!(Reader::new(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn no_packets() {
+        let writer = Writer::new(vec![], MediaKind::Audio, "opus");
+        assert!(writer.into_inner().is_empty());
+    }
+
+    #[test]
+    fn too_long_packet() {
+        let mut writer = Writer::new(vec![], MediaKind::Audio, "opus");
+        assert!(writer.write_packet(&[0; 70_000]).is_err());
+        assert!(writer.into_inner().is_empty());
+    }
+}
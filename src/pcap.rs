@@ -0,0 +1,354 @@
+//! Reader of UDP packets from classic pcap captures, e.g. RTP captured with `tcpdump`.
+//!
+//! ```
+//! use janus_app::pcap::Reader;
+//!
+//! # fn payloads(capture: &[u8]) -> Result<Vec<Vec<u8>>, janus_app::Error> {
+//! let mut reader = Reader::new(capture)?;
+//! let mut payloads = vec![];
+//!
+//! while let Some(packet) = reader.read_udp()? {
+//!     payloads.push(packet.payload);
+//! }
+//! # Ok(payloads)
+//! # }
+//! ```
+//!
+//! Ethernet, Linux cooked, BSD loopback and raw IP link layers are supported. Packets other than
+//! UDP over IPv4 or IPv6 are being skipped as well as IP fragments and packets truncated by
+//! the capture's snapshot length. The pcapng format isn't supported.
+
+use std::convert::TryInto;
+use std::io::{ErrorKind as IoErrorKind, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use crate::Error;
+
+const GLOBAL_HEADER_LEN: usize = 24;
+const RECORD_HEADER_LEN: usize = 16;
+const UDP_HEADER_LEN: usize = 8;
+
+/// Maximum snapshot length of libpcap. Records longer than that come from a corrupted capture
+/// and are being rejected before allocating their buffers.
+const MAX_RECORD_LEN: usize = 262_144;
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+const IP_PROTOCOL_UDP: u8 = 17;
+
+/// A captured UDP datagram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UdpPacket {
+    /// Capture time since Unix epoch.
+    pub time: Duration,
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    pub payload: Vec<u8>,
+}
+
+/// Reads UDP packets from a pcap capture.
+pub struct Reader<R: Read> {
+    inner: R,
+    big_endian: bool,
+    nanosecond: bool,
+    link_type: u32,
+}
+
+impl<R: Read> Reader<R> {
+    /// Reads the global header. Fails if the capture is in another format or its link layer
+    /// isn't supported.
+    pub fn new(mut inner: R) -> Result<Self, Error> {
+        let mut header = [0; GLOBAL_HEADER_LEN];
+
+        if read_full(&mut inner, &mut header)? < header.len() {
+            return Err(Error::new("pcap header is truncated"));
+        }
+
+        let (big_endian, nanosecond) = match header[..4] {
+            [0xD4, 0xC3, 0xB2, 0xA1] => (false, false),
+            [0x4D, 0x3C, 0xB2, 0xA1] => (false, true),
+            [0xA1, 0xB2, 0xC3, 0xD4] => (true, false),
+            [0xA1, 0xB2, 0x3C, 0x4D] => (true, true),
+            _ => return Err(Error::new("Not a pcap capture")),
+        };
+
+        let link_type = read_u32(&header[20..24], big_endian);
+
+        match link_type {
+            LINKTYPE_NULL | LINKTYPE_ETHERNET | LINKTYPE_RAW | LINKTYPE_LINUX_SLL => Ok(Self {
+                inner,
+                big_endian,
+                nanosecond,
+                link_type,
+            }),
+            _ => Err(Error::new(&format!(
+                "Unsupported pcap link type: {}",
+                link_type
+            ))),
+        }
+    }
+
+    /// Reads the next UDP packet or returns `None` at the end of the capture.
+    pub fn read_udp(&mut self) -> Result<Option<UdpPacket>, Error> {
+        loop {
+            let mut header = [0; RECORD_HEADER_LEN];
+
+            if read_full(&mut self.inner, &mut header)? < header.len() {
+                return Ok(None);
+            }
+
+            let seconds = read_u32(&header[0..4], self.big_endian) as u64;
+            let fraction = read_u32(&header[4..8], self.big_endian);
+            let len = read_u32(&header[8..12], self.big_endian) as usize;
+
+            if len > MAX_RECORD_LEN {
+                let err = format!("pcap record of {} bytes is too long", len);
+                return Err(Error::new(&err));
+            }
+
+            let mut frame = vec![0; len];
+
+            if read_full(&mut self.inner, &mut frame)? < frame.len() {
+                return Ok(None);
+            }
+
+            let time = if self.nanosecond {
+                Duration::new(seconds, fraction)
+            } else {
+                Duration::new(seconds, 0) + Duration::from_micros(fraction as u64)
+            };
+
+            if let Some(packet) = self.parse_frame(&frame, time) {
+                return Ok(Some(packet));
+            }
+        }
+    }
+
+    fn parse_frame(&self, frame: &[u8], time: Duration) -> Option<UdpPacket> {
+        let ip_packet = match self.link_type {
+            LINKTYPE_NULL => frame.get(4..)?,
+            LINKTYPE_ETHERNET => {
+                let (ethertype, offset) = match read_u16(frame.get(12..14)?) {
+                    ETHERTYPE_VLAN => (read_u16(frame.get(16..18)?), 18),
+                    ethertype => (ethertype, 14),
+                };
+
+                match ethertype {
+                    ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => frame.get(offset..)?,
+                    _ => return None,
+                }
+            }
+            LINKTYPE_LINUX_SLL => match read_u16(frame.get(14..16)?) {
+                ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => frame.get(16..)?,
+                _ => return None,
+            },
+            _ => frame,
+        };
+
+        let (source, destination, udp_datagram) = parse_ip(ip_packet)?;
+        let len = read_u16(udp_datagram.get(4..6)?) as usize;
+
+        Some(UdpPacket {
+            time,
+            source: SocketAddr::new(source, read_u16(udp_datagram.get(0..2)?)),
+            destination: SocketAddr::new(destination, read_u16(udp_datagram.get(2..4)?)),
+            payload: udp_datagram.get(UDP_HEADER_LEN..len)?.to_vec(),
+        })
+    }
+}
+
+/// Source and destination addresses and the UDP datagram of an IP packet.
+fn parse_ip(packet: &[u8]) -> Option<(IpAddr, IpAddr, &[u8])> {
+    match packet.first()? >> 4 {
+        4 => {
+            let header_len = (packet[0] & 0x0F) as usize * 4;
+            let total_len = read_u16(packet.get(2..4)?) as usize;
+            let fragment = read_u16(packet.get(6..8)?);
+
+            // More fragments flag or a fragment offset.
+            if fragment & 0x3FFF != 0 || *packet.get(9)? != IP_PROTOCOL_UDP {
+                return None;
+            }
+
+            let source: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+            let destination: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+
+            Some((
+                IpAddr::V4(Ipv4Addr::from(source)),
+                IpAddr::V4(Ipv4Addr::from(destination)),
+                packet.get(header_len..total_len)?,
+            ))
+        }
+        6 => {
+            // Extension headers aren't supported.
+            if *packet.get(6)? != IP_PROTOCOL_UDP {
+                return None;
+            }
+
+            let payload_len = read_u16(packet.get(4..6)?) as usize;
+            let source: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+            let destination: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+
+            Some((
+                IpAddr::V6(Ipv6Addr::from(source)),
+                IpAddr::V6(Ipv6Addr::from(destination)),
+                packet.get(40..40 + payload_len)?,
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn read_u16(buffer: &[u8]) -> u16 {
+    u16::from_be_bytes([buffer[0], buffer[1]])
+}
+
+fn read_u32(buffer: &[u8], big_endian: bool) -> u32 {
+    let bytes = [buffer[0], buffer[1], buffer[2], buffer[3]];
+
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
+
+/// Reads until the buffer is full or the end and returns the number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, Error> {
+    let mut len = 0;
+
+    while len < buffer.len() {
+        match reader.read(&mut buffer[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(ref err) if err.kind() == IoErrorKind::Interrupted => (),
+            Err(err) => return Err(Error::new(&format!("Failed to read pcap: {}", err))),
+        }
+    }
+
+    Ok(len)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn global_header(link_type: u32) -> Vec<u8> {
+        let mut header = vec![0xD4, 0xC3, 0xB2, 0xA1, 2, 0, 4, 0];
+        header.extend_from_slice(&[0; 8]);
+        header.extend_from_slice(&65535u32.to_le_bytes());
+        header.extend_from_slice(&link_type.to_le_bytes());
+        header
+    }
+
+    fn record(capture: &mut Vec<u8>, seconds: u32, micros: u32, frame: &[u8]) {
+        capture.extend_from_slice(&seconds.to_le_bytes());
+        capture.extend_from_slice(&micros.to_le_bytes());
+        capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        capture.extend_from_slice(frame);
+    }
+
+    fn ipv4(protocol: u8, fragment: u16, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45, 0];
+        packet.extend_from_slice(&(20 + payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(&fragment.to_be_bytes());
+        packet.extend_from_slice(&[64, protocol, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn udp(payload: &[u8]) -> Vec<u8> {
+        let mut datagram = vec![0x13, 0x88, 0x13, 0x89];
+        datagram.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+        datagram.extend_from_slice(&[0, 0]);
+        datagram.extend_from_slice(payload);
+        datagram
+    }
+
+    fn ethernet(ip_packet: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        frame.extend_from_slice(ip_packet);
+        // Padding to the minimal frame size.
+        frame.extend_from_slice(&[0; 4]);
+        frame
+    }
+
+    #[test]
+    fn read_ethernet() {
+        let mut capture = global_header(LINKTYPE_ETHERNET);
+        record(&mut capture, 10, 500, &ethernet(&ipv4(6, 0, &[1, 2, 3])));
+        record(
+            &mut capture,
+            10,
+            600,
+            &ethernet(&ipv4(17, 0x2000, &udp(&[1]))),
+        );
+        record(
+            &mut capture,
+            11,
+            700,
+            &ethernet(&ipv4(17, 0, &udp(&[4, 5, 6]))),
+        );
+
+        let mut reader = Reader::new(&capture[..]).unwrap();
+
+        assert_eq!(
+            reader.read_udp().unwrap(),
+            Some(UdpPacket {
+                time: Duration::new(11, 700_000),
+                source: "10.0.0.1:5000".parse().unwrap(),
+                destination: "10.0.0.2:5001".parse().unwrap(),
+                payload: vec![4, 5, 6],
+            })
+        );
+
+        assert_eq!(reader.read_udp().unwrap(), None);
+    }
+
+    #[test]
+    fn read_raw_ipv6() {
+        let datagram = udp(&[7, 8]);
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend_from_slice(&(datagram.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[IP_PROTOCOL_UDP, 64]);
+        packet.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        packet.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        packet.extend_from_slice(&datagram);
+
+        let mut capture = global_header(LINKTYPE_RAW);
+        record(&mut capture, 1, 0, &packet);
+
+        let packet = Reader::new(&capture[..])
+            .unwrap()
+            .read_udp()
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.source, "[::1]:5000".parse().unwrap());
+        assert_eq!(packet.payload, vec![7, 8]);
+    }
+
+    #[test]
+    fn read_invalid() {
+        assert!(Reader::new(&[0x0A, 0x0D, 0x0D, 0x0A][..]).is_err());
+        assert!(Reader::new(&global_header(105)[..]).is_err());
+
+        let mut capture = global_header(LINKTYPE_RAW);
+        record(&mut capture, 0, 0, &[]);
+        capture[GLOBAL_HEADER_LEN + 8..GLOBAL_HEADER_LEN + 12].copy_from_slice(&[0xFF; 4]);
+        let err = Reader::new(&capture[..]).unwrap().read_udp().unwrap_err();
+        assert_eq!(err.as_str(), "pcap record of 4294967295 bytes is too long");
+    }
+}
@@ -3,68 +3,74 @@ use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_int};
 use std::path::Path;
 use std::sync::{
-    atomic::{AtomicPtr, Ordering},
-    RwLock,
+    atomic::{AtomicPtr, AtomicU8, Ordering},
+    Arc, OnceLock, RwLock,
 };
+use std::time::Duration;
 
-use jansson_sys::{json_dumps, json_loads, json_t};
+use futures::future;
+use jansson_sys::{json_decref, json_t};
 use janus_plugin_sys::plugin::{
-    janus_callbacks as JanusCallbacks, janus_plugin_result as JanusPluginResult,
-    janus_plugin_result_type as JanusPluginResultType, janus_plugin_session as JanusPluginSession,
+    janus_plugin_result as JanusPluginResult, janus_plugin_result_type as JanusPluginResultType,
+    janus_plugin_session as JanusPluginSession,
 };
+use parking_lot::{ArcRwLockReadGuard, ArcRwLockWriteGuard, RawRwLock};
 use serde::{de::DeserializeOwned, ser::Serialize};
 
+use crate::executor::BoxFuture;
+use crate::responder::Sink;
+use crate::rtcp::{self, Fir, FirEntry, Pli, RtcpPacket};
 use crate::{
-    Error, Handle, IncomingMessage, Jsep, MediaEvent, MediaKind, MediaProtocol, MessageResponse,
-    OutgoingMessage, Plugin,
+    ffi, jansson, logger, validation, DataInfo, Error, ErrorKind, Handle, IncomingMessage, Jsep,
+    MediaEvent, MediaKind, MediaProtocol, MessageResponse, OutgoingMessage, Plugin, Responder,
 };
+use forwarder::Forwarders;
 use handle_registry::HandleRegistry;
-
-pub use janus_plugin_sys::plugin::janus_plugin as JanusPlugin;
+use player::Players;
+use recorder::Recorders;
+use room::Rooms;
+
+pub use crate::ffi::{janus_callbacks as JanusCallbacks, janus_plugin as JanusPlugin};
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+pub use crate::ffi::{
+    janus_plugin_data as JanusPluginData, janus_plugin_rtcp as JanusPluginRtcp,
+    janus_plugin_rtp as JanusPluginRtp, janus_plugin_rtp_extensions as JanusPluginRtpExtensions,
+};
+pub use handle_ref::HandleRef;
 
 ///////////////////////////////////////////////////////////////////////////////
 
 /// This macro defines low-level stuff to make the project a proper Janus plugin.
 ///
 /// Call it in the main module of you project with a type that implements `Plugin` trait.
+/// The plugin is being built for the Janus version selected with the crate's `janus-*` features.
 #[macro_export]
 macro_rules! janus_plugin {
     ($plugin:ty) => {
-        const JANUS_PLUGIN: janus_app::plugin::JanusPlugin = janus_app::plugin::JanusPlugin {
-            init: janus_app::plugin::init::<$plugin>,
-            destroy: janus_app::plugin::destroy::<$plugin>,
-            get_api_compatibility: janus_app::plugin::get_api_compatibility,
-            get_version: janus_app::plugin::get_version::<$plugin>,
-            get_version_string: janus_app::plugin::get_version_string::<$plugin>,
-            get_description: janus_app::plugin::get_description::<$plugin>,
-            get_name: janus_app::plugin::get_name::<$plugin>,
-            get_author: janus_app::plugin::get_author::<$plugin>,
-            get_package: janus_app::plugin::get_package::<$plugin>,
-            create_session: janus_app::plugin::create_session::<$plugin>,
-            handle_message: janus_app::plugin::handle_message::<$plugin>,
-            setup_media: janus_app::plugin::setup_media::<$plugin>,
-            incoming_rtp: janus_app::plugin::incoming_rtp::<$plugin>,
-            incoming_rtcp: janus_app::plugin::incoming_rtcp::<$plugin>,
-            incoming_data: janus_app::plugin::incoming_data::<$plugin>,
-            slow_link: janus_app::plugin::slow_link::<$plugin>,
-            hangup_media: janus_app::plugin::hangup_media::<$plugin>,
-            destroy_session: janus_app::plugin::destroy_session::<$plugin>,
-            query_session: janus_app::plugin::query_session::<$plugin>,
-        };
+        static JANUS_PLUGIN: janus_app::plugin::JanusPlugin =
+            janus_app::plugin::janus_plugin_table::<$plugin>();
 
         janus_app::lazy_static! {
             static ref APP: std::sync::RwLock<Option<janus_app::plugin::App<$plugin>>> =
                 std::sync::RwLock::new(None);
         }
 
+        static CORE: std::sync::OnceLock<janus_app::plugin::Core<$plugin>> =
+            std::sync::OnceLock::new();
+
         impl janus_app::plugin::PluginApp for $plugin {
             fn janus_plugin() -> *mut janus_app::plugin::JanusPlugin {
-                &mut JANUS_PLUGIN
+                // Janus never writes to the table so it's fine to keep it immutable.
+                &JANUS_PLUGIN as *const _ as *mut _
             }
 
             fn app() -> &'static std::sync::RwLock<Option<janus_app::plugin::App<$plugin>>> {
                 &APP
             }
+
+            fn core() -> &'static std::sync::OnceLock<janus_app::plugin::Core<$plugin>> {
+                &CORE
+            }
         }
 
         // Required by Janus Gateway core to initialize the plugin.
@@ -83,18 +89,23 @@ macro_rules! c_str {
 
 ///////////////////////////////////////////////////////////////////////////////
 
+/// Handle locked for reading which may be kept regardless of the app lock.
+pub type HandleReadGuard<H> = ArcRwLockReadGuard<RawRwLock, H>;
+
+/// Handle locked for writing which may be kept regardless of the app lock.
+pub type HandleWriteGuard<H> = ArcRwLockWriteGuard<RawRwLock, H>;
+
 pub struct App<P: PluginApp> {
-    plugin: P,
-    janus_callbacks: AtomicPtr<JanusCallbacks>,
-    handle_registry: HandleRegistry<P>,
+    // Shared so plugin hooks may be called without the app lock.
+    plugin: Arc<P>,
+    core: &'static Core<P>,
 }
 
 impl<P: PluginApp> App<P> {
-    fn new(plugin: P, janus_callbacks: *mut JanusCallbacks) -> Self {
+    fn new(plugin: P, core: &'static Core<P>) -> Self {
         Self {
-            plugin,
-            janus_callbacks: AtomicPtr::new(janus_callbacks),
-            handle_registry: HandleRegistry::<P>::new(),
+            plugin: Arc::new(plugin),
+            core,
         }
     }
 
@@ -103,43 +114,133 @@ impl<P: PluginApp> App<P> {
     }
 
     fn handle_registry(&self) -> &HandleRegistry<P> {
-        &self.handle_registry
-    }
-
-    fn handle_registry_mut(&mut self) -> &mut HandleRegistry<P> {
-        &mut self.handle_registry
-    }
-
-    fn janus_callbacks(&self) -> *mut JanusCallbacks {
-        self.janus_callbacks.load(Ordering::Relaxed)
+        &self.core.handle_registry
     }
 
     fn build_handle(&self, id: u64) -> P::Handle {
         self.plugin().build_handle(id)
     }
 
-    pub fn handle(&self, id: u64) -> Option<&P::Handle> {
-        self.handle_registry
+    /// Returns a movable reference to the handle by its ID.
+    pub fn handle_ref(&self, id: u64) -> Option<HandleRef<P>> {
+        self.handle_registry()
+            .get_by_id(id)
+            .map(|entry| HandleRef::new(id, &entry))
+    }
+
+    /// Returns the handle by its ID locked for reading.
+    pub fn handle(&self, id: u64) -> Option<HandleReadGuard<P::Handle>> {
+        self.handle_registry()
             .get_by_id(id)
             .map(|entry| entry.plugin_handle())
     }
 
-    pub fn handle_mut(&mut self, id: u64) -> Option<&mut P::Handle> {
-        self.handle_registry
-            .get_by_id_mut(id)
+    /// Returns the handle by its ID locked for writing.
+    ///
+    /// The lock is per handle so it doesn't block events of other handles. Events of this handle
+    /// are waiting until the guard is dropped. The handle's own messages and hooks already take
+    /// it mutably so don't call it from them or from its media events since it would deadlock.
+    pub fn handle_mut(&self, id: u64) -> Option<HandleWriteGuard<P::Handle>> {
+        self.handle_registry()
+            .get_by_id(id)
             .map(|entry| entry.plugin_handle_mut())
     }
 }
 
+/// Janus callbacks, handles of the plugin and their rooms, forwarders, recorders and players.
+/// They're being kept apart from the [App](struct.App.html) so media events and callbacks
+/// don't take the app lock.
+pub struct Core<P: PluginApp> {
+    // Janus never changes callbacks but the plugin may be initialized again, e.g. in tests.
+    janus_callbacks: AtomicPtr<JanusCallbacks>,
+    handle_registry: HandleRegistry<P>,
+    // Entries of the handles are being removed on their destruction so these get empty
+    // on plugin destroy too.
+    rooms: Rooms,
+    forwarders: Forwarders,
+    recorders: Recorders,
+    players: Players,
+}
+
+impl<P: PluginApp> Core<P> {
+    fn new() -> Self {
+        Self {
+            janus_callbacks: AtomicPtr::new(std::ptr::null_mut()),
+            handle_registry: HandleRegistry::<P>::new(),
+            rooms: Rooms::default(),
+            forwarders: Forwarders::default(),
+            recorders: Recorders::default(),
+            players: Players::default(),
+        }
+    }
+
+    fn handle_registry(&self) -> &HandleRegistry<P> {
+        &self.handle_registry
+    }
+
+    fn rooms(&self) -> &Rooms {
+        &self.rooms
+    }
+
+    fn forwarders(&self) -> &Forwarders {
+        &self.forwarders
+    }
+
+    fn recorders(&self) -> &Recorders {
+        &self.recorders
+    }
+
+    fn players(&self) -> &Players {
+        &self.players
+    }
+
+    fn janus_callbacks(&self) -> Result<&'static JanusCallbacks, Error> {
+        // Janus keeps callbacks alive until the plugin is destroyed.
+        unsafe { self.janus_callbacks.load(Ordering::Acquire).as_ref() }
+            .ok_or_else(|| Error::new("Plugin not initialized"))
+    }
+}
+
 pub trait PluginApp: 'static + Send + Sized + Plugin {
     fn janus_plugin() -> *mut JanusPlugin;
     fn app() -> &'static RwLock<Option<App<Self>>>;
+    fn core() -> &'static OnceLock<Core<Self>>;
 }
 
 ///////////////////////////////////////////////////////////////////////////////
 
+/// Builds the table of plugin entry points Janus calls for the targeted plugin API.
+/// Used by [janus_plugin!](../macro.janus_plugin.html).
+pub const fn janus_plugin_table<P: PluginApp>() -> JanusPlugin {
+    JanusPlugin {
+        init: init::<P>,
+        destroy: destroy::<P>,
+        get_api_compatibility,
+        get_version: get_version::<P>,
+        get_version_string: get_version_string::<P>,
+        get_description: get_description::<P>,
+        get_name: get_name::<P>,
+        get_author: get_author::<P>,
+        get_package: get_package::<P>,
+        create_session: create_session::<P>,
+        handle_message: handle_message::<P>,
+        #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+        handle_admin_message: handle_admin_message::<P>,
+        setup_media: setup_media::<P>,
+        incoming_rtp: incoming_rtp::<P>,
+        incoming_rtcp: incoming_rtcp::<P>,
+        incoming_data: incoming_data::<P>,
+        #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+        data_ready: data_ready::<P>,
+        slow_link: slow_link::<P>,
+        hangup_media: hangup_media::<P>,
+        destroy_session: destroy_session::<P>,
+        query_session: query_session::<P>,
+    }
+}
+
 pub extern "C" fn get_api_compatibility() -> c_int {
-    13
+    ffi::JANUS_PLUGIN_API_VERSION
 }
 
 pub extern "C" fn get_version<P: Plugin>() -> c_int {
@@ -170,10 +271,12 @@ pub extern "C" fn init<P: PluginApp>(
     callbacks: *mut JanusCallbacks,
     config_path: *const c_char,
 ) -> c_int {
+    logger::init(P::PACKAGE);
+
     match init_impl::<P>(callbacks, config_path) {
         Ok(()) => 0,
         Err(err) => {
-            janus_log(err.as_str());
+            log::error!("{}", err);
             1
         }
     }
@@ -195,25 +298,65 @@ fn init_impl<P: PluginApp>(
         .to_str()
         .map_err(|err| Error::new(&format!("Failed to cast config path: {}", err)))?;
 
-    let plugin = P::init(&Path::new(config_path))
-        .map_err(|err| Error::new(&format!("Failed to init plugin: {}", err)))?;
+    // Errors of the default loader already name the file.
+    let config = P::load_config(Path::new(config_path))?;
+    let plugin =
+        P::init(config).map_err(|err| Error::new(&format!("Failed to init plugin: {}", err)))?;
+
+    if callbacks.is_null() {
+        return Err(Error::new("Janus callbacks are missing"));
+    }
 
-    *app_ref = Some(App::new(*plugin, unsafe { &mut *callbacks }));
+    let core = P::core().get_or_init(Core::new);
+    core.janus_callbacks.store(callbacks, Ordering::Release);
+    *app_ref = Some(App::new(*plugin, core));
     Ok(())
 }
 
 pub extern "C" fn destroy<P: PluginApp>() {
-    match P::app().write() {
-        Ok(mut app_ref) => *app_ref = None,
-        Err(err) => janus_log(&format!("Failed to acquire app write lock: {}", err)),
+    if let Err(err) = destroy_impl::<P>() {
+        log::error!("{}", err);
     }
 }
 
+fn destroy_impl<P: PluginApp>() -> Result<(), Error> {
+    if let Some(core) = P::core().get() {
+        for entry in core.handle_registry.drain() {
+            destroy_handle(&entry);
+        }
+    }
+
+    {
+        // Hooks may still use callbacks so the app is being shut down under the read lock.
+        let app_ref = P::app()
+            .read()
+            .map_err(|err| Error::new(&format!("Failed to acquire app read lock: {}", err)))?;
+
+        if let Some(app) = &*app_ref {
+            app.plugin().shutdown();
+        }
+    }
+
+    let mut app_ref = P::app()
+        .write()
+        .map_err(|err| Error::new(&format!("Failed to acquire app write lock: {}", err)))?;
+
+    *app_ref = None;
+
+    if let Some(core) = P::core().get() {
+        core.janus_callbacks
+            .store(std::ptr::null_mut(), Ordering::Release);
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn create_session<P: PluginApp>(handle: *mut JanusPluginSession, error: *mut c_int) {
     let return_code = match create_session_impl::<P>(handle) {
         Ok(()) => 0,
         Err(err) => {
-            janus_log(err.as_str());
+            log::error!("{}", err);
             1
         }
     };
@@ -222,41 +365,68 @@ pub extern "C" fn create_session<P: PluginApp>(handle: *mut JanusPluginSession,
 }
 
 fn create_session_impl<P: PluginApp>(raw_handle: *mut JanusPluginSession) -> Result<(), Error> {
-    let mut app_ref = P::app()
-        .write()
-        .map_err(|err| Error::new(&format!("Failed to acquire app write lock: {}", err)))?;
-
-    match &mut *app_ref {
-        None => Err(Error::new("Plugin not initialized")),
-        Some(app) => {
-            let handle_id = HandleRegistry::<P>::fetch_id(raw_handle);
-            let plugin_handle = app.build_handle(handle_id);
-            let handle_registry = app.handle_registry_mut();
-
-            match handle_registry.get_by_raw_handle(raw_handle) {
-                Some(_) => Err(Error::new("Handle already registered")),
-                None => handle_registry
-                    .add(raw_handle, plugin_handle)
-                    .map(|_| ())
-                    .map_err(|err| Error::new(&format!("Failed to register handle: {}", err))),
-            }
-        }
-    }
+    let handle_id = HandleRegistry::<P>::fetch_id(raw_handle);
+    let _scope = logger::handle_scope(handle_id);
+
+    let mut plugin_handle = with_app::<P, _>(|app| {
+        app.handle_registry()
+            .add(raw_handle, app.build_handle(handle_id))
+            .map_err(|err| Error::new(&format!("Failed to register handle: {}", err)))
+    })?;
+
+    // The hook may use the app so it's being called without the app lock. The handle is still
+    // locked for writing so no event is being dispatched to it before the hook.
+    plugin_handle.on_created();
+    Ok(())
 }
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn handle_message<P: PluginApp>(
     raw_handle: *mut JanusPluginSession,
     transaction: *mut c_char,
     payload: *mut json_t,
     jsep: *mut json_t,
 ) -> *mut JanusPluginResult {
-    let mut plugin_result = match handle_message_impl::<P>(raw_handle, transaction, payload, jsep) {
+    let result = handle_message_impl::<P>(raw_handle, transaction, payload, jsep);
+
+    // Janus passes the ownership over the message and JSEP objects to the plugin.
+    unsafe {
+        json_decref(payload);
+        json_decref(jsep);
+    }
+
+    let plugin_result = match result {
         Ok(res) => res,
         Err(err) => {
-            janus_log(err.as_str());
+            log_message_error(&err);
+            error_result(&err)
+        }
+    };
+
+    ffi::new_plugin_result(plugin_result)
+}
+
+/// Client errors are expected so they're not being logged as errors.
+fn log_message_error(err: &Error) {
+    match err.kind() {
+        ErrorKind::Internal => log::error!("Error handling message: {}", err),
+        _ => log::warn!("Error handling message: {}", err),
+    }
+}
+
+/// Builds a response with `error_code` and `error` fields like the stock Janus plugins do.
+fn error_result(err: &Error) -> JanusPluginResult {
+    match serialize(err) {
+        Ok(content) => JanusPluginResult {
+            type_: JanusPluginResultType::JANUS_PLUGIN_OK,
+            text: std::ptr::null_mut(),
+            content,
+        },
+        Err(serialization_err) => {
+            log::error!("Failed to serialize error: {}", serialization_err);
 
             let text = CString::new(err.as_str()).unwrap_or_else(|ref err| {
-                janus_log(&format!("Failed to cast error message text: {}", err));
+                log::error!("Failed to cast error message text: {}", err);
                 CString::new("").expect("Failed to cast text")
             });
 
@@ -266,9 +436,7 @@ pub extern "C" fn handle_message<P: PluginApp>(
                 content: std::ptr::null_mut(),
             }
         }
-    };
-
-    &mut plugin_result
+    }
 }
 
 fn handle_message_impl<P: PluginApp>(
@@ -277,60 +445,183 @@ fn handle_message_impl<P: PluginApp>(
     payload: *mut json_t,
     jsep: *mut json_t,
 ) -> Result<JanusPluginResult, Error> {
+    let entry = handle_entry::<P>(raw_handle)?;
+    let mut plugin_handle = lock_handle_mut(&entry)?;
+
+    let _scope = logger::handle_scope(plugin_handle.id());
+
+    let transaction_str = unsafe { CString::from_raw(transaction) }
+        .to_str()
+        .map(String::from)
+        .map_err(|err| Error::new(&format!("Failed to cast transaction: {}", err)))?;
+
+    let message = IncomingMessage::new(transaction_str, deserialize(payload)?);
+
+    let message = match unsafe { jsep.as_mut() } {
+        Some(jsep_ref) => message.set_jsep(deserialize::<Jsep>(jsep_ref)?),
+        None => message,
+    };
+
+    let handle_ref = HandleRef::new(plugin_handle.id(), &entry);
+    let sink = Box::new(HandleSink { handle_ref });
+    let responder = Responder::new(plugin_handle.id(), message.transaction().to_owned(), sink);
+    let pending_responder = responder.share();
+    entry.add_pending_responder(responder.share());
+
+    let response = match plugin_handle.handle_message(message, responder) {
+        Ok(response) => response,
+        Err(err) => {
+            pending_responder.finish();
+            return Err(err);
+        }
+    };
+
+    match response {
+        MessageResponse::Ack => {
+            pending_responder.expire_in(response_timeout::<P>()?);
+            Ok(ack_result())
+        }
+        MessageResponse::Deferred(future) => {
+            pending_responder
+                .share()
+                .expire_in(response_timeout::<P>()?);
+            spawn_deferred_response::<P>(pending_responder, future)?;
+            Ok(ack_result())
+        }
+        MessageResponse::Syncronous(ref response_payload) => {
+            pending_responder.finish();
+
+            serialize(response_payload)
+                .map(|content| JanusPluginResult {
+                    type_: JanusPluginResultType::JANUS_PLUGIN_OK,
+                    text: CString::new("").expect("Failed to cast text").into_raw(),
+                    content,
+                })
+                .map_err(|err| {
+                    Error::new(&format!("Failed to serialize response payload: {}", err))
+                })
+        }
+    }
+}
+
+fn ack_result() -> JanusPluginResult {
+    JanusPluginResult {
+        type_: JanusPluginResultType::JANUS_PLUGIN_OK_WAIT,
+        text: CString::new("").expect("Failed to cast text").into_raw(),
+        content: std::ptr::null_mut(),
+    }
+}
+
+fn response_timeout<P: PluginApp>() -> Result<Duration, Error> {
     let app_ref = P::app()
         .read()
         .map_err(|err| Error::new(&format!("Failed to acquire app read lock: {}", err)))?;
 
     match &*app_ref {
         None => Err(Error::new("Plugin not initialized")),
-        Some(app) => {
-            let plugin_handle = app
-                .handle_registry()
-                .get_by_raw_handle(raw_handle)
-                .ok_or_else(|| Error::new("Handle not found"))?
-                .plugin_handle();
-
-            let transaction_str = unsafe { CString::from_raw(transaction) }
-                .to_str()
-                .map(|s| String::from(s))
-                .map_err(|err| Error::new(&format!("Failed to cast transaction: {}", err)))?;
-
-            let message = IncomingMessage::new(transaction_str, deserialize(payload)?);
-
-            let message = match unsafe { jsep.as_mut() } {
-                Some(jsep_ref) => message.set_jsep(deserialize::<Jsep>(jsep_ref)?),
-                None => message,
-            };
+        Some(app) => Ok(app.plugin().response_timeout()),
+    }
+}
 
-            match plugin_handle.handle_message(message) {
-                Err(err) => Err(Error::new(&format!("Error handlung message: {}", err))),
-                Ok(MessageResponse::Ack) => Ok(JanusPluginResult {
-                    type_: JanusPluginResultType::JANUS_PLUGIN_OK_WAIT,
-                    text: CString::new("").expect("Failed to cast text").into_raw(),
-                    content: std::ptr::null_mut(),
-                }),
-                Ok(MessageResponse::Syncronous(ref response_payload)) => {
-                    serialize(response_payload)
-                        .map(|content| JanusPluginResult {
-                            type_: JanusPluginResultType::JANUS_PLUGIN_OK,
-                            text: CString::new("").expect("Failed to cast text").into_raw(),
-                            content,
-                        })
-                        .map_err(|err| {
-                            Error::new(&format!("Failed to serialize response payload: {}", err))
-                        })
-                }
+/// Drives the future on the plugin's executor and replies with its result.
+fn spawn_deferred_response<P: PluginApp>(
+    responder: Responder<<P::Handle as Handle>::OutgoingMessagePayload>,
+    mut future: BoxFuture<Result<<P::Handle as Handle>::OutgoingMessagePayload, Error>>,
+) -> Result<(), Error> {
+    let app_ref = P::app()
+        .read()
+        .map_err(|err| Error::new(&format!("Failed to acquire app read lock: {}", err)))?;
+
+    let app = app_ref
+        .as_ref()
+        .ok_or_else(|| Error::new("Plugin not initialized"))?;
+
+    let handle_id = responder.handle_id();
+
+    // The handle scope is thread-local so enter it on each poll since the executor may
+    // poll the future on different threads.
+    let scoped_future = future::poll_fn(move |cx| {
+        let _scope = logger::handle_scope(handle_id);
+        future.as_mut().poll(cx)
+    });
+
+    app.plugin().executor().spawn(Box::pin(async move {
+        let result = scoped_future.await;
+        let _scope = logger::handle_scope(handle_id);
+
+        let push_result = match result {
+            Ok(payload) => responder.reply(payload),
+            Err(err) => {
+                log_message_error(&err);
+                responder.reply_error(err)
             }
+        };
+
+        if let Err(err) = push_result {
+            log::error!("Failed to push deferred response: {}", err);
+        }
+    }));
+
+    Ok(())
+}
+
+/// Pushes replies of a [Responder](../struct.Responder.html) to the handle.
+struct HandleSink<P: PluginApp> {
+    handle_ref: HandleRef<P>,
+}
+
+impl<P: PluginApp> Sink<<P::Handle as Handle>::OutgoingMessagePayload> for HandleSink<P> {
+    fn push_response(
+        &self,
+        transaction: &str,
+        payload: &<P::Handle as Handle>::OutgoingMessagePayload,
+        jsep: Option<&Jsep>,
+    ) -> Result<(), Error> {
+        push_json_event::<P, _>(self.handle_ref.id(), transaction, payload, jsep)
+    }
+
+    fn push_error(&self, transaction: &str, err: &Error) -> Result<(), Error> {
+        push_json_event::<P, _>(self.handle_ref.id(), transaction, err, None)
+    }
+}
+
+/// Janus keeps the ownership over the message and takes the response.
+pub extern "C" fn handle_admin_message<P: PluginApp>(message: *mut json_t) -> *mut json_t {
+    let result = handle_admin_message_impl::<P>(message).and_then(|ref response| {
+        serialize(response)
+            .map_err(|err| Error::new(&format!("Failed to serialize admin response: {}", err)))
+    });
+
+    match result {
+        Ok(json) => json,
+        Err(err) => {
+            log_message_error(&err);
+
+            serialize(&err).unwrap_or_else(|serialization_err| {
+                log::error!("Failed to serialize error: {}", serialization_err);
+                std::ptr::null_mut()
+            })
         }
     }
 }
 
+fn handle_admin_message_impl<P: PluginApp>(
+    message: *mut json_t,
+) -> Result<P::OutgoingAdminMessagePayload, Error> {
+    let payload = deserialize::<P::IncomingAdminMessagePayload>(message)?;
+
+    // The handler may use the app so it's being called without the app lock.
+    let plugin = with_app::<P, _>(|app| Ok(app.plugin.clone()))?;
+    plugin.handle_admin_message(payload)
+}
+
 pub extern "C" fn setup_media<P: PluginApp>(raw_handle: *mut JanusPluginSession) {
-    if let Err(err) = dispatch_media_event::<P>(raw_handle, &MediaEvent::Setup) {
-        janus_log(err.as_str());
+    if let Err(err) = dispatch::<P, _>(raw_handle, |plugin_handle| plugin_handle.on_media_setup()) {
+        log::error!("{}", err);
     }
 }
 
+#[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
 pub extern "C" fn incoming_rtp<P: PluginApp>(
     raw_handle: *mut JanusPluginSession,
     is_video: c_int,
@@ -341,13 +632,35 @@ pub extern "C" fn incoming_rtp<P: PluginApp>(
         protocol: MediaProtocol::Rtp,
         kind: media_kind(is_video),
         buffer: unsafe { std::slice::from_raw_parts(buffer as *const i8, len as usize) },
+        info: Default::default(),
+    };
+
+    if let Err(err) = dispatch_media_event::<P>(raw_handle, &media_event) {
+        log::error!("{}", err);
+    }
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn incoming_rtp<P: PluginApp>(
+    raw_handle: *mut JanusPluginSession,
+    packet: *mut ffi::janus_plugin_rtp,
+) {
+    let packet = unsafe { &*packet };
+
+    let media_event = MediaEvent::Media {
+        protocol: MediaProtocol::Rtp,
+        kind: media_kind(packet.video),
+        buffer: unsafe { packet_buffer(packet.buffer, packet.length) },
+        info: packet.info(),
     };
 
     if let Err(err) = dispatch_media_event::<P>(raw_handle, &media_event) {
-        janus_log(err.as_str());
+        log::error!("{}", err);
     }
 }
 
+#[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
 pub extern "C" fn incoming_rtcp<P: PluginApp>(
     raw_handle: *mut JanusPluginSession,
     is_video: c_int,
@@ -358,13 +671,35 @@ pub extern "C" fn incoming_rtcp<P: PluginApp>(
         protocol: MediaProtocol::Rtcp,
         kind: media_kind(is_video),
         buffer: unsafe { std::slice::from_raw_parts(buffer as *const i8, len as usize) },
+        info: Default::default(),
     };
 
     if let Err(err) = dispatch_media_event::<P>(raw_handle, &media_event) {
-        janus_log(err.as_str());
+        log::error!("{}", err);
     }
 }
 
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn incoming_rtcp<P: PluginApp>(
+    raw_handle: *mut JanusPluginSession,
+    packet: *mut ffi::janus_plugin_rtcp,
+) {
+    let packet = unsafe { &*packet };
+
+    let media_event = MediaEvent::Media {
+        protocol: MediaProtocol::Rtcp,
+        kind: media_kind(packet.video),
+        buffer: unsafe { packet_buffer(packet.buffer, packet.length) },
+        info: packet.info(),
+    };
+
+    if let Err(err) = dispatch_media_event::<P>(raw_handle, &media_event) {
+        log::error!("{}", err);
+    }
+}
+
+#[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
 pub extern "C" fn incoming_data<P: PluginApp>(
     raw_handle: *mut JanusPluginSession,
     buffer: *mut c_char,
@@ -372,20 +707,56 @@ pub extern "C" fn incoming_data<P: PluginApp>(
 ) {
     let media_event = MediaEvent::Data {
         buffer: unsafe { std::slice::from_raw_parts(buffer as *const i8, len as usize) },
+        info: Default::default(),
     };
 
     if let Err(err) = dispatch_media_event::<P>(raw_handle, &media_event) {
-        janus_log(err.as_str());
+        log::error!("{}", err);
     }
 }
 
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn incoming_data<P: PluginApp>(
+    raw_handle: *mut JanusPluginSession,
+    packet: *mut ffi::janus_plugin_data,
+) {
+    let packet = unsafe { &*packet };
+
+    let media_event = MediaEvent::Data {
+        buffer: unsafe { packet_buffer(packet.buffer, packet.length) },
+        info: packet.info(),
+    };
+
+    if let Err(err) = dispatch_media_event::<P>(raw_handle, &media_event) {
+        log::error!("{}", err);
+    }
+}
+
+/// Janus tells that the data channel is ready to send, nothing to do about it yet.
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+pub extern "C" fn data_ready<P: PluginApp>(_raw_handle: *mut JanusPluginSession) {}
+
+#[cfg(not(feature = "janus-1"))]
 pub extern "C" fn slow_link<P: PluginApp>(
     raw_handle: *mut JanusPluginSession,
     uplink: c_int,
     is_video: c_int,
 ) {
     if let Err(err) = slow_link_impl::<P>(raw_handle, uplink, is_video) {
-        janus_log(err.as_str());
+        log::error!("{}", err);
+    }
+}
+
+#[cfg(feature = "janus-1")]
+pub extern "C" fn slow_link<P: PluginApp>(
+    raw_handle: *mut JanusPluginSession,
+    _mindex: c_int,
+    is_video: c_int,
+    uplink: c_int,
+) {
+    if let Err(err) = slow_link_impl::<P>(raw_handle, uplink, is_video) {
+        log::error!("{}", err);
     }
 }
 
@@ -407,11 +778,12 @@ fn slow_link_impl<P: PluginApp>(
 }
 
 pub extern "C" fn hangup_media<P: PluginApp>(raw_handle: *mut JanusPluginSession) {
-    if let Err(err) = dispatch_media_event::<P>(raw_handle, &MediaEvent::Hangup) {
-        janus_log(err.as_str());
+    if let Err(err) = dispatch::<P, _>(raw_handle, |plugin_handle| plugin_handle.on_hangup()) {
+        log::error!("{}", err);
     }
 }
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn destroy_session<P: PluginApp>(
     raw_handle: *mut JanusPluginSession,
     error: *mut c_int,
@@ -419,7 +791,7 @@ pub extern "C" fn destroy_session<P: PluginApp>(
     let return_code = match destroy_session_impl::<P>(raw_handle) {
         Ok(()) => 0,
         Err(err) => {
-            janus_log(err.as_str());
+            log::error!("{}", err);
             1
         }
     };
@@ -428,13 +800,34 @@ pub extern "C" fn destroy_session<P: PluginApp>(
 }
 
 fn destroy_session_impl<P: PluginApp>(raw_handle: *mut JanusPluginSession) -> Result<(), Error> {
-    let mut app_ref = P::app()
-        .write()
-        .map_err(|err| Error::new(&format!("Failed to acquire app write lock: {}", err)))?;
+    if let Some(entry) = core::<P>()?.handle_registry.remove(raw_handle) {
+        destroy_handle(&entry);
+    }
 
-    match &mut *app_ref {
-        None => Err(Error::new("Plugin not initialized")),
-        Some(app) => app.handle_registry_mut().remove(raw_handle),
+    Ok(())
+}
+
+/// Waits for the events being handled at the moment and calls the hook. The entry must be
+/// already marked as destroyed so no more events are being dispatched.
+/// The hook is being called without the app lock since it may use the app.
+/// The handle leaves its rooms after the hook so it may still notify the other members.
+fn destroy_handle<P: PluginApp>(entry: &handle_registry::Entry<P>) {
+    let mut plugin_handle = entry.plugin_handle_mut();
+    let handle_id = plugin_handle.id();
+    let _scope = logger::handle_scope(handle_id);
+    plugin_handle.on_destroy();
+
+    // Players are being waited for and they may be locking the handle to complete.
+    drop(plugin_handle);
+
+    match core::<P>() {
+        Ok(core) => {
+            core.rooms().remove_handle(handle_id);
+            core.forwarders().remove_handle(handle_id);
+            core.recorders().remove_handle(handle_id);
+            core.players().remove_handle(handle_id);
+        }
+        Err(err) => log::error!("Failed to clean up handle {}: {}", handle_id, err),
     }
 }
 
@@ -442,7 +835,7 @@ pub extern "C" fn query_session<P: PluginApp>(raw_handle: *mut JanusPluginSessio
     match query_session_impl::<P>(raw_handle) {
         Ok(json) => json,
         Err(err) => {
-            janus_log(err.as_str());
+            log::error!("{}", err);
             std::ptr::null_mut()
         }
     }
@@ -451,29 +844,31 @@ pub extern "C" fn query_session<P: PluginApp>(raw_handle: *mut JanusPluginSessio
 fn query_session_impl<P: PluginApp>(
     raw_handle: *mut JanusPluginSession,
 ) -> Result<*mut json_t, Error> {
-    let app_ref = P::app()
-        .read()
-        .map_err(|err| Error::new(&format!("Failed to acquire app read lock: {}", err)))?;
-
-    match &*app_ref {
-        None => Err(Error::new("Plugin not initialized")),
-        Some(app) => {
-            let plugin_handle = app
-                .handle_registry()
-                .get_by_raw_handle(raw_handle)
-                .ok_or_else(|| Error::new("Handle not found"))?
-                .plugin_handle();
-
-            serialize(plugin_handle)
-        }
-    }
+    let entry = handle_entry::<P>(raw_handle)?;
+    serialize(&*lock_handle(&entry)?)
 }
 
 ///////////////////////////////////////////////////////////////////////////////
 
+/// RTCP feedback message to request a keyframe with.
+#[derive(Clone, Copy, Debug)]
+pub enum KeyframeRequest {
+    /// Picture Loss Indication.
+    Pli,
+    /// Full Intra Request.
+    Fir,
+}
+
+/// FIR command sequence number which must be increased with each new request.
+static FIR_SEQUENCE_NUMBER: AtomicU8 = AtomicU8::new(0);
+
 /// This trait contains methods to interact with Janus core.
 /// It's being automatically implemented for any type that is a plugin [Handle](trait.Handle.html).
 pub trait Callbacks<P: PluginApp>: Handle {
+    /// Returns a `Send + 'static` reference to the current handle to use in other threads
+    /// or futures.
+    fn handle_ref(&self) -> Result<HandleRef<P>, Error>;
+
     /// Sends a binary media `buffer` of `kind` type to the current handle by `protocol`.
     fn relay_media_packet(
         &self,
@@ -482,8 +877,15 @@ pub trait Callbacks<P: PluginApp>: Handle {
         buffer: &[i8],
     ) -> Result<(), Error>;
 
-    /// Sends a binary `buffer` to the current handle via data channel.
-    fn relay_data_packet(&self, buffer: &[i8]) -> Result<(), Error>;
+    /// Sends a `buffer` to the current handle via data channel.
+    /// Label, subprotocol and binary flag of `info` are passed to Janus 0.10+ only.
+    fn relay_data_packet(&self, buffer: &[i8], info: DataInfo) -> Result<(), Error>;
+
+    /// Asks the sender of the handle's video for a keyframe with PLI or FIR RTCP feedback.
+    fn request_keyframe(&self, request: KeyframeRequest) -> Result<(), Error>;
+
+    /// Sends REMB RTCP feedback to limit the bitrate of the handle's sender, in bits per second.
+    fn send_remb(&self, bitrate: u64) -> Result<(), Error>;
 
     /// Tells Janus to close the PeerConnection for the current handle.
     fn close_peer_connection(&self) -> Result<(), Error>;
@@ -496,6 +898,8 @@ pub trait Callbacks<P: PluginApp>: Handle {
 
     /// Sends an event message to the current handle.
     /// This may be used for unicast notifications as well as for asynchronous responses.
+    /// A response finishes the message's [Responder](../struct.Responder.html) so the client
+    /// doesn't get a timeout error after it.
     fn push_event(
         &self,
         message: &OutgoingMessage<Self::OutgoingMessagePayload>,
@@ -503,60 +907,70 @@ pub trait Callbacks<P: PluginApp>: Handle {
 }
 
 impl<P: PluginApp> Callbacks<P> for P::Handle {
+    fn handle_ref(&self) -> Result<HandleRef<P>, Error> {
+        let entry = core::<P>()?
+            .handle_registry
+            .get_by_id(self.id())
+            .ok_or_else(|| Error::not_found(&format!("Handle {} not found", self.id())))?;
+
+        Ok(HandleRef::new(self.id(), &entry))
+    }
+
     fn relay_media_packet(
         &self,
         protocol: MediaProtocol,
         kind: MediaKind,
         buffer: &[i8],
     ) -> Result<(), Error> {
-        let callbacks = janus_callbacks::<P>()?;
-
-        let janus_callback = match protocol {
-            MediaProtocol::Rtp => callbacks.relay_rtp,
-            MediaProtocol::Rtcp => callbacks.relay_rtcp,
-        };
+        let (callbacks, raw_handle) = janus_context::<P>(self.id())?;
+        relay_media(callbacks, raw_handle, protocol, kind, buffer)
+    }
 
-        let raw_handle = raw_handle::<P>(self.id())?;
+    fn relay_data_packet(&self, buffer: &[i8], info: DataInfo) -> Result<(), Error> {
+        let (callbacks, raw_handle) = janus_context::<P>(self.id())?;
+        relay_data(callbacks, raw_handle, buffer, info)
+    }
 
-        let is_video = match kind {
-            MediaKind::Video => 1,
-            MediaKind::Audio => 0,
-        };
+    fn request_keyframe(&self, request: KeyframeRequest) -> Result<(), Error> {
+        let (callbacks, raw_handle) = janus_context::<P>(self.id())?;
+        send_keyframe_request(callbacks, raw_handle, request)
+    }
 
-        janus_callback(
-            raw_handle,
-            is_video,
-            buffer.as_ptr() as *mut i8,
-            buffer.len() as i32,
-        );
+    #[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
+    fn send_remb(&self, bitrate: u64) -> Result<(), Error> {
+        let buffer = rtcp::serialize(&[RtcpPacket::Remb(rtcp::Remb {
+            sender_ssrc: 0,
+            bitrate,
+            ssrcs: vec![0],
+        })]);
 
-        Ok(())
+        Callbacks::<P>::relay_media_packet(self, MediaProtocol::Rtcp, MediaKind::Video, &buffer)
     }
 
-    fn relay_data_packet(&self, buffer: &[i8]) -> Result<(), Error> {
-        let janus_callback = janus_callbacks::<P>()?.relay_data;
-        let raw_handle = raw_handle::<P>(self.id())?;
-        janus_callback(raw_handle, buffer.as_ptr() as *mut i8, buffer.len() as i32);
+    #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+    fn send_remb(&self, bitrate: u64) -> Result<(), Error> {
+        let (callbacks, raw_handle) = janus_context::<P>(self.id())?;
+        (callbacks.send_remb)(raw_handle, bitrate.min(u32::MAX as u64) as u32);
         Ok(())
     }
 
     fn close_peer_connection(&self) -> Result<(), Error> {
-        let janus_callback = janus_callbacks::<P>()?.close_pc;
-        let raw_handle = raw_handle::<P>(self.id())?;
+        let (callbacks, raw_handle) = janus_context::<P>(self.id())?;
+        let janus_callback = callbacks.close_pc;
         janus_callback(raw_handle);
         Ok(())
     }
 
     fn end_handle(&self) -> Result<(), Error> {
-        let janus_callback = janus_callbacks::<P>()?.end_session;
-        let raw_handle = raw_handle::<P>(self.id())?;
+        let (callbacks, raw_handle) = janus_context::<P>(self.id())?;
+        let janus_callback = callbacks.end_session;
         janus_callback(raw_handle);
         Ok(())
     }
 
     fn notify_event<E: Serialize>(&self, event: &E) -> Result<(), Error> {
-        let janus_callback = janus_callbacks::<P>()?.notify_event;
-        let raw_handle = raw_handle::<P>(self.id())?;
+        let (callbacks, raw_handle) = janus_context::<P>(self.id())?;
+        let janus_callback = callbacks.notify_event;
 
         let event_json =
             serialize(event).map_err(|err| Error::new(&format!("Failed to serialize: {}", err)))?;
@@ -569,46 +983,237 @@ impl<P: PluginApp> Callbacks<P> for P::Handle {
         &self,
         message: &OutgoingMessage<Self::OutgoingMessagePayload>,
     ) -> Result<(), Error> {
-        let janus_callback = janus_callbacks::<P>()?.push_event;
-        let raw_handle = raw_handle::<P>(self.id())?;
+        // Finish first so the timeout can't be pushed after the response.
+        if let Some(entry) = core::<P>()?.handle_registry().get_by_id(self.id()) {
+            entry.finish_pending_responder(message.transaction());
+        }
 
-        let txn = CString::new(message.transaction().to_owned())
-            .map_err(|err| Error::new(&format!("Failed to cast transaction: {}", err)))?;
+        push_json_event::<P, _>(
+            self.id(),
+            message.transaction(),
+            message.payload(),
+            message.jsep(),
+        )
+    }
+}
 
-        let payload = serialize(message.payload())
-            .map_err(|err| Error::new(&format!("Failed to serialize payload: {}", err)))?;
+/// Pushes an arbitrary serializable payload, e.g. an error, as an event to the handle.
+fn push_json_event<P: PluginApp, S: Serialize + ?Sized>(
+    id: u64,
+    transaction: &str,
+    payload: &S,
+    jsep: Option<&Jsep>,
+) -> Result<(), Error> {
+    let (callbacks, raw_handle) = janus_context::<P>(id)?;
+    let janus_callback = callbacks.push_event;
 
-        let jsep_ptr = match message.jsep() {
-            None => std::ptr::null_mut(),
-            Some(jsep) => serialize::<Jsep>(jsep)
-                .map_err(|err| Error::new(&format!("Failed to serialize JSEP: {}", err)))?,
-        };
+    let txn = CString::new(transaction)
+        .map_err(|err| Error::new(&format!("Failed to cast transaction: {}", err)))?;
+
+    let payload = serialize(payload)
+        .map_err(|err| Error::new(&format!("Failed to serialize payload: {}", err)))?;
+
+    let jsep_ptr = match jsep {
+        None => std::ptr::null_mut(),
+        Some(jsep) => serialize::<Jsep>(jsep)
+            .map_err(|err| Error::new(&format!("Failed to serialize JSEP: {}", err)))?,
+    };
+
+    // Janus copies the transaction and takes its own references to the JSON objects
+    // so they're only being kept alive for the call.
+    let return_code = janus_callback(
+        raw_handle,
+        P::janus_plugin(),
+        txn.as_ptr(),
+        payload,
+        jsep_ptr,
+    );
+
+    unsafe {
+        json_decref(payload);
+        json_decref(jsep_ptr);
+    }
+
+    match return_code {
+        0 => Ok(()),
+        _ => Err(Error::new("Failed to push event")),
+    }
+}
+
+fn send_keyframe_request(
+    callbacks: &JanusCallbacks,
+    raw_handle: *mut JanusPluginSession,
+    request: KeyframeRequest,
+) -> Result<(), Error> {
+    // Newer Janus sends PLIs itself with proper SSRCs and throttling.
+    #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+    {
+        if let KeyframeRequest::Pli = request {
+            (callbacks.send_pli)(raw_handle);
+            return Ok(());
+        }
+    }
+
+    // SSRCs are being fixed by Janus core on relaying RTCP.
+    let packet = match request {
+        KeyframeRequest::Pli => RtcpPacket::Pli(Pli {
+            sender_ssrc: 0,
+            media_ssrc: 0,
+        }),
+        KeyframeRequest::Fir => RtcpPacket::Fir(Fir {
+            sender_ssrc: 0,
+            entries: vec![FirEntry {
+                ssrc: 0,
+                sequence_number: FIR_SEQUENCE_NUMBER.fetch_add(1, Ordering::Relaxed),
+            }],
+        }),
+    };
+
+    let buffer = rtcp::serialize(&[packet]);
+    relay_media(
+        callbacks,
+        raw_handle,
+        MediaProtocol::Rtcp,
+        MediaKind::Video,
+        &buffer,
+    )
+}
+
+#[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
+fn relay_media(
+    callbacks: &JanusCallbacks,
+    raw_handle: *mut JanusPluginSession,
+    protocol: MediaProtocol,
+    kind: MediaKind,
+    buffer: &[i8],
+) -> Result<(), Error> {
+    let janus_callback = match protocol {
+        MediaProtocol::Rtp => callbacks.relay_rtp,
+        MediaProtocol::Rtcp => callbacks.relay_rtcp,
+    };
+
+    janus_callback(
+        raw_handle,
+        is_video(kind),
+        buffer.as_ptr() as *mut i8,
+        buffer.len() as i32,
+    );
+
+    Ok(())
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+fn relay_media(
+    callbacks: &JanusCallbacks,
+    raw_handle: *mut JanusPluginSession,
+    protocol: MediaProtocol,
+    kind: MediaKind,
+    buffer: &[i8],
+) -> Result<(), Error> {
+    let length = packet_length(buffer)?;
+
+    // Janus 1.x picks the first stream of the kind for m-line index -1.
+    match protocol {
+        MediaProtocol::Rtp => {
+            let mut packet = ffi::janus_plugin_rtp {
+                #[cfg(feature = "janus-1")]
+                mindex: -1,
+                video: is_video(kind),
+                buffer: buffer.as_ptr() as *mut c_char,
+                length,
+                extensions: Default::default(),
+            };
+
+            (callbacks.relay_rtp)(raw_handle, &mut packet);
+        }
+        MediaProtocol::Rtcp => {
+            let mut packet = ffi::janus_plugin_rtcp {
+                #[cfg(feature = "janus-1")]
+                mindex: -1,
+                video: is_video(kind),
+                buffer: buffer.as_ptr() as *mut c_char,
+                length,
+            };
 
-        let return_code = janus_callback(
-            raw_handle,
-            P::janus_plugin(),
-            txn.into_raw(),
-            payload,
-            jsep_ptr,
-        );
-
-        match return_code {
-            0 => Ok(()),
-            _ => Err(Error::new("Failed to push event")),
+            (callbacks.relay_rtcp)(raw_handle, &mut packet);
         }
     }
+
+    Ok(())
+}
+
+#[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
+fn relay_data(
+    callbacks: &JanusCallbacks,
+    raw_handle: *mut JanusPluginSession,
+    buffer: &[i8],
+    _info: DataInfo,
+) -> Result<(), Error> {
+    let janus_callback = callbacks.relay_data;
+    janus_callback(raw_handle, buffer.as_ptr() as *mut i8, buffer.len() as i32);
+    Ok(())
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+fn relay_data(
+    callbacks: &JanusCallbacks,
+    raw_handle: *mut JanusPluginSession,
+    buffer: &[i8],
+    info: DataInfo,
+) -> Result<(), Error> {
+    // Janus copies the strings so they only have to outlive the call.
+    let label = optional_c_string("label", info.label)?;
+    let protocol = optional_c_string("protocol", info.protocol)?;
+
+    // Janus uses the default channel for null label and protocol.
+    let mut packet = ffi::janus_plugin_data {
+        label: label
+            .as_ref()
+            .map_or(std::ptr::null(), |label| label.as_ptr()) as *mut c_char,
+        protocol: protocol
+            .as_ref()
+            .map_or(std::ptr::null(), |protocol| protocol.as_ptr())
+            as *mut c_char,
+        binary: info.binary as c_int,
+        buffer: buffer.as_ptr() as *mut c_char,
+        length: packet_length(buffer)?,
+    };
+
+    (callbacks.relay_data)(raw_handle, &mut packet);
+    Ok(())
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+fn optional_c_string(name: &str, value: Option<&str>) -> Result<Option<CString>, Error> {
+    value
+        .map(CString::new)
+        .transpose()
+        .map_err(|err| Error::new(&format!("Failed to cast data channel {}: {}", name, err)))
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+pub(crate) fn packet_length(buffer: &[i8]) -> Result<u16, Error> {
+    buffer
+        .len()
+        .try_into()
+        .map_err(|_| Error::new(&format!("Packet of {} bytes is too large", buffer.len())))
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+unsafe fn packet_buffer<'a>(buffer: *mut c_char, length: u16) -> &'a [i8] {
+    std::slice::from_raw_parts(buffer as *const i8, length as usize)
 }
 
 ///////////////////////////////////////////////////////////////////////////////
 
-fn janus_log(message: &str) {
-    // TODO: Add better logging with levels and colors.
-    let message_nl = format!("{}\n", message);
-    let c_message = CString::new(message_nl.as_str()).expect("Failed to cast error message");
-    unsafe { janus_plugin_sys::janus_vprintf(c_message.as_ptr()) };
+pub(crate) fn is_video(kind: MediaKind) -> c_int {
+    match kind {
+        MediaKind::Video => 1,
+        MediaKind::Audio => 0,
+    }
 }
 
-fn media_kind(is_video: c_int) -> MediaKind {
+pub(crate) fn media_kind(is_video: c_int) -> MediaKind {
     match is_video {
         0 => MediaKind::Audio,
         _ => MediaKind::Video,
@@ -619,83 +1224,116 @@ fn dispatch_media_event<P: PluginApp>(
     raw_handle: *mut JanusPluginSession,
     media_event: &MediaEvent,
 ) -> Result<(), Error> {
-    let app_ref = P::app()
-        .read()
-        .map_err(|err| Error::new(&format!("Failed to acquire app read lock: {}", err)))?;
-
-    match &*app_ref {
-        None => Err(Error::new("Plugin not initialized")),
-        Some(app) => match app.handle_registry().get_by_raw_handle(raw_handle) {
-            None => Err(Error::new("Handle not found")),
-            Some(entry) => {
-                let plugin_handle = entry.plugin_handle();
-                plugin_handle.handle_media_event(media_event);
-                Ok(())
-            }
-        },
-    }
+    let entry = handle_entry::<P>(raw_handle)?;
+    let plugin_handle = lock_handle(&entry)?;
+    let _scope = logger::handle_scope(plugin_handle.id());
+    plugin_handle.handle_media_event(media_event);
+    Ok(())
 }
 
-fn raw_handle<P: PluginApp>(id: u64) -> Result<*mut JanusPluginSession, Error> {
-    let mut app_ref = P::app()
-        .write()
-        .map_err(|err| Error::new(&format!("Failed to acquire app write lock: {}", err)))?;
+/// Dispatches a hook to the handle locked for writing.
+fn dispatch<P, F>(raw_handle: *mut JanusPluginSession, f: F) -> Result<(), Error>
+where
+    P: PluginApp,
+    F: FnOnce(&mut P::Handle),
+{
+    let entry = handle_entry::<P>(raw_handle)?;
+    let mut plugin_handle = lock_handle_mut(&entry)?;
+    let _scope = logger::handle_scope(plugin_handle.id());
+    f(&mut plugin_handle);
+    Ok(())
+}
 
-    match &mut *app_ref {
-        None => Err(Error::new("Plugin not initialized")),
-        Some(app) => Ok(app
-            .handle_registry_mut()
-            .get_by_id_mut(id)
-            .ok_or_else(|| Error::new(&format!("Handle {} not found", id)))?
-            .raw_handle_mut()),
+/// Locks the handle for dispatching a media event. The entry may have been destroyed after
+/// the lookup so it's being checked under the lock to never dispatch after `on_destroy`.
+fn lock_handle<P: PluginApp>(
+    entry: &handle_registry::Entry<P>,
+) -> Result<HandleReadGuard<P::Handle>, Error> {
+    let plugin_handle = entry.plugin_handle();
+
+    if entry.is_destroyed() {
+        Err(Error::not_found("Handle has been destroyed"))
+    } else {
+        Ok(plugin_handle)
     }
 }
 
-fn janus_callbacks<P: PluginApp>() -> Result<&'static JanusCallbacks, Error> {
-    let mut app_ref = P::app()
-        .write()
-        .map_err(|err| Error::new(&format!("Failed to acquire app write lock: {}", err)))?;
+/// Locks the handle for dispatching a message or a hook which may change the handle.
+fn lock_handle_mut<P: PluginApp>(
+    entry: &handle_registry::Entry<P>,
+) -> Result<HandleWriteGuard<P::Handle>, Error> {
+    let plugin_handle = entry.plugin_handle_mut();
 
-    match &mut *app_ref {
-        None => Err(Error::new("Plugin not initialized")),
-        Some(app) => {
-            let callbacks = app.janus_callbacks();
-            Ok(unsafe { &*callbacks })
-        }
+    if entry.is_destroyed() {
+        Err(Error::not_found("Handle has been destroyed"))
+    } else {
+        Ok(plugin_handle)
     }
 }
 
-fn serialize<S: Serialize>(object: &S) -> Result<*mut json_t, Error> {
-    // TODO: Dump JSON to string with serde and load back with jansson is suboptimal.
-    //       It would be better to implement serde_jansson.
-    let dump = serde_json::ser::to_string(object)
-        .map_err(|err| Error::new(&format!("Failed to dump JSON: {}", err)))?;
+/// Returns the plugin's core which doesn't need the app lock.
+fn core<P: PluginApp>() -> Result<&'static Core<P>, Error> {
+    P::core()
+        .get()
+        .ok_or_else(|| Error::new("Plugin not initialized"))
+}
+
+/// Looks up the handle's registry entry without the app lock so events are being dispatched
+/// without blocking plugin init or destroy.
+fn handle_entry<P: PluginApp>(
+    raw_handle: *mut JanusPluginSession,
+) -> Result<Arc<handle_registry::Entry<P>>, Error> {
+    core::<P>()?
+        .handle_registry
+        .get_by_raw_handle(raw_handle)
+        .ok_or_else(|| Error::not_found("Handle not found"))
+}
 
-    let dump_cstring = CString::new(dump.as_str())
-        .map_err(|err| Error::new(&format!("Failed to cast dumped JSON: {}", err)))?;
+/// Returns Janus callbacks and the raw handle to call them for without the app lock.
+fn janus_context<P: PluginApp>(
+    id: u64,
+) -> Result<(&'static JanusCallbacks, *mut JanusPluginSession), Error> {
+    let core = core::<P>()?;
 
-    let ptr = unsafe { json_loads((&dump_cstring).as_ptr(), 0, std::ptr::null_mut()).as_mut() };
+    let raw_handle = core
+        .handle_registry
+        .get_by_id(id)
+        .ok_or_else(|| Error::new(&format!("Handle {} not found", id)))?
+        .raw_handle();
 
-    ptr.map(|p| p as *mut json_t)
-        .ok_or_else(|| Error::new("Failed to load dumped JSON"))
+    Ok((core.janus_callbacks()?, raw_handle))
 }
 
-fn deserialize<D: DeserializeOwned>(json: *mut json_t) -> Result<D, Error> {
-    // TODO: Dump JSON to string with jansson and load back with serde is suboptimal.
-    //       It would be better to implement serde_jansson.
-    let dump_cstring = match unsafe { json_dumps(json, 0).as_mut() } {
-        Some(ptr) => unsafe { CString::from_raw(ptr) },
-        None => return Err(Error::new("Failed to dump JSON")),
-    };
+/// Runs `f` with the app under the read lock. Plugin hooks mustn't be called from `f`
+/// since taking the lock again from them may deadlock with a pending writer.
+fn with_app<P, T>(f: impl FnOnce(&App<P>) -> Result<T, Error>) -> Result<T, Error>
+where
+    P: PluginApp,
+{
+    let app_ref = P::app()
+        .read()
+        .map_err(|err| Error::new(&format!("Failed to acquire app read lock: {}", err)))?;
 
-    let dump_str = dump_cstring
-        .to_str()
-        .map_err(|err| Error::new(&format!("Failed to cast dumped JSON: {}", err)))?;
+    match &*app_ref {
+        None => Err(Error::new("Plugin not initialized")),
+        Some(app) => f(app),
+    }
+}
 
-    serde_json::from_str::<D>(dump_str)
-        .map_err(|err| Error::new(&format!("Failed to deserialize JSON: {}", err)))
+fn serialize<S: Serialize + ?Sized>(object: &S) -> Result<*mut json_t, Error> {
+    jansson::to_json(object).map_err(|err| Error::new(&format!("Failed to dump JSON: {}", err)))
+}
+
+/// Deserializes client input so the failure is the client's fault.
+fn deserialize<D: DeserializeOwned>(json: *mut json_t) -> Result<D, Error> {
+    jansson::from_json(json).map_err(|err| validation::request_error("Invalid request", err))
 }
 
 ///////////////////////////////////////////////////////////////////////////////
 
+pub mod forwarder;
+mod handle_ref;
 mod handle_registry;
+pub mod player;
+pub mod recorder;
+pub mod room;
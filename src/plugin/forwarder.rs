@@ -0,0 +1,594 @@
+//! Copying handles' incoming media to external UDP destinations, e.g. recorders or transcoders.
+//!
+//! A forwarder sends RTP of one media kind the handle receives to a `host:port` and optionally
+//! its RTCP to another one, like VideoRoom's `rtp_forward` does. The SSRC and payload type may
+//! be rewritten on the way. Call [forward](fn.forward.html) from the handle's media event handler:
+//!
+//! ```
+//! use janus_app::plugin::forwarder::{self, Forward};
+//! use janus_app::plugin::PluginApp;
+//! use janus_app::{Error, MediaEvent, MediaKind};
+//!
+//! fn start_forwarding<P: PluginApp>(handle_id: u64) -> Result<u32, Error> {
+//!     let forward = Forward::new(MediaKind::Video, "10.0.0.2:5004".parse().unwrap())
+//!         .set_rtcp_address("10.0.0.2:5005".parse().unwrap())
+//!         .set_ssrc(1234)
+//!         .set_payload_type(96);
+//!
+//!     forwarder::start::<P>(handle_id, forward)
+//! }
+//!
+//! // Called from `Handle::handle_media_event`.
+//! fn handle_media_event<P: PluginApp>(handle_id: u64, media_event: &MediaEvent) {
+//!     if let Err(err) = forwarder::forward::<P>(handle_id, media_event) {
+//!         eprintln!("Failed to forward media: {}", err);
+//!     }
+//! }
+//! ```
+//!
+//! Forwarders of the same media kind share a non-blocking UDP socket of the handle for each
+//! address family so RTP and RTCP destinations may be of different families.
+//! Packets which don't fit the socket's send buffer are being dropped.
+//! The forwarders and sockets are being closed when the handle is destroyed.
+
+use std::collections::hash_map::{Entry as MapEntry, HashMap};
+use std::io::ErrorKind as IoErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use parking_lot::RwLock;
+
+use super::{core, PluginApp};
+use crate::rtp::{self, RtpPacketMut};
+use crate::{Error, MediaEvent, MediaKind, MediaProtocol};
+
+/// RTCP packet types identifying the sender by the SSRC right after the header.
+const PT_SR: u8 = 200;
+const PT_SDES: u8 = 202;
+
+/// Destination and rewriting settings of a forwarder.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Forward {
+    kind: MediaKind,
+    rtp_address: SocketAddr,
+    rtcp_address: Option<SocketAddr>,
+    ssrc: Option<u32>,
+    payload_type: Option<u8>,
+}
+
+impl Forward {
+    /// Forwards RTP of `kind` to `rtp_address` as is.
+    pub fn new(kind: MediaKind, rtp_address: SocketAddr) -> Self {
+        Self {
+            kind,
+            rtp_address,
+            rtcp_address: None,
+            ssrc: None,
+            payload_type: None,
+        }
+    }
+
+    /// Forwards RTCP of the media kind to `rtcp_address` too.
+    pub fn set_rtcp_address(self, rtcp_address: SocketAddr) -> Self {
+        Self {
+            rtcp_address: Some(rtcp_address),
+            ..self
+        }
+    }
+
+    /// Rewrites SSRC of RTP packets and of sender reports and SDES in RTCP.
+    pub fn set_ssrc(self, ssrc: u32) -> Self {
+        Self {
+            ssrc: Some(ssrc),
+            ..self
+        }
+    }
+
+    /// Rewrites payload type of RTP packets. Only the lower 7 bits are being used.
+    pub fn set_payload_type(self, payload_type: u8) -> Self {
+        Self {
+            payload_type: Some(payload_type),
+            ..self
+        }
+    }
+
+    pub fn kind(&self) -> MediaKind {
+        self.kind
+    }
+
+    pub fn rtp_address(&self) -> SocketAddr {
+        self.rtp_address
+    }
+
+    pub fn rtcp_address(&self) -> Option<SocketAddr> {
+        self.rtcp_address
+    }
+
+    pub fn ssrc(&self) -> Option<u32> {
+        self.ssrc
+    }
+
+    pub fn payload_type(&self) -> Option<u8> {
+        self.payload_type
+    }
+
+    /// Keys of the sockets to send to the destinations with.
+    fn socket_keys(&self) -> impl Iterator<Item = SocketKey> + '_ {
+        std::iter::once(self.rtp_address)
+            .chain(self.rtcp_address)
+            .map(move |address| (self.kind, address.is_ipv6()))
+    }
+
+    /// Destination of the packet and its buffer with rewritten fields if any.
+    /// Returns `None` if the packet isn't for this forwarder.
+    fn prepare(&self, protocol: MediaProtocol, buffer: &[i8]) -> Option<(SocketAddr, Vec<u8>)> {
+        let address = match protocol {
+            MediaProtocol::Rtp => self.rtp_address,
+            MediaProtocol::Rtcp => self.rtcp_address?,
+        };
+
+        let mut bytes = rtp::to_bytes(buffer).to_vec();
+
+        match protocol {
+            MediaProtocol::Rtp => {
+                // Malformed packets are being forwarded as is like Janus does.
+                if let Ok(mut packet) = RtpPacketMut::from_bytes(&mut bytes) {
+                    if let Some(ssrc) = self.ssrc {
+                        packet.set_ssrc(ssrc);
+                    }
+
+                    if let Some(payload_type) = self.payload_type {
+                        packet.set_payload_type(payload_type);
+                    }
+                }
+            }
+            MediaProtocol::Rtcp => {
+                if let Some(ssrc) = self.ssrc {
+                    rewrite_rtcp_ssrc(&mut bytes, ssrc);
+                }
+            }
+        }
+
+        Some((address, bytes))
+    }
+}
+
+/// Sets the sender SSRC of SR and the first chunk's SSRC of SDES packets in a compound packet.
+/// Stops at a malformed packet.
+fn rewrite_rtcp_ssrc(mut buffer: &mut [u8], ssrc: u32) {
+    while buffer.len() >= 8 {
+        let len = (u16::from_be_bytes([buffer[2], buffer[3]]) as usize + 1) * 4;
+
+        if buffer.len() < len {
+            return;
+        }
+
+        if let PT_SR | PT_SDES = buffer[1] {
+            buffer[4..8].copy_from_slice(&ssrc.to_be_bytes());
+        }
+
+        buffer = &mut buffer[len..];
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Starts forwarding the handle's media and returns the forwarder ID which is unique
+/// within the plugin. Fails with `not_found` if the handle doesn't exist.
+pub fn start<P: PluginApp>(handle_id: u64, forward: Forward) -> Result<u32, Error> {
+    let core = core::<P>()?;
+    let is_registered = || core.handle_registry().get_by_id(handle_id).is_some();
+    core.forwarders().start(handle_id, forward, is_registered)
+}
+
+/// Stops the forwarder of the handle. Fails with `not_found` if there's no such forwarder.
+pub fn stop<P: PluginApp>(handle_id: u64, forwarder_id: u32) -> Result<(), Error> {
+    core::<P>()?.forwarders().stop(handle_id, forwarder_id)
+}
+
+/// Returns forwarders of the handle with their IDs in the order of starting.
+pub fn list<P: PluginApp>(handle_id: u64) -> Result<Vec<(u32, Forward)>, Error> {
+    Ok(core::<P>()?.forwarders().list(handle_id))
+}
+
+/// Sends the handle's incoming RTP or RTCP to its forwarders of the media kind.
+/// Other events are being ignored.
+///
+/// A failure to send to a destination doesn't prevent sending to the others;
+/// the first one is being returned.
+pub fn forward<P: PluginApp>(handle_id: u64, media_event: &MediaEvent) -> Result<(), Error> {
+    match media_event {
+        MediaEvent::Media {
+            protocol,
+            kind,
+            buffer,
+            ..
+        } => core::<P>()?
+            .forwarders()
+            .forward(handle_id, *protocol, *kind, buffer),
+        _ => Ok(()),
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Media kind and whether the socket is IPv6.
+type SocketKey = (MediaKind, bool);
+
+/// Forwarders of the app's handles.
+#[derive(Default)]
+pub(crate) struct Forwarders {
+    handles: RwLock<HashMap<u64, HandleForwarders>>,
+    last_id: AtomicU32,
+}
+
+#[derive(Default)]
+struct HandleForwarders {
+    forwards: Vec<(u32, Forward)>,
+    sockets: HashMap<SocketKey, UdpSocket>,
+}
+
+impl HandleForwarders {
+    fn bind_sockets(&mut self, forward: &Forward) -> Result<(), Error> {
+        for key in forward.socket_keys() {
+            if let MapEntry::Vacant(vacant) = self.sockets.entry(key) {
+                vacant.insert(bind_socket(key.1)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Closes the sockets none of the forwarders sends with.
+    fn close_unused_sockets(&mut self) {
+        let forwards = &self.forwards;
+
+        self.sockets.retain(|key, _| {
+            forwards
+                .iter()
+                .any(|(_, forward)| forward.socket_keys().any(|k| k == *key))
+        });
+    }
+}
+
+impl Forwarders {
+    /// Registration is being checked under the lock so the handle can't be destroyed
+    /// and cleaned up in between leaving a stale socket.
+    fn start<F>(&self, handle_id: u64, forward: Forward, is_registered: F) -> Result<u32, Error>
+    where
+        F: FnOnce() -> bool,
+    {
+        let mut handles = self.handles.write();
+
+        if !is_registered() {
+            return Err(Error::not_found(&format!("Handle {} not found", handle_id)));
+        }
+
+        let forwarders = handles.entry(handle_id).or_default();
+
+        if let Err(err) = forwarders.bind_sockets(&forward) {
+            forwarders.close_unused_sockets();
+
+            if forwarders.forwards.is_empty() {
+                handles.remove(&handle_id);
+            }
+
+            return Err(err);
+        }
+
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        forwarders.forwards.push((id, forward));
+        Ok(id)
+    }
+
+    fn stop(&self, handle_id: u64, forwarder_id: u32) -> Result<(), Error> {
+        let mut handles = self.handles.write();
+
+        let not_found = || {
+            let err = format!(
+                "Forwarder {} of handle {} not found",
+                forwarder_id, handle_id
+            );
+            Error::not_found(&err)
+        };
+
+        let forwarders = handles.get_mut(&handle_id).ok_or_else(not_found)?;
+
+        let index = forwarders
+            .forwards
+            .iter()
+            .position(|(id, _)| *id == forwarder_id)
+            .ok_or_else(not_found)?;
+
+        forwarders.forwards.remove(index);
+        forwarders.close_unused_sockets();
+
+        if forwarders.forwards.is_empty() {
+            handles.remove(&handle_id);
+        }
+
+        Ok(())
+    }
+
+    fn list(&self, handle_id: u64) -> Vec<(u32, Forward)> {
+        let handles = self.handles.read();
+
+        handles
+            .get(&handle_id)
+            .map(|forwarders| forwarders.forwards.clone())
+            .unwrap_or_default()
+    }
+
+    fn forward(
+        &self,
+        handle_id: u64,
+        protocol: MediaProtocol,
+        kind: MediaKind,
+        buffer: &[i8],
+    ) -> Result<(), Error> {
+        let handles = self.handles.read();
+
+        let forwarders = match handles.get(&handle_id) {
+            Some(forwarders) => forwarders,
+            None => return Ok(()),
+        };
+
+        let mut result = Ok(());
+
+        for (_, forward) in forwarders.forwards.iter().filter(|(_, f)| f.kind == kind) {
+            let (address, bytes) = match forward.prepare(protocol, buffer) {
+                Some(prepared) => prepared,
+                None => continue,
+            };
+
+            let socket = match forwarders.sockets.get(&(kind, address.is_ipv6())) {
+                Some(socket) => socket,
+                None => continue,
+            };
+
+            match socket.send_to(&bytes, address) {
+                Ok(_) => (),
+                Err(ref err) if err.kind() == IoErrorKind::WouldBlock => (),
+                Err(err) => {
+                    if result.is_ok() {
+                        let err = format!("Failed to forward {} to {}: {}", protocol, address, err);
+                        result = Err(Error::new(&err));
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Stops all of the handle's forwarders and closes its sockets.
+    pub(crate) fn remove_handle(&self, handle_id: u64) {
+        self.handles.write().remove(&handle_id);
+    }
+}
+
+fn bind_socket(ipv6: bool) -> Result<UdpSocket, Error> {
+    let address = if ipv6 { "[::]:0" } else { "0.0.0.0:0" };
+
+    let socket = UdpSocket::bind(address)
+        .map_err(|err| Error::new(&format!("Failed to bind forwarder socket: {}", err)))?;
+
+    // Media events are being dispatched on Janus's threads which must never block.
+    socket
+        .set_nonblocking(true)
+        .map_err(|err| Error::new(&format!("Failed to set forwarder socket options: {}", err)))?;
+
+    Ok(socket)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::rtcp::{self, Pli, RtcpPacket, SdesChunk, SenderReport};
+    use crate::testing::{test_plugin, MockJanus};
+    use crate::ErrorKind;
+
+    test_plugin!(ForwardPlugin, ForwardHandle, |handle_id, media_event| {
+        forward::<ForwardPlugin>(handle_id, media_event).unwrap()
+    });
+
+    const RTP: [i8; 16] = [-128, 96, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 1, 2, 3, 4];
+
+    fn receiver() -> (UdpSocket, SocketAddr) {
+        receiver_at("127.0.0.1:0")
+    }
+
+    fn receiver_at(address: &str) -> (UdpSocket, SocketAddr) {
+        let socket = UdpSocket::bind(address).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let address = socket.local_addr().unwrap();
+        (socket, address)
+    }
+
+    fn receive(socket: &UdpSocket) -> Vec<u8> {
+        let mut buffer = [0; 1500];
+        let len = socket.recv(&mut buffer).unwrap();
+        buffer[..len].to_vec()
+    }
+
+    #[test]
+    fn forward_rtp() {
+        let janus = MockJanus::<ForwardPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+        let (as_is_socket, as_is_address) = receiver();
+        let (rewritten_socket, rewritten_address) = receiver();
+
+        let forward = Forward::new(MediaKind::Video, rewritten_address)
+            .set_ssrc(0x0A0B0C0D)
+            .set_payload_type(100);
+
+        start::<ForwardPlugin>(handle_id, Forward::new(MediaKind::Video, as_is_address)).unwrap();
+        start::<ForwardPlugin>(handle_id, forward).unwrap();
+
+        janus
+            .incoming_rtp(handle_id, MediaKind::Audio, &[-128, 111, 0, 1])
+            .unwrap();
+
+        janus
+            .incoming_rtp(handle_id, MediaKind::Video, &RTP)
+            .unwrap();
+
+        let original = rtp::to_bytes(&RTP).to_vec();
+        assert_eq!(receive(&as_is_socket), original);
+
+        let packet = receive(&rewritten_socket);
+        let packet = rtp::RtpPacket::from_bytes(&packet).unwrap();
+        assert_eq!(packet.ssrc(), 0x0A0B0C0D);
+        assert_eq!(packet.payload_type(), 100);
+        assert_eq!(packet.sequence_number(), 1);
+        assert_eq!(packet.payload(), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn forward_rtcp() {
+        let janus = MockJanus::<ForwardPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+        let (rtp_socket, rtp_address) = receiver();
+        let (rtcp_socket, rtcp_address) = receiver();
+
+        let forward = Forward::new(MediaKind::Audio, rtp_address)
+            .set_rtcp_address(rtcp_address)
+            .set_ssrc(42);
+
+        start::<ForwardPlugin>(handle_id, forward).unwrap();
+
+        let sender_report = SenderReport {
+            ssrc: 1,
+            ntp_timestamp: 2,
+            rtp_timestamp: 3,
+            packet_count: 4,
+            octet_count: 5,
+            reports: vec![],
+        };
+
+        let buffer = rtcp::serialize(&[
+            RtcpPacket::SenderReport(sender_report),
+            RtcpPacket::SourceDescription(vec![SdesChunk {
+                ssrc: 1,
+                items: vec![],
+            }]),
+            RtcpPacket::Pli(Pli {
+                sender_ssrc: 1,
+                media_ssrc: 2,
+            }),
+        ]);
+
+        janus
+            .incoming_rtcp(handle_id, MediaKind::Audio, &buffer)
+            .unwrap();
+
+        janus
+            .incoming_rtp(handle_id, MediaKind::Audio, &RTP)
+            .unwrap();
+
+        let packets = rtcp::parse_bytes(&receive(&rtcp_socket)).unwrap();
+
+        match &packets[..] {
+            [RtcpPacket::SenderReport(sr), RtcpPacket::SourceDescription(chunks), RtcpPacket::Pli(pli)] =>
+            {
+                assert_eq!((sr.ssrc, sr.packet_count), (42, 4));
+                assert_eq!(chunks[0].ssrc, 42);
+                assert_eq!(pli.sender_ssrc, 1);
+            }
+            other => panic!("Unexpected packets: {:?}", other),
+        }
+
+        // RTCP doesn't go to the RTP destination.
+        let packet = receive(&rtp_socket);
+        assert_eq!(rtp::RtpPacket::from_bytes(&packet).unwrap().ssrc(), 42);
+    }
+
+    #[test]
+    fn forward_to_mixed_address_families() {
+        let janus = MockJanus::<ForwardPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+        let (rtp_socket, rtp_address) = receiver();
+        let (rtcp_socket, rtcp_address) = receiver_at("[::1]:0");
+
+        let forward = Forward::new(MediaKind::Video, rtp_address).set_rtcp_address(rtcp_address);
+        let forwarder_id = start::<ForwardPlugin>(handle_id, forward).unwrap();
+
+        let buffer = rtcp::serialize(&[RtcpPacket::Pli(Pli {
+            sender_ssrc: 1,
+            media_ssrc: 2,
+        })]);
+
+        janus
+            .incoming_rtcp(handle_id, MediaKind::Video, &buffer)
+            .unwrap();
+
+        janus
+            .incoming_rtp(handle_id, MediaKind::Video, &RTP)
+            .unwrap();
+
+        assert_eq!(receive(&rtcp_socket), rtp::to_bytes(&buffer));
+        assert_eq!(receive(&rtp_socket), rtp::to_bytes(&RTP));
+
+        let socket_keys = || {
+            let handles = core::<ForwardPlugin>().unwrap().forwarders().handles.read();
+
+            handles[&handle_id]
+                .sockets
+                .keys()
+                .copied()
+                .collect::<Vec<_>>()
+        };
+
+        let mut keys = socket_keys();
+        keys.sort_by_key(|(_, ipv6)| *ipv6);
+        assert_eq!(
+            keys,
+            vec![(MediaKind::Video, false), (MediaKind::Video, true)]
+        );
+
+        let audio = Forward::new(MediaKind::Audio, rtp_address);
+        let audio_id = start::<ForwardPlugin>(handle_id, audio).unwrap();
+        stop::<ForwardPlugin>(handle_id, forwarder_id).unwrap();
+        assert_eq!(socket_keys(), vec![(MediaKind::Audio, false)]);
+
+        stop::<ForwardPlugin>(handle_id, audio_id).unwrap();
+    }
+
+    #[test]
+    fn lifecycle() {
+        let janus = MockJanus::<ForwardPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+        let (_socket, address) = receiver();
+
+        let err = start::<ForwardPlugin>(0, Forward::new(MediaKind::Audio, address)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        let audio = Forward::new(MediaKind::Audio, address);
+        let video = Forward::new(MediaKind::Video, address);
+        let audio_id = start::<ForwardPlugin>(handle_id, audio.clone()).unwrap();
+        let video_id = start::<ForwardPlugin>(handle_id, video.clone()).unwrap();
+        assert_ne!(audio_id, video_id);
+
+        assert_eq!(
+            list::<ForwardPlugin>(handle_id).unwrap(),
+            vec![(audio_id, audio), (video_id, video.clone())]
+        );
+
+        stop::<ForwardPlugin>(handle_id, audio_id).unwrap();
+        let err = stop::<ForwardPlugin>(handle_id, audio_id).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(
+            list::<ForwardPlugin>(handle_id).unwrap(),
+            vec![(video_id, video)]
+        );
+
+        janus.destroy_handle(handle_id).unwrap();
+        assert!(list::<ForwardPlugin>(handle_id).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+use std::sync::{Arc, Weak};
+
+use crate::plugin::handle_registry::Entry;
+use crate::plugin::{HandleReadGuard, HandleWriteGuard, PluginApp};
+use crate::Error;
+
+/// Cheap reference to a plugin handle which may be moved into another thread or a future.
+///
+/// It doesn't keep the handle alive: after Janus destroys the handle all methods fail
+/// with a `not_found` [Error](../struct.Error.html).
+/// Get one with [Callbacks::handle_ref](trait.Callbacks.html#tymethod.handle_ref).
+///
+/// ```
+/// # use janus_app::plugin::{Callbacks, HandleRef, PluginApp};
+/// # use janus_app::{Error, Handle, OutgoingMessage};
+/// fn respond<P>(handle_ref: HandleRef<P>, message: OutgoingMessage<<P::Handle as Handle>::OutgoingMessagePayload>)
+///     -> Result<(), Error>
+/// where
+///     P: PluginApp,
+/// {
+///     let handle = handle_ref.read()?;
+///     Callbacks::<P>::push_event(&*handle, &message)
+/// }
+/// ```
+pub struct HandleRef<P: PluginApp> {
+    id: u64,
+    entry: Weak<Entry<P>>,
+}
+
+impl<P: PluginApp> HandleRef<P> {
+    pub(crate) fn new(id: u64, entry: &Arc<Entry<P>>) -> Self {
+        Self {
+            id,
+            entry: Arc::downgrade(entry),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Whether the handle hasn't been destroyed yet.
+    pub fn is_alive(&self) -> bool {
+        self.upgrade().is_ok()
+    }
+
+    /// Locks the handle for reading, e.g. to call [Callbacks](trait.Callbacks.html) methods.
+    pub fn read(&self) -> Result<HandleReadGuard<P::Handle>, Error> {
+        self.upgrade().map(|entry| entry.plugin_handle())
+    }
+
+    /// Locks the handle for writing. Events of this handle are waiting until the guard is dropped.
+    /// It's for other threads and handles: the handle's own messages and hooks already take it
+    /// mutably and its events would deadlock on it.
+    pub fn write(&self) -> Result<HandleWriteGuard<P::Handle>, Error> {
+        self.upgrade().map(|entry| entry.plugin_handle_mut())
+    }
+
+    fn upgrade(&self) -> Result<Arc<Entry<P>>, Error> {
+        match self.entry.upgrade() {
+            Some(entry) if !entry.is_destroyed() => Ok(entry),
+            _ => Err(Error::not_found(&format!(
+                "Handle {} has been destroyed",
+                self.id
+            ))),
+        }
+    }
+}
+
+impl<P: PluginApp> Clone for HandleRef<P> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            entry: self.entry.clone(),
+        }
+    }
+}
+
+impl<P: PluginApp> fmt::Debug for HandleRef<P> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("HandleRef").field("id", &self.id).finish()
+    }
+}
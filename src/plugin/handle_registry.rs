@@ -1,86 +1,148 @@
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicPtr, Ordering};
+use std::collections::hash_map::{Entry as MapEntry, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::Arc;
 
 use janus_plugin_sys::plugin::janus_plugin_session as JanusPluginSession;
+use parking_lot::{ArcRwLockReadGuard, ArcRwLockWriteGuard, Mutex, RawRwLock, RwLock};
 
 use crate::error::Error;
 use crate::ffi::janus_ice_handle as JanusIceHandle;
-use crate::Plugin;
+use crate::{Handle, Plugin, Responder};
+
+/// Number of independently locked parts of the registry.
+/// Handle IDs are random so they are being distributed evenly among the shards.
+const SHARDS_COUNT: usize = 64;
 
 pub(crate) struct Entry<P: Plugin> {
     raw_handle: AtomicPtr<JanusPluginSession>,
-    plugin_handle: P::Handle,
+    // Set on removal so handle references fail even while the entry is being dispatched.
+    destroyed: AtomicBool,
+    // Per-entry lock so mutating a handle blocks only the events of this very handle.
+    plugin_handle: Arc<RwLock<P::Handle>>,
+    // Responders of the messages being handled so pushing an event finishes the transaction.
+    pending_responders: Mutex<Vec<Responder<OutgoingPayload<P>>>>,
 }
 
+type OutgoingPayload<P> = <<P as Plugin>::Handle as Handle>::OutgoingMessagePayload;
+
 impl<P: Plugin> Entry<P> {
     fn new(raw_handle: AtomicPtr<JanusPluginSession>, plugin_handle: P::Handle) -> Self {
         Self {
             raw_handle,
-            plugin_handle,
+            destroyed: AtomicBool::new(false),
+            plugin_handle: Arc::new(RwLock::new(plugin_handle)),
+            pending_responders: Mutex::new(Vec::new()),
         }
     }
 
-    pub(crate) fn raw_handle_mut(&mut self) -> *mut JanusPluginSession {
+    pub(crate) fn raw_handle(&self) -> *mut JanusPluginSession {
         self.raw_handle.load(Ordering::Relaxed)
     }
 
-    pub(crate) fn plugin_handle(&self) -> &P::Handle {
-        &self.plugin_handle
+    pub(crate) fn is_destroyed(&self) -> bool {
+        self.destroyed.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn plugin_handle(&self) -> ArcRwLockReadGuard<RawRwLock, P::Handle> {
+        self.plugin_handle.read_arc()
     }
 
-    pub(crate) fn plugin_handle_mut(&mut self) -> &mut P::Handle {
-        &mut self.plugin_handle
+    pub(crate) fn plugin_handle_mut(&self) -> ArcRwLockWriteGuard<RawRwLock, P::Handle> {
+        self.plugin_handle.write_arc()
+    }
+
+    /// Keeps the responder until its transaction is finished.
+    pub(crate) fn add_pending_responder(&self, responder: Responder<OutgoingPayload<P>>) {
+        let mut pending_responders = self.pending_responders.lock();
+        pending_responders.retain(|responder| !responder.is_replied());
+        pending_responders.push(responder);
+    }
+
+    /// Finishes the transaction if it's still pending.
+    pub(crate) fn finish_pending_responder(&self, transaction: &str) {
+        let mut pending_responders = self.pending_responders.lock();
+        pending_responders.retain(|responder| !responder.is_replied());
+
+        let index = pending_responders
+            .iter()
+            .position(|responder| responder.transaction() == transaction);
+
+        if let Some(index) = index {
+            pending_responders.swap_remove(index).finish();
+        }
     }
 }
 
+type Shard<P> = RwLock<HashMap<u64, Arc<Entry<P>>>>;
+
+/// Handle storage split into shards so registering and looking up handles
+/// on different threads rarely contend for the same lock.
+/// Lookups return a shared entry so the shard lock is being held only for the lookup itself.
 pub(crate) struct HandleRegistry<P: Plugin> {
-    handles: HashMap<u64, Entry<P>>,
+    shards: Vec<Shard<P>>,
 }
 
 impl<P: Plugin> HandleRegistry<P> {
     pub(crate) fn new() -> Self {
         Self {
-            handles: HashMap::new(),
+            shards: (0..SHARDS_COUNT)
+                .map(|_| RwLock::new(HashMap::new()))
+                .collect(),
         }
     }
 
-    pub(crate) fn get_by_id(&self, id: u64) -> Option<&Entry<P>> {
-        self.handles.get(&id)
-    }
-
-    pub(crate) fn get_by_id_mut(&mut self, id: u64) -> Option<&mut Entry<P>> {
-        self.handles.get_mut(&id)
+    pub(crate) fn get_by_id(&self, id: u64) -> Option<Arc<Entry<P>>> {
+        self.shard(id).read().get(&id).cloned()
     }
 
     pub(crate) fn get_by_raw_handle(
         &self,
         raw_handle_ptr: *mut JanusPluginSession,
-    ) -> Option<&Entry<P>> {
+    ) -> Option<Arc<Entry<P>>> {
         self.get_by_id(Self::fetch_id(raw_handle_ptr))
     }
 
+    /// Registers the handle and returns it locked for writing so no event is being dispatched
+    /// to it until the guard is dropped.
     pub(crate) fn add(
-        &mut self,
+        &self,
         raw_handle_ptr: *mut JanusPluginSession,
         plugin_handle: P::Handle,
-    ) -> Result<&Entry<P>, Error> {
-        if self.get_by_raw_handle(raw_handle_ptr).is_some() {
-            return Err(Error::new("Handle already registered"));
-        }
-
+    ) -> Result<ArcRwLockWriteGuard<RawRwLock, P::Handle>, Error> {
         let id = Self::fetch_id(raw_handle_ptr);
         let raw_handle = AtomicPtr::new(raw_handle_ptr);
 
-        self.handles
-            .insert(id, Entry::new(raw_handle, plugin_handle));
+        match self.shard(id).write().entry(id) {
+            MapEntry::Occupied(_) => Err(Error::new("Handle already registered")),
+            MapEntry::Vacant(vacant) => {
+                let entry = Arc::new(Entry::new(raw_handle, plugin_handle));
+                let plugin_handle = entry.plugin_handle_mut();
+                vacant.insert(entry);
+                Ok(plugin_handle)
+            }
+        }
+    }
 
-        self.get_by_id(id)
-            .ok_or_else(|| Error::new(&format!("Failed to register handle with id {}", id)))
+    /// Unregisters the handle and marks the entry as destroyed so dispatches holding it bail out.
+    pub(crate) fn remove(&self, raw_handle_ptr: *mut JanusPluginSession) -> Option<Arc<Entry<P>>> {
+        let id = Self::fetch_id(raw_handle_ptr);
+        let entry = self.shard(id).write().remove(&id)?;
+        entry.destroyed.store(true, Ordering::Release);
+        Some(entry)
     }
 
-    pub(crate) fn remove(&mut self, raw_handle_ptr: *mut JanusPluginSession) -> Result<(), Error> {
-        self.handles.remove(&Self::fetch_id(raw_handle_ptr));
-        Ok(())
+    /// Unregisters all of the handles like [remove](#method.remove) does.
+    pub(crate) fn drain(&self) -> Vec<Arc<Entry<P>>> {
+        let mut entries = vec![];
+
+        for shard in &self.shards {
+            for (_, entry) in shard.write().drain() {
+                entry.destroyed.store(true, Ordering::Release);
+                entries.push(entry);
+            }
+        }
+
+        entries
     }
 
     pub(crate) fn fetch_id(raw_handle: *mut JanusPluginSession) -> u64 {
@@ -89,4 +151,8 @@ impl<P: Plugin> HandleRegistry<P> {
             (*ptr).handle_id
         }
     }
+
+    fn shard(&self, id: u64) -> &Shard<P> {
+        &self.shards[(id % SHARDS_COUNT as u64) as usize]
+    }
 }
@@ -0,0 +1,965 @@
+//! Playing recorded media into handles, e.g. announcements or test calls.
+//!
+//! A player reads RTP from an [mjr](../../mjr/index.html) recording or a
+//! [pcap](../../pcap/index.html) capture and sends it to the handle's peer in real time
+//! like the peer's own media is being relayed:
+//!
+//! ```
+//! use janus_app::plugin::player::{self, Playback};
+//! use janus_app::plugin::PluginApp;
+//! use janus_app::Error;
+//!
+//! fn play_announcement<P: PluginApp>(handle_id: u64) -> Result<u32, Error> {
+//!     let playback = Playback::mjr("/recordings/announcement-audio.mjr").set_payload_type(111);
+//!     player::start::<P>(handle_id, playback)
+//! }
+//!
+//! // Called from `Handle::on_playback_complete`.
+//! fn on_playback_complete(handle_id: u64, player_id: u32) {
+//!     log::info!("Player {} of handle {} is done", player_id, handle_id);
+//! }
+//! ```
+//!
+//! Packets are being paced by the times they've been recorded or captured at. SSRC, sequence
+//! numbers and timestamps are being rewritten so the peer gets a single continuous stream
+//! regardless of pausing, seeking and looping.
+//!
+//! Each player runs on its own thread. Players are being stopped when the handle is destroyed.
+//! Stopping a player waits for its thread to finish so no packet is being sent after that.
+
+use std::collections::hash_map::{HashMap, RandomState};
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufReader, ErrorKind as IoErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use parking_lot::{Condvar, Mutex, MutexGuard, RwLock};
+
+use super::{core, janus_context, lock_handle_mut, relay_media, PluginApp};
+use crate::rtp::{RtpPacket, RtpPacketMut, SwitchingContext};
+use crate::{logger, mjr, pcap, Error, Handle, MediaKind, MediaProtocol};
+
+/// Pause before starting a single packet over, e.g. a comfort noise frame.
+const DEFAULT_LOOP_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Media source and rewriting settings of a player.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Playback {
+    path: PathBuf,
+    format: Format,
+    source_ssrc: Option<u32>,
+    ssrc: Option<u32>,
+    payload_type: Option<u8>,
+    clock_rate: Option<u32>,
+    looped: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Mjr,
+    Pcap(MediaKind),
+}
+
+impl Playback {
+    /// Plays the `.mjr` recording at `path` as the media kind it has been recorded as.
+    pub fn mjr<T: Into<PathBuf>>(path: T) -> Self {
+        Self::new(path.into(), Format::Mjr)
+    }
+
+    /// Plays RTP from the pcap capture at `path` as `kind`. Only one stream is being played,
+    /// see [set_source_ssrc](#method.set_source_ssrc).
+    pub fn pcap<T: Into<PathBuf>>(kind: MediaKind, path: T) -> Self {
+        Self::new(path.into(), Format::Pcap(kind))
+    }
+
+    fn new(path: PathBuf, format: Format) -> Self {
+        Self {
+            path,
+            format,
+            source_ssrc: None,
+            ssrc: None,
+            payload_type: None,
+            clock_rate: None,
+            looped: false,
+        }
+    }
+
+    /// Plays only packets with the SSRC. Defaults to the first RTP packet's one for pcap
+    /// captures while `.mjr` recordings are being played whole.
+    pub fn set_source_ssrc(self, source_ssrc: u32) -> Self {
+        Self {
+            source_ssrc: Some(source_ssrc),
+            ..self
+        }
+    }
+
+    /// Sends packets with the SSRC instead of a random one.
+    pub fn set_ssrc(self, ssrc: u32) -> Self {
+        Self {
+            ssrc: Some(ssrc),
+            ..self
+        }
+    }
+
+    /// Rewrites payload type of the packets, e.g. to the one negotiated with the peer.
+    /// Only the lower 7 bits are being used.
+    pub fn set_payload_type(self, payload_type: u8) -> Self {
+        Self {
+            payload_type: Some(payload_type),
+            ..self
+        }
+    }
+
+    /// RTP clock rate of the codec to keep timestamps continuous. Defaults to 8000 for G.711
+    /// and G.722 recordings, 48000 for other audio and 90000 for video.
+    pub fn set_clock_rate(self, clock_rate: u32) -> Self {
+        Self {
+            clock_rate: Some(clock_rate),
+            ..self
+        }
+    }
+
+    /// Starts over after the end instead of completing.
+    pub fn set_looped(self, looped: bool) -> Self {
+        Self { looped, ..self }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn source_ssrc(&self) -> Option<u32> {
+        self.source_ssrc
+    }
+
+    pub fn ssrc(&self) -> Option<u32> {
+        self.ssrc
+    }
+
+    pub fn payload_type(&self) -> Option<u8> {
+        self.payload_type
+    }
+
+    pub fn clock_rate(&self) -> Option<u32> {
+        self.clock_rate
+    }
+
+    pub fn looped(&self) -> bool {
+        self.looped
+    }
+}
+
+/// Current state of a player.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Status {
+    pub kind: MediaKind,
+    /// Time of the last sent packet since the beginning.
+    pub position: Duration,
+    pub duration: Duration,
+    pub paused: bool,
+    pub looped: bool,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Loads the media and starts playing it into the handle. Returns the player ID which is unique
+/// within the plugin.
+///
+/// The whole file is being read right away. Fails with `not_found` if the handle or the file
+/// doesn't exist and with `bad_request` if there's no RTP to play in the file.
+pub fn start<P: PluginApp>(handle_id: u64, playback: Playback) -> Result<u32, Error> {
+    let track = Track::load(&playback)?;
+
+    let core = core::<P>()?;
+    let is_registered = || core.handle_registry().get_by_id(handle_id).is_some();
+    core.players()
+        .start::<P, _>(handle_id, playback, track, is_registered)
+}
+
+/// Stops the player. Fails with `not_found` if there's no such player.
+pub fn stop<P: PluginApp>(handle_id: u64, player_id: u32) -> Result<(), Error> {
+    let player = core::<P>()?
+        .players()
+        .remove(handle_id, player_id)
+        .ok_or_else(|| player_not_found(handle_id, player_id))?;
+
+    player.stop();
+    Ok(())
+}
+
+/// Pauses the player. Packets are being continued from the same position on resuming.
+pub fn pause<P: PluginApp>(handle_id: u64, player_id: u32) -> Result<(), Error> {
+    update::<P, _>(handle_id, player_id, |state| state.paused = true)
+}
+
+pub fn resume<P: PluginApp>(handle_id: u64, player_id: u32) -> Result<(), Error> {
+    update::<P, _>(handle_id, player_id, |state| state.paused = false)
+}
+
+/// Continues playing from the first packet at or after `position` since the beginning.
+/// Fails with `bad_request` if the position is beyond the duration.
+pub fn seek<P: PluginApp>(handle_id: u64, player_id: u32, position: Duration) -> Result<(), Error> {
+    let player = core::<P>()?
+        .players()
+        .get(handle_id, player_id)
+        .ok_or_else(|| player_not_found(handle_id, player_id))?;
+
+    if position > player.duration {
+        let err = format!(
+            "Position {:?} is beyond duration {:?}",
+            position, player.duration
+        );
+
+        return Err(Error::bad_request(&err));
+    }
+
+    player.control.update(|state| state.seek = Some(position));
+    Ok(())
+}
+
+/// Changes whether the player starts over after the end.
+pub fn set_looped<P: PluginApp>(handle_id: u64, player_id: u32, looped: bool) -> Result<(), Error> {
+    update::<P, _>(handle_id, player_id, |state| state.looped = looped)
+}
+
+pub fn status<P: PluginApp>(handle_id: u64, player_id: u32) -> Result<Status, Error> {
+    let player = core::<P>()?
+        .players()
+        .get(handle_id, player_id)
+        .ok_or_else(|| player_not_found(handle_id, player_id))?;
+
+    let state = player.control.state.lock();
+
+    Ok(Status {
+        kind: player.kind,
+        position: state.position,
+        duration: player.duration,
+        paused: state.paused,
+        looped: state.looped,
+    })
+}
+
+/// Returns players of the handle with their IDs in the order of starting.
+pub fn list<P: PluginApp>(handle_id: u64) -> Result<Vec<(u32, Playback)>, Error> {
+    Ok(core::<P>()?.players().list(handle_id))
+}
+
+fn update<P, F>(handle_id: u64, player_id: u32, f: F) -> Result<(), Error>
+where
+    P: PluginApp,
+    F: FnOnce(&mut State),
+{
+    let player = core::<P>()?
+        .players()
+        .get(handle_id, player_id)
+        .ok_or_else(|| player_not_found(handle_id, player_id))?;
+
+    player.control.update(f);
+    Ok(())
+}
+
+fn player_not_found(handle_id: u64, player_id: u32) -> Error {
+    let err = format!("Player {} of handle {} not found", player_id, handle_id);
+    Error::not_found(&err)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// RTP packets of a file with their times since the first one.
+struct Track {
+    kind: MediaKind,
+    clock_rate: u32,
+    packets: Vec<(Duration, Vec<u8>)>,
+}
+
+impl Track {
+    fn load(playback: &Playback) -> Result<Self, Error> {
+        let file = File::open(&playback.path).map_err(|err| {
+            let detail = format!("Failed to open {}: {}", playback.path.display(), err);
+
+            match err.kind() {
+                IoErrorKind::NotFound => Error::not_found(&detail),
+                _ => Error::new(&detail),
+            }
+        })?;
+
+        let reader = BufReader::new(file);
+
+        let mut track = match playback.format {
+            Format::Mjr => Self::load_mjr(reader, playback)?,
+            Format::Pcap(kind) => Self::load_pcap(reader, kind, playback)?,
+        };
+
+        if track.packets.is_empty() {
+            let err = format!("No RTP to play in {}", playback.path.display());
+            return Err(Error::bad_request(&err));
+        }
+
+        if let Some(clock_rate) = playback.clock_rate {
+            track.clock_rate = clock_rate;
+        }
+
+        // Keep the times monotonic to pace reordered packets right after the previous ones.
+        let mut time = Duration::default();
+
+        for (packet_time, _) in track.packets.iter_mut() {
+            time = time.max(*packet_time);
+            *packet_time = time;
+        }
+
+        Ok(track)
+    }
+
+    fn load_mjr(reader: BufReader<File>, playback: &Playback) -> Result<Self, Error> {
+        let mut reader = mjr::Reader::new(reader)?;
+        let info = reader.info();
+        let clock_rate = default_clock_rate(info.kind, Some(&info.codec));
+        let mut track = Self::new(info.kind, clock_rate);
+
+        while let Some(frame) = reader.read_frame()? {
+            let time = Duration::from_millis(frame.time as u64);
+            track.push(time, frame.packet, playback.source_ssrc);
+        }
+
+        Ok(track)
+    }
+
+    fn load_pcap(
+        reader: BufReader<File>,
+        kind: MediaKind,
+        playback: &Playback,
+    ) -> Result<Self, Error> {
+        let mut reader = pcap::Reader::new(reader)?;
+        let mut track = Self::new(kind, default_clock_rate(kind, None));
+        let mut source_ssrc = playback.source_ssrc;
+        let mut start = None;
+
+        while let Some(packet) = reader.read_udp()? {
+            // RTCP multiplexed with RTP has payload types 192-223 in the RTP header position.
+            if let Some(192..=223) = packet.payload.get(1) {
+                continue;
+            }
+
+            let ssrc = match RtpPacket::from_bytes(&packet.payload) {
+                Ok(rtp_packet) => rtp_packet.ssrc(),
+                Err(_) => continue,
+            };
+
+            if *source_ssrc.get_or_insert(ssrc) != ssrc {
+                continue;
+            }
+
+            let start = *start.get_or_insert(packet.time);
+            let time = packet.time.checked_sub(start).unwrap_or_default();
+            track.push(time, packet.payload, None);
+        }
+
+        Ok(track)
+    }
+
+    fn new(kind: MediaKind, clock_rate: u32) -> Self {
+        Self {
+            kind,
+            clock_rate,
+            packets: vec![],
+        }
+    }
+
+    /// Adds the packet if it's valid RTP of the source.
+    fn push(&mut self, time: Duration, packet: Vec<u8>, source_ssrc: Option<u32>) {
+        let ssrc = match RtpPacket::from_bytes(&packet) {
+            Ok(rtp_packet) => rtp_packet.ssrc(),
+            Err(_) => return,
+        };
+
+        if source_ssrc.unwrap_or(ssrc) == ssrc {
+            self.packets.push((time, packet));
+        }
+    }
+
+    fn duration(&self) -> Duration {
+        self.packets
+            .last()
+            .map(|(time, _)| *time)
+            .unwrap_or_default()
+    }
+
+    /// Pause before starting over: the average interval between packets.
+    fn loop_interval(&self) -> Duration {
+        match self.packets.len() {
+            0 | 1 => DEFAULT_LOOP_INTERVAL,
+            len => (self.duration() / (len as u32 - 1)).max(Duration::from_millis(1)),
+        }
+    }
+}
+
+fn default_clock_rate(kind: MediaKind, codec: Option<&str>) -> u32 {
+    match (kind, codec) {
+        (MediaKind::Video, _) => 90000,
+        (MediaKind::Audio, Some("pcmu")) | (MediaKind::Audio, Some("pcma")) => 8000,
+        (MediaKind::Audio, Some("g711")) | (MediaKind::Audio, Some("g722")) => 8000,
+        (MediaKind::Audio, _) => 48000,
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Rewrites packets of the track into a continuous stream.
+struct Rewriter {
+    context: SwitchingContext,
+    payload_type: Option<u8>,
+}
+
+impl Rewriter {
+    fn new(playback: &Playback, clock_rate: u32) -> Self {
+        let ssrc = playback.ssrc.unwrap_or_else(random_u32);
+
+        Self {
+            context: SwitchingContext::new(ssrc, clock_rate),
+            payload_type: playback.payload_type,
+        }
+    }
+
+    /// The next packet isn't the one following the previous in the track.
+    fn jump(&mut self) {
+        self.context.switch();
+    }
+
+    fn rewrite(&mut self, packet: &[u8]) -> Vec<i8> {
+        let mut buffer: Vec<i8> = packet.iter().map(|byte| *byte as i8).collect();
+
+        // The track has only valid packets.
+        if let Ok(mut rtp_packet) = RtpPacketMut::new(&mut buffer) {
+            self.context.rewrite_packet(&mut rtp_packet);
+
+            if let Some(payload_type) = self.payload_type {
+                rtp_packet.set_payload_type(payload_type);
+            }
+        }
+
+        buffer
+    }
+}
+
+/// Random value for the SSRC.
+fn random_u32() -> u32 {
+    // The standard library seeds hash keys randomly and changes them for each `RandomState`.
+    RandomState::new().build_hasher().finish() as u32
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Default)]
+struct State {
+    paused: bool,
+    looped: bool,
+    stopped: bool,
+    seek: Option<Duration>,
+    position: Duration,
+}
+
+/// State shared by the player's thread and its controls.
+#[derive(Default)]
+struct Control {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+impl Control {
+    /// Changes the state and wakes the thread up to apply it.
+    fn update<F: FnOnce(&mut State)>(&self, f: F) {
+        f(&mut self.state.lock());
+        self.condvar.notify_one();
+    }
+}
+
+/// Sends the track's packets on time until it's done or the player is stopped.
+fn run<P: PluginApp>(
+    handle_id: u64,
+    player_id: u32,
+    track: Track,
+    mut rewriter: Rewriter,
+    control: Arc<Control>,
+) {
+    let mut index = 0;
+    // Wall clock time of playing the track's time.
+    let mut base = (Instant::now(), Duration::default());
+    let mut state = control.state.lock();
+
+    loop {
+        if state.stopped {
+            return;
+        }
+
+        if state.paused {
+            control.condvar.wait(&mut state);
+
+            if !state.paused {
+                base = (Instant::now(), state.position);
+                rewriter.jump();
+            }
+
+            continue;
+        }
+
+        if let Some(position) = state.seek.take() {
+            index = track.packets.partition_point(|(time, _)| *time < position);
+            base = (Instant::now(), position);
+            rewriter.jump();
+        }
+
+        let (time, packet) = match track.packets.get(index) {
+            Some(entry) => entry,
+            None if state.looped => {
+                index = 0;
+                base = (Instant::now() + track.loop_interval(), Duration::default());
+                rewriter.jump();
+                continue;
+            }
+            None => break,
+        };
+
+        let now = Instant::now();
+        let due = base.0 + time.saturating_sub(base.1);
+
+        if due > now {
+            control.condvar.wait_until(&mut state, due);
+            continue;
+        }
+
+        let buffer = rewriter.rewrite(packet);
+        state.position = *time;
+        index += 1;
+
+        // Don't block the controls while Janus is sending.
+        let result = MutexGuard::unlocked(&mut state, || send::<P>(handle_id, track.kind, &buffer));
+
+        if let Err(err) = result {
+            log::error!(
+                "Player {} of handle {} failed: {}",
+                player_id,
+                handle_id,
+                err
+            );
+            if let Ok(core) = core::<P>() {
+                core.players().remove(handle_id, player_id);
+            }
+
+            return;
+        }
+    }
+
+    drop(state);
+
+    // The player may have been stopped meanwhile.
+    if let Err(err) = complete::<P>(handle_id, player_id) {
+        log::error!(
+            "Failed to complete player {} of handle {}: {}",
+            player_id,
+            handle_id,
+            err
+        );
+    }
+}
+
+fn send<P: PluginApp>(handle_id: u64, kind: MediaKind, buffer: &[i8]) -> Result<(), Error> {
+    let (callbacks, raw_handle) = janus_context::<P>(handle_id)?;
+    relay_media(callbacks, raw_handle, MediaProtocol::Rtp, kind, buffer)
+}
+
+/// Removes the player and dispatches the completion to the handle unless it's been stopped.
+fn complete<P: PluginApp>(handle_id: u64, player_id: u32) -> Result<(), Error> {
+    let core = core::<P>()?;
+
+    if core.players().remove(handle_id, player_id).is_none() {
+        return Ok(());
+    }
+
+    if let Some(entry) = core.handle_registry().get_by_id(handle_id) {
+        let mut plugin_handle = lock_handle_mut(&entry)?;
+        let _scope = logger::handle_scope(plugin_handle.id());
+        plugin_handle.on_playback_complete(player_id);
+    }
+
+    Ok(())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Players of the app's handles.
+#[derive(Default)]
+pub(crate) struct Players {
+    handles: RwLock<HashMap<u64, Vec<Arc<Player>>>>,
+    last_id: AtomicU32,
+}
+
+struct Player {
+    id: u32,
+    playback: Playback,
+    kind: MediaKind,
+    duration: Duration,
+    control: Arc<Control>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Player {
+    /// Stops the thread and waits for it unless it's the current one, e.g. when the handle
+    /// gets destroyed from the player's `on_playback_complete`.
+    fn stop(&self) {
+        self.control.update(|state| state.stopped = true);
+
+        let thread = match self.thread.lock().take() {
+            Some(thread) if thread.thread().id() != thread::current().id() => thread,
+            _ => return,
+        };
+
+        if thread.join().is_err() {
+            log::error!("Player {} thread panicked", self.id);
+        }
+    }
+}
+
+impl Players {
+    /// Registration is being checked under the lock so the handle can't be destroyed
+    /// and cleaned up in between leaving a running player.
+    fn start<P, F>(
+        &self,
+        handle_id: u64,
+        playback: Playback,
+        track: Track,
+        is_registered: F,
+    ) -> Result<u32, Error>
+    where
+        P: PluginApp,
+        F: FnOnce() -> bool,
+    {
+        let mut handles = self.handles.write();
+
+        if !is_registered() {
+            return Err(Error::not_found(&format!("Handle {} not found", handle_id)));
+        }
+
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let rewriter = Rewriter::new(&playback, track.clock_rate);
+
+        let control = Arc::new(Control::default());
+        control.state.lock().looped = playback.looped;
+
+        let kind = track.kind;
+        let duration = track.duration();
+        let thread_control = control.clone();
+
+        let thread = thread::Builder::new()
+            .name(String::from("janus-app-player"))
+            .spawn(move || run::<P>(handle_id, id, track, rewriter, thread_control))
+            .map_err(|err| Error::new(&format!("Failed to start player thread: {}", err)))?;
+
+        let player = Player {
+            id,
+            kind,
+            duration,
+            playback,
+            control,
+            thread: Mutex::new(Some(thread)),
+        };
+
+        handles.entry(handle_id).or_default().push(Arc::new(player));
+        Ok(id)
+    }
+
+    fn get(&self, handle_id: u64, player_id: u32) -> Option<Arc<Player>> {
+        let handles = self.handles.read();
+
+        handles
+            .get(&handle_id)
+            .and_then(|players| players.iter().find(|p| p.id == player_id))
+            .cloned()
+    }
+
+    fn remove(&self, handle_id: u64, player_id: u32) -> Option<Arc<Player>> {
+        let mut handles = self.handles.write();
+        let players = handles.get_mut(&handle_id)?;
+        let index = players.iter().position(|p| p.id == player_id)?;
+        let player = players.remove(index);
+
+        if players.is_empty() {
+            handles.remove(&handle_id);
+        }
+
+        Some(player)
+    }
+
+    fn list(&self, handle_id: u64) -> Vec<(u32, Playback)> {
+        let handles = self.handles.read();
+
+        handles
+            .get(&handle_id)
+            .map(|players| {
+                players
+                    .iter()
+                    .map(|player| (player.id, player.playback.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Stops all of the handle's players and waits for them.
+    pub(crate) fn remove_handle(&self, handle_id: u64) {
+        let players = match self.handles.write().remove(&handle_id) {
+            Some(players) => players,
+            None => return,
+        };
+
+        // All of them are being stopped at once rather than waiting for each in turn.
+        for player in &players {
+            player.control.update(|state| state.stopped = true);
+        }
+
+        for player in players {
+            player.stop();
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use parking_lot::const_mutex;
+
+    use super::*;
+    use crate::testing::{test_plugin, Call, MockJanus};
+    use crate::ErrorKind;
+
+    static COMPLETED: Mutex<Vec<(u64, u32)>> = const_mutex(Vec::new());
+
+    test_plugin!(PlayPlugin, PlayHandle, |_, _| (), |handle_id, player_id| {
+        COMPLETED.lock().push((handle_id, player_id))
+    });
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn rtp(sequence_number: u16, timestamp: u32, ssrc: u32) -> Vec<u8> {
+        let mut packet = vec![0x80, 96];
+        packet.extend_from_slice(&sequence_number.to_be_bytes());
+        packet.extend_from_slice(&timestamp.to_be_bytes());
+        packet.extend_from_slice(&ssrc.to_be_bytes());
+        packet.extend_from_slice(&[1, 2, 3]);
+        packet
+    }
+
+    /// Writes a recording with packets at the given times.
+    fn mjr_file(name: &str, packets: &[(u32, Vec<u8>)]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("janus-app-{}-{}.mjr", name, std::process::id()));
+        let info = br#"{"t":"a","c":"opus","s":1,"u":2}"#;
+        let mut bytes = b"MJR00002".to_vec();
+        bytes.extend_from_slice(&(info.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(info);
+
+        for (time, packet) in packets {
+            bytes.extend_from_slice(b"MEET");
+            bytes.extend_from_slice(&time.to_be_bytes());
+            bytes.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(packet);
+        }
+
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn next_packet(janus: &MockJanus<PlayPlugin>) -> (MediaKind, Vec<u8>) {
+        match janus.wait_for(TIMEOUT, |call| matches!(call, Call::RelayRtp { .. })) {
+            Ok(Call::RelayRtp { kind, buffer, .. }) => {
+                (kind, buffer.iter().map(|byte| *byte as u8).collect())
+            }
+            other => panic!("Unexpected call: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn play_mjr() {
+        let janus = MockJanus::<PlayPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+
+        let path = mjr_file(
+            "play",
+            &[
+                (0, rtp(10, 1000, 5)),
+                (20, rtp(11, 1960, 5)),
+                (40, rtp(12, 2920, 5)),
+            ],
+        );
+
+        let started_at = Instant::now();
+        let playback = Playback::mjr(&path).set_ssrc(77).set_payload_type(100);
+        let player_id = start::<PlayPlugin>(handle_id, playback).unwrap();
+
+        let mut packets = vec![];
+
+        for _ in 0..3 {
+            let (kind, packet) = next_packet(&janus);
+            assert_eq!(kind, MediaKind::Audio);
+            packets.push(packet);
+        }
+
+        assert!(started_at.elapsed() >= Duration::from_millis(40));
+
+        let packets: Vec<RtpPacket> = packets
+            .iter()
+            .map(|packet| RtpPacket::from_bytes(packet).unwrap())
+            .collect();
+
+        for (prev, next) in packets.iter().zip(packets.iter().skip(1)) {
+            assert_eq!(next.ssrc(), 77);
+            assert_eq!(next.payload_type(), 100);
+            assert_eq!(
+                next.sequence_number(),
+                prev.sequence_number().wrapping_add(1)
+            );
+            assert_eq!(next.timestamp(), prev.timestamp().wrapping_add(960));
+            assert_eq!(next.payload(), &[1, 2, 3]);
+        }
+
+        let deadline = Instant::now() + TIMEOUT;
+
+        while !COMPLETED.lock().contains(&(handle_id, player_id)) {
+            assert!(Instant::now() < deadline, "Playback hasn't completed");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(list::<PlayPlugin>(handle_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn controls() {
+        let janus = MockJanus::<PlayPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+        let path = mjr_file("controls", &[(0, rtp(1, 0, 5)), (50, rtp(2, 2400, 5))]);
+
+        let err = start::<PlayPlugin>(0, Playback::mjr(&path)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        let err = start::<PlayPlugin>(handle_id, Playback::mjr("/nonexistent.mjr")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        let playback = Playback::mjr(&path).set_looped(true);
+        let player_id = start::<PlayPlugin>(handle_id, playback.clone()).unwrap();
+        assert_eq!(
+            list::<PlayPlugin>(handle_id).unwrap(),
+            vec![(player_id, playback)]
+        );
+
+        // Looping keeps the stream continuous.
+        let packets: Vec<Vec<u8>> = (0..3).map(|_| next_packet(&janus).1).collect();
+        let first = RtpPacket::from_bytes(&packets[0]).unwrap();
+        let third = RtpPacket::from_bytes(&packets[2]).unwrap();
+        assert_eq!(
+            third.sequence_number(),
+            first.sequence_number().wrapping_add(2)
+        );
+        assert!(third.timestamp().wrapping_sub(first.timestamp()) > 2400);
+
+        pause::<PlayPlugin>(handle_id, player_id).unwrap();
+        let status = status::<PlayPlugin>(handle_id, player_id).unwrap();
+        assert!(status.paused && status.looped);
+        assert_eq!(status.duration, Duration::from_millis(50));
+        thread::sleep(Duration::from_millis(100));
+
+        // Packets might have been sent before pausing.
+        let last_packet = janus
+            .take_calls()
+            .into_iter()
+            .rev()
+            .find_map(|call| match call {
+                Call::RelayRtp { buffer, .. } => Some(buffer.iter().map(|b| *b as u8).collect()),
+                _ => None,
+            })
+            .unwrap_or_else(|| packets[2].clone());
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(janus.take_calls().is_empty());
+
+        let err = seek::<PlayPlugin>(handle_id, player_id, Duration::from_secs(1)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BadRequest);
+        seek::<PlayPlugin>(handle_id, player_id, Duration::from_millis(50)).unwrap();
+        set_looped::<PlayPlugin>(handle_id, player_id, false).unwrap();
+        resume::<PlayPlugin>(handle_id, player_id).unwrap();
+
+        let packet = next_packet(&janus).1;
+        let packet = RtpPacket::from_bytes(&packet).unwrap();
+        let last_packet = RtpPacket::from_bytes(&last_packet).unwrap();
+        assert_eq!(packet.ssrc(), first.ssrc());
+        assert_eq!(
+            packet.sequence_number(),
+            last_packet.sequence_number().wrapping_add(1)
+        );
+
+        let playback = Playback::mjr(&path).set_looped(true);
+        let player_id = start::<PlayPlugin>(handle_id, playback.clone()).unwrap();
+        stop::<PlayPlugin>(handle_id, player_id).unwrap();
+        let err = stop::<PlayPlugin>(handle_id, player_id).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        start::<PlayPlugin>(handle_id, playback).unwrap();
+        next_packet(&janus);
+        janus.destroy_handle(handle_id).unwrap();
+        assert!(list::<PlayPlugin>(handle_id).unwrap().is_empty());
+
+        // The thread has finished so nothing is being sent after destroying the handle.
+        janus.take_calls();
+        thread::sleep(Duration::from_millis(100));
+        assert!(janus.take_calls().is_empty());
+    }
+
+    #[test]
+    fn play_pcap() {
+        let janus = MockJanus::<PlayPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+
+        let mut capture = vec![0xD4, 0xC3, 0xB2, 0xA1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        capture.extend_from_slice(&[0xFF, 0xFF, 0, 0, 101, 0, 0, 0]);
+
+        let rtcp = vec![0x80, 200, 0, 1, 0, 0, 0, 1];
+        let payloads = [rtp(1, 0, 5), rtcp, rtp(1, 0, 6), rtp(2, 960, 5)];
+
+        for (index, payload) in payloads.iter().enumerate() {
+            let mut packet = vec![0x45, 0];
+            packet.extend_from_slice(&(28 + payload.len() as u16).to_be_bytes());
+            packet.extend_from_slice(&[0, 0, 0, 0, 64, 17, 0, 0, 127, 0, 0, 1, 127, 0, 0, 1]);
+            packet.extend_from_slice(&[0x13, 0x88, 0x13, 0x88]);
+            packet.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+            packet.extend_from_slice(&[0, 0]);
+            packet.extend_from_slice(payload);
+
+            capture.extend_from_slice(&[1, 0, 0, 0]);
+            capture.extend_from_slice(&(index as u32 * 10_000).to_le_bytes());
+            capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&packet);
+        }
+
+        let path = std::env::temp_dir().join(format!("janus-app-play-{}.pcap", std::process::id()));
+        fs::write(&path, capture).unwrap();
+
+        let playback = Playback::pcap(MediaKind::Video, &path).set_ssrc(42);
+        start::<PlayPlugin>(handle_id, playback).unwrap();
+
+        for _ in 0..2 {
+            let (kind, packet) = next_packet(&janus);
+            assert_eq!(kind, MediaKind::Video);
+            assert_eq!(RtpPacket::from_bytes(&packet).unwrap().ssrc(), 42);
+        }
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(!janus
+            .take_calls()
+            .iter()
+            .any(|call| matches!(call, Call::RelayRtp { .. })));
+    }
+}
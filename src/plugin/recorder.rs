@@ -0,0 +1,462 @@
+//! Recording handles' incoming media into `.mjr` files to post-process with `janus-pp-rec`.
+//!
+//! A recorder writes RTP of one media kind the handle receives into a file in the
+//! [mjr](../../mjr/index.html) format. Call [record](fn.record.html) from the handle's
+//! media event handler:
+//!
+//! ```
+//! use janus_app::plugin::recorder::{self, Recording};
+//! use janus_app::plugin::PluginApp;
+//! use janus_app::{Error, MediaEvent, MediaKind};
+//!
+//! fn start_recording<P: PluginApp>(handle_id: u64) -> Result<u32, Error> {
+//!     let path = format!("/recordings/{}-video.mjr", handle_id);
+//!     let recording = Recording::new(MediaKind::Video, "vp8", path);
+//!     recorder::start::<P>(handle_id, recording)
+//! }
+//!
+//! // Called from `Handle::handle_media_event`.
+//! fn handle_media_event<P: PluginApp>(handle_id: u64, media_event: &MediaEvent) {
+//!     if let Err(err) = recorder::record::<P>(handle_id, media_event) {
+//!         eprintln!("Failed to record media: {}", err);
+//!     }
+//! }
+//! ```
+//!
+//! [rotate](fn.rotate.html) closes a recorder's file and continues in a new one, e.g. to split
+//! long calls. The handle's recorders are being stopped and their files closed on
+//! [MediaEvent::Hangup](../../enum.MediaEvent.html#variant.Hangup) passed to `record` and when
+//! the handle is destroyed.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, ErrorKind as IoErrorKind};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use parking_lot::{Mutex, RwLock};
+
+use super::{core, PluginApp};
+use crate::mjr::Writer;
+use crate::rtp;
+use crate::{Error, MediaEvent, MediaKind, MediaProtocol};
+
+/// Media kind, codec and file of a recorder.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recording {
+    kind: MediaKind,
+    codec: String,
+    fmtp: Option<String>,
+    path: PathBuf,
+}
+
+impl Recording {
+    /// Records RTP of `kind` encoded with `codec` into a new file at `path`.
+    /// The codec name is as in Janus, e.g. `opus`, `vp8` or `h264`.
+    pub fn new<T: Into<PathBuf>>(kind: MediaKind, codec: &str, path: T) -> Self {
+        Self {
+            kind,
+            codec: codec.to_owned(),
+            fmtp: None,
+            path: path.into(),
+        }
+    }
+
+    /// Stores the `a=fmtp` parameters of the codec in the file's header.
+    pub fn set_fmtp(self, fmtp: &str) -> Self {
+        Self {
+            fmtp: Some(fmtp.to_owned()),
+            ..self
+        }
+    }
+
+    pub fn kind(&self) -> MediaKind {
+        self.kind
+    }
+
+    pub fn codec(&self) -> &str {
+        &self.codec
+    }
+
+    pub fn fmtp(&self) -> Option<&str> {
+        self.fmtp.as_deref()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+    }
+
+    fn create_writer(&self) -> Result<Writer<BufWriter<File>>, Error> {
+        let file = create_file(&self.path)?;
+        let writer = Writer::new(BufWriter::new(file), self.kind, &self.codec);
+
+        match self.fmtp {
+            Some(ref fmtp) => Ok(writer.set_fmtp(fmtp)),
+            None => Ok(writer),
+        }
+    }
+}
+
+/// Creates the file failing with `conflict` if it exists to never overwrite a recording.
+fn create_file(path: &Path) -> Result<File, Error> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|err| {
+            let detail = format!("Failed to create recording {}: {}", path.display(), err);
+
+            match err.kind() {
+                IoErrorKind::AlreadyExists => Error::conflict(&detail),
+                _ => Error::new(&detail),
+            }
+        })
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Starts recording the handle's media and returns the recorder ID which is unique
+/// within the plugin. Fails with `not_found` if the handle doesn't exist and with `conflict`
+/// if the file exists.
+pub fn start<P: PluginApp>(handle_id: u64, recording: Recording) -> Result<u32, Error> {
+    let core = core::<P>()?;
+    let is_registered = || core.handle_registry().get_by_id(handle_id).is_some();
+    core.recorders().start(handle_id, recording, is_registered)
+}
+
+/// Stops the recorder of the handle and closes its file.
+/// Fails with `not_found` if there's no such recorder.
+pub fn stop<P: PluginApp>(handle_id: u64, recorder_id: u32) -> Result<(), Error> {
+    core::<P>()?.recorders().stop(handle_id, recorder_id)
+}
+
+/// Closes the recorder's file and continues recording into a new file at `path`.
+/// Returns the path of the closed file.
+///
+/// Fails with `not_found` if there's no such recorder and with `conflict` if the new file exists.
+/// The recorder keeps writing the old file if the new one can't be created.
+pub fn rotate<P: PluginApp>(
+    handle_id: u64,
+    recorder_id: u32,
+    path: &Path,
+) -> Result<PathBuf, Error> {
+    core::<P>()?
+        .recorders()
+        .rotate(handle_id, recorder_id, path.to_owned())
+}
+
+/// Returns recorders of the handle with their IDs in the order of starting.
+pub fn list<P: PluginApp>(handle_id: u64) -> Result<Vec<(u32, Recording)>, Error> {
+    Ok(core::<P>()?.recorders().list(handle_id))
+}
+
+/// Writes the handle's incoming RTP to its recorders of the media kind and stops all of them
+/// on hangup. Other events are being ignored.
+///
+/// A failure to write a file doesn't prevent writing the others; the first one is being returned.
+pub fn record<P: PluginApp>(handle_id: u64, media_event: &MediaEvent) -> Result<(), Error> {
+    match media_event {
+        MediaEvent::Media {
+            protocol: MediaProtocol::Rtp,
+            kind,
+            buffer,
+            ..
+        } => core::<P>()?
+            .recorders()
+            .record(handle_id, *kind, rtp::to_bytes(buffer)),
+        MediaEvent::Hangup => core::<P>()?.recorders().stop_all(handle_id),
+        _ => Ok(()),
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Recorders of the app's handles.
+#[derive(Default)]
+pub(crate) struct Recorders {
+    handles: RwLock<HashMap<u64, Vec<Recorder>>>,
+    last_id: AtomicU32,
+}
+
+struct Recorder {
+    id: u32,
+    recording: Recording,
+    // Media events of a handle may be dispatched on several Janus threads at once.
+    writer: Mutex<Writer<BufWriter<File>>>,
+}
+
+impl Recorder {
+    fn close(&self) -> Result<(), Error> {
+        self.writer.lock().flush()
+    }
+}
+
+impl Recorders {
+    /// Registration is being checked under the lock so the handle can't be destroyed
+    /// and cleaned up in between leaving an open file.
+    fn start<F>(&self, handle_id: u64, recording: Recording, is_registered: F) -> Result<u32, Error>
+    where
+        F: FnOnce() -> bool,
+    {
+        let mut handles = self.handles.write();
+
+        if !is_registered() {
+            return Err(Error::not_found(&format!("Handle {} not found", handle_id)));
+        }
+
+        let writer = recording.create_writer()?;
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+
+        handles.entry(handle_id).or_default().push(Recorder {
+            id,
+            recording,
+            writer: Mutex::new(writer),
+        });
+
+        Ok(id)
+    }
+
+    fn stop(&self, handle_id: u64, recorder_id: u32) -> Result<(), Error> {
+        let mut handles = self.handles.write();
+
+        let recorders = handles
+            .get_mut(&handle_id)
+            .ok_or_else(|| recorder_not_found(handle_id, recorder_id))?;
+
+        let index = recorders
+            .iter()
+            .position(|recorder| recorder.id == recorder_id)
+            .ok_or_else(|| recorder_not_found(handle_id, recorder_id))?;
+
+        let recorder = recorders.remove(index);
+
+        if recorders.is_empty() {
+            handles.remove(&handle_id);
+        }
+
+        recorder.close()
+    }
+
+    fn rotate(&self, handle_id: u64, recorder_id: u32, path: PathBuf) -> Result<PathBuf, Error> {
+        let mut handles = self.handles.write();
+
+        let recorder = handles
+            .get_mut(&handle_id)
+            .and_then(|recorders| recorders.iter_mut().find(|r| r.id == recorder_id))
+            .ok_or_else(|| recorder_not_found(handle_id, recorder_id))?;
+
+        let mut recording = recorder.recording.clone();
+        recording.set_path(path);
+        let writer = recording.create_writer()?;
+
+        let mut old_writer = mem::replace(recorder.writer.get_mut(), writer);
+        let old_recording = mem::replace(&mut recorder.recording, recording);
+        old_writer.flush()?;
+        Ok(old_recording.path)
+    }
+
+    fn list(&self, handle_id: u64) -> Vec<(u32, Recording)> {
+        let handles = self.handles.read();
+
+        handles
+            .get(&handle_id)
+            .map(|recorders| {
+                recorders
+                    .iter()
+                    .map(|recorder| (recorder.id, recorder.recording.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn record(&self, handle_id: u64, kind: MediaKind, packet: &[u8]) -> Result<(), Error> {
+        let handles = self.handles.read();
+
+        let recorders = match handles.get(&handle_id) {
+            Some(recorders) => recorders,
+            None => return Ok(()),
+        };
+
+        let mut result = Ok(());
+
+        for recorder in recorders.iter().filter(|r| r.recording.kind == kind) {
+            if let Err(err) = recorder.writer.lock().write_packet(packet) {
+                if result.is_ok() {
+                    let path = recorder.recording.path.display();
+                    result = Err(Error::new(&format!("{}: {}", path, err)));
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Stops all of the handle's recorders and closes their files.
+    fn stop_all(&self, handle_id: u64) -> Result<(), Error> {
+        let recorders = match self.handles.write().remove(&handle_id) {
+            Some(recorders) => recorders,
+            None => return Ok(()),
+        };
+
+        let mut result = Ok(());
+
+        for recorder in recorders {
+            if let Err(err) = recorder.close() {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+
+        result
+    }
+
+    pub(crate) fn remove_handle(&self, handle_id: u64) {
+        if let Err(err) = self.stop_all(handle_id) {
+            log::error!(
+                "Failed to close recordings of handle {}: {}",
+                handle_id,
+                err
+            );
+        }
+    }
+}
+
+fn recorder_not_found(handle_id: u64, recorder_id: u32) -> Error {
+    let err = format!("Recorder {} of handle {} not found", recorder_id, handle_id);
+    Error::not_found(&err)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::{test_plugin, MockJanus};
+    use crate::ErrorKind;
+
+    test_plugin!(RecordPlugin, RecordHandle, |handle_id, media_event| {
+        record::<RecordPlugin>(handle_id, media_event).unwrap()
+    });
+
+    const RTP: [i8; 16] = [-128, 111, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 1, 2, 3, 4];
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("janus-app-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    /// Packets of the frames skipping the header.
+    fn frames(path: &Path) -> Vec<Vec<u8>> {
+        let bytes = fs::read(path).unwrap();
+        assert_eq!(&bytes[..8], b"MJR00002");
+        let info_len = u16::from_be_bytes([bytes[8], bytes[9]]) as usize;
+        let mut rest = &bytes[10 + info_len..];
+        let mut packets = vec![];
+
+        while !rest.is_empty() {
+            assert_eq!(&rest[..4], b"MEET");
+            let len = u16::from_be_bytes([rest[8], rest[9]]) as usize;
+            packets.push(rest[10..10 + len].to_vec());
+            rest = &rest[10 + len..];
+        }
+
+        packets
+    }
+
+    #[test]
+    fn record_and_hangup() {
+        let janus = MockJanus::<RecordPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+        let dir = temp_dir("record");
+        let audio_path = dir.join("audio.mjr");
+        let video_path = dir.join("video.mjr");
+
+        let audio = Recording::new(MediaKind::Audio, "opus", &audio_path);
+        let video = Recording::new(MediaKind::Video, "vp8", &video_path);
+        start::<RecordPlugin>(handle_id, audio).unwrap();
+        start::<RecordPlugin>(handle_id, video).unwrap();
+
+        janus
+            .incoming_rtp(handle_id, MediaKind::Audio, &RTP)
+            .unwrap();
+
+        janus
+            .incoming_rtp(handle_id, MediaKind::Audio, &RTP[..12])
+            .unwrap();
+
+        janus
+            .incoming_rtcp(handle_id, MediaKind::Audio, &[-127, -55, 0, 1, 0, 0, 0, 1])
+            .unwrap();
+
+        janus.hangup_media(handle_id).unwrap();
+        assert!(list::<RecordPlugin>(handle_id).unwrap().is_empty());
+
+        let packet = rtp::to_bytes(&RTP).to_vec();
+        assert_eq!(
+            frames(&audio_path),
+            vec![packet.clone(), packet[..12].to_vec()]
+        );
+        assert!(fs::read(&video_path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn lifecycle() {
+        let janus = MockJanus::<RecordPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+        let dir = temp_dir("lifecycle");
+        let recording = Recording::new(MediaKind::Audio, "opus", dir.join("1.mjr"));
+
+        let err = start::<RecordPlugin>(0, recording.clone()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        let id = start::<RecordPlugin>(handle_id, recording.clone()).unwrap();
+        let err = start::<RecordPlugin>(handle_id, recording.clone()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Conflict);
+        assert_eq!(
+            list::<RecordPlugin>(handle_id).unwrap(),
+            vec![(id, recording)]
+        );
+
+        janus
+            .incoming_rtp(handle_id, MediaKind::Audio, &RTP)
+            .unwrap();
+
+        let old_path = rotate::<RecordPlugin>(handle_id, id, &dir.join("2.mjr")).unwrap();
+        assert_eq!(old_path, dir.join("1.mjr"));
+        let err = rotate::<RecordPlugin>(handle_id, id, &dir.join("1.mjr")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Conflict);
+
+        janus
+            .incoming_rtp(handle_id, MediaKind::Audio, &RTP[..12])
+            .unwrap();
+
+        let packet = rtp::to_bytes(&RTP).to_vec();
+        assert_eq!(frames(&dir.join("1.mjr")), vec![packet.clone()]);
+
+        stop::<RecordPlugin>(handle_id, id).unwrap();
+        let err = stop::<RecordPlugin>(handle_id, id).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(frames(&dir.join("2.mjr")), vec![packet[..12].to_vec()]);
+
+        let recording = Recording::new(MediaKind::Video, "vp8", dir.join("3.mjr"));
+        start::<RecordPlugin>(handle_id, recording).unwrap();
+
+        janus
+            .incoming_rtp(handle_id, MediaKind::Video, &RTP)
+            .unwrap();
+
+        janus.destroy_handle(handle_id).unwrap();
+        assert!(list::<RecordPlugin>(handle_id).unwrap().is_empty());
+        assert_eq!(frames(&dir.join("3.mjr")), vec![packet]);
+    }
+}
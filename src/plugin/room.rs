@@ -0,0 +1,697 @@
+//! Rooms relaying media of publishers to subscribers.
+//!
+//! A room is a set of handles identified by a string ID where each member is a publisher,
+//! a subscriber or both. Media a publisher sends to the plugin is being relayed to the room's
+//! subscribers by calling [relay](fn.relay.html) from the handle's media event handler:
+//!
+//! ```
+//! use janus_app::plugin::room::{self, Role, Subscription};
+//! use janus_app::plugin::PluginApp;
+//! use janus_app::{Error, MediaEvent};
+//!
+//! fn join<P: PluginApp>(handle_id: u64, room_id: &str, video: bool) -> Result<(), Error> {
+//!     room::join::<P>(room_id, handle_id, Role::Subscriber)?;
+//!
+//!     let subscription = Subscription {
+//!         video,
+//!         ..Default::default()
+//!     };
+//!
+//!     room::set_subscription::<P>(room_id, handle_id, subscription)
+//! }
+//!
+//! // Called from `Handle::handle_media_event`.
+//! fn handle_media_event<P: PluginApp>(handle_id: u64, media_event: &MediaEvent) {
+//!     if let Err(err) = room::relay::<P>(handle_id, media_event) {
+//!         eprintln!("Failed to relay media: {}", err);
+//!     }
+//! }
+//! ```
+//!
+//! Rooms are being created on the first join and removed after the last member leaves.
+//! A destroyed handle leaves its rooms right after its
+//! [on_destroy](../../trait.Handle.html#method.on_destroy) hook so the hook may still list
+//! the members, e.g. to notify them.
+//!
+//! Subscribers' PLI and FIR RTCP feedback is being turned into keyframe requests to the video
+//! publishers. Other RTCP isn't being relayed since Janus core generates reports for each
+//! PeerConnection itself.
+
+use std::collections::{HashMap, HashSet};
+
+use parking_lot::RwLock;
+use serde_derive::{Deserialize, Serialize};
+
+use super::{
+    core, relay_data, relay_media, send_keyframe_request, Core, JanusPluginSession,
+    KeyframeRequest, PluginApp,
+};
+use crate::rtcp::{self, RtcpPacket};
+use crate::{Error, MediaEvent, MediaKind, MediaProtocol};
+
+/// Role of a room member.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Sends media to the subscribers.
+    Publisher,
+    /// Receives media of the publishers.
+    Subscriber,
+    /// Both sends and receives media, e.g. in an audio conference.
+    /// Its own media isn't being relayed back to it.
+    Participant,
+}
+
+impl Role {
+    fn publishes(self) -> bool {
+        matches!(self, Self::Publisher | Self::Participant)
+    }
+
+    fn subscribes(self) -> bool {
+        matches!(self, Self::Subscriber | Self::Participant)
+    }
+}
+
+/// Kinds of media being relayed to a subscriber. All of them are enabled by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Subscription {
+    pub audio: bool,
+    pub video: bool,
+    pub data: bool,
+}
+
+impl Subscription {
+    fn accepts(&self, kind: MediaKind) -> bool {
+        match kind {
+            MediaKind::Audio => self.audio,
+            MediaKind::Video => self.video,
+        }
+    }
+}
+
+impl Default for Subscription {
+    fn default() -> Self {
+        Self {
+            audio: true,
+            video: true,
+            data: true,
+        }
+    }
+}
+
+/// Handle in a room.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Member {
+    handle_id: u64,
+    role: Role,
+    subscription: Subscription,
+}
+
+impl Member {
+    pub fn handle_id(&self) -> u64 {
+        self.handle_id
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn subscription(&self) -> Subscription {
+        self.subscription
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Adds the handle to the room with all kinds of media subscribed. The room is being created
+/// if it doesn't exist yet.
+///
+/// Fails with `conflict` if the handle is already in the room and with `not_found` if
+/// the handle doesn't exist.
+pub fn join<P: PluginApp>(room_id: &str, handle_id: u64, role: Role) -> Result<(), Error> {
+    let core = core::<P>()?;
+
+    let member = Member {
+        handle_id,
+        role,
+        subscription: Subscription::default(),
+    };
+
+    let is_registered = || core.handle_registry().get_by_id(handle_id).is_some();
+    core.rooms().join(room_id, member, is_registered)
+}
+
+/// Removes the handle from the room. The room is being removed if it gets empty.
+/// Fails with `not_found` if the handle isn't in the room.
+pub fn leave<P: PluginApp>(room_id: &str, handle_id: u64) -> Result<(), Error> {
+    core::<P>()?.rooms().leave(room_id, handle_id)
+}
+
+/// Changes kinds of media being relayed to the handle in the room.
+/// Fails with `not_found` if the handle isn't in the room.
+pub fn set_subscription<P: PluginApp>(
+    room_id: &str,
+    handle_id: u64,
+    subscription: Subscription,
+) -> Result<(), Error> {
+    core::<P>()?
+        .rooms()
+        .set_subscription(room_id, handle_id, subscription)
+}
+
+/// Returns members of the room in the order of joining or none if there's no such room.
+pub fn members<P: PluginApp>(room_id: &str) -> Result<Vec<Member>, Error> {
+    Ok(core::<P>()?.rooms().members(room_id))
+}
+
+/// Returns IDs of the rooms the handle is in.
+pub fn rooms_of<P: PluginApp>(handle_id: u64) -> Result<Vec<String>, Error> {
+    Ok(core::<P>()?.rooms().rooms_of(handle_id))
+}
+
+/// Relays the handle's media event to the members of its rooms.
+///
+/// RTP and data of a publisher go to the subscribers which accept them. PLI and FIR from
+/// a subscriber with video go to the publishers as a keyframe request. Other events are
+/// being ignored. Each member gets a packet once even if it shares several rooms with the sender.
+///
+/// A failure to relay to a member doesn't prevent relaying to the others;
+/// the first one is being returned.
+pub fn relay<P: PluginApp>(handle_id: u64, media_event: &MediaEvent) -> Result<(), Error> {
+    let core = core::<P>()?;
+
+    match media_event {
+        MediaEvent::Media {
+            protocol: MediaProtocol::Rtp,
+            kind,
+            buffer,
+            ..
+        } => {
+            let callbacks = core.janus_callbacks()?;
+
+            let pick = |sender: &Member, member: &Member| {
+                sender.role.publishes()
+                    && member.role.subscribes()
+                    && member.subscription.accepts(*kind)
+            };
+
+            for_each_raw_handle(core, handle_id, pick, |raw_handle| {
+                relay_media(callbacks, raw_handle, MediaProtocol::Rtp, *kind, buffer)
+            })
+        }
+        MediaEvent::Media {
+            protocol: MediaProtocol::Rtcp,
+            kind: MediaKind::Video,
+            buffer,
+            ..
+        } => {
+            let request = match keyframe_request(buffer)? {
+                Some(request) => request,
+                None => return Ok(()),
+            };
+
+            let callbacks = core.janus_callbacks()?;
+
+            let pick = |sender: &Member, member: &Member| {
+                sender.role.subscribes() && sender.subscription.video && member.role.publishes()
+            };
+
+            for_each_raw_handle(core, handle_id, pick, |raw_handle| {
+                send_keyframe_request(callbacks, raw_handle, request)
+            })
+        }
+        MediaEvent::Data { buffer, info } => {
+            let callbacks = core.janus_callbacks()?;
+
+            let pick = |sender: &Member, member: &Member| {
+                sender.role.publishes() && member.role.subscribes() && member.subscription.data
+            };
+
+            for_each_raw_handle(core, handle_id, pick, |raw_handle| {
+                relay_data(callbacks, raw_handle, buffer, *info)
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Picks the keyframe request from subscriber's RTCP feedback if there's any.
+fn keyframe_request(buffer: &[i8]) -> Result<Option<KeyframeRequest>, Error> {
+    let request = rtcp::parse(buffer)?.iter().find_map(|packet| match packet {
+        RtcpPacket::Pli(_) => Some(KeyframeRequest::Pli),
+        RtcpPacket::Fir(_) => Some(KeyframeRequest::Fir),
+        _ => None,
+    });
+
+    Ok(request)
+}
+
+/// Calls `f` for raw handles of the members picked as in [Rooms::for_each_target].
+/// Handles being destroyed at the moment are skipped.
+fn for_each_raw_handle<P, G, F>(
+    core: &Core<P>,
+    handle_id: u64,
+    pick: G,
+    mut f: F,
+) -> Result<(), Error>
+where
+    P: PluginApp,
+    G: Fn(&Member, &Member) -> bool,
+    F: FnMut(*mut JanusPluginSession) -> Result<(), Error>,
+{
+    core.rooms().for_each_target(handle_id, pick, |target_id| {
+        match core.handle_registry().get_by_id(target_id) {
+            Some(entry) => f(entry.raw_handle()),
+            None => Ok(()),
+        }
+    })
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Room memberships of the app's handles.
+#[derive(Default)]
+pub(crate) struct Rooms {
+    inner: RwLock<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    rooms: HashMap<String, Vec<Member>>,
+    // Room IDs by handle to relay and clean up without scanning all of the rooms.
+    memberships: HashMap<u64, Vec<String>>,
+}
+
+impl Rooms {
+    /// Registration is being checked under the lock so the handle can't be destroyed
+    /// and cleaned up in between leaving a stale member.
+    fn join<F>(&self, room_id: &str, member: Member, is_registered: F) -> Result<(), Error>
+    where
+        F: FnOnce() -> bool,
+    {
+        let mut inner = self.inner.write();
+
+        if !is_registered() {
+            let err = format!("Handle {} not found", member.handle_id);
+            return Err(Error::not_found(&err));
+        }
+
+        let members = inner.rooms.entry(room_id.to_owned()).or_default();
+
+        if members.iter().any(|m| m.handle_id == member.handle_id) {
+            let err = format!("Handle {} is already in room {}", member.handle_id, room_id);
+            return Err(Error::conflict(&err));
+        }
+
+        members.push(member);
+
+        inner
+            .memberships
+            .entry(member.handle_id)
+            .or_default()
+            .push(room_id.to_owned());
+
+        Ok(())
+    }
+
+    fn leave(&self, room_id: &str, handle_id: u64) -> Result<(), Error> {
+        let mut inner = self.inner.write();
+
+        if !inner.remove_member(room_id, handle_id) {
+            return Err(not_in_room(room_id, handle_id));
+        }
+
+        if let Some(room_ids) = inner.memberships.get_mut(&handle_id) {
+            room_ids.retain(|id| id != room_id);
+
+            if room_ids.is_empty() {
+                inner.memberships.remove(&handle_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_subscription(
+        &self,
+        room_id: &str,
+        handle_id: u64,
+        subscription: Subscription,
+    ) -> Result<(), Error> {
+        let mut inner = self.inner.write();
+
+        let member = inner
+            .rooms
+            .get_mut(room_id)
+            .and_then(|members| members.iter_mut().find(|m| m.handle_id == handle_id))
+            .ok_or_else(|| not_in_room(room_id, handle_id))?;
+
+        member.subscription = subscription;
+        Ok(())
+    }
+
+    fn members(&self, room_id: &str) -> Vec<Member> {
+        let inner = self.inner.read();
+        inner.rooms.get(room_id).cloned().unwrap_or_default()
+    }
+
+    fn rooms_of(&self, handle_id: u64) -> Vec<String> {
+        let inner = self.inner.read();
+        inner
+            .memberships
+            .get(&handle_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Calls `f` with IDs of the other members of the handle's rooms picked by
+    /// `pick(sender, member)`. Each member is being passed once. A failure doesn't prevent
+    /// calling `f` for the rest of the members; the first one is being returned.
+    fn for_each_target<G, F>(&self, handle_id: u64, pick: G, mut f: F) -> Result<(), Error>
+    where
+        G: Fn(&Member, &Member) -> bool,
+        F: FnMut(u64) -> Result<(), Error>,
+    {
+        let inner = self.inner.read();
+
+        let room_ids = match inner.memberships.get(&handle_id) {
+            Some(room_ids) => room_ids,
+            None => return Ok(()),
+        };
+
+        // Members of a single room are unique so the IDs are only tracked across rooms.
+        let mut passed = HashSet::new();
+        let track = room_ids.len() > 1;
+        let mut result = Ok(());
+
+        for members in room_ids.iter().filter_map(|id| inner.rooms.get(id)) {
+            let sender = match members.iter().find(|m| m.handle_id == handle_id) {
+                Some(sender) => sender,
+                None => continue,
+            };
+
+            for member in members {
+                if member.handle_id == handle_id || !pick(sender, member) {
+                    continue;
+                }
+
+                if track && !passed.insert(member.handle_id) {
+                    continue;
+                }
+
+                if let Err(err) = f(member.handle_id) {
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Removes the handle from all of its rooms.
+    pub(crate) fn remove_handle(&self, handle_id: u64) {
+        let mut inner = self.inner.write();
+
+        if let Some(room_ids) = inner.memberships.remove(&handle_id) {
+            for room_id in room_ids {
+                inner.remove_member(&room_id, handle_id);
+            }
+        }
+    }
+}
+
+impl Inner {
+    /// Removes the member and the room if it gets empty. Memberships are up to the caller.
+    fn remove_member(&mut self, room_id: &str, handle_id: u64) -> bool {
+        let members = match self.rooms.get_mut(room_id) {
+            Some(members) => members,
+            None => return false,
+        };
+
+        let len = members.len();
+        members.retain(|m| m.handle_id != handle_id);
+        let removed = members.len() < len;
+
+        if members.is_empty() {
+            self.rooms.remove(room_id);
+        }
+
+        removed
+    }
+}
+
+fn not_in_room(room_id: &str, handle_id: u64) -> Error {
+    Error::not_found(&format!("Handle {} is not in room {}", handle_id, room_id))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtcp::Pli;
+    use crate::testing::{test_plugin, Call, MockJanus};
+    use crate::{DataInfo, ErrorKind};
+
+    test_plugin!(RoomPlugin, RoomHandle, |handle_id, media_event| {
+        relay::<RoomPlugin>(handle_id, media_event).unwrap()
+    });
+
+    fn relayed_rtp(handle_id: u64, kind: MediaKind) -> Call {
+        Call::RelayRtp {
+            handle_id,
+            kind,
+            buffer: vec![-128, 96, 0, 1],
+        }
+    }
+
+    #[test]
+    fn relay_to_subscribers() {
+        let janus = MockJanus::<RoomPlugin>::new("/tmp").unwrap();
+        let publisher = janus.create_handle().unwrap();
+        let subscriber = janus.create_handle().unwrap();
+        let audio_subscriber = janus.create_handle().unwrap();
+        let outsider = janus.create_handle().unwrap();
+
+        join::<RoomPlugin>("1", publisher, Role::Publisher).unwrap();
+        join::<RoomPlugin>("1", subscriber, Role::Subscriber).unwrap();
+        join::<RoomPlugin>("1", audio_subscriber, Role::Subscriber).unwrap();
+        join::<RoomPlugin>("2", outsider, Role::Subscriber).unwrap();
+
+        let subscription = Subscription {
+            video: false,
+            data: false,
+            ..Default::default()
+        };
+
+        set_subscription::<RoomPlugin>("1", audio_subscriber, subscription).unwrap();
+
+        for kind in [MediaKind::Video, MediaKind::Audio] {
+            janus
+                .incoming_rtp(publisher, kind, &[-128, 96, 0, 1])
+                .unwrap();
+        }
+
+        janus
+            .incoming_data(publisher, &[104, 105], DataInfo::default())
+            .unwrap();
+
+        // Subscribers' media goes nowhere.
+        janus
+            .incoming_rtp(subscriber, MediaKind::Audio, &[-128, 96, 0, 1])
+            .unwrap();
+
+        assert_eq!(
+            janus.take_calls(),
+            vec![
+                relayed_rtp(subscriber, MediaKind::Video),
+                relayed_rtp(subscriber, MediaKind::Audio),
+                relayed_rtp(audio_subscriber, MediaKind::Audio),
+                Call::RelayData {
+                    handle_id: subscriber,
+                    buffer: vec![104, 105],
+                    binary: false,
+                    label: None,
+                },
+            ]
+        );
+    }
+
+    #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+    #[test]
+    fn relay_binary_data() {
+        let janus = MockJanus::<RoomPlugin>::new("/tmp").unwrap();
+        let publisher = janus.create_handle().unwrap();
+        let subscriber = janus.create_handle().unwrap();
+        join::<RoomPlugin>("1", publisher, Role::Publisher).unwrap();
+        join::<RoomPlugin>("1", subscriber, Role::Subscriber).unwrap();
+
+        let info = DataInfo {
+            label: Some("files"),
+            binary: true,
+            ..Default::default()
+        };
+
+        janus.incoming_data(publisher, &[0, -1], info).unwrap();
+
+        assert_eq!(
+            janus.take_calls(),
+            vec![Call::RelayData {
+                handle_id: subscriber,
+                buffer: vec![0, -1],
+                binary: true,
+                label: Some(String::from("files")),
+            }]
+        );
+    }
+
+    #[test]
+    fn relay_between_participants() {
+        let janus = MockJanus::<RoomPlugin>::new("/tmp").unwrap();
+        let first = janus.create_handle().unwrap();
+        let second = janus.create_handle().unwrap();
+
+        for room_id in ["1", "2"] {
+            join::<RoomPlugin>(room_id, first, Role::Participant).unwrap();
+            join::<RoomPlugin>(room_id, second, Role::Participant).unwrap();
+        }
+
+        janus
+            .incoming_rtp(first, MediaKind::Audio, &[-128, 96, 0, 1])
+            .unwrap();
+
+        assert_eq!(
+            janus.take_calls(),
+            vec![relayed_rtp(second, MediaKind::Audio)]
+        );
+    }
+
+    #[test]
+    fn keyframe_request() {
+        let janus = MockJanus::<RoomPlugin>::new("/tmp").unwrap();
+        let publisher = janus.create_handle().unwrap();
+        let subscriber = janus.create_handle().unwrap();
+        join::<RoomPlugin>("1", publisher, Role::Publisher).unwrap();
+        join::<RoomPlugin>("1", subscriber, Role::Subscriber).unwrap();
+
+        let pli = rtcp::serialize(&[RtcpPacket::Pli(Pli {
+            sender_ssrc: 1,
+            media_ssrc: 2,
+        })]);
+
+        janus
+            .incoming_rtcp(subscriber, MediaKind::Video, &pli)
+            .unwrap();
+
+        // Publishers' feedback goes nowhere.
+        janus
+            .incoming_rtcp(publisher, MediaKind::Video, &pli)
+            .unwrap();
+
+        let calls = janus.take_calls();
+        assert_eq!(calls.len(), 1, "{:?}", calls);
+
+        match &calls[0] {
+            Call::SendPli { handle_id } | Call::RelayRtcp { handle_id, .. } => {
+                assert_eq!(*handle_id, publisher)
+            }
+            other => panic!("Unexpected call: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn membership() {
+        let janus = MockJanus::<RoomPlugin>::new("/tmp").unwrap();
+        let publisher = janus.create_handle().unwrap();
+        let subscriber = janus.create_handle().unwrap();
+
+        join::<RoomPlugin>("1", publisher, Role::Publisher).unwrap();
+        join::<RoomPlugin>("1", subscriber, Role::Subscriber).unwrap();
+        join::<RoomPlugin>("2", subscriber, Role::Participant).unwrap();
+
+        let err = join::<RoomPlugin>("1", subscriber, Role::Publisher).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Conflict);
+        let err = join::<RoomPlugin>("1", 0, Role::Publisher).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        let room_members = members::<RoomPlugin>("1").unwrap();
+        let ids = room_members
+            .iter()
+            .map(Member::handle_id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![publisher, subscriber]);
+        assert_eq!(room_members[1].role(), Role::Subscriber);
+        assert_eq!(rooms_of::<RoomPlugin>(subscriber).unwrap(), vec!["1", "2"]);
+
+        leave::<RoomPlugin>("2", subscriber).unwrap();
+        let err = leave::<RoomPlugin>("2", subscriber).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(members::<RoomPlugin>("2").unwrap().is_empty());
+
+        janus.destroy_handle(publisher).unwrap();
+        assert_eq!(
+            rooms_of::<RoomPlugin>(publisher).unwrap(),
+            Vec::<String>::new()
+        );
+
+        let room_members = members::<RoomPlugin>("1").unwrap();
+        assert_eq!(room_members.len(), 1);
+        assert_eq!(room_members[0].handle_id(), subscriber);
+
+        janus.destroy_handle(subscriber).unwrap();
+        assert!(members::<RoomPlugin>("1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn fan_out_past_failures() {
+        let rooms = Rooms::default();
+
+        let member = |handle_id, role| Member {
+            handle_id,
+            role,
+            subscription: Subscription::default(),
+        };
+
+        rooms
+            .join("1", member(1, Role::Publisher), || true)
+            .unwrap();
+        rooms
+            .join("2", member(1, Role::Publisher), || true)
+            .unwrap();
+
+        for handle_id in 2..5 {
+            rooms
+                .join("1", member(handle_id, Role::Subscriber), || true)
+                .unwrap();
+        }
+
+        rooms
+            .join("2", member(3, Role::Subscriber), || true)
+            .unwrap();
+        rooms
+            .join("2", member(5, Role::Subscriber), || true)
+            .unwrap();
+
+        let mut targets = vec![];
+
+        let result = rooms.for_each_target(
+            1,
+            |sender, member| sender.role.publishes() && member.role.subscribes(),
+            |handle_id| {
+                targets.push(handle_id);
+
+                if handle_id % 2 == 0 {
+                    Err(Error::new(&format!("Handle {} failed", handle_id)))
+                } else {
+                    Ok(())
+                }
+            },
+        );
+
+        assert_eq!(targets, vec![2, 3, 4, 5]);
+        assert_eq!(result.unwrap_err().to_string(), "Handle 2 failed");
+    }
+}
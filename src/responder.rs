@@ -0,0 +1,436 @@
+//! Single-use replies to incoming messages.
+
+use std::cmp::{Ordering as CmpOrdering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use parking_lot::{Condvar, Mutex};
+use serde::ser::Serialize;
+
+use crate::{logger, Error, Jsep};
+
+/// Default time for a handler to reply before the client gets a timeout error.
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Destination of the replies, i.e. the Janus handle which received the message.
+pub(crate) trait Sink<O>: Send + Sync {
+    fn push_response(
+        &self,
+        transaction: &str,
+        payload: &O,
+        jsep: Option<&Jsep>,
+    ) -> Result<(), Error>;
+
+    fn push_error(&self, transaction: &str, err: &Error) -> Result<(), Error>;
+}
+
+struct State<O> {
+    handle_id: u64,
+    transaction: String,
+    replied: AtomicBool,
+    sink: Box<dyn Sink<O>>,
+}
+
+impl<O> State<O> {
+    /// Returns `true` only for the first caller so a transaction is being replied only once.
+    fn claim(&self) -> bool {
+        !self.replied.swap(true, Ordering::AcqRel)
+    }
+
+    fn try_claim(&self) -> Result<(), Error> {
+        if self.claim() {
+            Ok(())
+        } else {
+            Err(Error::conflict(&format!(
+                "Transaction {} has already been replied",
+                self.transaction
+            )))
+        }
+    }
+}
+
+/// Converts replies of a [mapped](struct.Responder.html#method.map) responder and pushes them
+/// through the original one.
+struct MapSink<O, F> {
+    inner: Arc<State<O>>,
+    convert: F,
+}
+
+impl<O, T, F> Sink<T> for MapSink<O, F>
+where
+    F: Fn(&T) -> Result<O, Error> + Send + Sync,
+{
+    fn push_response(
+        &self,
+        transaction: &str,
+        payload: &T,
+        jsep: Option<&Jsep>,
+    ) -> Result<(), Error> {
+        self.inner.try_claim()?;
+
+        match (self.convert)(payload) {
+            Ok(payload) => self.inner.sink.push_response(transaction, &payload, jsep),
+            Err(err) => {
+                // Don't leave the client waiting for the timeout.
+                self.inner.sink.push_error(transaction, &err)?;
+                Err(err)
+            }
+        }
+    }
+
+    fn push_error(&self, transaction: &str, err: &Error) -> Result<(), Error> {
+        self.inner.try_claim()?;
+        self.inner.sink.push_error(transaction, err)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Replies to a single incoming message with its transaction.
+///
+/// It's being passed to [Handle::handle_message](../trait.Handle.html#tymethod.handle_message)
+/// and may be moved into another thread or a future. Replying consumes the responder so a message
+/// can't be replied twice. If the handler returns
+/// [Ack](../enum.MessageResponse.html#variant.Ack) and doesn't reply within the
+/// [plugin's timeout](../trait.Plugin.html#method.response_timeout) the client gets
+/// a `timeout` error event and the miss is being logged.
+///
+/// Returning [Syncronous](../enum.MessageResponse.html#variant.Syncronous) or an error from the
+/// handler consumes the transaction as well so later replies fail with a `conflict` error.
+///
+/// ```
+/// # use janus_app::{Error, Jsep, Responder};
+/// fn answer(responder: Responder<serde_json::Value>, jsep: Jsep) -> Result<(), Error> {
+///     responder.reply_with_jsep(serde_json::json!({ "status": "ok" }), jsep)
+/// }
+/// ```
+pub struct Responder<O> {
+    state: Arc<State<O>>,
+}
+
+impl<O: Serialize + 'static> Responder<O> {
+    pub(crate) fn new(handle_id: u64, transaction: String, sink: Box<dyn Sink<O>>) -> Self {
+        Self {
+            state: Arc::new(State {
+                handle_id,
+                transaction,
+                replied: AtomicBool::new(false),
+                sink,
+            }),
+        }
+    }
+
+    /// Another responder for the same transaction kept by the crate to finish it on
+    /// the handler's behalf.
+    pub(crate) fn share(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+
+    pub(crate) fn handle_id(&self) -> u64 {
+        self.state.handle_id
+    }
+
+    pub fn transaction(&self) -> &str {
+        &self.state.transaction
+    }
+
+    /// Whether the transaction is already finished either by a reply or by the timeout.
+    pub fn is_replied(&self) -> bool {
+        self.state.replied.load(Ordering::Acquire)
+    }
+
+    /// Pushes the payload as an event with the transaction.
+    pub fn reply(self, payload: O) -> Result<(), Error> {
+        self.claim()?;
+        let state = &self.state;
+        state.sink.push_response(&state.transaction, &payload, None)
+    }
+
+    /// Pushes the payload along with JSEP, e.g. an SDP answer.
+    pub fn reply_with_jsep(self, payload: O, jsep: Jsep) -> Result<(), Error> {
+        self.claim()?;
+        let state = &self.state;
+        state
+            .sink
+            .push_response(&state.transaction, &payload, Some(&jsep))
+    }
+
+    /// Pushes the error as an event with `error_code` and `error` fields.
+    pub fn reply_error(self, err: Error) -> Result<(), Error> {
+        self.claim()?;
+        let state = &self.state;
+        state.sink.push_error(&state.transaction, &err)
+    }
+
+    /// Marks the transaction as replied by other means, e.g. with a synchronous response.
+    pub(crate) fn finish(self) {
+        self.state.claim();
+    }
+
+    /// Responder for another payload type which is being converted into `O` on reply.
+    /// Both of them share the transaction so replying with either finishes it.
+    pub(crate) fn map<T, F>(self, convert: F) -> Responder<T>
+    where
+        T: 'static,
+        F: Fn(&T) -> Result<O, Error> + Send + Sync + 'static,
+    {
+        Responder {
+            state: Arc::new(State {
+                handle_id: self.state.handle_id,
+                transaction: self.state.transaction.clone(),
+                replied: AtomicBool::new(false),
+                sink: Box::new(MapSink {
+                    inner: self.state,
+                    convert,
+                }),
+            }),
+        }
+    }
+
+    /// Sends a timeout error unless replied in `timeout`.
+    pub(crate) fn expire_in(self, timeout: Duration) {
+        if !self.is_replied() {
+            TIMER.schedule(Instant::now() + timeout, timeout, self.state);
+        }
+    }
+
+    fn claim(&self) -> Result<(), Error> {
+        self.state.try_claim()
+    }
+}
+
+impl<O> fmt::Debug for Responder<O> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Responder")
+            .field("handle_id", &self.state.handle_id)
+            .field("transaction", &self.state.transaction)
+            .finish()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+trait Expire: Send + Sync {
+    fn expire(&self, timeout: Duration);
+}
+
+impl<O> Expire for State<O> {
+    fn expire(&self, timeout: Duration) {
+        if !self.claim() {
+            return;
+        }
+
+        let _scope = logger::handle_scope(self.handle_id);
+
+        log::warn!(
+            "No reply to transaction {} in {:?}",
+            self.transaction,
+            timeout
+        );
+
+        let err = Error::timeout(&format!(
+            "Response to transaction {} timed out",
+            self.transaction
+        ));
+
+        if let Err(push_err) = self.sink.push_error(&self.transaction, &err) {
+            log::error!("Failed to push timeout error: {}", push_err);
+        }
+    }
+}
+
+struct Deadline {
+    at: Instant,
+    // Keeps the order of deadlines at the same instant.
+    seq: u64,
+    timeout: Duration,
+    state: Arc<dyn Expire>,
+}
+
+impl PartialEq for Deadline {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+
+impl Eq for Deadline {}
+
+impl PartialOrd for Deadline {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Deadline {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+/// A single thread expiring all pending transactions so waiting doesn't take a thread per message.
+struct Timer {
+    queue: Mutex<BinaryHeap<Reverse<Deadline>>>,
+    condvar: Condvar,
+    seq: AtomicU64,
+}
+
+impl Timer {
+    fn start() -> Arc<Self> {
+        let timer = Arc::new(Self {
+            queue: Mutex::new(BinaryHeap::new()),
+            condvar: Condvar::new(),
+            seq: AtomicU64::new(0),
+        });
+
+        let thread_timer = timer.clone();
+
+        thread::Builder::new()
+            .name(String::from("janus-app-timer"))
+            .spawn(move || thread_timer.run())
+            .expect("Failed to start responder timer thread");
+
+        timer
+    }
+
+    fn schedule<E: Expire + 'static>(&self, at: Instant, timeout: Duration, state: Arc<E>) {
+        let deadline = Deadline {
+            at,
+            seq: self.seq.fetch_add(1, Ordering::Relaxed),
+            timeout,
+            state,
+        };
+
+        self.queue.lock().push(Reverse(deadline));
+        self.condvar.notify_one();
+    }
+
+    fn run(&self) {
+        let mut queue = self.queue.lock();
+
+        loop {
+            let now = Instant::now();
+
+            match queue.peek().map(|Reverse(deadline)| deadline.at) {
+                None => {
+                    self.condvar.wait(&mut queue);
+                }
+                Some(at) if at > now => {
+                    self.condvar.wait_for(&mut queue, at - now);
+                }
+                Some(_) => {
+                    if let Some(Reverse(deadline)) = queue.pop() {
+                        // Pushing the error calls Janus so don't block scheduling meanwhile.
+                        drop(queue);
+                        deadline.state.expire(deadline.timeout);
+                        queue = self.queue.lock();
+                    }
+                }
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref TIMER: Arc<Timer> = Timer::start();
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Receiver, Sender};
+
+    use serde_json::{json, Value as JsonValue};
+
+    use super::*;
+    use crate::ErrorKind;
+
+    struct ChannelSink(Mutex<Sender<(String, JsonValue)>>);
+
+    impl Sink<JsonValue> for ChannelSink {
+        fn push_response(
+            &self,
+            transaction: &str,
+            payload: &JsonValue,
+            _jsep: Option<&Jsep>,
+        ) -> Result<(), Error> {
+            let event = (transaction.to_owned(), payload.clone());
+            self.0.lock().send(event).map_err(|_| Error::new("Closed"))
+        }
+
+        fn push_error(&self, transaction: &str, err: &Error) -> Result<(), Error> {
+            let event = (transaction.to_owned(), serde_json::to_value(err).unwrap());
+            self.0.lock().send(event).map_err(|_| Error::new("Closed"))
+        }
+    }
+
+    fn responder(transaction: &str) -> (Responder<JsonValue>, Receiver<(String, JsonValue)>) {
+        let (tx, rx) = mpsc::channel();
+        let sink = Box::new(ChannelSink(Mutex::new(tx)));
+        (Responder::new(1, transaction.to_owned(), sink), rx)
+    }
+
+    #[test]
+    fn reply_once() {
+        let (responder, rx) = responder("txn1");
+        let shared = responder.share();
+        responder.reply(json!({"pong": true})).unwrap();
+        assert_eq!(
+            rx.recv().unwrap(),
+            ("txn1".to_owned(), json!({"pong": true}))
+        );
+
+        let err = shared.reply_error(Error::new("Too late")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Conflict);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn finish() {
+        let (responder, rx) = responder("txn2");
+        responder.share().finish();
+        assert!(responder.is_replied());
+        assert!(responder.reply(json!({})).is_err());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn map() {
+        let (responder, rx) = responder("txn5");
+        let shared = responder.share();
+        let mapped = responder.map(|payload: &u64| Ok(json!({ "value": payload })));
+        mapped.reply(42).unwrap();
+        assert_eq!(rx.recv().unwrap().1, json!({"value": 42}));
+        assert!(shared.reply(json!({})).is_err());
+    }
+
+    #[test]
+    fn expire() {
+        let (responder, rx) = responder("txn3");
+        responder.share().expire_in(Duration::from_millis(10));
+
+        let (transaction, payload) = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(transaction, "txn3");
+        assert_eq!(payload["error_code"], 408);
+
+        let err = responder.reply(json!({})).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Conflict);
+    }
+
+    #[test]
+    fn reply_before_timeout() {
+        let (responder, rx) = responder("txn4");
+        responder.share().expire_in(Duration::from_millis(10));
+        responder.reply(json!({"ok": true})).unwrap();
+        assert_eq!(rx.recv().unwrap().1, json!({"ok": true}));
+        thread::sleep(Duration::from_millis(50));
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,466 @@
+//! Dispatching incoming messages to handler functions by their `method` field.
+//!
+//! Instead of a single payload enum and a big `match` in
+//! [Handle::handle_message](../trait.Handle.html#tymethod.handle_message) each method gets its own
+//! handler with own request and response types. The handle takes raw JSON payloads and passes
+//! them to the router:
+//!
+//! ```
+//! use janus_app::router::{Route, Router};
+//! use janus_app::{lazy_static, Error, Handle, IncomingMessage, MediaEvent, MessageResponse, Responder};
+//! use serde_derive::{Deserialize, Serialize};
+//! use serde_json::Value as JsonValue;
+//!
+//! #[derive(Clone, Serialize)]
+//! struct MyHandle {
+//!     id: u64,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct PingRequest {
+//!     data: String,
+//! }
+//!
+//! #[derive(Serialize)]
+//! struct PingResponse {
+//!     data: String,
+//! }
+//!
+//! fn ping(
+//!     _handle: &mut MyHandle,
+//!     message: IncomingMessage<PingRequest>,
+//!     _responder: Responder<PingResponse>,
+//! ) -> Result<MessageResponse<PingResponse>, Error> {
+//!     let data = format!("{} pong", message.payload().data);
+//!     Ok(MessageResponse::Syncronous(PingResponse { data }))
+//! }
+//!
+//! lazy_static! {
+//!     static ref ROUTER: Router<MyHandle> = Router::new()
+//!         .add_middleware(|handle: &MyHandle, method: &str, _: &IncomingMessage<JsonValue>| {
+//!             log::debug!("Handle {} calls {}", handle.id, method);
+//!             Ok(())
+//!         })
+//!         .add_route(Route::new("ping", ping));
+//! }
+//!
+//! impl Handle for MyHandle {
+//!     type IncomingMessagePayload = JsonValue;
+//!     type OutgoingMessagePayload = JsonValue;
+//!
+//!     fn id(&self) -> u64 {
+//!         self.id
+//!     }
+//!
+//!     fn handle_media_event(&self, _media_event: &MediaEvent) {}
+//!
+//!     fn handle_message(
+//!         &mut self,
+//!         message: IncomingMessage<JsonValue>,
+//!         responder: Responder<JsonValue>,
+//!     ) -> Result<MessageResponse<JsonValue>, Error> {
+//!         ROUTER.handle(self, message, responder)
+//!     }
+//! }
+//! ```
+//!
+//! A message with a method missing in the router gets a `bad_request` error with the list of
+//! supported methods in `data`:
+//!
+//! ```json
+//! {"error_code": 400, "error": "Unknown method `pong`", "data": {"method": "pong", "supported_methods": ["ping"]}}
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::{de::DeserializeOwned, ser::Serialize};
+use serde_json::{json, Value as JsonValue};
+
+use crate::{jansson, validation, Error, IncomingMessage, MessageResponse, Responder};
+
+/// Name of the payload field to route by.
+const METHOD_FIELD: &str = "method";
+
+type BoxHandler<H> = Box<
+    dyn Fn(
+            &mut H,
+            IncomingMessage<JsonValue>,
+            Responder<JsonValue>,
+        ) -> Result<MessageResponse<JsonValue>, Error>
+        + Send
+        + Sync,
+>;
+
+/// A check running before the handler, e.g. authorization or rate limiting.
+/// Returning an error rejects the message with it.
+///
+/// It's implemented for closures taking the same arguments.
+pub trait Middleware<H>: Send + Sync {
+    fn before(
+        &self,
+        handle: &H,
+        method: &str,
+        message: &IncomingMessage<JsonValue>,
+    ) -> Result<(), Error>;
+}
+
+impl<H, F> Middleware<H> for F
+where
+    F: Fn(&H, &str, &IncomingMessage<JsonValue>) -> Result<(), Error> + Send + Sync,
+{
+    fn before(
+        &self,
+        handle: &H,
+        method: &str,
+        message: &IncomingMessage<JsonValue>,
+    ) -> Result<(), Error> {
+        self(handle, method, message)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Handler of a single method with its own middleware.
+pub struct Route<H> {
+    method: String,
+    handler: BoxHandler<H>,
+    middleware: Vec<Box<dyn Middleware<H>>>,
+}
+
+impl<H: 'static> Route<H> {
+    /// Creates a route for `method`. The payload without the `method` field is being deserialized
+    /// into `Req` and a response of `Resp` type is being serialized back to JSON.
+    ///
+    /// Requests without parameters may use a struct without fields since `()` doesn't
+    /// deserialize from a JSON object.
+    pub fn new<Req, Resp, F>(method: &str, handler: F) -> Self
+    where
+        Req: DeserializeOwned + 'static,
+        Resp: Serialize + Send + 'static,
+        F: Fn(
+                &mut H,
+                IncomingMessage<Req>,
+                Responder<Resp>,
+            ) -> Result<MessageResponse<Resp>, Error>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let method_name = method.to_owned();
+
+        let handler = move |handle: &mut H,
+                            message: IncomingMessage<JsonValue>,
+                            responder: Responder<JsonValue>| {
+            let message = parse_message::<Req>(&method_name, message)?;
+            let responder = responder.map(|payload: &Resp| to_json(payload));
+            handler(handle, message, responder).and_then(into_json_response)
+        };
+
+        Self {
+            method: method.to_owned(),
+            handler: Box::new(handler),
+            middleware: vec![],
+        }
+    }
+
+    /// Adds a middleware running only for this route after the router's ones.
+    pub fn add_middleware<M: Middleware<H> + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Method name to handler map with middleware for all of the routes.
+pub struct Router<H> {
+    routes: BTreeMap<String, Route<H>>,
+    middleware: Vec<Box<dyn Middleware<H>>>,
+}
+
+impl<H: 'static> Router<H> {
+    pub fn new() -> Self {
+        Self {
+            routes: BTreeMap::new(),
+            middleware: vec![],
+        }
+    }
+
+    /// Adds a route replacing the one for the same method if any.
+    pub fn add_route(mut self, route: Route<H>) -> Self {
+        self.routes.insert(route.method.clone(), route);
+        self
+    }
+
+    /// Adds a middleware running for every route in the order of adding.
+    pub fn add_middleware<M: Middleware<H> + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Supported method names in alphabetical order.
+    pub fn methods(&self) -> impl Iterator<Item = &str> {
+        self.routes.keys().map(String::as_str)
+    }
+
+    /// Runs the middleware and the handler for the message's method.
+    pub fn handle(
+        &self,
+        handle: &mut H,
+        message: IncomingMessage<JsonValue>,
+        responder: Responder<JsonValue>,
+    ) -> Result<MessageResponse<JsonValue>, Error> {
+        let route = match message.payload().get(METHOD_FIELD) {
+            None => {
+                let err = Error::bad_request(&format!("Missing `{}` field", METHOD_FIELD));
+                return Err(err.set_data(json!({ "supported_methods": self.supported_methods() })));
+            }
+            Some(JsonValue::String(method)) => self
+                .routes
+                .get(method)
+                .ok_or_else(|| self.unknown_method_error(method))?,
+            Some(_) => {
+                let err = Error::bad_request(&format!("`{}` must be a string", METHOD_FIELD));
+                return Err(err.set_data(json!({ "supported_methods": self.supported_methods() })));
+            }
+        };
+
+        for middleware in self.middleware.iter().chain(route.middleware.iter()) {
+            middleware.before(handle, &route.method, &message)?;
+        }
+
+        (route.handler)(handle, message, responder)
+    }
+
+    fn supported_methods(&self) -> Vec<&str> {
+        self.methods().collect()
+    }
+
+    fn unknown_method_error(&self, method: &str) -> Error {
+        Error::bad_request(&format!("Unknown method `{}`", method)).set_data(json!({
+            "method": method,
+            "supported_methods": self.supported_methods(),
+        }))
+    }
+}
+
+impl<H: 'static> Default for Router<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+fn parse_message<Req: DeserializeOwned>(
+    method: &str,
+    message: IncomingMessage<JsonValue>,
+) -> Result<IncomingMessage<Req>, Error> {
+    let (transaction, mut payload, jsep) = message.into_parts();
+
+    if let Some(object) = payload.as_object_mut() {
+        object.remove(METHOD_FIELD);
+    }
+
+    let subject = format!("Invalid `{}` request", method);
+    let payload =
+        jansson::from_value(&payload).map_err(|err| validation::request_error(&subject, err))?;
+    let message = IncomingMessage::new(transaction, payload);
+
+    Ok(match jsep {
+        Some(jsep) => message.set_jsep(jsep),
+        None => message,
+    })
+}
+
+fn to_json<S: Serialize>(payload: &S) -> Result<JsonValue, Error> {
+    serde_json::to_value(payload)
+        .map_err(|err| Error::new(&format!("Failed to serialize response: {}", err)))
+}
+
+fn into_json_response<Resp: Serialize + Send + 'static>(
+    response: MessageResponse<Resp>,
+) -> Result<MessageResponse<JsonValue>, Error> {
+    match response {
+        MessageResponse::Syncronous(payload) => to_json(&payload).map(MessageResponse::Syncronous),
+        MessageResponse::Ack => Ok(MessageResponse::Ack),
+        MessageResponse::Deferred(future) => Ok(MessageResponse::deferred(async move {
+            future.await.and_then(|payload| to_json(&payload))
+        })),
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::responder::Sink;
+    use crate::{ErrorKind, Jsep};
+
+    struct TestHandle {
+        authorized: bool,
+    }
+
+    #[derive(Deserialize)]
+    struct AddRequest {
+        a: i64,
+        b: i64,
+    }
+
+    #[derive(Serialize)]
+    struct AddResponse {
+        sum: i64,
+    }
+
+    fn add(
+        _handle: &mut TestHandle,
+        message: IncomingMessage<AddRequest>,
+        _responder: Responder<AddResponse>,
+    ) -> Result<MessageResponse<AddResponse>, Error> {
+        let AddRequest { a, b } = message.payload();
+        Ok(MessageResponse::Syncronous(AddResponse { sum: a + b }))
+    }
+
+    #[derive(Deserialize)]
+    struct Empty {}
+
+    fn later(
+        _handle: &mut TestHandle,
+        _message: IncomingMessage<Empty>,
+        responder: Responder<AddResponse>,
+    ) -> Result<MessageResponse<AddResponse>, Error> {
+        responder.reply(AddResponse { sum: 0 })?;
+        Ok(MessageResponse::Ack)
+    }
+
+    #[derive(Default)]
+    struct RecordingSink(Mutex<Vec<JsonValue>>);
+
+    impl Sink<JsonValue> for Arc<RecordingSink> {
+        fn push_response(
+            &self,
+            _transaction: &str,
+            payload: &JsonValue,
+            _jsep: Option<&Jsep>,
+        ) -> Result<(), Error> {
+            self.0.lock().push(payload.clone());
+            Ok(())
+        }
+
+        fn push_error(&self, _transaction: &str, err: &Error) -> Result<(), Error> {
+            self.0.lock().push(serde_json::to_value(err).unwrap());
+            Ok(())
+        }
+    }
+
+    fn call(
+        router: &Router<TestHandle>,
+        handle: &mut TestHandle,
+        payload: JsonValue,
+    ) -> (Result<MessageResponse<JsonValue>, Error>, Vec<JsonValue>) {
+        let sink = Arc::new(RecordingSink::default());
+        let responder = Responder::new(1, String::from("txn"), Box::new(sink.clone()));
+        let message = IncomingMessage::new(String::from("txn"), payload);
+        let result = router.handle(handle, message, responder);
+        let pushed = sink.0.lock().clone();
+        (result, pushed)
+    }
+
+    fn router() -> Router<TestHandle> {
+        Router::new().add_route(Route::new("add", add)).add_route(
+            Route::new("later", later).add_middleware(
+                |handle: &TestHandle, _: &str, _: &IncomingMessage<JsonValue>| {
+                    if handle.authorized {
+                        Ok(())
+                    } else {
+                        Err(Error::forbidden("Not authorized"))
+                    }
+                },
+            ),
+        )
+    }
+
+    #[test]
+    fn dispatch() {
+        let mut handle = TestHandle { authorized: true };
+        let (result, _) = call(
+            &router(),
+            &mut handle,
+            json!({"method": "add", "a": 2, "b": 3}),
+        );
+
+        match result.unwrap() {
+            MessageResponse::Syncronous(payload) => assert_eq!(payload, json!({"sum": 5})),
+            other => panic!("Unexpected response: {:?}", other),
+        }
+
+        let (result, pushed) = call(&router(), &mut handle, json!({"method": "later"}));
+        assert!(matches!(result, Ok(MessageResponse::Ack)));
+        assert_eq!(pushed, vec![json!({"sum": 0})]);
+    }
+
+    #[test]
+    fn unknown_method() {
+        let mut handle = TestHandle { authorized: true };
+        let (result, _) = call(&router(), &mut handle, json!({"method": "mul"}));
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BadRequest);
+
+        assert_eq!(
+            err.data(),
+            Some(&json!({"method": "mul", "supported_methods": ["add", "later"]}))
+        );
+
+        let (result, _) = call(&router(), &mut handle, json!({"a": 1}));
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::BadRequest);
+    }
+
+    #[test]
+    fn invalid_request() {
+        let mut handle = TestHandle { authorized: true };
+        let (result, _) = call(&router(), &mut handle, json!({"method": "add", "a": 1}));
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BadRequest);
+        assert!(err.as_str().starts_with("Invalid `add` request"));
+        assert_eq!(err.data(), Some(&json!({"path": "$.b"})));
+
+        let (result, _) = call(
+            &router(),
+            &mut handle,
+            json!({"method": "add", "a": 1, "b": "2"}),
+        );
+        let err = result.unwrap_err();
+        assert_eq!(err.data(), Some(&json!({"path": "$.b", "expected": "i64"})));
+    }
+
+    #[test]
+    fn middleware() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+
+        let router = router().add_middleware(
+            move |_: &TestHandle, method: &str, _: &IncomingMessage<JsonValue>| {
+                assert_eq!(method, "later");
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            },
+        );
+
+        let mut handle = TestHandle { authorized: false };
+        let (result, pushed) = call(&router, &mut handle, json!({"method": "later"}));
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::Forbidden);
+        assert!(pushed.is_empty());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,795 @@
+//! RTCP (RFC 3550, RFC 4585, RFC 5104) compound packet parser and builder.
+//!
+//! Use [MediaEvent::rtcp_packets](../enum.MediaEvent.html#method.rtcp_packets) to parse
+//! incoming RTCP and [serialize](fn.serialize.html) to build a buffer for
+//! [relay_media_packet](../plugin/trait.Callbacks.html#method.relay_media_packet).
+//!
+//! ```
+//! use janus_app::rtcp::{self, Nack, RtcpPacket};
+//!
+//! let buffer = rtcp::serialize(&[RtcpPacket::Nack(Nack {
+//!     sender_ssrc: 1,
+//!     media_ssrc: 2,
+//!     lost: vec![100, 101, 116],
+//! })]);
+//!
+//! match rtcp::parse(&buffer).unwrap().as_slice() {
+//!     [RtcpPacket::Nack(nack)] => assert_eq!(nack.lost, vec![100, 101, 116]),
+//!     packets => panic!("Unexpected packets: {:?}", packets),
+//! }
+//! ```
+
+use crate::rtp;
+use crate::Error;
+
+const HEADER_LEN: usize = 4;
+const REPORT_BLOCK_LEN: usize = 24;
+const REMB_IDENTIFIER: &[u8] = b"REMB";
+
+const PT_SR: u8 = 200;
+const PT_RR: u8 = 201;
+const PT_SDES: u8 = 202;
+const PT_BYE: u8 = 203;
+const PT_RTPFB: u8 = 205;
+const PT_PSFB: u8 = 206;
+
+const FMT_NACK: u8 = 1;
+const FMT_PLI: u8 = 1;
+const FMT_FIR: u8 = 4;
+const FMT_AFB: u8 = 15;
+
+/// SDES item type of the canonical end-point identifier.
+pub const SDES_CNAME: u8 = 1;
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A single packet of an RTCP compound packet.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RtcpPacket {
+    SenderReport(SenderReport),
+    ReceiverReport(ReceiverReport),
+    SourceDescription(Vec<SdesChunk>),
+    Goodbye(Goodbye),
+    /// Generic NACK transport layer feedback.
+    Nack(Nack),
+    /// Picture Loss Indication.
+    Pli(Pli),
+    /// Full Intra Request.
+    Fir(Fir),
+    /// Receiver Estimated Maximum Bitrate.
+    Remb(Remb),
+    /// Packet of any other type kept as is without the header.
+    Other {
+        packet_type: u8,
+        count: u8,
+        payload: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SenderReport {
+    pub ssrc: u32,
+    /// 64-bit NTP timestamp: seconds in the higher word and the fraction in the lower one.
+    pub ntp_timestamp: u64,
+    pub rtp_timestamp: u32,
+    pub packet_count: u32,
+    pub octet_count: u32,
+    pub reports: Vec<ReportBlock>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceiverReport {
+    pub ssrc: u32,
+    pub reports: Vec<ReportBlock>,
+}
+
+/// Reception statistics of a single source.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportBlock {
+    pub ssrc: u32,
+    pub fraction_lost: u8,
+    /// Signed 24-bit number of packets lost.
+    pub cumulative_lost: i32,
+    pub highest_sequence_number: u32,
+    pub jitter: u32,
+    pub last_sender_report: u32,
+    pub delay_since_last_sender_report: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SdesChunk {
+    pub ssrc: u32,
+    pub items: Vec<SdesItem>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SdesItem {
+    /// Item type, e.g. [SDES_CNAME](constant.SDES_CNAME.html).
+    pub kind: u8,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Goodbye {
+    pub ssrcs: Vec<u32>,
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Nack {
+    pub sender_ssrc: u32,
+    pub media_ssrc: u32,
+    /// Sequence numbers of lost packets.
+    pub lost: Vec<u16>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pli {
+    pub sender_ssrc: u32,
+    pub media_ssrc: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fir {
+    pub sender_ssrc: u32,
+    pub entries: Vec<FirEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FirEntry {
+    pub ssrc: u32,
+    pub sequence_number: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Remb {
+    pub sender_ssrc: u32,
+    /// Bitrate in bits per second. It's being rounded down to 18 bits of precision on serialization.
+    pub bitrate: u64,
+    pub ssrcs: Vec<u32>,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Parses an RTCP compound packet from a media buffer.
+pub fn parse(buffer: &[i8]) -> Result<Vec<RtcpPacket>, Error> {
+    parse_bytes(rtp::to_bytes(buffer))
+}
+
+pub fn parse_bytes(mut buffer: &[u8]) -> Result<Vec<RtcpPacket>, Error> {
+    let mut packets = vec![];
+
+    while !buffer.is_empty() {
+        if buffer.len() < HEADER_LEN {
+            return Err(Error::new("RTCP header is truncated"));
+        }
+
+        let version = buffer[0] >> 6;
+
+        if version != 2 {
+            return Err(Error::new(&format!(
+                "Unsupported RTCP version: {}",
+                version
+            )));
+        }
+
+        let len = (read_u16(buffer, 2) as usize + 1) * 4;
+
+        if buffer.len() < len {
+            return Err(Error::new(&format!(
+                "RTCP packet is truncated: {} of {} bytes",
+                buffer.len(),
+                len
+            )));
+        }
+
+        let mut body = &buffer[HEADER_LEN..len];
+
+        if buffer[0] & 0x20 != 0 {
+            let padding_len = buffer[len - 1] as usize;
+
+            if padding_len == 0 || padding_len > body.len() {
+                return Err(Error::new(&format!(
+                    "Invalid RTCP padding length: {}",
+                    padding_len
+                )));
+            }
+
+            body = &body[..body.len() - padding_len];
+        }
+
+        let count = buffer[0] & 0x1F;
+        packets.push(RtcpPacket::parse(buffer[1], count, body)?);
+        buffer = &buffer[len..];
+    }
+
+    Ok(packets)
+}
+
+/// Builds an RTCP compound packet to send with
+/// [relay_media_packet](../plugin/trait.Callbacks.html#method.relay_media_packet).
+pub fn serialize(packets: &[RtcpPacket]) -> Vec<i8> {
+    serialize_bytes(packets)
+        .into_iter()
+        .map(|byte| byte as i8)
+        .collect()
+}
+
+pub fn serialize_bytes(packets: &[RtcpPacket]) -> Vec<u8> {
+    let mut buffer = vec![];
+
+    for packet in packets {
+        packet.write(&mut buffer);
+    }
+
+    buffer
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+impl RtcpPacket {
+    fn parse(packet_type: u8, count: u8, body: &[u8]) -> Result<Self, Error> {
+        let packet = match (packet_type, count) {
+            (PT_SR, _) => Self::SenderReport(SenderReport::parse(count, body)?),
+            (PT_RR, _) => Self::ReceiverReport(ReceiverReport::parse(count, body)?),
+            (PT_SDES, _) => Self::SourceDescription(SdesChunk::parse_all(count, body)?),
+            (PT_BYE, _) => Self::Goodbye(Goodbye::parse(count, body)?),
+            (PT_RTPFB, FMT_NACK) => Self::Nack(Nack::parse(body)?),
+            (PT_PSFB, FMT_PLI) => {
+                let (sender_ssrc, media_ssrc) = parse_feedback_ssrcs(body)?;
+
+                Self::Pli(Pli {
+                    sender_ssrc,
+                    media_ssrc,
+                })
+            }
+            (PT_PSFB, FMT_FIR) => Self::Fir(Fir::parse(body)?),
+            (PT_PSFB, FMT_AFB) if body.get(8..12) == Some(REMB_IDENTIFIER) => {
+                Self::Remb(Remb::parse(body)?)
+            }
+            _ => Self::Other {
+                packet_type,
+                count,
+                payload: body.to_vec(),
+            },
+        };
+
+        Ok(packet)
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) {
+        let start = buffer.len();
+
+        let (packet_type, count) = match self {
+            Self::SenderReport(sr) => {
+                sr.write(buffer);
+                (PT_SR, sr.reports.len() as u8)
+            }
+            Self::ReceiverReport(rr) => {
+                rr.write(buffer);
+                (PT_RR, rr.reports.len() as u8)
+            }
+            Self::SourceDescription(chunks) => {
+                buffer.extend_from_slice(&[0; HEADER_LEN]);
+
+                for chunk in chunks {
+                    chunk.write(buffer);
+                }
+
+                (PT_SDES, chunks.len() as u8)
+            }
+            Self::Goodbye(bye) => {
+                bye.write(buffer);
+                (PT_BYE, bye.ssrcs.len() as u8)
+            }
+            Self::Nack(nack) => {
+                nack.write(buffer);
+                (PT_RTPFB, FMT_NACK)
+            }
+            Self::Pli(pli) => {
+                buffer.extend_from_slice(&[0; HEADER_LEN]);
+                write_u32(buffer, pli.sender_ssrc);
+                write_u32(buffer, pli.media_ssrc);
+                (PT_PSFB, FMT_PLI)
+            }
+            Self::Fir(fir) => {
+                fir.write(buffer);
+                (PT_PSFB, FMT_FIR)
+            }
+            Self::Remb(remb) => {
+                remb.write(buffer);
+                (PT_PSFB, FMT_AFB)
+            }
+            Self::Other {
+                packet_type,
+                count,
+                payload,
+            } => {
+                buffer.extend_from_slice(&[0; HEADER_LEN]);
+                buffer.extend_from_slice(payload);
+                (*packet_type, *count)
+            }
+        };
+
+        // Pad to 32-bit words with zeroes since the length is measured in words.
+        pad_to_words(buffer, start);
+
+        let words = ((buffer.len() - start) / 4 - 1) as u16;
+        buffer[start] = 0x80 | (count & 0x1F);
+        buffer[start + 1] = packet_type;
+        buffer[start + 2..start + 4].copy_from_slice(&words.to_be_bytes());
+    }
+}
+
+impl SenderReport {
+    fn parse(count: u8, body: &[u8]) -> Result<Self, Error> {
+        ensure_len("SR", body, 24)?;
+
+        Ok(Self {
+            ssrc: read_u32(body, 0),
+            ntp_timestamp: (read_u32(body, 4) as u64) << 32 | read_u32(body, 8) as u64,
+            rtp_timestamp: read_u32(body, 12),
+            packet_count: read_u32(body, 16),
+            octet_count: read_u32(body, 20),
+            reports: ReportBlock::parse_all(count, &body[24..])?,
+        })
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&[0; HEADER_LEN]);
+        write_u32(buffer, self.ssrc);
+        write_u32(buffer, (self.ntp_timestamp >> 32) as u32);
+        write_u32(buffer, self.ntp_timestamp as u32);
+        write_u32(buffer, self.rtp_timestamp);
+        write_u32(buffer, self.packet_count);
+        write_u32(buffer, self.octet_count);
+
+        for report in &self.reports {
+            report.write(buffer);
+        }
+    }
+}
+
+impl ReceiverReport {
+    fn parse(count: u8, body: &[u8]) -> Result<Self, Error> {
+        ensure_len("RR", body, 4)?;
+
+        Ok(Self {
+            ssrc: read_u32(body, 0),
+            reports: ReportBlock::parse_all(count, &body[4..])?,
+        })
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&[0; HEADER_LEN]);
+        write_u32(buffer, self.ssrc);
+
+        for report in &self.reports {
+            report.write(buffer);
+        }
+    }
+}
+
+impl ReportBlock {
+    fn parse_all(count: u8, body: &[u8]) -> Result<Vec<Self>, Error> {
+        ensure_len("report block", body, count as usize * REPORT_BLOCK_LEN)?;
+
+        let reports = body
+            .chunks_exact(REPORT_BLOCK_LEN)
+            .take(count as usize)
+            .map(|block| {
+                // Sign-extend the 24-bit number.
+                let cumulative_lost = (read_u32(block, 4) << 8) as i32 >> 8;
+
+                Self {
+                    ssrc: read_u32(block, 0),
+                    fraction_lost: block[4],
+                    cumulative_lost,
+                    highest_sequence_number: read_u32(block, 8),
+                    jitter: read_u32(block, 12),
+                    last_sender_report: read_u32(block, 16),
+                    delay_since_last_sender_report: read_u32(block, 20),
+                }
+            })
+            .collect();
+
+        Ok(reports)
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) {
+        write_u32(buffer, self.ssrc);
+        let lost = (self.cumulative_lost as u32) & 0x00FF_FFFF;
+        write_u32(buffer, (self.fraction_lost as u32) << 24 | lost);
+        write_u32(buffer, self.highest_sequence_number);
+        write_u32(buffer, self.jitter);
+        write_u32(buffer, self.last_sender_report);
+        write_u32(buffer, self.delay_since_last_sender_report);
+    }
+}
+
+impl SdesChunk {
+    fn parse_all(count: u8, mut body: &[u8]) -> Result<Vec<Self>, Error> {
+        let mut chunks = vec![];
+
+        for _ in 0..count {
+            ensure_len("SDES chunk", body, 4)?;
+            let ssrc = read_u32(body, 0);
+            let mut items = vec![];
+            let mut offset = 4;
+
+            loop {
+                let kind = *body
+                    .get(offset)
+                    .ok_or_else(|| Error::new("SDES chunk is not terminated"))?;
+
+                if kind == 0 {
+                    break;
+                }
+
+                let len = *body
+                    .get(offset + 1)
+                    .ok_or_else(|| Error::new("SDES item is truncated"))?
+                    as usize;
+
+                let value = body
+                    .get(offset + 2..offset + 2 + len)
+                    .ok_or_else(|| Error::new("SDES item is truncated"))?;
+
+                items.push(SdesItem {
+                    kind,
+                    value: String::from_utf8_lossy(value).into_owned(),
+                });
+
+                offset += 2 + len;
+            }
+
+            chunks.push(Self { ssrc, items });
+
+            // Skip the terminating null item and padding up to the next word.
+            let next = (offset / 4 + 1) * 4;
+            body = body.get(next..).unwrap_or(&[]);
+        }
+
+        Ok(chunks)
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) {
+        let start = buffer.len();
+        write_u32(buffer, self.ssrc);
+
+        for item in &self.items {
+            let value = item.value.as_bytes();
+            let len = value.len().min(255);
+            buffer.push(item.kind);
+            buffer.push(len as u8);
+            buffer.extend_from_slice(&value[..len]);
+        }
+
+        buffer.push(0);
+        pad_to_words(buffer, start);
+    }
+}
+
+impl Goodbye {
+    fn parse(count: u8, body: &[u8]) -> Result<Self, Error> {
+        let ssrcs_len = count as usize * 4;
+        ensure_len("BYE", body, ssrcs_len)?;
+        let ssrcs = (0..count as usize)
+            .map(|idx| read_u32(body, idx * 4))
+            .collect();
+
+        let reason = match body.get(ssrcs_len) {
+            None => None,
+            Some(len) => {
+                let reason = body
+                    .get(ssrcs_len + 1..ssrcs_len + 1 + *len as usize)
+                    .ok_or_else(|| Error::new("BYE reason is truncated"))?;
+
+                Some(String::from_utf8_lossy(reason).into_owned())
+            }
+        };
+
+        Ok(Self { ssrcs, reason })
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&[0; HEADER_LEN]);
+
+        for ssrc in &self.ssrcs {
+            write_u32(buffer, *ssrc);
+        }
+
+        if let Some(ref reason) = self.reason {
+            let reason = reason.as_bytes();
+            let len = reason.len().min(255);
+            buffer.push(len as u8);
+            buffer.extend_from_slice(&reason[..len]);
+        }
+    }
+}
+
+impl Nack {
+    fn parse(body: &[u8]) -> Result<Self, Error> {
+        let (sender_ssrc, media_ssrc) = parse_feedback_ssrcs(body)?;
+        let mut lost = vec![];
+
+        // Each entry is a packet ID and a bitmask of following lost packets.
+        for entry in body[8..].chunks_exact(4) {
+            let packet_id = read_u16(entry, 0);
+            let bitmask = read_u16(entry, 2);
+            lost.push(packet_id);
+
+            for bit in 0..16 {
+                if bitmask & (1 << bit) != 0 {
+                    lost.push(packet_id.wrapping_add(bit + 1));
+                }
+            }
+        }
+
+        Ok(Self {
+            sender_ssrc,
+            media_ssrc,
+            lost,
+        })
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&[0; HEADER_LEN]);
+        write_u32(buffer, self.sender_ssrc);
+        write_u32(buffer, self.media_ssrc);
+
+        let mut entries: Vec<(u16, u16)> = vec![];
+
+        for seq in &self.lost {
+            match entries.last_mut() {
+                Some((packet_id, bitmask))
+                    if seq.wrapping_sub(*packet_id) >= 1 && seq.wrapping_sub(*packet_id) <= 16 =>
+                {
+                    *bitmask |= 1 << (seq.wrapping_sub(*packet_id) - 1);
+                }
+                _ => entries.push((*seq, 0)),
+            }
+        }
+
+        for (packet_id, bitmask) in entries {
+            buffer.extend_from_slice(&packet_id.to_be_bytes());
+            buffer.extend_from_slice(&bitmask.to_be_bytes());
+        }
+    }
+}
+
+impl Fir {
+    fn parse(body: &[u8]) -> Result<Self, Error> {
+        let (sender_ssrc, _) = parse_feedback_ssrcs(body)?;
+
+        let entries = body[8..]
+            .chunks_exact(8)
+            .map(|entry| FirEntry {
+                ssrc: read_u32(entry, 0),
+                sequence_number: entry[4],
+            })
+            .collect();
+
+        Ok(Self {
+            sender_ssrc,
+            entries,
+        })
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&[0; HEADER_LEN]);
+        write_u32(buffer, self.sender_ssrc);
+        write_u32(buffer, 0);
+
+        for entry in &self.entries {
+            write_u32(buffer, entry.ssrc);
+            buffer.extend_from_slice(&[entry.sequence_number, 0, 0, 0]);
+        }
+    }
+}
+
+impl Remb {
+    fn parse(body: &[u8]) -> Result<Self, Error> {
+        ensure_len("REMB", body, 16)?;
+        let (sender_ssrc, _) = parse_feedback_ssrcs(body)?;
+        let ssrc_count = body[12] as usize;
+        let exponent = body[13] >> 2;
+        let mantissa = read_u32(body, 12) & 0x0003_FFFF;
+        ensure_len("REMB", body, 16 + ssrc_count * 4)?;
+
+        Ok(Self {
+            sender_ssrc,
+            bitrate: (mantissa as u64) << exponent,
+            ssrcs: (0..ssrc_count)
+                .map(|idx| read_u32(body, 16 + idx * 4))
+                .collect(),
+        })
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) {
+        let mut exponent = 0;
+        let mut mantissa = self.bitrate;
+
+        while mantissa > 0x0003_FFFF && exponent < 63 {
+            mantissa >>= 1;
+            exponent += 1;
+        }
+
+        buffer.extend_from_slice(&[0; HEADER_LEN]);
+        write_u32(buffer, self.sender_ssrc);
+        write_u32(buffer, 0);
+        buffer.extend_from_slice(REMB_IDENTIFIER);
+        let ssrc_count = self.ssrcs.len().min(255) as u32;
+        write_u32(buffer, ssrc_count << 24 | exponent << 18 | mantissa as u32);
+
+        for ssrc in self.ssrcs.iter().take(255) {
+            write_u32(buffer, *ssrc);
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+fn parse_feedback_ssrcs(body: &[u8]) -> Result<(u32, u32), Error> {
+    ensure_len("feedback", body, 8)?;
+    Ok((read_u32(body, 0), read_u32(body, 4)))
+}
+
+fn ensure_len(name: &str, body: &[u8], len: usize) -> Result<(), Error> {
+    if body.len() < len {
+        Err(Error::new(&format!(
+            "RTCP {} is truncated: {} of {} bytes",
+            name,
+            body.len(),
+            len
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+fn read_u16(buffer: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([buffer[offset], buffer[offset + 1]])
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        buffer[offset],
+        buffer[offset + 1],
+        buffer[offset + 2],
+        buffer[offset + 3],
+    ])
+}
+
+fn write_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Appends zeroes so the data written since `start` takes whole 32-bit words.
+fn pad_to_words(buffer: &mut Vec<u8>, start: usize) {
+    let len = (buffer.len() - start + 3) & !3;
+    buffer.resize(start + len, 0);
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report_block() -> ReportBlock {
+        ReportBlock {
+            ssrc: 2,
+            fraction_lost: 10,
+            cumulative_lost: -3,
+            highest_sequence_number: 65_600,
+            jitter: 20,
+            last_sender_report: 30,
+            delay_since_last_sender_report: 40,
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let packets = vec![
+            RtcpPacket::SenderReport(SenderReport {
+                ssrc: 1,
+                ntp_timestamp: 0x0102_0304_0506_0708,
+                rtp_timestamp: 90_000,
+                packet_count: 5,
+                octet_count: 500,
+                reports: vec![report_block()],
+            }),
+            RtcpPacket::ReceiverReport(ReceiverReport {
+                ssrc: 1,
+                reports: vec![report_block(), report_block()],
+            }),
+            RtcpPacket::SourceDescription(vec![
+                SdesChunk {
+                    ssrc: 1,
+                    items: vec![SdesItem {
+                        kind: SDES_CNAME,
+                        value: String::from("abc"),
+                    }],
+                },
+                SdesChunk {
+                    ssrc: 2,
+                    items: vec![SdesItem {
+                        kind: SDES_CNAME,
+                        value: String::from("abcdef"),
+                    }],
+                },
+            ]),
+            RtcpPacket::Goodbye(Goodbye {
+                ssrcs: vec![1, 2],
+                reason: Some(String::from("bye")),
+            }),
+            RtcpPacket::Nack(Nack {
+                sender_ssrc: 1,
+                media_ssrc: 2,
+                lost: vec![65_535, 0, 16, 100],
+            }),
+            RtcpPacket::Pli(Pli {
+                sender_ssrc: 1,
+                media_ssrc: 2,
+            }),
+            RtcpPacket::Fir(Fir {
+                sender_ssrc: 1,
+                entries: vec![FirEntry {
+                    ssrc: 2,
+                    sequence_number: 7,
+                }],
+            }),
+            RtcpPacket::Remb(Remb {
+                sender_ssrc: 1,
+                bitrate: 256_000,
+                ssrcs: vec![2, 3],
+            }),
+            RtcpPacket::Other {
+                packet_type: 207,
+                count: 0,
+                payload: vec![1, 2, 3, 4],
+            },
+        ];
+
+        let buffer = serialize(&packets);
+        assert_eq!(buffer.len() % 4, 0);
+        assert_eq!(parse(&buffer).unwrap(), packets);
+    }
+
+    #[test]
+    fn remb_precision() {
+        let packets = vec![RtcpPacket::Remb(Remb {
+            sender_ssrc: 0,
+            bitrate: 1_000_001,
+            ssrcs: vec![],
+        })];
+
+        match parse_bytes(&serialize_bytes(&packets)).unwrap().as_slice() {
+            [RtcpPacket::Remb(remb)] => assert_eq!(remb.bitrate, 1_000_000),
+            packets => panic!("Unexpected packets: {:?}", packets),
+        }
+    }
+
+    #[test]
+    fn padding() {
+        // RR with no report blocks and 4 bytes of padding.
+        let buffer = [0xA0, 201, 0x00, 0x02, 0, 0, 0, 1, 0, 0, 0, 4];
+
+        assert_eq!(
+            parse_bytes(&buffer).unwrap(),
+            vec![RtcpPacket::ReceiverReport(ReceiverReport {
+                ssrc: 1,
+                reports: vec![],
+            })]
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(parse_bytes(&[0x80, 201, 0x00]).is_err());
+        assert!(parse_bytes(&[0x80, 201, 0x00, 0x02, 0, 0, 0, 1]).is_err());
+        assert!(parse_bytes(&[0x40, 201, 0x00, 0x01, 0, 0, 0, 1]).is_err());
+        assert!(parse_bytes(&[0x81, 201, 0x00, 0x01, 0, 0, 0, 1]).is_err());
+    }
+}
@@ -0,0 +1,665 @@
+//! Zero-copy RTP (RFC 3550) packet views.
+//!
+//! [RtpPacket](struct.RtpPacket.html) reads header fields right from the media buffer
+//! passed by Janus and [RtpPacketMut](struct.RtpPacketMut.html) rewrites them in place
+//! before relaying the buffer with
+//! [relay_media_packet](../plugin/trait.Callbacks.html#method.relay_media_packet).
+//!
+//! ```
+//! use janus_app::rtp::RtpPacketMut;
+//!
+//! # fn rewrite(buffer: &[i8]) -> Result<Vec<i8>, janus_app::Error> {
+//! let mut buffer = buffer.to_vec();
+//! let mut packet = RtpPacketMut::new(&mut buffer)?;
+//! let sequence_number = packet.sequence_number().wrapping_add(100);
+//! packet.set_ssrc(12345);
+//! packet.set_sequence_number(sequence_number);
+//! # Ok(buffer)
+//! # }
+//! ```
+//!
+//! [SwitchingContext](struct.SwitchingContext.html) keeps the stream continuous for the peer
+//! when the relayed source changes:
+//!
+//! ```
+//! use janus_app::rtp::SwitchingContext;
+//!
+//! # fn relay(context: &mut SwitchingContext, buffer: &[i8]) -> Result<(), janus_app::Error> {
+//! // The same context for all publishers the subscriber is switching between.
+//! let mut buffer = buffer.to_vec();
+//! context.rewrite(&mut buffer)?;
+//! // Pass `buffer` to `relay_media_packet`.
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::time::Instant;
+
+use crate::Error;
+
+const FIXED_HEADER_LEN: usize = 12;
+const ONE_BYTE_PROFILE: u16 = 0xBEDE;
+const TWO_BYTE_PROFILE: u16 = 0x1000;
+const TWO_BYTE_PROFILE_MASK: u16 = 0xFFF0;
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Read-only RTP packet view over a media buffer.
+#[derive(Clone, Copy)]
+pub struct RtpPacket<'a> {
+    buffer: &'a [u8],
+    layout: Layout,
+}
+
+impl<'a> RtpPacket<'a> {
+    /// Parses the RTP header of a media buffer as it comes in
+    /// [MediaEvent::Media](../enum.MediaEvent.html).
+    pub fn new(buffer: &'a [i8]) -> Result<Self, Error> {
+        Self::from_bytes(to_bytes(buffer))
+    }
+
+    pub fn from_bytes(buffer: &'a [u8]) -> Result<Self, Error> {
+        let layout = Layout::parse(buffer)?;
+        Ok(Self { buffer, layout })
+    }
+
+    /// RTP version. Always 2 for a successfully parsed packet.
+    pub fn version(&self) -> u8 {
+        self.buffer[0] >> 6
+    }
+
+    pub fn has_padding(&self) -> bool {
+        self.buffer[0] & 0x20 != 0
+    }
+
+    pub fn has_extension(&self) -> bool {
+        self.buffer[0] & 0x10 != 0
+    }
+
+    pub fn marker(&self) -> bool {
+        self.buffer[1] & 0x80 != 0
+    }
+
+    pub fn payload_type(&self) -> u8 {
+        self.buffer[1] & 0x7F
+    }
+
+    pub fn sequence_number(&self) -> u16 {
+        read_u16(self.buffer, 2)
+    }
+
+    pub fn timestamp(&self) -> u32 {
+        read_u32(self.buffer, 4)
+    }
+
+    pub fn ssrc(&self) -> u32 {
+        read_u32(self.buffer, 8)
+    }
+
+    /// Contributing sources.
+    pub fn csrcs(&self) -> impl Iterator<Item = u32> + 'a {
+        let buffer = self.buffer;
+
+        (0..self.layout.csrc_count).map(move |idx| read_u32(buffer, FIXED_HEADER_LEN + idx * 4))
+    }
+
+    /// Header extension profile, `0xBEDE` for one-byte and `0x100X` for two-byte extensions.
+    pub fn extension_profile(&self) -> Option<u16> {
+        self.layout
+            .extension
+            .map(|(start, _)| read_u16(self.buffer, start - 4))
+    }
+
+    /// Raw header extension data without the profile and length words.
+    pub fn extension_data(&self) -> Option<&'a [u8]> {
+        self.layout
+            .extension
+            .map(|(start, end)| &self.buffer[start..end])
+    }
+
+    /// One-byte or two-byte (RFC 8285) header extension elements.
+    /// Nothing is being yielded for other profiles.
+    pub fn extensions(&self) -> Extensions<'a> {
+        let format = self
+            .extension_profile()
+            .and_then(ExtensionFormat::from_profile);
+
+        Extensions {
+            data: self.extension_data().unwrap_or(&[]),
+            format,
+            offset: 0,
+        }
+    }
+
+    /// Value of the header extension element with the given `id`.
+    pub fn extension(&self, id: u8) -> Option<&'a [u8]> {
+        self.extensions()
+            .find(|extension| extension.id == id)
+            .map(|extension| extension.data)
+    }
+
+    /// Payload without the header and padding.
+    pub fn payload(&self) -> &'a [u8] {
+        &self.buffer[self.layout.payload.0..self.layout.payload.1]
+    }
+
+    /// Number of padding bytes at the end of the packet.
+    pub fn padding_len(&self) -> usize {
+        self.buffer.len() - self.layout.payload.1
+    }
+
+    /// The whole packet.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.buffer
+    }
+}
+
+impl<'a> fmt::Debug for RtpPacket<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("RtpPacket")
+            .field("marker", &self.marker())
+            .field("payload_type", &self.payload_type())
+            .field("sequence_number", &self.sequence_number())
+            .field("timestamp", &self.timestamp())
+            .field("ssrc", &self.ssrc())
+            .field("payload_len", &self.payload().len())
+            .finish()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Mutable RTP packet view to rewrite header fields and payload in place.
+pub struct RtpPacketMut<'a> {
+    buffer: &'a mut [u8],
+    layout: Layout,
+}
+
+impl<'a> RtpPacketMut<'a> {
+    pub fn new(buffer: &'a mut [i8]) -> Result<Self, Error> {
+        Self::from_bytes(to_bytes_mut(buffer))
+    }
+
+    pub fn from_bytes(buffer: &'a mut [u8]) -> Result<Self, Error> {
+        let layout = Layout::parse(buffer)?;
+        Ok(Self { buffer, layout })
+    }
+
+    /// Read-only view to get header fields.
+    pub fn as_packet(&self) -> RtpPacket<'_> {
+        RtpPacket {
+            buffer: self.buffer,
+            layout: self.layout,
+        }
+    }
+
+    pub fn marker(&self) -> bool {
+        self.as_packet().marker()
+    }
+
+    pub fn set_marker(&mut self, marker: bool) {
+        self.buffer[1] = (self.buffer[1] & 0x7F) | if marker { 0x80 } else { 0 };
+    }
+
+    pub fn payload_type(&self) -> u8 {
+        self.as_packet().payload_type()
+    }
+
+    /// Sets the payload type. Only the lower 7 bits are being used.
+    pub fn set_payload_type(&mut self, payload_type: u8) {
+        self.buffer[1] = (self.buffer[1] & 0x80) | (payload_type & 0x7F);
+    }
+
+    pub fn sequence_number(&self) -> u16 {
+        self.as_packet().sequence_number()
+    }
+
+    pub fn set_sequence_number(&mut self, sequence_number: u16) {
+        self.buffer[2..4].copy_from_slice(&sequence_number.to_be_bytes());
+    }
+
+    pub fn timestamp(&self) -> u32 {
+        self.as_packet().timestamp()
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: u32) {
+        self.buffer[4..8].copy_from_slice(&timestamp.to_be_bytes());
+    }
+
+    pub fn ssrc(&self) -> u32 {
+        self.as_packet().ssrc()
+    }
+
+    pub fn set_ssrc(&mut self, ssrc: u32) {
+        self.buffer[8..12].copy_from_slice(&ssrc.to_be_bytes());
+    }
+
+    /// Mutable value of the header extension element with the given `id`.
+    /// The value length can't be changed in place.
+    pub fn extension_mut(&mut self, id: u8) -> Option<&mut [u8]> {
+        let (start, _) = self.layout.extension?;
+
+        let range = self
+            .as_packet()
+            .extensions()
+            .find(|extension| extension.id == id)
+            .map(|extension| {
+                let offset =
+                    extension.data.as_ptr() as usize - self.buffer[start..].as_ptr() as usize;
+                (start + offset, start + offset + extension.data.len())
+            })?;
+
+        Some(&mut self.buffer[range.0..range.1])
+    }
+
+    /// Mutable payload without the header and padding.
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer[self.layout.payload.0..self.layout.payload.1]
+    }
+
+    /// The whole packet as a media buffer to pass to
+    /// [relay_media_packet](../plugin/trait.Callbacks.html#method.relay_media_packet).
+    pub fn as_buffer(&self) -> &[i8] {
+        unsafe { std::slice::from_raw_parts(self.buffer.as_ptr() as *const i8, self.buffer.len()) }
+    }
+}
+
+impl<'a> fmt::Debug for RtpPacketMut<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_packet(), fmt)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Keeps outgoing SSRC, sequence numbers and timestamps continuous when the source changes,
+/// e.g. when a subscriber switches from one publisher to another.
+///
+/// A new source is being detected by its SSRC. Its first packet continues the sequence numbers
+/// and advances the timestamp by the time passed since the last packet of the previous source
+/// so the peer sees a single stream instead of a jump.
+#[derive(Clone, Debug)]
+pub struct SwitchingContext {
+    ssrc: u32,
+    clock_rate: u32,
+    source_ssrc: Option<u32>,
+    switched: bool,
+    sequence_number_offset: u16,
+    timestamp_offset: u32,
+    last: Option<LastPacket>,
+}
+
+/// The newest outgoing packet.
+#[derive(Clone, Copy, Debug)]
+struct LastPacket {
+    sequence_number: u16,
+    timestamp: u32,
+    sent_at: Instant,
+}
+
+impl SwitchingContext {
+    /// `ssrc` is being set to all outgoing packets and `clock_rate` is the RTP clock rate of
+    /// the media, e.g. 48000 for Opus or 90000 for video.
+    pub fn new(ssrc: u32, clock_rate: u32) -> Self {
+        Self {
+            ssrc,
+            clock_rate,
+            source_ssrc: None,
+            switched: true,
+            sequence_number_offset: 0,
+            timestamp_offset: 0,
+            last: None,
+        }
+    }
+
+    pub fn ssrc(&self) -> u32 {
+        self.ssrc
+    }
+
+    /// Makes the next packet start a new source even if its SSRC is the same, e.g. when the
+    /// source has been restarted with new numbering.
+    pub fn switch(&mut self) {
+        self.switched = true;
+    }
+
+    /// Rewrites a media buffer in place before relaying it with
+    /// [relay_media_packet](../plugin/trait.Callbacks.html#method.relay_media_packet).
+    pub fn rewrite(&mut self, buffer: &mut [i8]) -> Result<(), Error> {
+        let mut packet = RtpPacketMut::new(buffer)?;
+        self.rewrite_packet(&mut packet);
+        Ok(())
+    }
+
+    /// Sets the context's SSRC to an already parsed packet and shifts its sequence number and
+    /// timestamp by the offsets of its source. A packet of a new source or the first one after
+    /// [switch](#method.switch) recalculates the offsets to continue the outgoing stream.
+    pub fn rewrite_packet(&mut self, packet: &mut RtpPacketMut) {
+        self.rewrite_packet_at(packet, Instant::now());
+    }
+
+    fn rewrite_packet_at(&mut self, packet: &mut RtpPacketMut, now: Instant) {
+        if self.switched || self.source_ssrc != Some(packet.ssrc()) {
+            if let Some(last) = self.last {
+                // Advance the timestamp as much as the time has passed since the last packet.
+                let elapsed = now.saturating_duration_since(last.sent_at).as_secs_f64();
+                let ticks = ((elapsed * self.clock_rate as f64) as u32).max(1);
+                let timestamp = last.timestamp.wrapping_add(ticks);
+                let sequence_number = last.sequence_number.wrapping_add(1);
+                self.timestamp_offset = timestamp.wrapping_sub(packet.timestamp());
+                self.sequence_number_offset =
+                    sequence_number.wrapping_sub(packet.sequence_number());
+            }
+
+            self.source_ssrc = Some(packet.ssrc());
+            self.switched = false;
+        }
+
+        let sequence_number = packet
+            .sequence_number()
+            .wrapping_add(self.sequence_number_offset);
+
+        let timestamp = packet.timestamp().wrapping_add(self.timestamp_offset);
+        packet.set_sequence_number(sequence_number);
+        packet.set_timestamp(timestamp);
+        packet.set_ssrc(self.ssrc);
+
+        // Retransmitted or reordered packets must not move the baseline back.
+        let is_newest = match self.last {
+            Some(last) => sequence_number.wrapping_sub(last.sequence_number) as i16 > 0,
+            None => true,
+        };
+
+        if is_newest {
+            self.last = Some(LastPacket {
+                sequence_number,
+                timestamp,
+                sent_at: now,
+            });
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// RTP header extension element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Extension<'a> {
+    pub id: u8,
+    pub data: &'a [u8],
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ExtensionFormat {
+    OneByte,
+    TwoByte,
+}
+
+impl ExtensionFormat {
+    fn from_profile(profile: u16) -> Option<Self> {
+        if profile == ONE_BYTE_PROFILE {
+            Some(Self::OneByte)
+        } else if profile & TWO_BYTE_PROFILE_MASK == TWO_BYTE_PROFILE {
+            Some(Self::TwoByte)
+        } else {
+            None
+        }
+    }
+}
+
+/// Iterator over header extension elements. Stops at the first malformed element.
+#[derive(Clone, Debug)]
+pub struct Extensions<'a> {
+    data: &'a [u8],
+    format: Option<ExtensionFormat>,
+    offset: usize,
+}
+
+impl<'a> Iterator for Extensions<'a> {
+    type Item = Extension<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let format = self.format?;
+
+        loop {
+            let byte = *self.data.get(self.offset)?;
+
+            // Skip padding bytes between elements.
+            if byte == 0 {
+                self.offset += 1;
+                continue;
+            }
+
+            let (id, len, header_len) = match format {
+                ExtensionFormat::OneByte if byte >> 4 == 15 => return None,
+                ExtensionFormat::OneByte => (byte >> 4, (byte & 0x0F) as usize + 1, 1),
+                ExtensionFormat::TwoByte => {
+                    let len = *self.data.get(self.offset + 1)?;
+                    (byte, len as usize, 2)
+                }
+            };
+
+            let start = self.offset + header_len;
+            let data = self.data.get(start..start + len)?;
+            self.offset = start + len;
+            return Some(Extension { id, data });
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Offsets of variable length header parts.
+#[derive(Clone, Copy, Debug)]
+struct Layout {
+    csrc_count: usize,
+    extension: Option<(usize, usize)>,
+    payload: (usize, usize),
+}
+
+impl Layout {
+    fn parse(buffer: &[u8]) -> Result<Self, Error> {
+        if buffer.len() < FIXED_HEADER_LEN {
+            return Err(Error::new(&format!(
+                "RTP packet is too short: {} bytes",
+                buffer.len()
+            )));
+        }
+
+        let version = buffer[0] >> 6;
+
+        if version != 2 {
+            return Err(Error::new(&format!("Unsupported RTP version: {}", version)));
+        }
+
+        let csrc_count = (buffer[0] & 0x0F) as usize;
+        let mut header_len = FIXED_HEADER_LEN + csrc_count * 4;
+        let mut extension = None;
+
+        if buffer[0] & 0x10 != 0 {
+            if buffer.len() < header_len + 4 {
+                return Err(Error::new("RTP header extension is truncated"));
+            }
+
+            let len = read_u16(buffer, header_len + 2) as usize * 4;
+            let start = header_len + 4;
+            extension = Some((start, start + len));
+            header_len = start + len;
+        }
+
+        if buffer.len() < header_len {
+            return Err(Error::new("RTP header is truncated"));
+        }
+
+        let mut payload_end = buffer.len();
+
+        if buffer[0] & 0x20 != 0 {
+            let padding_len = buffer[buffer.len() - 1] as usize;
+
+            if padding_len == 0 || header_len + padding_len > buffer.len() {
+                return Err(Error::new(&format!(
+                    "Invalid RTP padding length: {}",
+                    padding_len
+                )));
+            }
+
+            payload_end -= padding_len;
+        }
+
+        Ok(Self {
+            csrc_count,
+            extension,
+            payload: (header_len, payload_end),
+        })
+    }
+}
+
+fn read_u16(buffer: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([buffer[offset], buffer[offset + 1]])
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        buffer[offset],
+        buffer[offset + 1],
+        buffer[offset + 2],
+        buffer[offset + 3],
+    ])
+}
+
+pub(crate) fn to_bytes(buffer: &[i8]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(buffer.as_ptr() as *const u8, buffer.len()) }
+}
+
+fn to_bytes_mut(buffer: &mut [i8]) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, buffer.len()) }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet() -> Vec<u8> {
+        vec![
+            // V=2, P=1, X=1, CC=1; M=1, PT=111
+            0xB1, 0xEF, 0x12, 0x34, 0x00, 0x00, 0x10, 0x00, 0xDE, 0xAD, 0xBE, 0xEF,
+            // CSRC
+            0x00, 0x00, 0x00, 0x2A,
+            // One-byte extensions: id=1 len=1, id=3 len=2, padding.
+            0xBE, 0xDE, 0x00, 0x02, 0x10, 0x7F, 0x31, 0xAA, 0xBB, 0x00, 0x00, 0x00,
+            // Payload and 2 bytes of padding.
+            0x01, 0x02, 0x03, 0x00, 0x02,
+        ]
+    }
+
+    #[test]
+    fn parse() {
+        let buffer = packet();
+        let packet = RtpPacket::from_bytes(&buffer).unwrap();
+        assert_eq!(packet.version(), 2);
+        assert!(packet.marker());
+        assert_eq!(packet.payload_type(), 111);
+        assert_eq!(packet.sequence_number(), 0x1234);
+        assert_eq!(packet.timestamp(), 0x1000);
+        assert_eq!(packet.ssrc(), 0xDEADBEEF);
+        assert_eq!(packet.csrcs().collect::<Vec<_>>(), vec![42]);
+        assert_eq!(packet.extension_profile(), Some(ONE_BYTE_PROFILE));
+        assert_eq!(packet.extensions().count(), 2);
+        assert_eq!(packet.extension(1), Some(&[0x7F][..]));
+        assert_eq!(packet.extension(3), Some(&[0xAA, 0xBB][..]));
+        assert_eq!(packet.payload(), &[0x01, 0x02, 0x03]);
+        assert_eq!(packet.padding_len(), 2);
+    }
+
+    #[test]
+    fn two_byte_extensions() {
+        let buffer = vec![
+            0x90, 0x60, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x10, 0x00,
+            0x00, 0x02, 0x05, 0x00, 0x07, 0x03, 0x01, 0x02, 0x03, 0x00, 0xFF,
+        ];
+
+        let packet = RtpPacket::from_bytes(&buffer).unwrap();
+        assert_eq!(packet.extension(5), Some(&[][..]));
+        assert_eq!(packet.extension(7), Some(&[0x01, 0x02, 0x03][..]));
+        assert_eq!(packet.payload(), &[0xFF]);
+    }
+
+    #[test]
+    fn rewrite() {
+        let mut buffer = packet();
+        let mut packet = RtpPacketMut::from_bytes(&mut buffer).unwrap();
+        packet.set_marker(false);
+        packet.set_payload_type(96);
+        packet.set_sequence_number(1);
+        packet.set_timestamp(2);
+        packet.set_ssrc(3);
+        packet
+            .extension_mut(3)
+            .unwrap()
+            .copy_from_slice(&[0xCC, 0xDD]);
+        packet.payload_mut()[0] = 0x09;
+
+        let packet = RtpPacket::from_bytes(&buffer).unwrap();
+        assert!(!packet.marker());
+        assert_eq!(packet.payload_type(), 96);
+        assert_eq!(packet.sequence_number(), 1);
+        assert_eq!(packet.timestamp(), 2);
+        assert_eq!(packet.ssrc(), 3);
+        assert_eq!(packet.csrcs().collect::<Vec<_>>(), vec![42]);
+        assert_eq!(packet.extension(3), Some(&[0xCC, 0xDD][..]));
+        assert_eq!(packet.payload(), &[0x09, 0x02, 0x03]);
+    }
+
+    #[test]
+    fn switching() {
+        let start = Instant::now();
+        let mut context = SwitchingContext::new(9, 48000);
+
+        let send = |context: &mut SwitchingContext, sequence_number, timestamp, ssrc, millis| {
+            let mut buffer = packet();
+            let mut packet = RtpPacketMut::from_bytes(&mut buffer).unwrap();
+            packet.set_sequence_number(sequence_number);
+            packet.set_timestamp(timestamp);
+            packet.set_ssrc(ssrc);
+            let now = start + std::time::Duration::from_millis(millis);
+            context.rewrite_packet_at(&mut packet, now);
+            (packet.sequence_number(), packet.timestamp(), packet.ssrc())
+        };
+
+        // The first source keeps its numbering.
+        assert_eq!(send(&mut context, 65535, 1000, 1, 0), (65535, 1000, 9));
+        assert_eq!(send(&mut context, 0, 1960, 1, 20), (0, 1960, 9));
+        assert_eq!(send(&mut context, 65534, 40, 1, 30), (65534, 40, 9));
+
+        // Switching continues after the newest packet, not the reordered one.
+        assert_eq!(send(&mut context, 5000, 7, 2, 60), (1, 1960 + 1920, 9));
+        assert_eq!(
+            send(&mut context, 5001, 967, 2, 80),
+            (2, 1960 + 1920 + 960, 9)
+        );
+
+        // Restarted source with the same SSRC.
+        context.switch();
+        assert_eq!(
+            send(&mut context, 10, 0, 2, 80),
+            (3, 1960 + 1920 + 960 + 1, 9)
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let buffer = packet();
+        assert!(RtpPacket::from_bytes(&buffer[..8]).is_err());
+        assert!(RtpPacket::from_bytes(&buffer[..20]).is_err());
+
+        let mut buffer = packet();
+        buffer[0] = 0x31;
+        assert!(RtpPacket::from_bytes(&buffer).is_err());
+
+        let mut buffer = packet();
+        *buffer.last_mut().unwrap() = 100;
+        assert!(RtpPacket::from_bytes(&buffer).is_err());
+    }
+}
@@ -0,0 +1,1544 @@
+//! SDP (Session Description Protocol) model.
+//!
+//! [SessionDescription](struct.SessionDescription.html) is being parsed from the text carried
+//! in [Jsep](../enum.Jsep.html) and printed back to exactly the same text unless modified.
+//! Well-known attributes are available in a typed form while all other lines are preserved as is.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de, ser};
+
+use crate::Error;
+
+mod answer;
+pub use answer::{AnswerBuilder, Codec};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Parsed session description.
+#[derive(Clone, Debug)]
+pub struct SessionDescription {
+    /// `v=` line value.
+    pub version: u32,
+    /// `o=` line.
+    pub origin: Origin,
+    /// `s=` line value.
+    pub session_name: String,
+    /// Other session-level lines in their original order.
+    pub lines: Vec<Line>,
+    /// Media sections (`m=` line and everything below it until the next `m=` line).
+    pub media: Vec<MediaDescription>,
+    line_ending: LineEnding,
+    source: Option<Source>,
+}
+
+/// The text a description has been parsed from along with the description as parsed
+/// to tell whether it has been modified since.
+#[derive(Clone, Debug)]
+struct Source {
+    text: String,
+    parsed: Box<SessionDescription>,
+}
+
+impl SessionDescription {
+    pub fn new(origin: Origin, session_name: &str) -> Self {
+        Self {
+            version: 0,
+            origin,
+            session_name: session_name.to_owned(),
+            lines: vec![],
+            media: vec![],
+            line_ending: LineEnding::CrLf,
+            source: None,
+        }
+    }
+
+    /// Session-level attributes.
+    pub fn attributes(&self) -> impl Iterator<Item = &Attribute> {
+        attributes(&self.lines)
+    }
+
+    /// BUNDLE and other groups of media sections.
+    pub fn groups(&self) -> impl Iterator<Item = &Group> {
+        self.attributes().filter_map(|attr| match attr {
+            Attribute::Group(group) => Some(group),
+            _ => None,
+        })
+    }
+
+    /// Finds a media section by its `a=mid` value.
+    pub fn media_by_mid(&self, mid: &str) -> Option<&MediaDescription> {
+        self.media.iter().find(|media| media.mid() == Some(mid))
+    }
+
+    pub fn media_by_mid_mut(&mut self, mid: &str) -> Option<&mut MediaDescription> {
+        self.media.iter_mut().find(|media| media.mid() == Some(mid))
+    }
+}
+
+impl PartialEq for SessionDescription {
+    fn eq(&self, other: &Self) -> bool {
+        // The source text is only a way of printing the description back.
+        self.version == other.version
+            && self.origin == other.origin
+            && self.session_name == other.session_name
+            && self.lines == other.lines
+            && self.media == other.media
+            && self.line_ending == other.line_ending
+    }
+}
+
+impl FromStr for SessionDescription {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Error> {
+        let line_ending = match text.find('\n') {
+            Some(idx) if idx > 0 && text.as_bytes()[idx - 1] == b'\r' => LineEnding::CrLf,
+            _ => LineEnding::Lf,
+        };
+
+        let mut version = None;
+        let mut origin = None;
+        let mut session_name = None;
+        let mut lines = vec![];
+        let mut media: Vec<MediaDescription> = vec![];
+
+        for (idx, raw_line) in text.lines().enumerate() {
+            if raw_line.is_empty() {
+                continue;
+            }
+
+            let (kind, value) = split_line(raw_line)
+                .map_err(|err| Error::new(&format!("Bad SDP line {}: {}", idx + 1, err)))?;
+
+            let wrap_err = |err: Error| Error::new(&format!("Bad SDP line {}: {}", idx + 1, err));
+
+            match (kind, media.last_mut()) {
+                ('m', _) => media.push(MediaDescription::from_media_line(
+                    value.parse().map_err(wrap_err)?,
+                )),
+                (_, Some(current)) => current.lines.push(Line::parse(kind, value)),
+                ('v', None) => {
+                    version = Some(value.parse::<u32>().map_err(|err| {
+                        wrap_err(Error::new(&format!("Failed to parse version: {}", err)))
+                    })?)
+                }
+                ('o', None) => origin = Some(value.parse::<Origin>().map_err(wrap_err)?),
+                ('s', None) => session_name = Some(value.to_owned()),
+                (_, None) => lines.push(Line::parse(kind, value)),
+            }
+        }
+
+        let parsed = Self {
+            version: version.ok_or_else(|| Error::new("Missing SDP version line"))?,
+            origin: origin.ok_or_else(|| Error::new("Missing SDP origin line"))?,
+            session_name: session_name.ok_or_else(|| Error::new("Missing SDP session name"))?,
+            lines,
+            media,
+            line_ending,
+            source: None,
+        };
+
+        Ok(Self {
+            source: Some(Source {
+                text: text.to_owned(),
+                parsed: Box::new(parsed.clone()),
+            }),
+            ..parsed
+        })
+    }
+}
+
+impl fmt::Display for SessionDescription {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        // Empty lines and mixed line endings only survive as long as nothing gets modified.
+        // Otherwise the line ending of the first line is used for all of them.
+        if let Some(ref source) = self.source {
+            if *source.parsed == *self {
+                return fmt.write_str(&source.text);
+            }
+        }
+
+        let nl = self.line_ending.as_str();
+        write!(fmt, "v={}{}", self.version, nl)?;
+        write!(fmt, "o={}{}", self.origin, nl)?;
+        write!(fmt, "s={}{}", self.session_name, nl)?;
+
+        for line in &self.lines {
+            write!(fmt, "{}{}", line, nl)?;
+        }
+
+        for media in &self.media {
+            write!(fmt, "m={}{}", media.media_line(), nl)?;
+
+            for line in &media.lines {
+                write!(fmt, "{}{}", line, nl)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ser::Serialize for SessionDescription {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for SessionDescription {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = SessionDescription;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                write!(fmt, "SDP string")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LineEnding {
+    CrLf,
+    Lf,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::CrLf => "\r\n",
+            Self::Lf => "\n",
+        }
+    }
+}
+
+fn split_line(line: &str) -> Result<(char, &str), Error> {
+    let mut chars = line.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(kind), Some('=')) => Ok((kind, &line[kind.len_utf8() + 1..])),
+        _ => Err(Error::new("expected `<type>=<value>`")),
+    }
+}
+
+fn attributes(lines: &[Line]) -> impl Iterator<Item = &Attribute> {
+    lines.iter().filter_map(|line| match line {
+        Line::Attribute(attr) => Some(attr),
+        _ => None,
+    })
+}
+
+fn parse_field<T: FromStr>(value: Option<&str>, name: &str) -> Result<T, Error>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .ok_or_else(|| Error::new(&format!("missing {}", name)))?
+        .parse::<T>()
+        .map_err(|err| Error::new(&format!("bad {}: {}", name, err)))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// `o=` line: `<username> <sess-id> <sess-version> <nettype> <addrtype> <unicast-address>`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Origin {
+    pub username: String,
+    /// Kept as is since clients send ids with leading zeros or beyond 64 bits.
+    pub session_id: String,
+    pub session_version: String,
+    pub network_type: String,
+    pub address_type: String,
+    pub address: String,
+}
+
+impl FromStr for Origin {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let mut parts = value.split(' ');
+
+        let origin = Self {
+            username: parse_field(parts.next(), "origin username")?,
+            session_id: parse_field(parts.next(), "origin session id")?,
+            session_version: parse_field(parts.next(), "origin session version")?,
+            network_type: parse_field(parts.next(), "origin network type")?,
+            address_type: parse_field(parts.next(), "origin address type")?,
+            address: parse_field(parts.next(), "origin address")?,
+        };
+
+        match parts.next() {
+            None => Ok(origin),
+            Some(_) => Err(Error::new("extra origin fields")),
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{} {} {} {} {} {}",
+            self.username,
+            self.session_id,
+            self.session_version,
+            self.network_type,
+            self.address_type,
+            self.address
+        )
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A media section.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MediaDescription {
+    /// Media type: `audio`, `video`, `application` etc.
+    pub kind: String,
+    /// Transport port. Zero port means a rejected media section.
+    pub port: u16,
+    /// Optional number of ports after the slash.
+    pub port_count: Option<u16>,
+    /// Transport protocol, e.g. `UDP/TLS/RTP/SAVPF`.
+    pub protocol: String,
+    /// Formats list. For RTP media these are payload types.
+    pub formats: Vec<String>,
+    /// Lines below the `m=` line in their original order.
+    pub lines: Vec<Line>,
+}
+
+/// Just the `m=` line of a media section.
+struct MediaLine {
+    kind: String,
+    port: u16,
+    port_count: Option<u16>,
+    protocol: String,
+    formats: Vec<String>,
+}
+
+impl FromStr for MediaLine {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let mut parts = value.split(' ');
+        let kind = parse_field(parts.next(), "media type")?;
+        let port_str: String = parse_field(parts.next(), "media port")?;
+
+        let (port, port_count) = match port_str.find('/') {
+            None => (parse_field(Some(&port_str), "media port")?, None),
+            Some(idx) => (
+                parse_field(Some(&port_str[..idx]), "media port")?,
+                Some(parse_field(Some(&port_str[idx + 1..]), "media port count")?),
+            ),
+        };
+
+        let protocol = parse_field(parts.next(), "media protocol")?;
+        let formats = parts.map(|s| s.to_owned()).collect();
+
+        Ok(Self {
+            kind,
+            port,
+            port_count,
+            protocol,
+            formats,
+        })
+    }
+}
+
+impl fmt::Display for MediaLine {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} {}", self.kind, self.port)?;
+
+        if let Some(port_count) = self.port_count {
+            write!(fmt, "/{}", port_count)?;
+        }
+
+        write!(fmt, " {}", self.protocol)?;
+
+        for format in &self.formats {
+            write!(fmt, " {}", format)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MediaDescription {
+    pub fn new(kind: &str, port: u16, protocol: &str, formats: Vec<String>) -> Self {
+        Self {
+            kind: kind.to_owned(),
+            port,
+            port_count: None,
+            protocol: protocol.to_owned(),
+            formats,
+            lines: vec![],
+        }
+    }
+
+    fn from_media_line(media_line: MediaLine) -> Self {
+        Self {
+            kind: media_line.kind,
+            port: media_line.port,
+            port_count: media_line.port_count,
+            protocol: media_line.protocol,
+            formats: media_line.formats,
+            lines: vec![],
+        }
+    }
+
+    fn media_line(&self) -> MediaLine {
+        MediaLine {
+            kind: self.kind.clone(),
+            port: self.port,
+            port_count: self.port_count,
+            protocol: self.protocol.clone(),
+            formats: self.formats.clone(),
+        }
+    }
+
+    /// Whether the section has been rejected with zero port.
+    pub fn is_rejected(&self) -> bool {
+        self.port == 0
+    }
+
+    /// Payload types for RTP media sections.
+    pub fn payload_types(&self) -> impl Iterator<Item = u8> + '_ {
+        self.formats.iter().filter_map(|fmt| fmt.parse().ok())
+    }
+
+    pub fn attributes(&self) -> impl Iterator<Item = &Attribute> {
+        attributes(&self.lines)
+    }
+
+    /// Appends an attribute line.
+    pub fn push_attribute(&mut self, attribute: Attribute) {
+        self.lines.push(Line::Attribute(attribute));
+    }
+
+    /// Removes all attributes matching the `predicate`.
+    pub fn remove_attributes<F: Fn(&Attribute) -> bool>(&mut self, predicate: F) {
+        self.lines.retain(|line| match line {
+            Line::Attribute(attr) => !predicate(attr),
+            _ => true,
+        });
+    }
+
+    pub fn mid(&self) -> Option<&str> {
+        self.attributes().find_map(|attr| match attr {
+            Attribute::Mid(mid) => Some(mid.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Media direction. Defaults to `sendrecv` when not specified.
+    pub fn direction(&self) -> Direction {
+        self.attributes()
+            .find_map(|attr| match attr {
+                Attribute::Direction(direction) => Some(*direction),
+                _ => None,
+            })
+            .unwrap_or(Direction::SendRecv)
+    }
+
+    /// Replaces the media direction attribute.
+    pub fn set_direction(&mut self, direction: Direction) {
+        let mut replaced = false;
+
+        for line in self.lines.iter_mut() {
+            if let Line::Attribute(Attribute::Direction(ref mut value)) = line {
+                *value = direction;
+                replaced = true;
+            }
+        }
+
+        if !replaced {
+            self.push_attribute(Attribute::Direction(direction));
+        }
+    }
+
+    pub fn rtpmaps(&self) -> impl Iterator<Item = &Rtpmap> {
+        self.attributes().filter_map(|attr| match attr {
+            Attribute::Rtpmap(rtpmap) => Some(rtpmap),
+            _ => None,
+        })
+    }
+
+    pub fn rtpmap(&self, payload_type: u8) -> Option<&Rtpmap> {
+        self.rtpmaps()
+            .find(|rtpmap| rtpmap.payload_type == payload_type)
+    }
+
+    pub fn fmtps(&self) -> impl Iterator<Item = &Fmtp> {
+        self.attributes().filter_map(|attr| match attr {
+            Attribute::Fmtp(fmtp) => Some(fmtp),
+            _ => None,
+        })
+    }
+
+    pub fn fmtp(&self, payload_type: u8) -> Option<&Fmtp> {
+        self.fmtps().find(|fmtp| fmtp.payload_type == payload_type)
+    }
+
+    pub fn rtcp_fbs(&self, payload_type: u8) -> impl Iterator<Item = &RtcpFb> {
+        self.attributes().filter_map(move |attr| match attr {
+            Attribute::RtcpFb(fb) if fb.payload_type == PayloadTypeRef::Id(payload_type) => {
+                Some(fb)
+            }
+            Attribute::RtcpFb(fb) if fb.payload_type == PayloadTypeRef::Any => Some(fb),
+            _ => None,
+        })
+    }
+
+    pub fn extmaps(&self) -> impl Iterator<Item = &Extmap> {
+        self.attributes().filter_map(|attr| match attr {
+            Attribute::Extmap(extmap) => Some(extmap),
+            _ => None,
+        })
+    }
+
+    pub fn ssrcs(&self) -> impl Iterator<Item = &Ssrc> {
+        self.attributes().filter_map(|attr| match attr {
+            Attribute::Ssrc(ssrc) => Some(ssrc),
+            _ => None,
+        })
+    }
+
+    pub fn ssrc_groups(&self) -> impl Iterator<Item = &SsrcGroup> {
+        self.attributes().filter_map(|attr| match attr {
+            Attribute::SsrcGroup(group) => Some(group),
+            _ => None,
+        })
+    }
+
+    pub fn rids(&self) -> impl Iterator<Item = &Rid> {
+        self.attributes().filter_map(|attr| match attr {
+            Attribute::Rid(rid) => Some(rid),
+            _ => None,
+        })
+    }
+
+    pub fn simulcast(&self) -> Option<&Simulcast> {
+        self.attributes().find_map(|attr| match attr {
+            Attribute::Simulcast(simulcast) => Some(simulcast),
+            _ => None,
+        })
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A non-`m=` line.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Line {
+    /// `a=` line.
+    Attribute(Attribute),
+    /// Any other line like `c=`, `t=` or `b=`.
+    Other { kind: char, value: String },
+}
+
+impl Line {
+    fn parse(kind: char, value: &str) -> Self {
+        match kind {
+            'a' => Self::Attribute(Attribute::parse(value)),
+            _ => Self::Other {
+                kind,
+                value: value.to_owned(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Attribute(attr) => write!(fmt, "a={}", attr),
+            Self::Other { kind, value } => write!(fmt, "{}={}", kind, value),
+        }
+    }
+}
+
+/// `a=` line value.
+///
+/// An attribute is being parsed into a typed variant only when printing it back gives
+/// the same text. Otherwise it gets preserved as [Other](#variant.Other).
+#[derive(Clone, Debug, PartialEq)]
+pub enum Attribute {
+    /// `a=mid:<id>`
+    Mid(String),
+    /// `a=sendrecv`, `a=sendonly`, `a=recvonly` or `a=inactive`.
+    Direction(Direction),
+    /// `a=group:<semantics> <mid>...`
+    Group(Group),
+    /// `a=rtpmap:<pt> <encoding>/<clock rate>[/<channels>]`
+    Rtpmap(Rtpmap),
+    /// `a=fmtp:<pt> <parameters>`
+    Fmtp(Fmtp),
+    /// `a=rtcp-fb:<pt> <type> [<subtype>]`
+    RtcpFb(RtcpFb),
+    /// `a=extmap:<id>[/<direction>] <uri> [<attributes>]`
+    Extmap(Extmap),
+    /// `a=ssrc:<ssrc> <attribute>[:<value>]`
+    Ssrc(Ssrc),
+    /// `a=ssrc-group:<semantics> <ssrc>...`
+    SsrcGroup(SsrcGroup),
+    /// `a=rid:<id> <direction> [<restrictions>]`
+    Rid(Rid),
+    /// `a=simulcast:<direction> <streams> [<direction> <streams>]`
+    Simulcast(Simulcast),
+    /// Any other attribute: `a=<name>[:<value>]`.
+    Other { name: String, value: Option<String> },
+}
+
+impl Attribute {
+    fn parse(text: &str) -> Self {
+        let (name, value) = match text.find(':') {
+            Some(idx) => (&text[..idx], Some(&text[idx + 1..])),
+            None => (text, None),
+        };
+
+        let typed = match (name, value) {
+            ("mid", Some(value)) => Some(Self::Mid(value.to_owned())),
+            ("sendrecv", None) => Some(Self::Direction(Direction::SendRecv)),
+            ("sendonly", None) => Some(Self::Direction(Direction::SendOnly)),
+            ("recvonly", None) => Some(Self::Direction(Direction::RecvOnly)),
+            ("inactive", None) => Some(Self::Direction(Direction::Inactive)),
+            ("group", Some(value)) => value.parse().ok().map(Self::Group),
+            ("rtpmap", Some(value)) => value.parse().ok().map(Self::Rtpmap),
+            ("fmtp", Some(value)) => value.parse().ok().map(Self::Fmtp),
+            ("rtcp-fb", Some(value)) => value.parse().ok().map(Self::RtcpFb),
+            ("extmap", Some(value)) => value.parse().ok().map(Self::Extmap),
+            ("ssrc", Some(value)) => value.parse().ok().map(Self::Ssrc),
+            ("ssrc-group", Some(value)) => value.parse().ok().map(Self::SsrcGroup),
+            ("rid", Some(value)) => value.parse().ok().map(Self::Rid),
+            ("simulcast", Some(value)) => value.parse().ok().map(Self::Simulcast),
+            _ => None,
+        };
+
+        match typed {
+            Some(attr) if attr.to_string() == text => attr,
+            _ => Self::Other {
+                name: name.to_owned(),
+                value: value.map(|v| v.to_owned()),
+            },
+        }
+    }
+
+    /// Attribute name, i.e. the part before the colon.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Mid(_) => "mid",
+            Self::Direction(direction) => direction.as_str(),
+            Self::Group(_) => "group",
+            Self::Rtpmap(_) => "rtpmap",
+            Self::Fmtp(_) => "fmtp",
+            Self::RtcpFb(_) => "rtcp-fb",
+            Self::Extmap(_) => "extmap",
+            Self::Ssrc(_) => "ssrc",
+            Self::SsrcGroup(_) => "ssrc-group",
+            Self::Rid(_) => "rid",
+            Self::Simulcast(_) => "simulcast",
+            Self::Other { name, .. } => name,
+        }
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Mid(mid) => write!(fmt, "mid:{}", mid),
+            Self::Direction(direction) => write!(fmt, "{}", direction),
+            Self::Group(value) => write!(fmt, "group:{}", value),
+            Self::Rtpmap(value) => write!(fmt, "rtpmap:{}", value),
+            Self::Fmtp(value) => write!(fmt, "fmtp:{}", value),
+            Self::RtcpFb(value) => write!(fmt, "rtcp-fb:{}", value),
+            Self::Extmap(value) => write!(fmt, "extmap:{}", value),
+            Self::Ssrc(value) => write!(fmt, "ssrc:{}", value),
+            Self::SsrcGroup(value) => write!(fmt, "ssrc-group:{}", value),
+            Self::Rid(value) => write!(fmt, "rid:{}", value),
+            Self::Simulcast(value) => write!(fmt, "simulcast:{}", value),
+            Self::Other { name, value: None } => write!(fmt, "{}", name),
+            Self::Other {
+                name,
+                value: Some(value),
+            } => write!(fmt, "{}:{}", name, value),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Media direction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    SendRecv,
+    SendOnly,
+    RecvOnly,
+    Inactive,
+}
+
+impl Direction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::SendRecv => "sendrecv",
+            Self::SendOnly => "sendonly",
+            Self::RecvOnly => "recvonly",
+            Self::Inactive => "inactive",
+        }
+    }
+
+    /// The direction as seen from the other side.
+    pub fn reverse(self) -> Self {
+        match self {
+            Self::SendOnly => Self::RecvOnly,
+            Self::RecvOnly => Self::SendOnly,
+            other => other,
+        }
+    }
+
+    pub fn is_sending(self) -> bool {
+        self == Self::SendRecv || self == Self::SendOnly
+    }
+
+    pub fn is_receiving(self) -> bool {
+        self == Self::SendRecv || self == Self::RecvOnly
+    }
+
+    pub fn from_flags(sending: bool, receiving: bool) -> Self {
+        match (sending, receiving) {
+            (true, true) => Self::SendRecv,
+            (true, false) => Self::SendOnly,
+            (false, true) => Self::RecvOnly,
+            (false, false) => Self::Inactive,
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        match value {
+            "sendrecv" => Ok(Self::SendRecv),
+            "sendonly" => Ok(Self::SendOnly),
+            "recvonly" => Ok(Self::RecvOnly),
+            "inactive" => Ok(Self::Inactive),
+            other => Err(Error::new(&format!("unknown direction `{}`", other))),
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.as_str())
+    }
+}
+
+/// `a=group` value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+    pub semantics: String,
+    pub mids: Vec<String>,
+}
+
+impl FromStr for Group {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let mut parts = value.split(' ');
+
+        Ok(Self {
+            semantics: parse_field(parts.next(), "group semantics")?,
+            mids: parts.map(|s| s.to_owned()).collect(),
+        })
+    }
+}
+
+impl fmt::Display for Group {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.semantics)?;
+
+        for mid in &self.mids {
+            write!(fmt, " {}", mid)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `a=rtpmap` value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rtpmap {
+    pub payload_type: u8,
+    pub encoding: String,
+    pub clock_rate: u32,
+    pub channels: Option<u8>,
+}
+
+impl FromStr for Rtpmap {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let mut parts = value.splitn(2, ' ');
+        let payload_type = parse_field(parts.next(), "payload type")?;
+        let mut encoding_parts = parts.next().unwrap_or("").split('/');
+
+        Ok(Self {
+            payload_type,
+            encoding: parse_field(encoding_parts.next(), "encoding name")?,
+            clock_rate: parse_field(encoding_parts.next(), "clock rate")?,
+            channels: match encoding_parts.next() {
+                None => None,
+                channels => Some(parse_field(channels, "channels")?),
+            },
+        })
+    }
+}
+
+impl fmt::Display for Rtpmap {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{} {}/{}",
+            self.payload_type, self.encoding, self.clock_rate
+        )?;
+
+        if let Some(channels) = self.channels {
+            write!(fmt, "/{}", channels)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `a=fmtp` value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fmtp {
+    pub payload_type: u8,
+    /// Format-specific parameters as is, e.g. `minptime=10;useinbandfec=1`.
+    pub parameters: String,
+}
+
+impl Fmtp {
+    /// Splits [parameters](#structfield.parameters) into `key=value` pairs.
+    /// Parameters without `=` get `None` value.
+    pub fn parameter_list(&self) -> Vec<(&str, Option<&str>)> {
+        self.parameters
+            .split(';')
+            .map(str::trim)
+            .filter(|param| !param.is_empty())
+            .map(|param| match param.find('=') {
+                Some(idx) => (&param[..idx], Some(&param[idx + 1..])),
+                None => (param, None),
+            })
+            .collect()
+    }
+
+    /// Value of the `key=value` parameter.
+    pub fn parameter(&self, key: &str) -> Option<&str> {
+        self.parameter_list()
+            .into_iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .and_then(|(_, value)| value)
+    }
+}
+
+impl FromStr for Fmtp {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let mut parts = value.splitn(2, ' ');
+
+        Ok(Self {
+            payload_type: parse_field(parts.next(), "payload type")?,
+            parameters: parse_field(parts.next(), "format parameters")?,
+        })
+    }
+}
+
+impl fmt::Display for Fmtp {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} {}", self.payload_type, self.parameters)
+    }
+}
+
+/// Payload type reference in `a=rtcp-fb`: either a specific one or `*`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayloadTypeRef {
+    Id(u8),
+    Any,
+}
+
+impl FromStr for PayloadTypeRef {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        match value {
+            "*" => Ok(Self::Any),
+            other => parse_field(Some(other), "payload type").map(Self::Id),
+        }
+    }
+}
+
+impl fmt::Display for PayloadTypeRef {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(fmt, "{}", id),
+            Self::Any => write!(fmt, "*"),
+        }
+    }
+}
+
+/// `a=rtcp-fb` value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RtcpFb {
+    pub payload_type: PayloadTypeRef,
+    /// Feedback type, e.g. `nack`, `ccm`, `goog-remb`, `transport-cc`.
+    pub feedback_type: String,
+    /// Optional subtype, e.g. `pli` for `nack` or `fir` for `ccm`.
+    pub subtype: Option<String>,
+}
+
+impl FromStr for RtcpFb {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let mut parts = value.splitn(3, ' ');
+
+        Ok(Self {
+            payload_type: parse_field(parts.next(), "payload type")?,
+            feedback_type: parse_field(parts.next(), "feedback type")?,
+            subtype: parts.next().map(|s| s.to_owned()),
+        })
+    }
+}
+
+impl fmt::Display for RtcpFb {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} {}", self.payload_type, self.feedback_type)?;
+
+        if let Some(ref subtype) = self.subtype {
+            write!(fmt, " {}", subtype)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `a=extmap` value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Extmap {
+    pub id: u8,
+    pub direction: Option<Direction>,
+    pub uri: String,
+    pub attributes: Option<String>,
+}
+
+impl FromStr for Extmap {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let mut parts = value.splitn(3, ' ');
+        let id_part: String = parse_field(parts.next(), "extension id")?;
+
+        let (id, direction) = match id_part.find('/') {
+            None => (parse_field(Some(&id_part), "extension id")?, None),
+            Some(idx) => (
+                parse_field(Some(&id_part[..idx]), "extension id")?,
+                Some(parse_field(
+                    Some(&id_part[idx + 1..]),
+                    "extension direction",
+                )?),
+            ),
+        };
+
+        Ok(Self {
+            id,
+            direction,
+            uri: parse_field(parts.next(), "extension URI")?,
+            attributes: parts.next().map(|s| s.to_owned()),
+        })
+    }
+}
+
+impl fmt::Display for Extmap {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.id)?;
+
+        if let Some(direction) = self.direction {
+            write!(fmt, "/{}", direction)?;
+        }
+
+        write!(fmt, " {}", self.uri)?;
+
+        if let Some(ref attributes) = self.attributes {
+            write!(fmt, " {}", attributes)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `a=ssrc` value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ssrc {
+    pub ssrc: u32,
+    /// Source attribute name, e.g. `cname`, `msid`.
+    pub attribute: String,
+    pub value: Option<String>,
+}
+
+impl FromStr for Ssrc {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let mut parts = value.splitn(2, ' ');
+        let ssrc = parse_field(parts.next(), "SSRC")?;
+        let attribute_part = parts
+            .next()
+            .ok_or_else(|| Error::new("missing SSRC attribute"))?;
+
+        let (attribute, value) = match attribute_part.find(':') {
+            Some(idx) => (
+                attribute_part[..idx].to_owned(),
+                Some(attribute_part[idx + 1..].to_owned()),
+            ),
+            None => (attribute_part.to_owned(), None),
+        };
+
+        Ok(Self {
+            ssrc,
+            attribute,
+            value,
+        })
+    }
+}
+
+impl fmt::Display for Ssrc {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} {}", self.ssrc, self.attribute)?;
+
+        if let Some(ref value) = self.value {
+            write!(fmt, ":{}", value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `a=ssrc-group` value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SsrcGroup {
+    /// Group semantics, e.g. `FID` for RTX or `SIM` for simulcast.
+    pub semantics: String,
+    pub ssrcs: Vec<u32>,
+}
+
+impl FromStr for SsrcGroup {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let mut parts = value.split(' ');
+        let semantics = parse_field(parts.next(), "SSRC group semantics")?;
+        let mut ssrcs = vec![];
+
+        for part in parts {
+            ssrcs.push(parse_field(Some(part), "SSRC")?);
+        }
+
+        Ok(Self { semantics, ssrcs })
+    }
+}
+
+impl fmt::Display for SsrcGroup {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.semantics)?;
+
+        for ssrc in &self.ssrcs {
+            write!(fmt, " {}", ssrc)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// RID direction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RidDirection {
+    Send,
+    Recv,
+}
+
+impl FromStr for RidDirection {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        match value {
+            "send" => Ok(Self::Send),
+            "recv" => Ok(Self::Recv),
+            other => Err(Error::new(&format!("unknown RID direction `{}`", other))),
+        }
+    }
+}
+
+impl fmt::Display for RidDirection {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Send => write!(fmt, "send"),
+            Self::Recv => write!(fmt, "recv"),
+        }
+    }
+}
+
+/// `a=rid` value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rid {
+    pub id: String,
+    pub direction: RidDirection,
+    /// Restrictions as is, e.g. `pt=96;max-width=1280`.
+    pub restrictions: Option<String>,
+}
+
+impl FromStr for Rid {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let mut parts = value.splitn(3, ' ');
+
+        Ok(Self {
+            id: parse_field(parts.next(), "RID")?,
+            direction: parse_field(parts.next(), "RID direction")?,
+            restrictions: parts.next().map(|s| s.to_owned()),
+        })
+    }
+}
+
+impl fmt::Display for Rid {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} {}", self.id, self.direction)?;
+
+        if let Some(ref restrictions) = self.restrictions {
+            write!(fmt, " {}", restrictions)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Simulcast stream identifier in `a=simulcast`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulcastId {
+    pub rid: String,
+    /// Paused streams are prefixed with `~`.
+    pub paused: bool,
+}
+
+impl fmt::Display for SimulcastId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if self.paused {
+            write!(fmt, "~")?;
+        }
+
+        write!(fmt, "{}", self.rid)
+    }
+}
+
+/// `a=simulcast` value as of RFC 8853.
+///
+/// Each direction is a list of streams separated with `;` and each stream is a list of
+/// alternative formats separated with `,`.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Simulcast {
+    pub send: Vec<Vec<SimulcastId>>,
+    pub recv: Vec<Vec<SimulcastId>>,
+    order: Vec<RidDirection>,
+}
+
+impl Simulcast {
+    pub fn new(send: Vec<Vec<SimulcastId>>, recv: Vec<Vec<SimulcastId>>) -> Self {
+        let mut order = vec![];
+
+        if !send.is_empty() {
+            order.push(RidDirection::Send);
+        }
+
+        if !recv.is_empty() {
+            order.push(RidDirection::Recv);
+        }
+
+        Self { send, recv, order }
+    }
+
+    fn parse_streams(value: &str) -> Result<Vec<Vec<SimulcastId>>, Error> {
+        value
+            .split(';')
+            .map(|stream| {
+                stream
+                    .split(',')
+                    .map(|id| {
+                        let (rid, paused) = match id.strip_prefix('~') {
+                            Some(rid) => (rid, true),
+                            None => (id, false),
+                        };
+
+                        if rid.is_empty() {
+                            return Err(Error::new("empty simulcast id"));
+                        }
+
+                        Ok(SimulcastId {
+                            rid: rid.to_owned(),
+                            paused,
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Swaps send and receive directions as needed for the answer.
+    pub fn reverse(&self) -> Self {
+        Self {
+            send: self.recv.clone(),
+            recv: self.send.clone(),
+            order: self
+                .order
+                .iter()
+                .map(|direction| match direction {
+                    RidDirection::Send => RidDirection::Recv,
+                    RidDirection::Recv => RidDirection::Send,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for Simulcast {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let mut parts = value.split(' ');
+        let mut simulcast = Self::default();
+
+        while let Some(direction_str) = parts.next() {
+            let direction = direction_str.parse::<RidDirection>()?;
+            let streams = Self::parse_streams(parts.next().unwrap_or(""))?;
+
+            match direction {
+                RidDirection::Send => simulcast.send = streams,
+                RidDirection::Recv => simulcast.recv = streams,
+            }
+
+            simulcast.order.push(direction);
+        }
+
+        Ok(simulcast)
+    }
+}
+
+impl fmt::Display for Simulcast {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, direction) in self.order.iter().enumerate() {
+            if idx > 0 {
+                write!(fmt, " ")?;
+            }
+
+            let streams = match direction {
+                RidDirection::Send => &self.send,
+                RidDirection::Recv => &self.recv,
+            };
+
+            write!(fmt, "{} ", direction)?;
+
+            for (stream_idx, stream) in streams.iter().enumerate() {
+                if stream_idx > 0 {
+                    write!(fmt, ";")?;
+                }
+
+                for (id_idx, id) in stream.iter().enumerate() {
+                    if id_idx > 0 {
+                        write!(fmt, ",")?;
+                    }
+
+                    write!(fmt, "{}", id)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Jsep, MediaKind};
+
+    const OFFER: &str = "v=0\r\n\
+        o=- 4611731400430051336 2 IN IP4 127.0.0.1\r\n\
+        s=-\r\n\
+        t=0 0\r\n\
+        a=group:BUNDLE 0 1\r\n\
+        a=msid-semantic: WMS stream\r\n\
+        m=audio 9 UDP/TLS/RTP/SAVPF 111 0\r\n\
+        c=IN IP4 0.0.0.0\r\n\
+        a=rtcp:9 IN IP4 0.0.0.0\r\n\
+        a=ice-ufrag:abcd\r\n\
+        a=mid:0\r\n\
+        a=extmap:1 urn:ietf:params:rtp-hdrext:ssrc-audio-level\r\n\
+        a=sendrecv\r\n\
+        a=rtcp-mux\r\n\
+        a=rtpmap:111 opus/48000/2\r\n\
+        a=rtcp-fb:111 transport-cc\r\n\
+        a=fmtp:111 minptime=10;useinbandfec=1\r\n\
+        a=rtpmap:0 PCMU/8000\r\n\
+        a=ssrc:1001 cname:abc\r\n\
+        a=ssrc:1001 msid:stream audio\r\n\
+        m=video 9 UDP/TLS/RTP/SAVPF 96 97\r\n\
+        c=IN IP4 0.0.0.0\r\n\
+        a=mid:1\r\n\
+        a=extmap:4/sendonly urn:3gpp:video-orientation\r\n\
+        a=sendonly\r\n\
+        a=rtpmap:96 VP8/90000\r\n\
+        a=rtcp-fb:96 nack pli\r\n\
+        a=rtpmap:97 rtx/90000\r\n\
+        a=fmtp:97 apt=96\r\n\
+        a=rid:h send\r\n\
+        a=rid:l send pt=96;max-width=320\r\n\
+        a=simulcast:send h;~l\r\n\
+        a=ssrc-group:FID 2001 2002\r\n\
+        a=ssrc:2001 cname:abc\r\n";
+
+    #[test]
+    fn roundtrip() {
+        let sdp = OFFER.parse::<SessionDescription>().unwrap();
+        assert_eq!(sdp.to_string(), OFFER);
+
+        let lf = OFFER.replace("\r\n", "\n");
+        assert_eq!(lf.parse::<SessionDescription>().unwrap().to_string(), lf);
+    }
+
+    #[test]
+    fn typed_attributes() {
+        let sdp = OFFER.parse::<SessionDescription>().unwrap();
+        assert_eq!(sdp.origin.session_id, "4611731400430051336");
+        assert_eq!(sdp.groups().next().unwrap().mids, vec!["0", "1"]);
+        assert_eq!(sdp.media.len(), 2);
+
+        let audio = sdp.media_by_mid("0").unwrap();
+        assert_eq!(audio.direction(), Direction::SendRecv);
+        assert_eq!(audio.payload_types().collect::<Vec<_>>(), vec![111, 0]);
+        assert_eq!(audio.rtpmap(111).unwrap().channels, Some(2));
+        assert_eq!(
+            audio.fmtp(111).unwrap().parameter("useinbandfec"),
+            Some("1")
+        );
+        assert_eq!(audio.ssrcs().count(), 2);
+
+        let video = sdp.media_by_mid("1").unwrap();
+        assert_eq!(video.direction(), Direction::SendOnly);
+        assert_eq!(
+            video.extmaps().next().unwrap().direction,
+            Some(Direction::SendOnly)
+        );
+        assert_eq!(
+            video.rtcp_fbs(96).next().unwrap().subtype.as_deref(),
+            Some("pli")
+        );
+        assert_eq!(video.ssrc_groups().next().unwrap().ssrcs, vec![2001, 2002]);
+        assert_eq!(video.rids().count(), 2);
+
+        let simulcast = video.simulcast().unwrap();
+        assert_eq!(simulcast.send.len(), 2);
+        assert!(simulcast.send[1][0].paused);
+    }
+
+    #[test]
+    fn answer() {
+        let offer = Jsep::Offer {
+            sdp: OFFER.parse().unwrap(),
+        };
+
+        let answer = AnswerBuilder::new(&offer)
+            .unwrap()
+            .add_codec(Codec::new(MediaKind::Audio, "opus", 48000).set_channels(2))
+            .add_codec(Codec::new(MediaKind::Video, "VP8", 90000).add_feedback("nack", None))
+            .add_codec(Codec::new(MediaKind::Video, "rtx", 90000))
+            .add_extension("urn:3gpp:video-orientation")
+            .set_direction(MediaKind::Video, Direction::RecvOnly)
+            .build()
+            .unwrap();
+
+        let sdp = answer.sdp();
+        assert_eq!(sdp.groups().next().unwrap().mids, vec!["0", "1"]);
+
+        let audio = sdp.media_by_mid("0").unwrap();
+        assert_eq!(audio.payload_types().collect::<Vec<_>>(), vec![111]);
+        assert_eq!(audio.direction(), Direction::SendRecv);
+        assert_eq!(audio.extmaps().count(), 0);
+        assert_eq!(audio.rtcp_fbs(111).count(), 0);
+
+        let video = sdp.media_by_mid("1").unwrap();
+        assert_eq!(video.payload_types().collect::<Vec<_>>(), vec![96, 97]);
+        assert_eq!(video.direction(), Direction::RecvOnly);
+        assert_eq!(
+            video.extmaps().next().unwrap().direction,
+            Some(Direction::RecvOnly)
+        );
+        assert_eq!(video.rtcp_fbs(96).count(), 0);
+        assert!(video.rids().all(|rid| rid.direction == RidDirection::Recv));
+        assert_eq!(video.simulcast().unwrap().recv.len(), 2);
+
+        let rejected = AnswerBuilder::new(&offer).unwrap().build().unwrap();
+        assert!(rejected
+            .sdp()
+            .media
+            .iter()
+            .all(MediaDescription::is_rejected));
+        assert_eq!(rejected.sdp().groups().count(), 0);
+        assert!(AnswerBuilder::new(&rejected).is_err());
+    }
+
+    #[test]
+    fn answer_h264_profile() {
+        let offer = |profile_level_id: &str| Jsep::Offer {
+            sdp: format!(
+                "v=0\r\n\
+                o=- 1 2 IN IP4 127.0.0.1\r\n\
+                s=-\r\n\
+                t=0 0\r\n\
+                m=video 9 UDP/TLS/RTP/SAVPF 102\r\n\
+                c=IN IP4 0.0.0.0\r\n\
+                a=mid:0\r\n\
+                a=sendonly\r\n\
+                a=rtpmap:102 H264/90000\r\n\
+                a=fmtp:102 packetization-mode=1;profile-level-id={}\r\n",
+                profile_level_id
+            )
+            .parse()
+            .unwrap(),
+        };
+
+        let answer = |offer: &Jsep| {
+            let codec = Codec::new(MediaKind::Video, "H264", 90000)
+                .add_parameter("packetization-mode", Some("1"))
+                .add_parameter("profile-level-id", Some("42e01f"));
+
+            let answer = AnswerBuilder::new(offer)
+                .unwrap()
+                .add_codec(codec)
+                .build()
+                .unwrap();
+
+            let video = &answer.sdp().media[0];
+
+            video
+                .fmtp(102)
+                .and_then(|fmtp| fmtp.parameter("profile-level-id"))
+                .map(String::from)
+        };
+
+        assert_eq!(answer(&offer("42e015")).as_deref(), Some("42e015"));
+        assert_eq!(answer(&offer("640c1f")), None);
+        // Client input with the right length in bytes but not in characters.
+        assert_eq!(answer(&offer("aaa\u{e9}1")), None);
+        assert_eq!(answer(&offer("42e0\u{e9}")), None);
+    }
+
+    #[test]
+    fn answer_mixed_case_parameters() {
+        let offer = |parameters: &str| Jsep::Offer {
+            sdp: format!(
+                "v=0\r\n\
+                o=- 1 2 IN IP4 127.0.0.1\r\n\
+                s=-\r\n\
+                t=0 0\r\n\
+                m=video 9 UDP/TLS/RTP/SAVPF 102\r\n\
+                c=IN IP4 0.0.0.0\r\n\
+                a=mid:0\r\n\
+                a=sendonly\r\n\
+                a=rtpmap:102 H264/90000\r\n\
+                a=fmtp:102 {}\r\n",
+                parameters
+            )
+            .parse()
+            .unwrap(),
+        };
+
+        let answer = |offer: &Jsep| {
+            let codec = Codec::new(MediaKind::Video, "H264", 90000)
+                .add_parameter("Packetization-Mode", Some("1"))
+                .add_parameter("Max-FS", Some("8160"));
+
+            let answer = AnswerBuilder::new(offer)
+                .unwrap()
+                .add_codec(codec)
+                .build()
+                .unwrap();
+
+            answer.sdp().media[0].fmtp(102).map(|fmtp| {
+                let parameter = |key| fmtp.parameter(key).map(String::from);
+                (parameter("packetization-mode"), parameter("max-fs"))
+            })
+        };
+
+        let answered = answer(&offer("packetization-mode=1;max-fs=3600"));
+        let expected = (Some(String::from("1")), Some(String::from("3600")));
+        assert_eq!(answered, Some(expected));
+        assert_eq!(answer(&offer("packetization-mode=0;max-fs=3600")), None);
+    }
+
+    #[test]
+    fn malformed() {
+        assert!("v=0\r\ns=-\r\n".parse::<SessionDescription>().is_err());
+        assert!("v=0\r\no=- 1 IN IP4 0.0.0.0\r\ns=-\r\n"
+            .parse::<SessionDescription>()
+            .is_err());
+    }
+
+    #[test]
+    fn roundtrip_irregular() {
+        let texts = [
+            "v=0\r\no=- 1 2 IN IP4 0.0.0.0\r\n\r\ns=-\r\nt=0 0\r\n\r\n",
+            "v=0\r\no=- 1 2 IN IP4 0.0.0.0\ns=-\r\nt=0 0\n",
+            "v=0\no=- 1 2 IN IP4 0.0.0.0\r\ns=-\nt=0 0\r\n",
+            "v=0\r\no=- 0042 007 IN IP4 0.0.0.0\r\ns=-\r\n",
+            "v=0\r\no=- 123456789012345678901234567890 1 IN IP4 0.0.0.0\r\ns=-\r\n",
+            "v=0\r\no=- x y IN IP4 0.0.0.0\r\ns=-\r\n",
+        ];
+
+        for text in texts.iter() {
+            let sdp = text.parse::<SessionDescription>().unwrap();
+            assert_eq!(sdp.to_string(), *text);
+        }
+
+        let sdp = texts[3].parse::<SessionDescription>().unwrap();
+        assert_eq!(sdp.origin.session_id, "0042");
+        assert_eq!(sdp.origin.session_version, "007");
+    }
+
+    #[test]
+    fn modified_irregular() {
+        let mut sdp = "v=0\r\no=- 0042 1 IN IP4 0.0.0.0\n\ns=-\r\nm=audio 9 RTP/AVP 0\r\n"
+            .parse::<SessionDescription>()
+            .unwrap();
+
+        sdp.media[0].set_direction(Direction::RecvOnly);
+
+        assert_eq!(
+            sdp.to_string(),
+            "v=0\r\no=- 0042 1 IN IP4 0.0.0.0\r\ns=-\r\nm=audio 9 RTP/AVP 0\r\na=recvonly\r\n"
+        );
+    }
+}
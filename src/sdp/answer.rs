@@ -0,0 +1,552 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::sdp::{
+    Attribute, Direction, Extmap, Fmtp, Group, Line, MediaDescription, Origin, RidDirection,
+    Rtpmap, SessionDescription, Simulcast,
+};
+use crate::{Error, Jsep, MediaKind};
+
+/// Format parameters which identify a codec variant and must be equal to match.
+const IDENTITY_PARAMETERS: &[&str] = &["packetization-mode", "profile-id", "profile"];
+
+/// H.264 profile and level. Only the profile part must match while the level is a limit.
+const PROFILE_LEVEL_ID: &str = "profile-level-id";
+
+/// Format parameters which limit the stream so the lower value wins.
+const LIMIT_PARAMETERS: &[&str] = &[
+    "maxplaybackrate",
+    "sprop-maxcapturerate",
+    "maxaveragebitrate",
+    "max-fs",
+    "max-fr",
+    "max-mbps",
+    "max-br",
+    "level-asymmetry-allowed",
+];
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A codec supported by the plugin.
+#[derive(Clone, Debug)]
+pub struct Codec {
+    kind: MediaKind,
+    name: String,
+    clock_rate: u32,
+    channels: Option<u8>,
+    parameters: Vec<(String, Option<String>)>,
+    feedback: Vec<(String, Option<String>)>,
+}
+
+impl Codec {
+    /// `name` is an encoding name as in `a=rtpmap`, e.g. `opus` or `VP8`. Matched case-insensitive.
+    pub fn new(kind: MediaKind, name: &str, clock_rate: u32) -> Self {
+        Self {
+            kind,
+            name: name.to_owned(),
+            clock_rate,
+            channels: None,
+            parameters: vec![],
+            feedback: vec![],
+        }
+    }
+
+    /// Requires the offered number of channels to be equal to `channels`.
+    pub fn set_channels(self, channels: u8) -> Self {
+        Self {
+            channels: Some(channels),
+            ..self
+        }
+    }
+
+    /// Declares a format parameter.
+    ///
+    /// Identity parameters like `packetization-mode` or `profile-id` must be equal in the offer
+    /// for the codec to match. Limits like `max-fs` or `maxplaybackrate` are being answered
+    /// with the lowest value. Other parameters are being answered with the plugin's value when
+    /// they are present in the offer too.
+    pub fn add_parameter(mut self, key: &str, value: Option<&str>) -> Self {
+        self.parameters
+            .push((key.to_owned(), value.map(|v| v.to_owned())));
+
+        self
+    }
+
+    /// Declares a supported RTCP feedback mechanism, e.g. `("nack", Some("pli"))`.
+    pub fn add_feedback(mut self, feedback_type: &str, subtype: Option<&str>) -> Self {
+        self.feedback
+            .push((feedback_type.to_owned(), subtype.map(|v| v.to_owned())));
+
+        self
+    }
+
+    fn matches(&self, rtpmap: &Rtpmap, fmtp: Option<&Fmtp>) -> bool {
+        if !self.name.eq_ignore_ascii_case(&rtpmap.encoding) || self.clock_rate != rtpmap.clock_rate
+        {
+            return false;
+        }
+
+        if let Some(channels) = self.channels {
+            if rtpmap.channels.unwrap_or(1) != channels {
+                return false;
+            }
+        }
+
+        self.parameters.iter().all(|(key, value)| {
+            let offered = fmtp.and_then(|fmtp| fmtp.parameter(key));
+
+            if key.eq_ignore_ascii_case(PROFILE_LEVEL_ID) {
+                return match (offered, value.as_deref()) {
+                    (Some(offered), Some(value)) => same_h264_profile(offered, value),
+                    _ => true,
+                };
+            }
+
+            if !is_one_of(key, IDENTITY_PARAMETERS) {
+                return true;
+            }
+
+            match offered {
+                Some(offered) => Some(offered) == value.as_deref(),
+                // Absent identity parameters have default value which is usually `0`.
+                None => value.as_deref().unwrap_or("0") == "0",
+            }
+        })
+    }
+
+    /// Intersects offered format parameters with declared ones.
+    fn answer_parameters(&self, fmtp: Option<&Fmtp>) -> Option<String> {
+        let offered = fmtp.map(|fmtp| fmtp.parameter_list()).unwrap_or_default();
+        let mut answered = vec![];
+
+        for (key, value) in &self.parameters {
+            let offered_value = offered
+                .iter()
+                .find(|(offered_key, _)| offered_key.eq_ignore_ascii_case(key))
+                .map(|(_, offered_value)| *offered_value);
+
+            let answered_value = match offered_value {
+                None if is_one_of(key, IDENTITY_PARAMETERS) => value.clone(),
+                None => continue,
+                Some(offered_value) if key.eq_ignore_ascii_case(PROFILE_LEVEL_ID) => {
+                    let own = value.as_deref().and_then(split_profile_level_id);
+                    let offered = offered_value.and_then(split_profile_level_id);
+
+                    match (own, offered) {
+                        // Keep the offered profile and answer with the lowest level.
+                        (Some((_, own_level)), Some((profile, offered_level))) => {
+                            Some(format!("{}{:02x}", profile, own_level.min(offered_level)))
+                        }
+                        _ => value.clone(),
+                    }
+                }
+                Some(offered_value) if is_one_of(key, LIMIT_PARAMETERS) => {
+                    match (
+                        value.as_deref().and_then(|v| v.parse::<u64>().ok()),
+                        offered_value.and_then(|v| v.parse::<u64>().ok()),
+                    ) {
+                        (Some(own), Some(offered)) => Some(own.min(offered).to_string()),
+                        _ => value.clone(),
+                    }
+                }
+                Some(_) => value.clone(),
+            };
+
+            answered.push(match answered_value {
+                Some(value) => format!("{}={}", key, value),
+                None => key.to_owned(),
+            });
+        }
+
+        if answered.is_empty() {
+            None
+        } else {
+            Some(answered.join(";"))
+        }
+    }
+
+    fn is_rtx(&self) -> bool {
+        self.name.eq_ignore_ascii_case("rtx")
+    }
+
+    fn supports_feedback(&self, feedback_type: &str, subtype: Option<&str>) -> bool {
+        self.feedback
+            .iter()
+            .any(|(t, s)| t == feedback_type && s.as_deref() == subtype)
+    }
+}
+
+/// Format parameter keys are case-insensitive like the offered ones are being matched.
+fn is_one_of(key: &str, keys: &[&str]) -> bool {
+    keys.iter().any(|k| k.eq_ignore_ascii_case(key))
+}
+
+fn same_h264_profile(lhs: &str, rhs: &str) -> bool {
+    match (split_profile_level_id(lhs), split_profile_level_id(rhs)) {
+        (Some((lhs, _)), Some((rhs, _))) => lhs.eq_ignore_ascii_case(rhs),
+        _ => false,
+    }
+}
+
+/// Splits H.264 `profile-level-id` into the profile part and the level.
+/// The value comes from the client so anything but 6 hex digits is rejected.
+fn split_profile_level_id(value: &str) -> Option<(&str, u8)> {
+    if value.len() != 6 || !value.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let level = u8::from_str_radix(&value[4..], 16).ok()?;
+    Some((&value[..4], level))
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Builds an SDP answer for an offer received with an
+/// [IncomingMessage](../struct.IncomingMessage.html).
+///
+/// ```
+/// use janus_app::sdp::{AnswerBuilder, Codec, Direction};
+/// use janus_app::{Jsep, MediaKind};
+///
+/// # fn answer(offer: &Jsep) -> Result<Jsep, janus_app::Error> {
+/// let answer = AnswerBuilder::new(offer)?
+///     .add_codec(Codec::new(MediaKind::Audio, "opus", 48000).set_channels(2))
+///     .add_codec(Codec::new(MediaKind::Video, "VP8", 90000).add_feedback("nack", Some("pli")))
+///     .set_direction(MediaKind::Video, Direction::RecvOnly)
+///     .build()?;
+/// # Ok(answer)
+/// # }
+/// ```
+pub struct AnswerBuilder<'a> {
+    offer: &'a SessionDescription,
+    codecs: Vec<Codec>,
+    directions: HashMap<MediaKind, Direction>,
+    extensions: Vec<String>,
+    data_channels: bool,
+    session_name: String,
+}
+
+impl<'a> AnswerBuilder<'a> {
+    /// Fails when `offer` is not an offer.
+    pub fn new(offer: &'a Jsep) -> Result<Self, Error> {
+        match offer {
+            Jsep::Offer { sdp } => Ok(Self {
+                offer: sdp,
+                codecs: vec![],
+                directions: HashMap::new(),
+                extensions: vec![],
+                data_channels: false,
+                session_name: String::from("-"),
+            }),
+            Jsep::Answer { .. } => Err(Error::new("Expected JSEP offer, got answer")),
+        }
+    }
+
+    /// Adds a supported codec. Codecs added earlier are preferred.
+    pub fn add_codec(mut self, codec: Codec) -> Self {
+        self.codecs.push(codec);
+        self
+    }
+
+    /// Sets the plugin's desired direction for the media kind. The default is `sendrecv`.
+    /// The answered direction is also limited by the offered one.
+    pub fn set_direction(mut self, kind: MediaKind, direction: Direction) -> Self {
+        self.directions.insert(kind, direction);
+        self
+    }
+
+    /// Adds a supported RTP header extension URI.
+    pub fn add_extension(mut self, uri: &str) -> Self {
+        self.extensions.push(uri.to_owned());
+        self
+    }
+
+    /// Accepts data channel media sections which are being rejected by default.
+    pub fn enable_data_channels(self) -> Self {
+        Self {
+            data_channels: true,
+            ..self
+        }
+    }
+
+    /// Sets the `s=` line of the answer. The default is `-`.
+    pub fn set_session_name(self, session_name: &str) -> Self {
+        Self {
+            session_name: session_name.to_owned(),
+            ..self
+        }
+    }
+
+    /// Negotiates each offered media section. Sections without matching codecs are rejected.
+    pub fn build(self) -> Result<Jsep, Error> {
+        let session_id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| Error::new(&format!("Failed to get current time: {}", err)))?
+            .as_micros();
+
+        let origin = Origin {
+            username: String::from("-"),
+            session_id: session_id.to_string(),
+            session_version: String::from("1"),
+            network_type: String::from("IN"),
+            address_type: String::from("IP4"),
+            address: String::from("127.0.0.1"),
+        };
+
+        let mut answer = SessionDescription::new(origin, &self.session_name);
+
+        answer.lines.push(Line::Other {
+            kind: 't',
+            value: String::from("0 0"),
+        });
+
+        let media = self
+            .offer
+            .media
+            .iter()
+            .map(|offered| self.answer_media(offered))
+            .collect::<Vec<_>>();
+
+        for group in self.offer.groups() {
+            let mids = group
+                .mids
+                .iter()
+                .filter(|mid| {
+                    media
+                        .iter()
+                        .any(|m| !m.is_rejected() && m.mid() == Some(mid.as_str()))
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if !mids.is_empty() {
+                answer.lines.push(Line::Attribute(Attribute::Group(Group {
+                    semantics: group.semantics.clone(),
+                    mids,
+                })));
+            }
+        }
+
+        answer.media = media;
+        Ok(Jsep::Answer { sdp: answer })
+    }
+
+    fn answer_media(&self, offered: &MediaDescription) -> MediaDescription {
+        let kind = match offered.kind.as_str() {
+            "audio" => Some(MediaKind::Audio),
+            "video" => Some(MediaKind::Video),
+            _ => None,
+        };
+
+        let answer = match (kind, offered.is_rejected()) {
+            (_, true) => None,
+            (Some(kind), false) => self.answer_rtp_media(offered, kind),
+            (None, false) if offered.kind == "application" && self.data_channels => {
+                Some(self.answer_data_media(offered))
+            }
+            (None, false) => None,
+        };
+
+        answer.unwrap_or_else(|| Self::reject(offered))
+    }
+
+    fn answer_rtp_media(
+        &self,
+        offered: &MediaDescription,
+        kind: MediaKind,
+    ) -> Option<MediaDescription> {
+        // Match offered payload types against codecs in the plugin's order of preference.
+        let mut matched = vec![];
+
+        // RTX is not a codec on its own and gets answered only along with its associated one.
+        let codecs = self
+            .codecs
+            .iter()
+            .filter(|codec| codec.kind == kind && !codec.is_rtx());
+
+        for codec in codecs {
+            for payload_type in offered.payload_types() {
+                if matched.iter().any(|(pt, _)| *pt == payload_type) {
+                    continue;
+                }
+
+                if let Some(rtpmap) = offered.rtpmap(payload_type) {
+                    if codec.matches(rtpmap, offered.fmtp(payload_type)) {
+                        matched.push((payload_type, codec));
+                    }
+                }
+            }
+        }
+
+        if matched.is_empty() {
+            return None;
+        }
+
+        // Keep retransmission payload types associated with matched ones when RTX is supported.
+        let mut rtx = vec![];
+
+        if self
+            .codecs
+            .iter()
+            .any(|codec| codec.kind == kind && codec.is_rtx())
+        {
+            for payload_type in offered.payload_types() {
+                let is_rtx = offered
+                    .rtpmap(payload_type)
+                    .is_some_and(|rtpmap| rtpmap.encoding.eq_ignore_ascii_case("rtx"));
+
+                let apt = offered
+                    .fmtp(payload_type)
+                    .and_then(|fmtp| fmtp.parameter("apt"))
+                    .and_then(|apt| apt.parse::<u8>().ok());
+
+                if let (true, Some(apt)) = (is_rtx, apt) {
+                    if matched.iter().any(|(pt, _)| *pt == apt) {
+                        rtx.push(payload_type);
+                    }
+                }
+            }
+        }
+
+        let mut formats = matched
+            .iter()
+            .map(|(pt, _)| pt.to_string())
+            .collect::<Vec<_>>();
+
+        formats.extend(rtx.iter().map(|pt| pt.to_string()));
+
+        let mut media = MediaDescription::new(&offered.kind, 9, &offered.protocol, formats);
+        Self::copy_connection(offered, &mut media);
+
+        if let Some(mid) = offered.mid() {
+            media.push_attribute(Attribute::Mid(mid.to_owned()));
+        }
+
+        let desired = self
+            .directions
+            .get(&kind)
+            .copied()
+            .unwrap_or(Direction::SendRecv);
+
+        let offered_direction = offered.direction();
+
+        let direction = Direction::from_flags(
+            desired.is_sending() && offered_direction.is_receiving(),
+            desired.is_receiving() && offered_direction.is_sending(),
+        );
+
+        for extmap in offered.extmaps() {
+            if self.extensions.contains(&extmap.uri) {
+                media.push_attribute(Attribute::Extmap(Extmap {
+                    direction: extmap.direction.map(Direction::reverse),
+                    ..extmap.clone()
+                }));
+            }
+        }
+
+        media.push_attribute(Attribute::Direction(direction));
+
+        if offered.attributes().any(|attr| attr.name() == "rtcp-mux") {
+            media.push_attribute(Attribute::Other {
+                name: String::from("rtcp-mux"),
+                value: None,
+            });
+        }
+
+        for (payload_type, codec) in &matched {
+            if let Some(rtpmap) = offered.rtpmap(*payload_type) {
+                media.push_attribute(Attribute::Rtpmap(rtpmap.clone()));
+            }
+
+            for fb in offered.rtcp_fbs(*payload_type) {
+                if codec.supports_feedback(&fb.feedback_type, fb.subtype.as_deref()) {
+                    media.push_attribute(Attribute::RtcpFb(fb.clone()));
+                }
+            }
+
+            let fmtp = offered.fmtp(*payload_type);
+
+            if let Some(parameters) = codec.answer_parameters(fmtp) {
+                media.push_attribute(Attribute::Fmtp(Fmtp {
+                    payload_type: *payload_type,
+                    parameters,
+                }));
+            }
+        }
+
+        for payload_type in rtx {
+            if let Some(rtpmap) = offered.rtpmap(payload_type) {
+                media.push_attribute(Attribute::Rtpmap(rtpmap.clone()));
+            }
+
+            if let Some(fmtp) = offered.fmtp(payload_type) {
+                media.push_attribute(Attribute::Fmtp(fmtp.clone()));
+            }
+        }
+
+        // Accept offered simulcast streams when receiving.
+        if direction.is_receiving() {
+            if let Some(simulcast) = offered.simulcast() {
+                for rid in offered.rids() {
+                    if rid.direction == RidDirection::Send {
+                        let mut answered_rid = rid.clone();
+                        answered_rid.direction = RidDirection::Recv;
+                        answered_rid.restrictions = None;
+                        media.push_attribute(Attribute::Rid(answered_rid));
+                    }
+                }
+
+                let answered_simulcast = Simulcast::new(vec![], simulcast.send.clone());
+                media.push_attribute(Attribute::Simulcast(answered_simulcast));
+            }
+        }
+
+        Some(media)
+    }
+
+    fn answer_data_media(&self, offered: &MediaDescription) -> MediaDescription {
+        let mut media =
+            MediaDescription::new(&offered.kind, 9, &offered.protocol, offered.formats.clone());
+
+        Self::copy_connection(offered, &mut media);
+
+        if let Some(mid) = offered.mid() {
+            media.push_attribute(Attribute::Mid(mid.to_owned()));
+        }
+
+        for attr in offered.attributes() {
+            if attr.name() == "sctp-port" || attr.name() == "sctpmap" {
+                media.push_attribute(attr.clone());
+            }
+        }
+
+        media
+    }
+
+    fn reject(offered: &MediaDescription) -> MediaDescription {
+        let formats = offered.formats.iter().take(1).cloned().collect();
+        let mut media = MediaDescription::new(&offered.kind, 0, &offered.protocol, formats);
+        Self::copy_connection(offered, &mut media);
+
+        if let Some(mid) = offered.mid() {
+            media.push_attribute(Attribute::Mid(mid.to_owned()));
+        }
+
+        media.push_attribute(Attribute::Direction(Direction::Inactive));
+        media
+    }
+
+    fn copy_connection(offered: &MediaDescription, media: &mut MediaDescription) {
+        let connection = offered.lines.iter().find(|line| match line {
+            Line::Other { kind, .. } => *kind == 'c',
+            _ => false,
+        });
+
+        media
+            .lines
+            .push(connection.cloned().unwrap_or_else(|| Line::Other {
+                kind: 'c',
+                value: String::from("IN IP4 0.0.0.0"),
+            }));
+    }
+}
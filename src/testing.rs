@@ -0,0 +1,1437 @@
+//! In-process fake of Janus core for unit testing plugins.
+//!
+//! [MockJanus](struct.MockJanus.html) initializes the plugin with fake callbacks and drives it
+//! through the same `extern "C"` entry points Janus calls. Every callback the plugin makes is
+//! being recorded as a [Call](enum.Call.html) for assertions.
+//!
+//! The module is available with the `testing` feature so add to your `Cargo.toml`:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! janus-app = { version = "*", features = ["testing"] }
+//! ```
+//!
+//! Then in a test:
+//!
+//! ```ignore
+//! use janus_app::testing::{Call, MessageResult, MockJanus};
+//!
+//! let janus = MockJanus::<MyPlugin>::new("/path/to/configs")?;
+//! let handle_id = janus.create_handle()?;
+//! let result = janus.send_message(handle_id, "txn", &json!({"method": "ping"}), None)?;
+//! assert_eq!(result, MessageResult::OkWait);
+//!
+//! let call = janus.wait_for(Duration::from_secs(1), |call| call.is_push_event())?;
+//! ```
+//!
+//! A plugin has a single global instance so mocks are being serialized: creating a mock blocks
+//! until the previous one is dropped. Don't create two mocks on the same thread at once.
+
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr, CString};
+use std::marker::PhantomData;
+use std::os::raw::{c_char, c_int};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use jansson_sys::{json_decref, json_t};
+use janus_plugin_sys::janus_refcount as JanusRefcount;
+use janus_plugin_sys::plugin::{
+    janus_plugin_result_type as JanusPluginResultType, janus_plugin_session as JanusPluginSession,
+};
+use parking_lot::{const_mutex, Condvar, Mutex, MutexGuard};
+use serde::ser::Serialize;
+use serde_json::Value as JsonValue;
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+use crate::plugin::packet_length;
+use crate::plugin::{
+    self, is_video, media_kind, HandleRef, JanusCallbacks, JanusPlugin, PluginApp,
+};
+use crate::{ffi, jansson, DataInfo, Error, Jsep, MediaKind};
+
+/// Only one mock may drive a plugin at a time since the plugin instance is global.
+static MOCK_LOCK: Mutex<()> = const_mutex(());
+
+/// Callbacks find the mock's recorder by the handle so all of the mocks share them.
+static CALLBACKS: JanusCallbacks = JanusCallbacks {
+    push_event,
+    relay_rtp,
+    relay_rtcp,
+    relay_data,
+    #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+    send_pli,
+    #[cfg(feature = "janus-1")]
+    send_pli_stream,
+    #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+    send_remb,
+    close_pc,
+    end_session,
+    events_is_enabled,
+    notify_event,
+    #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+    auth_is_signature_valid,
+    #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+    auth_signature_contains,
+};
+
+/// Janus core callback call made by the plugin.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Call {
+    PushEvent {
+        handle_id: u64,
+        transaction: Option<String>,
+        message: JsonValue,
+        jsep: Option<JsonValue>,
+    },
+    RelayRtp {
+        handle_id: u64,
+        kind: MediaKind,
+        buffer: Vec<i8>,
+    },
+    RelayRtcp {
+        handle_id: u64,
+        kind: MediaKind,
+        buffer: Vec<i8>,
+    },
+    /// Data channel message. Older Janus versions than 0.10 get text without a label.
+    RelayData {
+        handle_id: u64,
+        buffer: Vec<i8>,
+        binary: bool,
+        label: Option<String>,
+    },
+    /// Keyframe request. Janus 0.10+ only, older versions get PLI relayed as RTCP.
+    SendPli {
+        handle_id: u64,
+    },
+    /// Bitrate limit. Janus 0.10+ only, older versions get REMB relayed as RTCP.
+    SendRemb {
+        handle_id: u64,
+        bitrate: u32,
+    },
+    ClosePc {
+        handle_id: u64,
+    },
+    EndSession {
+        handle_id: u64,
+    },
+    NotifyEvent {
+        handle_id: Option<u64>,
+        event: JsonValue,
+    },
+}
+
+impl Call {
+    /// ID of the handle the call has been made for.
+    pub fn handle_id(&self) -> Option<u64> {
+        match self {
+            Self::PushEvent { handle_id, .. }
+            | Self::RelayRtp { handle_id, .. }
+            | Self::RelayRtcp { handle_id, .. }
+            | Self::RelayData { handle_id, .. }
+            | Self::SendPli { handle_id }
+            | Self::SendRemb { handle_id, .. }
+            | Self::ClosePc { handle_id }
+            | Self::EndSession { handle_id } => Some(*handle_id),
+            Self::NotifyEvent { handle_id, .. } => *handle_id,
+        }
+    }
+
+    pub fn is_push_event(&self) -> bool {
+        matches!(self, Self::PushEvent { .. })
+    }
+}
+
+/// Plugin's response to `handle_message`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MessageResult {
+    /// `JANUS_PLUGIN_OK` with the response content. Errors returned from the handler are here too.
+    Ok(JsonValue),
+    /// `JANUS_PLUGIN_OK_WAIT`: the response is expected to be pushed as an event.
+    OkWait,
+    /// `JANUS_PLUGIN_ERROR` with the error text.
+    Error(String),
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+struct Recorder {
+    calls: Mutex<Vec<Call>>,
+    condvar: Condvar,
+}
+
+impl Recorder {
+    fn record(&self, call: Call) {
+        self.calls.lock().push(call);
+        self.condvar.notify_all();
+    }
+}
+
+/// Leading fields of Janus's `janus_ice_handle` which are being read by the crate followed
+/// by the recorder for the callbacks to find.
+#[repr(C)]
+struct FakeIceHandle {
+    session: *const c_void,
+    handle_id: u64,
+    recorder: *const Recorder,
+}
+
+struct FakeHandle {
+    session: *mut JanusPluginSession,
+    ice_handle: *mut FakeIceHandle,
+}
+
+impl FakeHandle {
+    fn new(handle_id: u64, recorder: &Arc<Recorder>) -> Self {
+        let ice_handle = Box::into_raw(Box::new(FakeIceHandle {
+            session: std::ptr::null(),
+            handle_id,
+            recorder: Arc::as_ptr(recorder),
+        }));
+
+        let session = Box::into_raw(Box::new(JanusPluginSession {
+            gateway_handle: ice_handle as *mut c_void,
+            plugin_handle: std::ptr::null_mut(),
+            stopped: 0,
+            ref_: JanusRefcount {
+                count: 1,
+                free: free_refcount,
+            },
+        }));
+
+        Self {
+            session,
+            ice_handle,
+        }
+    }
+}
+
+// The structures are being owned by the mock exclusively and Janus shares them among threads too.
+unsafe impl Send for FakeHandle {}
+
+impl Drop for FakeHandle {
+    fn drop(&mut self) {
+        unsafe {
+            drop(Box::from_raw(self.session));
+            drop(Box::from_raw(self.ice_handle));
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Fake Janus core running a plugin of type `P`.
+///
+/// Dropping it destroys the remaining handles and the plugin.
+pub struct MockJanus<P: PluginApp> {
+    recorder: Arc<Recorder>,
+    handles: Mutex<HashMap<u64, FakeHandle>>,
+    last_handle_id: Mutex<u64>,
+    _plugin: PhantomData<P>,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl<P: PluginApp> MockJanus<P> {
+    /// Initializes the plugin with `config_path` directory like Janus does on startup.
+    pub fn new<T: AsRef<Path>>(config_path: T) -> Result<Self, Error> {
+        let guard = MOCK_LOCK.lock();
+
+        let config_path = CString::new(config_path.as_ref().to_string_lossy().as_bytes())
+            .map_err(|err| Error::new(&format!("Failed to cast config path: {}", err)))?;
+
+        // The plugin never writes to the callbacks so it's fine to keep them immutable.
+        let callbacks = &CALLBACKS as *const _ as *mut _;
+
+        if plugin::init::<P>(callbacks, config_path.as_ptr()) != 0 {
+            return Err(Error::new("Failed to init plugin"));
+        }
+
+        Ok(Self {
+            recorder: Arc::new(Recorder {
+                calls: Mutex::new(Vec::new()),
+                condvar: Condvar::new(),
+            }),
+            handles: Mutex::new(HashMap::new()),
+            last_handle_id: Mutex::new(0),
+            _plugin: PhantomData,
+            _guard: guard,
+        })
+    }
+
+    /// Attaches a new handle like a client does and returns its ID.
+    pub fn create_handle(&self) -> Result<u64, Error> {
+        let handle_id = {
+            let mut last_handle_id = self.last_handle_id.lock();
+            *last_handle_id += 1;
+            *last_handle_id
+        };
+
+        let fake_handle = FakeHandle::new(handle_id, &self.recorder);
+        let mut error = 0;
+        plugin::create_session::<P>(fake_handle.session, &mut error);
+
+        if error != 0 {
+            return Err(Error::new("Failed to create handle"));
+        }
+
+        self.handles.lock().insert(handle_id, fake_handle);
+        Ok(handle_id)
+    }
+
+    /// Detaches the handle like a client does.
+    pub fn destroy_handle(&self, handle_id: u64) -> Result<(), Error> {
+        let fake_handle = self
+            .handles
+            .lock()
+            .remove(&handle_id)
+            .ok_or_else(|| Error::not_found(&format!("Handle {} not found", handle_id)))?;
+
+        let mut error = 0;
+        plugin::destroy_session::<P>(fake_handle.session, &mut error);
+
+        match error {
+            0 => Ok(()),
+            _ => Err(Error::new("Failed to destroy handle")),
+        }
+    }
+
+    /// Returns a reference to the plugin's handle to inspect its state.
+    pub fn handle_ref(&self, handle_id: u64) -> Option<HandleRef<P>> {
+        let app_ref = P::app().read().ok()?;
+        app_ref.as_ref()?.handle_ref(handle_id)
+    }
+
+    /// Sends a message with optional JSEP to the handle and returns the plugin's response.
+    pub fn send_message<S: Serialize>(
+        &self,
+        handle_id: u64,
+        transaction: &str,
+        payload: &S,
+        jsep: Option<&Jsep>,
+    ) -> Result<MessageResult, Error> {
+        let session = self.session(handle_id)?;
+
+        let transaction = CString::new(transaction)
+            .map_err(|err| Error::new(&format!("Failed to cast transaction: {}", err)))?;
+
+        let payload = to_json(payload)?;
+
+        let jsep = match jsep {
+            Some(jsep) => to_json(jsep)?,
+            None => std::ptr::null_mut(),
+        };
+
+        // The plugin takes ownership over all of the arguments like in Janus.
+        let result_ptr =
+            plugin::handle_message::<P>(session, transaction.into_raw(), payload, jsep);
+
+        if result_ptr.is_null() {
+            return Err(Error::new("Plugin returned no result"));
+        }
+
+        let result = unsafe { ffi::free_plugin_result(result_ptr) };
+
+        let content = if result.content.is_null() {
+            JsonValue::Null
+        } else {
+            let content = from_json(result.content);
+            unsafe { json_decref(result.content) };
+            content?
+        };
+
+        match result.type_ {
+            JanusPluginResultType::JANUS_PLUGIN_OK => Ok(MessageResult::Ok(content)),
+            JanusPluginResultType::JANUS_PLUGIN_OK_WAIT => Ok(MessageResult::OkWait),
+            JanusPluginResultType::JANUS_PLUGIN_ERROR => Ok(MessageResult::Error(
+                content.as_str().unwrap_or("").to_owned(),
+            )),
+        }
+    }
+
+    /// Sends a request with Janus's Admin API `message_plugin` call and returns the response.
+    pub fn send_admin_message<S: Serialize>(&self, payload: &S) -> Result<JsonValue, Error> {
+        let payload = to_json(payload)?;
+
+        // Unlike handle messages, Janus keeps the ownership over the request.
+        let response = plugin::handle_admin_message::<P>(payload);
+        unsafe { json_decref(payload) };
+
+        if response.is_null() {
+            return Err(Error::new("Plugin returned no admin response"));
+        }
+
+        let value = from_json(response);
+        unsafe { json_decref(response) };
+        value
+    }
+
+    /// Tells the plugin that the handle's PeerConnection is up.
+    pub fn setup_media(&self, handle_id: u64) -> Result<(), Error> {
+        plugin::setup_media::<P>(self.session(handle_id)?);
+        Ok(())
+    }
+
+    /// Passes an RTP packet from the handle's peer to the plugin.
+    pub fn incoming_rtp(
+        &self,
+        handle_id: u64,
+        kind: MediaKind,
+        buffer: &[i8],
+    ) -> Result<(), Error> {
+        let session = self.session(handle_id)?;
+        let mut buffer = buffer.to_vec();
+
+        #[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
+        {
+            let (ptr, len) = (buffer.as_mut_ptr(), buffer.len() as c_int);
+            plugin::incoming_rtp::<P>(session, is_video(kind), ptr, len);
+        }
+
+        #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+        {
+            let mut packet = ffi::janus_plugin_rtp {
+                #[cfg(feature = "janus-1")]
+                mindex: 0,
+                video: is_video(kind),
+                buffer: buffer.as_mut_ptr() as *mut c_char,
+                length: packet_length(&buffer)?,
+                extensions: Default::default(),
+            };
+
+            plugin::incoming_rtp::<P>(session, &mut packet);
+        }
+
+        Ok(())
+    }
+
+    /// Passes an RTCP compound packet from the handle's peer to the plugin.
+    pub fn incoming_rtcp(
+        &self,
+        handle_id: u64,
+        kind: MediaKind,
+        buffer: &[i8],
+    ) -> Result<(), Error> {
+        let session = self.session(handle_id)?;
+        let mut buffer = buffer.to_vec();
+
+        #[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
+        {
+            let (ptr, len) = (buffer.as_mut_ptr(), buffer.len() as c_int);
+            plugin::incoming_rtcp::<P>(session, is_video(kind), ptr, len);
+        }
+
+        #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+        {
+            let mut packet = ffi::janus_plugin_rtcp {
+                #[cfg(feature = "janus-1")]
+                mindex: 0,
+                video: is_video(kind),
+                buffer: buffer.as_mut_ptr() as *mut c_char,
+                length: packet_length(&buffer)?,
+            };
+
+            plugin::incoming_rtcp::<P>(session, &mut packet);
+        }
+
+        Ok(())
+    }
+
+    /// Passes a data channel message from the handle's peer to the plugin.
+    /// `info` is being passed to Janus 0.10+ plugins only.
+    pub fn incoming_data(
+        &self,
+        handle_id: u64,
+        buffer: &[i8],
+        info: DataInfo,
+    ) -> Result<(), Error> {
+        let session = self.session(handle_id)?;
+        let mut buffer = buffer.to_vec();
+
+        #[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
+        {
+            // Older Janus versions have no data channel details.
+            let _ = info;
+            let (ptr, len) = (buffer.as_mut_ptr(), buffer.len() as c_int);
+            plugin::incoming_data::<P>(session, ptr, len);
+        }
+
+        #[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+        {
+            let label = info.label.map(CString::new).transpose().map_err(|err| {
+                Error::new(&format!("Failed to cast data channel label: {}", err))
+            })?;
+
+            let mut packet = ffi::janus_plugin_data {
+                label: label
+                    .as_ref()
+                    .map_or(std::ptr::null(), |label| label.as_ptr())
+                    as *mut c_char,
+                protocol: std::ptr::null_mut(),
+                binary: info.binary as c_int,
+                buffer: buffer.as_mut_ptr() as *mut c_char,
+                length: packet_length(&buffer)?,
+            };
+
+            plugin::incoming_data::<P>(session, &mut packet);
+        }
+
+        Ok(())
+    }
+
+    /// Reports lost packets on the handle's PeerConnection.
+    pub fn slow_link(&self, handle_id: u64, kind: MediaKind, uplink: bool) -> Result<(), Error> {
+        let session = self.session(handle_id)?;
+
+        #[cfg(not(feature = "janus-1"))]
+        plugin::slow_link::<P>(session, uplink as c_int, is_video(kind));
+
+        #[cfg(feature = "janus-1")]
+        plugin::slow_link::<P>(session, 0, is_video(kind), uplink as c_int);
+
+        Ok(())
+    }
+
+    /// Tells the plugin that the handle's PeerConnection has gone.
+    pub fn hangup_media(&self, handle_id: u64) -> Result<(), Error> {
+        plugin::hangup_media::<P>(self.session(handle_id)?);
+        Ok(())
+    }
+
+    /// Returns the handle's state as Janus's admin API `query_handle` does.
+    pub fn query_handle(&self, handle_id: u64) -> Result<JsonValue, Error> {
+        let json = plugin::query_session::<P>(self.session(handle_id)?);
+
+        if json.is_null() {
+            return Err(Error::new("Failed to query handle"));
+        }
+
+        let value = from_json(json);
+        unsafe { json_decref(json) };
+        value
+    }
+
+    /// Returns all callback calls made so far.
+    pub fn calls(&self) -> Vec<Call> {
+        self.recorder.calls.lock().clone()
+    }
+
+    /// Returns all callback calls made so far and forgets them.
+    pub fn take_calls(&self) -> Vec<Call> {
+        std::mem::take(&mut *self.recorder.calls.lock())
+    }
+
+    /// Waits for a call matching the predicate, e.g. a deferred response, and takes it.
+    pub fn wait_for<F>(&self, timeout: Duration, predicate: F) -> Result<Call, Error>
+    where
+        F: Fn(&Call) -> bool,
+    {
+        let deadline = Instant::now() + timeout;
+        let mut calls = self.recorder.calls.lock();
+
+        loop {
+            if let Some(idx) = calls.iter().position(&predicate) {
+                return Ok(calls.remove(idx));
+            }
+
+            if self
+                .recorder
+                .condvar
+                .wait_until(&mut calls, deadline)
+                .timed_out()
+            {
+                return Err(Error::timeout("No matching call"));
+            }
+        }
+    }
+
+    fn session(&self, handle_id: u64) -> Result<*mut JanusPluginSession, Error> {
+        self.handles
+            .lock()
+            .get(&handle_id)
+            .map(|fake_handle| fake_handle.session)
+            .ok_or_else(|| Error::not_found(&format!("Handle {} not found", handle_id)))
+    }
+}
+
+impl<P: PluginApp> Drop for MockJanus<P> {
+    fn drop(&mut self) {
+        for (_, fake_handle) in self.handles.lock().drain() {
+            let mut error = 0;
+            plugin::destroy_session::<P>(fake_handle.session, &mut error);
+        }
+
+        plugin::destroy::<P>();
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+fn fake_ice_handle<'a>(handle: *mut JanusPluginSession) -> &'a FakeIceHandle {
+    unsafe { &*((*handle).gateway_handle as *const FakeIceHandle) }
+}
+
+fn record(handle: *mut JanusPluginSession, build_call: impl FnOnce(u64) -> Call) {
+    let ice_handle = fake_ice_handle(handle);
+    let recorder = unsafe { &*ice_handle.recorder };
+    recorder.record(build_call(ice_handle.handle_id));
+}
+
+fn to_json<S: Serialize + ?Sized>(value: &S) -> Result<*mut json_t, Error> {
+    jansson::to_json(value).map_err(|err| Error::new(&format!("Failed to dump JSON: {}", err)))
+}
+
+fn from_json(json: *mut json_t) -> Result<JsonValue, Error> {
+    jansson::from_json(json).map_err(|err| Error::new(&format!("Failed to load JSON: {}", err)))
+}
+
+fn json_value(json: *mut json_t) -> JsonValue {
+    from_json(json).unwrap_or(JsonValue::Null)
+}
+
+extern "C" fn push_event(
+    handle: *mut JanusPluginSession,
+    _plugin: *mut JanusPlugin,
+    transaction: *const c_char,
+    message: *mut json_t,
+    jsep: *mut json_t,
+) -> c_int {
+    // Like Janus, it doesn't take the ownership over the message and JSEP.
+    let transaction = if transaction.is_null() {
+        None
+    } else {
+        Some(
+            unsafe { CStr::from_ptr(transaction) }
+                .to_string_lossy()
+                .into_owned(),
+        )
+    };
+
+    let jsep = if jsep.is_null() {
+        None
+    } else {
+        Some(json_value(jsep))
+    };
+
+    record(handle, |handle_id| Call::PushEvent {
+        handle_id,
+        transaction,
+        message: json_value(message),
+        jsep,
+    });
+
+    0
+}
+
+#[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
+extern "C" fn relay_rtp(
+    handle: *mut JanusPluginSession,
+    video: c_int,
+    buf: *mut c_char,
+    len: c_int,
+) {
+    let buffer = unsafe { std::slice::from_raw_parts(buf as *const i8, len as usize) }.to_vec();
+
+    record(handle, |handle_id| Call::RelayRtp {
+        handle_id,
+        kind: media_kind(video),
+        buffer,
+    });
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+extern "C" fn relay_rtp(handle: *mut JanusPluginSession, packet: *mut ffi::janus_plugin_rtp) {
+    let packet = unsafe { &*packet };
+    let buffer = packet_buffer(packet.buffer, packet.length);
+
+    record(handle, |handle_id| Call::RelayRtp {
+        handle_id,
+        kind: media_kind(packet.video),
+        buffer,
+    });
+}
+
+#[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
+extern "C" fn relay_rtcp(
+    handle: *mut JanusPluginSession,
+    video: c_int,
+    buf: *mut c_char,
+    len: c_int,
+) {
+    let buffer = unsafe { std::slice::from_raw_parts(buf as *const i8, len as usize) }.to_vec();
+
+    record(handle, |handle_id| Call::RelayRtcp {
+        handle_id,
+        kind: media_kind(video),
+        buffer,
+    });
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+extern "C" fn relay_rtcp(handle: *mut JanusPluginSession, packet: *mut ffi::janus_plugin_rtcp) {
+    let packet = unsafe { &*packet };
+    let buffer = packet_buffer(packet.buffer, packet.length);
+
+    record(handle, |handle_id| Call::RelayRtcp {
+        handle_id,
+        kind: media_kind(packet.video),
+        buffer,
+    });
+}
+
+#[cfg(not(any(feature = "janus-0-10", feature = "janus-1")))]
+extern "C" fn relay_data(handle: *mut JanusPluginSession, buf: *mut c_char, len: c_int) {
+    let buffer = unsafe { std::slice::from_raw_parts(buf as *const i8, len as usize) }.to_vec();
+
+    record(handle, |handle_id| Call::RelayData {
+        handle_id,
+        buffer,
+        binary: false,
+        label: None,
+    });
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+extern "C" fn relay_data(handle: *mut JanusPluginSession, packet: *mut ffi::janus_plugin_data) {
+    let packet = unsafe { &*packet };
+    let buffer = packet_buffer(packet.buffer, packet.length);
+    let info = packet.info();
+
+    record(handle, |handle_id| Call::RelayData {
+        handle_id,
+        buffer,
+        binary: info.binary,
+        label: info.label.map(String::from),
+    });
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+fn packet_buffer(buffer: *mut c_char, length: u16) -> Vec<i8> {
+    unsafe { std::slice::from_raw_parts(buffer as *const i8, length as usize) }.to_vec()
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+extern "C" fn send_pli(handle: *mut JanusPluginSession) {
+    record(handle, |handle_id| Call::SendPli { handle_id });
+}
+
+#[cfg(feature = "janus-1")]
+extern "C" fn send_pli_stream(handle: *mut JanusPluginSession, _mindex: c_int) {
+    record(handle, |handle_id| Call::SendPli { handle_id });
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+extern "C" fn send_remb(handle: *mut JanusPluginSession, bitrate: u32) {
+    record(handle, |handle_id| Call::SendRemb { handle_id, bitrate });
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+extern "C" fn auth_is_signature_valid(_plugin: *mut JanusPlugin, _token: *const c_char) -> c_int {
+    0
+}
+
+#[cfg(any(feature = "janus-0-10", feature = "janus-1"))]
+extern "C" fn auth_signature_contains(
+    _plugin: *mut JanusPlugin,
+    _token: *const c_char,
+    _descriptor: *const c_char,
+) -> c_int {
+    0
+}
+
+extern "C" fn close_pc(handle: *mut JanusPluginSession) {
+    record(handle, |handle_id| Call::ClosePc { handle_id });
+}
+
+extern "C" fn end_session(handle: *mut JanusPluginSession) {
+    record(handle, |handle_id| Call::EndSession { handle_id });
+}
+
+extern "C" fn events_is_enabled() -> c_int {
+    1
+}
+
+extern "C" fn notify_event(
+    _plugin: *mut JanusPlugin,
+    handle: *mut JanusPluginSession,
+    event: *mut json_t,
+) {
+    // Janus steals the event.
+    let value = json_value(event);
+    unsafe { json_decref(event) };
+
+    if !handle.is_null() {
+        record(handle, |handle_id| Call::NotifyEvent {
+            handle_id: Some(handle_id),
+            event: value,
+        });
+    }
+}
+
+extern "C" fn free_refcount(_refcount: *const JanusRefcount) {}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Defines a minimal plugin for tests of the crate's media subsystems.
+///
+/// Handles of type `$handle` ack every message and pass media events with their ID
+/// to `$on_media` which is a `fn(u64, &MediaEvent)`. Completed players are being passed with
+/// the handle's ID to the optional `$on_playback_complete` which is a `fn(u64, u32)`.
+#[cfg(test)]
+macro_rules! test_plugin {
+    ($plugin:ident, $handle:ident, $on_media:expr) => {
+        $crate::testing::test_plugin!($plugin, $handle, $on_media, |_, _| ());
+    };
+    ($plugin:ident, $handle:ident, $on_media:expr, $on_playback_complete:expr) => {
+        #[derive(Clone, serde_derive::Serialize)]
+        struct $handle {
+            id: u64,
+        }
+
+        impl $crate::Handle for $handle {
+            type IncomingMessagePayload = ();
+            type OutgoingMessagePayload = ();
+
+            fn id(&self) -> u64 {
+                self.id
+            }
+
+            fn handle_media_event(&self, media_event: &$crate::MediaEvent) {
+                let on_media: fn(u64, &$crate::MediaEvent) = $on_media;
+                on_media(self.id, media_event);
+            }
+
+            fn on_playback_complete(&mut self, player_id: u32) {
+                let on_playback_complete: fn(u64, u32) = $on_playback_complete;
+                on_playback_complete(self.id, player_id);
+            }
+
+            fn handle_message(
+                &mut self,
+                _message: $crate::IncomingMessage<()>,
+                _responder: $crate::Responder<()>,
+            ) -> Result<$crate::MessageResponse<()>, $crate::Error> {
+                Ok($crate::MessageResponse::Ack)
+            }
+        }
+
+        struct $plugin;
+
+        impl $crate::Plugin for $plugin {
+            type Handle = $handle;
+            type Config = ();
+            type IncomingAdminMessagePayload = ();
+            type OutgoingAdminMessagePayload = ();
+
+            const VERSION: i32 = 1;
+            const VERSION_STRING: &'static str = "0.0.1";
+            const NAME: &'static str = stringify!($plugin);
+            const DESCRIPTION: &'static str = "Test plugin";
+            const AUTHOR: &'static str = "Test";
+            const PACKAGE: &'static str = concat!("janus.plugin.", stringify!($plugin));
+
+            fn init(_config: ()) -> Result<Box<Self>, $crate::Error> {
+                Ok(Box::new(Self))
+            }
+
+            fn build_handle(&self, id: u64) -> Self::Handle {
+                $handle { id }
+            }
+        }
+
+        $crate::lazy_static! {
+            static ref APP: std::sync::RwLock<Option<$crate::plugin::App<$plugin>>> =
+                std::sync::RwLock::new(None);
+        }
+
+        static CORE: std::sync::OnceLock<$crate::plugin::Core<$plugin>> =
+            std::sync::OnceLock::new();
+
+        impl $crate::plugin::PluginApp for $plugin {
+            fn janus_plugin() -> *mut $crate::plugin::JanusPlugin {
+                std::ptr::null_mut()
+            }
+
+            fn app() -> &'static std::sync::RwLock<Option<$crate::plugin::App<Self>>> {
+                &APP
+            }
+
+            fn core() -> &'static std::sync::OnceLock<$crate::plugin::Core<Self>> {
+                &CORE
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+pub(crate) use test_plugin;
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{OnceLock, RwLock};
+
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use super::*;
+    use crate::plugin::{room, App, Callbacks, Core};
+    use crate::{
+        lazy_static, Handle, IncomingMessage, MediaEvent, MessageResponse, OutgoingMessage, Plugin,
+        Responder,
+    };
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "lowercase", tag = "method")]
+    enum Request {
+        Echo { data: String },
+        Later { data: String },
+        Deferred { data: String },
+        Push { data: String },
+        Forget,
+        Fail,
+        Slow,
+        Count,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct Response {
+        data: String,
+    }
+
+    #[derive(Clone, Serialize)]
+    struct TestHandle {
+        id: u64,
+        #[serde(skip)]
+        messages: u32,
+    }
+
+    impl Handle for TestHandle {
+        type IncomingMessagePayload = Request;
+        type OutgoingMessagePayload = Response;
+
+        fn id(&self) -> u64 {
+            self.id
+        }
+
+        fn handle_media_event(&self, media_event: &MediaEvent) {
+            match media_event {
+                MediaEvent::Media {
+                    protocol,
+                    kind,
+                    buffer,
+                    ..
+                } => {
+                    Callbacks::<TestPlugin>::relay_media_packet(self, *protocol, *kind, buffer)
+                        .unwrap();
+                }
+                MediaEvent::Data { buffer, info } => {
+                    Callbacks::<TestPlugin>::relay_data_packet(self, buffer, *info).unwrap();
+                }
+                MediaEvent::Hangup => {
+                    Callbacks::<TestPlugin>::notify_event(self, &json!({"hangup": true})).unwrap();
+                    Callbacks::<TestPlugin>::end_handle(self).unwrap();
+                }
+                _ => (),
+            }
+        }
+
+        fn handle_message(
+            &mut self,
+            message: IncomingMessage<Request>,
+            responder: Responder<Response>,
+        ) -> Result<MessageResponse<Response>, Error> {
+            match message.payload() {
+                Request::Echo { data } => Ok(MessageResponse::Syncronous(Response {
+                    data: data.to_owned(),
+                })),
+                Request::Later { data } => {
+                    let data = data.to_owned();
+                    let jsep = message.jsep().cloned();
+
+                    std::thread::spawn(move || match jsep {
+                        Some(jsep) => responder.reply_with_jsep(Response { data }, jsep),
+                        None => responder.reply(Response { data }),
+                    });
+
+                    Ok(MessageResponse::Ack)
+                }
+                Request::Deferred { data } => {
+                    let data = data.to_owned();
+                    Ok(MessageResponse::deferred(
+                        async move { Ok(Response { data }) },
+                    ))
+                }
+                Request::Push { data } => {
+                    let response = Response {
+                        data: data.to_owned(),
+                    };
+
+                    let message = OutgoingMessage::new(message.transaction().to_owned(), response);
+                    Callbacks::<TestPlugin>::push_event(self, &message)?;
+                    Ok(MessageResponse::Ack)
+                }
+                Request::Forget => Ok(MessageResponse::Ack),
+                Request::Fail => Err(Error::not_found("Nothing here")),
+                Request::Count => {
+                    self.messages += 1;
+                    hook("count", self.id);
+                    std::thread::sleep(Duration::from_millis(100));
+
+                    Ok(MessageResponse::Syncronous(Response {
+                        data: self.messages.to_string(),
+                    }))
+                }
+                Request::Slow => {
+                    hook("slow start", self.id);
+                    std::thread::sleep(Duration::from_millis(100));
+                    hook("slow end", self.id);
+                    Ok(MessageResponse::Syncronous(Response {
+                        data: "slow".into(),
+                    }))
+                }
+            }
+        }
+
+        fn on_created(&mut self) {
+            hook("created", self.id);
+            use_app_with_pending_writer(self.id);
+        }
+
+        fn on_media_setup(&mut self) {
+            hook("setup", self.id);
+        }
+
+        fn on_destroy(&mut self) {
+            hook("destroy", self.id);
+            use_app_with_pending_writer(self.id);
+        }
+    }
+
+    static HOOKS: Mutex<Vec<String>> = const_mutex(Vec::new());
+    static PENDING_WRITER: AtomicBool = AtomicBool::new(false);
+
+    fn hook(name: &str, handle_id: u64) {
+        HOOKS.lock().push(format!("{} {}", name, handle_id));
+    }
+
+    /// Takes the app lock from a hook while another thread waits to write it like Janus
+    /// destroying the plugin does. It deadlocks if the hook is being called under the lock.
+    fn use_app_with_pending_writer(handle_id: u64) {
+        if !PENDING_WRITER.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let writer = std::thread::spawn(|| drop(TestPlugin::app().write()));
+        std::thread::sleep(Duration::from_millis(50));
+        room::rooms_of::<TestPlugin>(handle_id).unwrap();
+        writer.join().unwrap();
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "lowercase", tag = "request")]
+    enum AdminRequest {
+        Version,
+    }
+
+    struct TestPlugin;
+
+    impl Plugin for TestPlugin {
+        type Handle = TestHandle;
+        type Config = ();
+        type IncomingAdminMessagePayload = AdminRequest;
+        type OutgoingAdminMessagePayload = JsonValue;
+
+        const VERSION: i32 = 1;
+        const VERSION_STRING: &'static str = "0.0.1";
+        const NAME: &'static str = "Test";
+        const DESCRIPTION: &'static str = "Test plugin";
+        const AUTHOR: &'static str = "Test";
+        const PACKAGE: &'static str = "janus.plugin.test";
+
+        fn init(_config: ()) -> Result<Box<Self>, Error> {
+            Ok(Box::new(Self))
+        }
+
+        fn build_handle(&self, id: u64) -> Self::Handle {
+            TestHandle { id, messages: 0 }
+        }
+
+        fn response_timeout(&self) -> Duration {
+            Duration::from_millis(50)
+        }
+
+        fn handle_admin_message(&self, payload: AdminRequest) -> Result<JsonValue, Error> {
+            use_app_with_pending_writer(0);
+
+            match payload {
+                AdminRequest::Version => Ok(json!({ "version": Self::VERSION_STRING })),
+            }
+        }
+
+        fn shutdown(&self) {
+            HOOKS.lock().push(String::from("shutdown"));
+        }
+    }
+
+    lazy_static! {
+        static ref APP: RwLock<Option<App<TestPlugin>>> = RwLock::new(None);
+    }
+
+    static CORE: OnceLock<Core<TestPlugin>> = OnceLock::new();
+
+    impl PluginApp for TestPlugin {
+        fn janus_plugin() -> *mut JanusPlugin {
+            std::ptr::null_mut()
+        }
+
+        fn app() -> &'static RwLock<Option<App<Self>>> {
+            &APP
+        }
+
+        fn core() -> &'static OnceLock<Core<Self>> {
+            &CORE
+        }
+    }
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn synchronous_message() {
+        let janus = MockJanus::<TestPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+
+        let result = janus
+            .send_message(
+                handle_id,
+                "txn",
+                &json!({"method": "echo", "data": "hi"}),
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(result, MessageResult::Ok(json!({"data": "hi"})));
+
+        let result = janus
+            .send_message(handle_id, "txn", &json!({"method": "fail"}), None)
+            .unwrap();
+
+        assert_eq!(
+            result,
+            MessageResult::Ok(json!({"error_code": 404, "error": "Nothing here"}))
+        );
+
+        let result = janus
+            .send_message(handle_id, "txn", &json!({"method": "echo"}), None)
+            .unwrap();
+
+        match result {
+            MessageResult::Ok(response) => {
+                assert_eq!(response["error_code"], 400);
+                assert_eq!(response["data"], json!({"path": "$.data"}));
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn responder_reply_with_jsep() {
+        let janus = MockJanus::<TestPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+        let sdp = "v=0\r\no=- 1 1 IN IP4 127.0.0.1\r\ns=-\r\nt=0 0\r\n";
+        let jsep: Jsep = serde_json::from_value(json!({"type": "offer", "sdp": sdp})).unwrap();
+
+        let result = janus
+            .send_message(
+                handle_id,
+                "txn1",
+                &json!({"method": "later", "data": "hi"}),
+                Some(&jsep),
+            )
+            .unwrap();
+
+        assert_eq!(result, MessageResult::OkWait);
+
+        let call = janus.wait_for(TIMEOUT, Call::is_push_event).unwrap();
+
+        match call {
+            Call::PushEvent {
+                handle_id: call_handle_id,
+                transaction,
+                message,
+                jsep,
+            } => {
+                assert_eq!(call_handle_id, handle_id);
+                assert_eq!(transaction.as_deref(), Some("txn1"));
+                assert_eq!(message, json!({"data": "hi"}));
+                assert_eq!(jsep.unwrap()["type"], "offer");
+            }
+            other => panic!("Unexpected call: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn deferred_response() {
+        let janus = MockJanus::<TestPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+
+        let payload = json!({"method": "deferred", "data": "hi"});
+        let result = janus
+            .send_message(handle_id, "txn2", &payload, None)
+            .unwrap();
+        assert_eq!(result, MessageResult::OkWait);
+
+        let call = janus.wait_for(TIMEOUT, Call::is_push_event).unwrap();
+
+        if let Call::PushEvent {
+            transaction,
+            message,
+            ..
+        } = call
+        {
+            assert_eq!(transaction.as_deref(), Some("txn2"));
+            assert_eq!(message, json!({"data": "hi"}));
+        }
+    }
+
+    #[test]
+    fn push_event_response() {
+        let janus = MockJanus::<TestPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+
+        let payload = json!({"method": "push", "data": "hi"});
+        let result = janus
+            .send_message(handle_id, "txn4", &payload, None)
+            .unwrap();
+        assert_eq!(result, MessageResult::OkWait);
+
+        // Wait past the response timeout to make sure no timeout error follows the response.
+        std::thread::sleep(Duration::from_millis(150));
+
+        match janus.take_calls().as_slice() {
+            [Call::PushEvent {
+                transaction,
+                message,
+                ..
+            }] => {
+                assert_eq!(transaction.as_deref(), Some("txn4"));
+                assert_eq!(message, &json!({"data": "hi"}));
+            }
+            other => panic!("Unexpected calls: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn response_timeout() {
+        let janus = MockJanus::<TestPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+
+        let result = janus
+            .send_message(handle_id, "txn3", &json!({"method": "forget"}), None)
+            .unwrap();
+
+        assert_eq!(result, MessageResult::OkWait);
+        let call = janus.wait_for(TIMEOUT, Call::is_push_event).unwrap();
+
+        if let Call::PushEvent { message, .. } = call {
+            assert_eq!(message["error_code"], 408);
+        }
+    }
+
+    #[test]
+    fn media() {
+        let janus = MockJanus::<TestPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+
+        janus
+            .incoming_rtp(handle_id, MediaKind::Video, &[-128, 96, 0, 1])
+            .unwrap();
+
+        janus
+            .incoming_data(handle_id, &[104, 105], DataInfo::default())
+            .unwrap();
+        janus.hangup_media(handle_id).unwrap();
+
+        assert_eq!(
+            janus.take_calls(),
+            vec![
+                Call::RelayRtp {
+                    handle_id,
+                    kind: MediaKind::Video,
+                    buffer: vec![-128, 96, 0, 1],
+                },
+                Call::RelayData {
+                    handle_id,
+                    buffer: vec![104, 105],
+                    binary: false,
+                    label: None,
+                },
+                Call::NotifyEvent {
+                    handle_id: Some(handle_id),
+                    event: json!({"hangup": true}),
+                },
+                Call::EndSession { handle_id },
+            ]
+        );
+
+        assert!(janus.calls().is_empty());
+    }
+
+    #[test]
+    fn destroy_handle() {
+        let janus = MockJanus::<TestPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+        assert_eq!(
+            janus.query_handle(handle_id).unwrap(),
+            json!({"id": handle_id})
+        );
+
+        let handle_ref = janus.handle_ref(handle_id).unwrap();
+        assert!(handle_ref.is_alive());
+
+        janus.destroy_handle(handle_id).unwrap();
+        assert!(!handle_ref.is_alive());
+        assert!(janus.handle_ref(handle_id).is_none());
+        assert!(janus.destroy_handle(handle_id).is_err());
+    }
+
+    #[test]
+    fn admin_message() {
+        let janus = MockJanus::<TestPlugin>::new("/tmp").unwrap();
+
+        let response = janus
+            .send_admin_message(&json!({"request": "version"}))
+            .unwrap();
+
+        assert_eq!(response, json!({"version": "0.0.1"}));
+
+        let response = janus
+            .send_admin_message(&json!({"request": "unknown"}))
+            .unwrap();
+
+        assert_eq!(response["error_code"], 400);
+        assert_eq!(response["data"]["path"], "$.request");
+        assert_eq!(response["data"]["variants"], json!(["version"]));
+    }
+
+    #[test]
+    fn lifecycle_hooks() {
+        let janus = MockJanus::<TestPlugin>::new("/tmp").unwrap();
+        HOOKS.lock().clear();
+
+        let handle_id = janus.create_handle().unwrap();
+        let other_handle_id = janus.create_handle().unwrap();
+        janus.setup_media(handle_id).unwrap();
+        janus.destroy_handle(handle_id).unwrap();
+        drop(janus);
+
+        assert_eq!(
+            *HOOKS.lock(),
+            vec![
+                format!("created {}", handle_id),
+                format!("created {}", other_handle_id),
+                format!("setup {}", handle_id),
+                format!("destroy {}", handle_id),
+                format!("destroy {}", other_handle_id),
+                String::from("shutdown"),
+            ]
+        );
+    }
+
+    #[test]
+    fn hooks_using_app_with_pending_writer() {
+        let janus = MockJanus::<TestPlugin>::new("/tmp").unwrap();
+        PENDING_WRITER.store(true, Ordering::SeqCst);
+        let handle_id = janus.create_handle().unwrap();
+        janus.destroy_handle(handle_id).unwrap();
+        janus
+            .send_admin_message(&json!({"request": "version"}))
+            .unwrap();
+        PENDING_WRITER.store(false, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn mutate_from_message() {
+        let janus = MockJanus::<TestPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+        let other_handle_id = janus.create_handle().unwrap();
+        HOOKS.lock().clear();
+        janus.take_calls();
+
+        std::thread::scope(|scope| {
+            let message_thread = scope.spawn(|| {
+                janus
+                    .send_message(handle_id, "txn", &json!({"method": "count"}), None)
+                    .unwrap()
+            });
+
+            while HOOKS.lock().is_empty() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+
+            // Media of another handle isn't waiting for the message.
+            janus
+                .incoming_rtp(other_handle_id, MediaKind::Video, &[-128, 96, 0, 1])
+                .unwrap();
+
+            assert!(!message_thread.is_finished());
+
+            assert_eq!(
+                message_thread.join().unwrap(),
+                MessageResult::Ok(json!({"data": "1"}))
+            );
+        });
+
+        assert_eq!(
+            janus.take_calls(),
+            vec![Call::RelayRtp {
+                handle_id: other_handle_id,
+                kind: MediaKind::Video,
+                buffer: vec![-128, 96, 0, 1],
+            }]
+        );
+
+        let result = janus
+            .send_message(handle_id, "txn", &json!({"method": "count"}), None)
+            .unwrap();
+
+        assert_eq!(result, MessageResult::Ok(json!({"data": "2"})));
+    }
+
+    #[test]
+    fn destroy_after_in_flight_message() {
+        let janus = MockJanus::<TestPlugin>::new("/tmp").unwrap();
+        let handle_id = janus.create_handle().unwrap();
+        HOOKS.lock().clear();
+
+        std::thread::scope(|scope| {
+            let message_thread = scope.spawn(|| {
+                janus
+                    .send_message(handle_id, "txn", &json!({"method": "slow"}), None)
+                    .unwrap()
+            });
+
+            while HOOKS.lock().is_empty() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+
+            janus.destroy_handle(handle_id).unwrap();
+            assert_eq!(
+                message_thread.join().unwrap(),
+                MessageResult::Ok(json!({"data": "slow"}))
+            );
+        });
+
+        assert_eq!(
+            *HOOKS.lock(),
+            vec![
+                format!("slow start {}", handle_id),
+                format!("slow end {}", handle_id),
+                format!("destroy {}", handle_id),
+            ]
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Semantic checks of incoming payloads on top of deserialization.
+//!
+//! Payloads which fail to deserialize are being rejected with a `bad_request` error pointing
+//! to the offending field:
+//!
+//! ```json
+//! {
+//!     "error_code": 400,
+//!     "error": "Invalid request at `$.room`: invalid type: string \"one\", expected u64",
+//!     "data": {"path": "$.room", "expected": "u64"}
+//! }
+//! ```
+//!
+//! An unknown `method` gets the list of accepted ones in `variants`. Serde buffers the fields
+//! of internally tagged enum variants so for them the path of a mistyped field stops at
+//! the enum. Missing fields and unknown methods are being reported with the full path anyway.
+//!
+//! Constraints beyond the types are being declared on payload fields with serde's
+//! `deserialize_with` attribute so violations get reported the same way:
+//!
+//! ```
+//! use janus_app::validation::{self, Validate};
+//! use serde_derive::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Window {
+//!     start: u64,
+//!     end: u64,
+//! }
+//!
+//! impl Validate for Window {
+//!     fn validate(&self) -> Result<(), String> {
+//!         if self.start < self.end {
+//!             Ok(())
+//!         } else {
+//!             Err(String::from("start before end"))
+//!         }
+//!     }
+//! }
+//!
+//! #[derive(Deserialize)]
+//! #[serde(rename_all = "lowercase", tag = "method")]
+//! enum IncomingMessagePayload {
+//!     Join {
+//!         #[serde(deserialize_with = "validation::range::<_, _, 1, 1000>")]
+//!         room: u32,
+//!         #[serde(deserialize_with = "validation::length::<_, 1, 64>")]
+//!         display: String,
+//!         #[serde(deserialize_with = "validation::validated")]
+//!         window: Window,
+//!     },
+//! }
+//! ```
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+
+use crate::{jansson, Error};
+
+/// Check of a deserialized value for [validated](fn.validated.html).
+pub trait Validate {
+    /// Returns a description of what's expected when the value doesn't satisfy it.
+    fn validate(&self) -> Result<(), String>;
+}
+
+/// Deserializes an integer within `MIN..=MAX`.
+pub fn range<'de, D, T, const MIN: i128, const MAX: i128>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Copy + Into<i128>,
+{
+    let value = T::deserialize(deserializer)?;
+    let number: i128 = value.into();
+
+    if (MIN..=MAX).contains(&number) {
+        return Ok(value);
+    }
+
+    let unexpected = match number {
+        number if number < 0 => de::Unexpected::Signed(number as i64),
+        number => de::Unexpected::Unsigned(number as u64),
+    };
+
+    let expected = format!("an integer from {} to {}", MIN, MAX);
+    Err(de::Error::invalid_value(unexpected, &expected.as_str()))
+}
+
+/// Deserializes a string of `MIN..=MAX` characters.
+pub fn length<'de, D, const MIN: usize, const MAX: usize>(
+    deserializer: D,
+) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+
+    if (MIN..=MAX).contains(&value.chars().count()) {
+        return Ok(value);
+    }
+
+    let expected = format!("a string of {} to {} characters", MIN, MAX);
+    Err(de::Error::invalid_value(
+        de::Unexpected::Str(&value),
+        &expected.as_str(),
+    ))
+}
+
+/// Deserializes a value and runs its [Validate](trait.Validate.html) check.
+pub fn validated<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Validate,
+{
+    let value = T::deserialize(deserializer)?;
+
+    match value.validate() {
+        Ok(()) => Ok(value),
+        Err(expected) => Err(de::Error::invalid_value(
+            de::Unexpected::Other("value"),
+            &expected.as_str(),
+        )),
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Converts a deserialization failure to a `bad_request` error for the client.
+/// `subject` starts the message, e.g. `Invalid request`.
+pub(crate) fn request_error(subject: &str, err: jansson::Error) -> Error {
+    let path = err.path();
+    let mut data = JsonMap::new();
+    data.insert(String::from("path"), JsonValue::from(path.as_str()));
+
+    if let Some(expected) = err.expected() {
+        data.insert(String::from("expected"), JsonValue::from(expected));
+    }
+
+    if let Some(variants) = err.variants() {
+        data.insert(String::from("variants"), JsonValue::from(variants.to_vec()));
+    }
+
+    let message = format!("{} at `{}`: {}", subject, path, err.message());
+
+    Error::bad_request(&message)
+        .set_data(JsonValue::Object(data))
+        .set_source(err)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use serde_derive::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::ErrorKind;
+
+    #[derive(Debug, Deserialize)]
+    struct Window {
+        start: u64,
+        end: u64,
+    }
+
+    impl Validate for Window {
+        fn validate(&self) -> Result<(), String> {
+            if self.start < self.end {
+                Ok(())
+            } else {
+                Err(String::from("start before end"))
+            }
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Join {
+        #[serde(deserialize_with = "range::<_, _, -10, 10>")]
+        offset: i32,
+        #[serde(deserialize_with = "length::<_, 1, 4>")]
+        display: String,
+        #[serde(default, deserialize_with = "validated")]
+        windows: Vec<Window>,
+    }
+
+    impl Validate for Vec<Window> {
+        fn validate(&self) -> Result<(), String> {
+            self.iter().try_for_each(Validate::validate)
+        }
+    }
+
+    fn parse(payload: JsonValue) -> Result<Join, Error> {
+        let json = jansson::to_json(&payload).unwrap();
+        let result = jansson::from_json(json).map_err(|err| request_error("Invalid request", err));
+        unsafe { jansson_sys::json_decref(json) };
+        result
+    }
+
+    #[test]
+    fn valid() {
+        let payload = json!({"offset": -10, "display": "Åsa", "windows": [{"start": 1, "end": 2}]});
+        let join = parse(payload).unwrap();
+        assert_eq!((join.offset, join.display.as_str()), (-10, "Åsa"));
+        assert_eq!(join.windows.len(), 1);
+    }
+
+    #[test]
+    fn range_violation() {
+        let err = parse(json!({"offset": 11, "display": "a"})).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BadRequest);
+
+        assert_eq!(
+            err.data(),
+            Some(&json!({"path": "$.offset", "expected": "an integer from -10 to 10"}))
+        );
+    }
+
+    #[test]
+    fn length_violation() {
+        let err = parse(json!({"offset": 0, "display": "toolong"})).unwrap_err();
+
+        assert_eq!(
+            err.as_str(),
+            "Invalid request at `$.display`: invalid value: string \"toolong\", \
+             expected a string of 1 to 4 characters"
+        );
+    }
+
+    #[test]
+    fn validate_violation() {
+        let payload = json!({"offset": 0, "display": "a", "windows": [{"start": 2, "end": 1}]});
+        let err = parse(payload).unwrap_err();
+
+        assert_eq!(
+            err.data(),
+            Some(&json!({"path": "$.windows", "expected": "start before end"}))
+        );
+    }
+}